@@ -176,11 +176,19 @@ pub fn frequency_to_ftw(frequency: f64, sysclk: f64) -> u48 {
     u48::new(((frequency * lsb).round() as i64 as u64) & u48::MASK)
 }
 
+pub fn ftw_to_frequency(ftw: u48, sysclk: f64) -> f64 {
+    ftw.value() as f64 * sysclk / (1u64 << 48) as f64
+}
+
 pub fn phase_to_pow(phase: f32) -> u14 {
     // Alias into Nyquist
     u14::new(((phase * (1u32 << 14) as f32).round() as i32 as u16) & u14::MASK)
 }
 
+pub fn pow_to_phase(pow: u14) -> f32 {
+    pow.value() as f32 / (1u32 << 14) as f32
+}
+
 pub fn dac_fs_to_fsc(dac_fs: f32, r_dac_ref: f32) -> u10 {
     let lsb = r_dac_ref * (1024.0 / 192.0 / 1.2);
     let fsc = dac_fs * lsb - (1024.0 / 192.0 * 72.0);
@@ -191,6 +199,11 @@ pub fn dac_fs_to_fsc(dac_fs: f32, r_dac_ref: f32) -> u10 {
     )
 }
 
+pub fn fsc_to_dac_fs(fsc: u10, r_dac_ref: f32) -> f32 {
+    let lsb = r_dac_ref * (1024.0 / 192.0 / 1.2);
+    (fsc.value() as f32 + (1024.0 / 192.0 * 72.0)) / lsb
+}
+
 impl<B: SpiDevice<u8>> Ad9912<B> {
     pub fn new(bus: B) -> Self {
         Self { bus }
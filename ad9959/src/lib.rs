@@ -40,6 +40,17 @@ pub struct Csr {
     channel: u4,
 }
 
+impl Csr {
+    /// The channel select register contents for a set of channels.
+    ///
+    /// # Args
+    /// * `mode` - The communication mode.
+    /// * `channels` - The channels to select.
+    pub fn select(mode: Mode, channels: Channel) -> Self {
+        Self::default().with_mode(mode).with_channel(channels)
+    }
+}
+
 #[bitfield(u24, default = 0)]
 #[derive(Debug, PartialEq)]
 pub struct Fr1 {
@@ -86,6 +97,40 @@ pub struct Acr {
     arr: u8,
 }
 
+/// Channel function register.
+///
+/// The default has the sweep disabled and the DAC at full scale current.
+#[bitfield(u24, default = 0x000300)]
+#[derive(Debug, PartialEq)]
+pub struct Cfr {
+    #[bit(0, rw)]
+    sine: bool,
+    #[bit(1, rw)]
+    clear_phase: bool,
+    #[bit(2, rw)]
+    autoclear_phase: bool,
+    #[bit(3, rw)]
+    clear_sweep: bool,
+    #[bit(4, rw)]
+    autoclear_sweep: bool,
+    #[bit(5, rw)]
+    matched_pipe: bool,
+    #[bit(6, rw)]
+    dac_pd: bool,
+    #[bit(7, rw)]
+    digital_pd: bool,
+    #[bits(8..=9, rw)]
+    dac_current: u2,
+    #[bit(13, rw)]
+    load_srr: bool,
+    #[bit(14, rw)]
+    sweep: bool,
+    #[bit(15, rw)]
+    sweep_no_dwell: bool,
+    #[bits(22..=23, rw)]
+    afp_select: u2,
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[bitenum(u7)]
 pub enum Address {
@@ -116,6 +161,18 @@ pub enum Address {
     CW15 = 0x18,
 }
 
+impl Address {
+    /// The register width in bytes.
+    pub fn width(&self) -> usize {
+        match self {
+            Self::CSR => 1,
+            Self::FR2 | Self::CPOW0 | Self::LSRR => 2,
+            Self::FR1 | Self::CFR | Self::ACR => 3,
+            _ => 4,
+        }
+    }
+}
+
 /// Possible errors generated by the AD9959 driver.
 #[derive(Debug)]
 pub enum Error {
@@ -126,6 +183,55 @@ pub enum Error {
     Frequency,
 }
 
+/// Convert a frequency to a frequency tuning word.
+///
+/// Args:
+/// * `frequency` - The frequency in Hz.
+/// * `system_clock` - The system clock frequency in Hz.
+///
+/// Returns:
+/// The frequency tuning word or `None` if the frequency is negative or beyond Nyquist.
+pub fn frequency_to_ftw(frequency: f64, system_clock: f64) -> Option<u32> {
+    (0.0..=system_clock / 2.0)
+        .contains(&frequency)
+        .then(|| (frequency * ((1u64 << 32) as f64 / system_clock)) as u32)
+}
+
+/// Convert a frequency tuning word to a frequency in Hz.
+pub fn ftw_to_frequency(ftw: u32, system_clock: f64) -> f64 {
+    ftw as f64 * system_clock / (1u64 << 32) as f64
+}
+
+/// Convert a phase offset in turns to a phase offset word.
+pub fn phase_to_pow(phase: f32) -> u14 {
+    u14::new((phase * (1 << 14) as f32) as i32 as u16 & 0x3FFF)
+}
+
+/// Convert a normalized amplitude to an amplitude control register value.
+///
+/// Returns:
+/// The ACR or `None` if the amplitude is not within [0, 1]. Full scale disables the amplitude
+/// multiplier.
+pub fn amplitude_to_acr(amplitude: f32) -> Option<Acr> {
+    if !(0.0..=1.0).contains(&amplitude) {
+        return None;
+    }
+    let asf = (amplitude * (1 << 10) as f32) as u16;
+    Some(match u10::try_new(asf) {
+        Ok(asf) => Acr::default().with_multiplier(true).with_asf(asf),
+        Err(_) => Acr::default().with_multiplier(false),
+    })
+}
+
+/// Convert an amplitude control register value to a normalized amplitude.
+pub fn acr_to_amplitude(acr: Acr) -> f32 {
+    if acr.multiplier() {
+        acr.asf().value() as f32 / (1 << 10) as f32
+    } else {
+        1.0
+    }
+}
+
 /// A device driver for the AD9959 direct digital synthesis (DDS) chip.
 ///
 /// This chip provides four independently controllable digital-to-analog output sinusoids with
@@ -544,6 +650,98 @@ impl ProfileSerializer {
         }
     }
 
+    /// Add the linear frequency sweep parameters for a number of channels.
+    ///
+    /// # Note
+    /// The sweep starts at the frequency tuning word of the channels and is enabled in the
+    /// channel function register. It runs towards the end while the profile pin of a channel is
+    /// high and back to the start while it is low.
+    ///
+    /// # Args
+    /// * `channels` - The channels to apply the sweep to.
+    /// * `end` - The end frequency tuning word. It must exceed the start.
+    /// * `step` - The frequency tuning word step in both directions.
+    /// * `rate` - The number of SYNC_CLK cycles per step in both directions.
+    #[inline]
+    pub fn push_sweep(
+        &mut self,
+        channels: Channel,
+        end: u32,
+        step: u32,
+        rate: u8,
+    ) {
+        self.push_register(channels, Address::LSRR, &[rate, rate]);
+        self.push_write(Address::RDW, &step.to_be_bytes());
+        self.push_write(Address::FDW, &step.to_be_bytes());
+        self.push_write(Address::CW1, &end.to_be_bytes());
+    }
+
+    /// Add the channel function and a modulation level for a number of channels.
+    ///
+    /// # Args
+    /// * `channels` - The channels to configure.
+    /// * `cfr` - The channel function register.
+    /// * `word` - If provided, the channel word 1 (the second modulation level), MSB aligned.
+    #[inline]
+    pub fn push_function(
+        &mut self,
+        channels: Channel,
+        cfr: Cfr,
+        word: Option<u32>,
+    ) {
+        self.push_register(
+            channels,
+            Address::CFR,
+            &cfr.raw_value().to_be_bytes(),
+        );
+        if let Some(word) = word {
+            self.push_write(Address::CW1, &word.to_be_bytes());
+        }
+    }
+
+    /// Add a raw register write for a number of channels.
+    ///
+    /// # Args
+    /// * `channels` - The channels to select for channel registers.
+    /// * `register` - The register to write.
+    /// * `value` - The register contents. The length must match the register width.
+    #[inline]
+    pub fn push_register(
+        &mut self,
+        channels: Channel,
+        register: Address,
+        value: &[u8],
+    ) {
+        self.push_write(
+            Address::CSR,
+            &Csr::default()
+                .with_mode(self.mode)
+                .with_channel(channels)
+                .raw_value()
+                .to_be_bytes(),
+        );
+        self.push_write(register, value);
+    }
+
+    /// Check whether a number of additional bytes fit the profile.
+    ///
+    /// # Args
+    /// * `bytes` - The number of bytes to be pushed, including the register addresses.
+    ///
+    /// # Returns
+    /// Whether the finalized profile including the 32-bit alignment padding fits the buffer.
+    #[inline]
+    pub fn fits(&self, bytes: usize) -> bool {
+        let mut len = self.index + bytes;
+        if len & 1 != 0 {
+            len += 3;
+        }
+        if len & 2 != 0 {
+            len += 2;
+        }
+        len <= self.data.len()
+    }
+
     /// Add a register write to the serialization data.
     #[inline]
     fn push_write(&mut self, register: Address, value: &[u8]) {
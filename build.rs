@@ -1,5 +1,31 @@
+use std::{env, fmt::Write, fs, path::Path};
+
+/// Network buffer sizes that can be overridden at build time through `STABILIZER_<NAME>`
+/// environment variables.
+const NET_BUFFERS: [(&str, usize); 7] = [
+    ("TCP_RX_BUFFER", 1024),
+    ("TCP_TX_BUFFER", 1024),
+    ("UDP_RX_BUFFER", 1024),
+    ("UDP_TX_BUFFER", 2048),
+    ("UDP_PACKETS", 10),
+    ("MQTT_TELEMETRY_BUFFER", 2048),
+    ("MQTT_SETTINGS_BUFFER", 1024),
+];
+
 fn main() {
     built::write_built_file()
         .expect("Failed to acquire build-time information");
     println!("cargo:rerun-if-changed=memory.x");
+
+    let mut buffers = String::new();
+    for (name, default) in NET_BUFFERS {
+        let var = format!("STABILIZER_{name}");
+        println!("cargo:rerun-if-env-changed={var}");
+        let value = env::var(&var).map_or(default, |value| {
+            value.parse().unwrap_or_else(|_| panic!("Invalid {var}"))
+        });
+        writeln!(buffers, "pub const {name}: usize = {value};").unwrap();
+    }
+    let out = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out).join("net_buffers.rs"), buffers).unwrap();
 }
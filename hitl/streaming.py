@@ -55,7 +55,7 @@ async def _main():
             local = "0.0.0.0"
 
         logger.info("Starting stream")
-        await conf.set("/stream", f"{args.addr}:{args.port}")
+        await conf.set("/stream/target", f"{args.addr}:{args.port}")
 
         try:
             _transport, stream = await Stream.open(
@@ -67,7 +67,7 @@ async def _main():
                 raise RuntimeError("High frame loss", loss)
         finally:
             logger.info("Stopping stream")
-            await conf.set("/stream", "0.0.0.0:0")
+            await conf.set("/stream/target", "0.0.0.0:0")
 
         logger.info("Draining queue")
         await asyncio.sleep(0.1)
@@ -0,0 +1,131 @@
+//! Authenticated remote commands
+//!
+//! # Design
+//! Critical platform commands can be issued over MQTT on `<prefix>/command` as JSON, e.g.
+//! `{"command": "dfu", "mac": "<hex>"}`. The `mac` is the HMAC-SHA256 of `<command>:<nonce>` keyed
+//! with the device secret (`net/secret`). The device publishes its current nonce (retained) on
+//! `<prefix>/command/nonce`. Every command attempt, successful or not, advances the nonce so that
+//! captured commands can not be replayed. The initial nonce is random. The secret itself never
+//! leaves the device and can only be configured through the USB serial terminal.
+//!
+//! A reboot into the DFU bootloader takes the instrument offline. It requires two steps: `dfu` arms
+//! it and publishes an event, `confirm` within [DFU_CONFIRM_TIMEOUT] performs it. Both are
+//! authenticated individually.
+//!
+//! Remote commands are disabled while no secret is configured.
+use heapless::String;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+/// Time after arming within which a DFU reboot must be confirmed (ms)
+pub const DFU_CONFIRM_TIMEOUT: u32 = 10_000;
+
+/// A remote platform command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Command {
+    /// Reboot the device.
+    Reboot,
+    /// Arm a reboot into the DFU bootloader.
+    Dfu,
+    /// Confirm an armed reboot into the DFU bootloader.
+    Confirm,
+}
+
+impl Command {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reboot => "reboot",
+            Self::Dfu => "dfu",
+            Self::Confirm => "confirm",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request<'a> {
+    command: Command,
+    mac: &'a str,
+}
+
+/// Command verification error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The request is malformed.
+    Malformed,
+    /// The authentication code does not match.
+    Unauthorized,
+}
+
+/// Remote command authenticator.
+pub struct CommandAuth {
+    secret: String<64>,
+    nonce: u64,
+}
+
+impl CommandAuth {
+    /// Construct a new authenticator.
+    ///
+    /// # Args
+    /// * `secret` - The device secret.
+    /// * `seed` - The random initial nonce.
+    ///
+    /// # Returns
+    /// The authenticator or `None` if the secret is empty.
+    pub fn new(secret: &str, seed: u64) -> Option<Self> {
+        (!secret.is_empty()).then(|| Self {
+            // Note(unwrap): The secret setting has the same capacity.
+            secret: String::try_from(secret).unwrap(),
+            nonce: seed,
+        })
+    }
+
+    /// The nonce the next command must be authenticated with.
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Verify a command request.
+    ///
+    /// # Note
+    /// The nonce is advanced regardless of the outcome.
+    ///
+    /// # Args
+    /// * `message` - The JSON request.
+    ///
+    /// # Returns
+    /// The authenticated command.
+    pub fn verify(&mut self, message: &[u8]) -> Result<Command, Error> {
+        let nonce = self.nonce;
+        self.nonce = self.nonce.wrapping_add(1);
+
+        let (request, _) = serde_json_core::from_slice::<Request>(message)
+            .or(Err(Error::Malformed))?;
+        let mut tag = [0u8; 32];
+        if request.mac.len() != 2 * tag.len() {
+            return Err(Error::Malformed);
+        }
+        for (byte, hex) in tag.iter_mut().zip(request.mac.as_bytes().chunks(2))
+        {
+            *byte = core::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or(Error::Malformed)?;
+        }
+
+        let mut data: String<32> = String::new();
+        // Note(unwrap): The command name and the nonce fit.
+        core::fmt::write(
+            &mut data,
+            format_args!("{}:{nonce}", request.command.as_str()),
+        )
+        .unwrap();
+        // Note(unwrap): HMAC accepts keys of any length.
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).unwrap();
+        mac.update(data.as_bytes());
+        mac.verify_slice(&tag).or(Err(Error::Unauthorized))?;
+        Ok(request.command)
+    }
+}
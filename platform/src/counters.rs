@@ -0,0 +1,67 @@
+//! Persistent usage counters
+//!
+//! # Design
+//! The counters are persisted in the settings flash next to the settings. Boots are accounted
+//! during setup according to the reset cause. The operating time is accumulated in RAM and written
+//! to flash at most once per [COUNTERS_INTERVAL] to limit flash wear. Interlock trips are counted
+//! in RAM and written with the operating time. Operating time and trips since the last write are
+//! lost on power loss.
+use serde::{Deserialize, Serialize};
+
+/// Interval between operating time writes to flash in seconds.
+pub const COUNTERS_INTERVAL: u64 = 3600;
+
+/// Usage counters for maintenance planning.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Counters {
+    /// Total operating time in seconds.
+    pub uptime: u64,
+    /// Number of power-on resets
+    pub power_cycles: u32,
+    /// Number of watchdog resets
+    pub watchdog_resets: u32,
+    /// Number of interlock trips
+    pub interlock_trips: u32,
+}
+
+impl Counters {
+    /// Account a boot.
+    ///
+    /// # Args
+    /// * `power_on` - The reset was a power-on reset.
+    /// * `watchdog` - The reset was caused by a watchdog.
+    pub fn boot(&mut self, power_on: bool, watchdog: bool) {
+        self.power_cycles += power_on as u32;
+        self.watchdog_resets += watchdog as u32;
+    }
+}
+
+/// Operating time accounting.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Uptime {
+    /// Last timestamp in milliseconds
+    last: u32,
+    /// Unaccounted operating time in milliseconds
+    pending: u64,
+}
+
+impl Uptime {
+    /// Update the operating time.
+    ///
+    /// # Args
+    /// * `now` - The current wrapping timestamp in milliseconds. It must be updated at least once
+    ///   per wrap around.
+    ///
+    /// # Returns
+    /// The operating time in seconds to be added to the counters once it exceeds
+    /// [COUNTERS_INTERVAL].
+    pub fn update(&mut self, now: u32) -> Option<u64> {
+        self.pending += now.wrapping_sub(self.last) as u64;
+        self.last = now;
+        let seconds = self.pending / 1000;
+        (seconds >= COUNTERS_INTERVAL).then(|| {
+            self.pending -= seconds * 1000;
+            seconds
+        })
+    }
+}
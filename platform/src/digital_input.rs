@@ -0,0 +1,149 @@
+//! Digital input function mapping
+//!
+//! # Design
+//! Each digital input is assigned a [Role] and a polarity. A role is asserted while the input level
+//! (inverted if configured) is high. Applications act on the asserted roles ([Functions]) instead of
+//! hard-wired input levels. Roles an application does not support are ignored by it.
+use miniconf::Tree;
+use serde::{Deserialize, Serialize};
+
+/// Number of digital inputs
+pub const DIGITAL_INPUTS: usize = 2;
+
+/// Digital input role
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Role {
+    /// No function
+    #[default]
+    None,
+    /// Hold the channel with the given index while asserted
+    Hold(usize),
+    /// Enable the signal generator output of the channel with the given index only while
+    /// asserted
+    Gate(usize),
+    /// Restart the signal generator of the channel with the given index when asserted
+    Trigger(usize),
+    /// Bit with the given index of the profile selection
+    Profile(usize),
+    /// Trip the interlock while asserted
+    Interlock,
+    /// Line (mains) synchronization reference: the line phase is zero when asserted
+    LineSync,
+}
+
+/// Digital input configuration
+#[derive(Copy, Clone, Debug, Default, Tree)]
+#[tree(meta(doc, typename))]
+pub struct DigitalInputConfig {
+    /// Role
+    #[tree(with=miniconf::leaf)]
+    pub role: Role,
+    /// Invert the polarity: the role is asserted while the input is low
+    pub invert: bool,
+}
+
+impl DigitalInputConfig {
+    /// Whether the role is asserted at the given input level.
+    pub fn asserted(&self, level: bool) -> bool {
+        level != self.invert
+    }
+}
+
+/// The asserted digital input functions
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Functions {
+    /// Channels to hold
+    pub hold: [bool; 2],
+    /// Channels with enabled signal generator outputs
+    ///
+    /// Channels without a gating input are always enabled.
+    pub gate: [bool; 2],
+    /// Channels with signal generators to restart
+    pub trigger: [bool; 2],
+    /// Profile selection
+    pub profile: usize,
+    /// Interlock asserted
+    pub interlock: bool,
+    /// Line synchronization reference edge
+    pub line_sync: bool,
+}
+
+impl Default for Functions {
+    fn default() -> Self {
+        Self {
+            hold: [false; 2],
+            gate: [true; 2],
+            trigger: [false; 2],
+            profile: 0,
+            interlock: false,
+            line_sync: false,
+        }
+    }
+}
+
+/// Digital input function mapper
+///
+/// Tracks the asserted state of each input to detect trigger edges.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DigitalInputs {
+    asserted: [bool; DIGITAL_INPUTS],
+}
+
+impl DigitalInputs {
+    /// Construct a mapper with all inputs deasserted.
+    pub const fn new() -> Self {
+        Self {
+            asserted: [false; DIGITAL_INPUTS],
+        }
+    }
+
+    /// Map input levels to functions.
+    ///
+    /// # Args
+    /// * `config` - The input configurations.
+    /// * `levels` - The current input levels (`true` if high).
+    ///
+    /// # Returns
+    /// The asserted functions.
+    pub fn update(
+        &mut self,
+        config: &[DigitalInputConfig; DIGITAL_INPUTS],
+        levels: [bool; DIGITAL_INPUTS],
+    ) -> Functions {
+        let mut functions = Functions::default();
+        for ((config, level), last) in
+            config.iter().zip(levels).zip(self.asserted.iter_mut())
+        {
+            let asserted = config.asserted(level);
+            let rising = !core::mem::replace(last, asserted) && asserted;
+            match config.role {
+                Role::None => {}
+                Role::Hold(ch) => {
+                    if let Some(hold) = functions.hold.get_mut(ch) {
+                        *hold |= asserted;
+                    }
+                }
+                Role::Gate(ch) => {
+                    if let Some(gate) = functions.gate.get_mut(ch) {
+                        *gate &= asserted;
+                    }
+                }
+                Role::Trigger(ch) => {
+                    if let Some(trigger) = functions.trigger.get_mut(ch) {
+                        *trigger |= rising;
+                    }
+                }
+                Role::Profile(bit) => {
+                    if asserted && bit < usize::BITS as usize {
+                        functions.profile |= 1 << bit;
+                    }
+                }
+                Role::Interlock => functions.interlock |= asserted,
+                Role::LineSync => functions.line_sync |= rising,
+            }
+        }
+        functions
+    }
+}
@@ -0,0 +1,461 @@
+//! Minimal HTTP server
+//!
+//! # Design
+//! The server handles a single connection at a time. It reads an HTTP/1.x request, answers from
+//! the resources it holds and closes the connection once the response has been sent. Connections
+//! that are not completed within [CONNECTION_TIMEOUT] are closed so that a stalled client does
+//! not block the server.
+//!
+//! The served resources are rendered ahead of time by the application (e.g. in the telemetry
+//! task) so that request handling does not need access to application state. Only the settings
+//! are accessed directly.
+//!
+//! # Resources
+//! * `GET /metrics`: Metrics in the Prometheus text exposition format.
+//! * `GET /telemetry`: The latest telemetry as JSON.
+//! * `GET /meta`: The application metadata as JSON.
+//! * `GET /settings/<path>`: The JSON value of a setting.
+//! * `PUT /settings/<path>`: Update a setting with the JSON value in the request body. This
+//!   requires an `Authorization: Bearer <token>` header matching the configured token. Writes are
+//!   refused if no token is configured.
+//! * `GET /live`: Upgrade to a WebSocket receiving the live data pushed by the application. A
+//!   single WebSocket client is served besides the HTTP requests.
+use core::fmt::Write;
+use heapless::{String, Vec};
+use miniconf::{Path, TreeDeserializeOwned, TreeSerialize, json_core};
+use minimq::embedded_nal::{TcpClientStack, TcpFullStack, nb};
+use serde::Serialize;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    ApplicationMetadata, metrics,
+    websocket::{self, LIVE_SIZE, WebSocket},
+};
+
+/// Capacity for the served metrics in bytes.
+pub const METRICS_SIZE: usize = 2048;
+
+/// Capacity for the served telemetry in bytes.
+pub const TELEMETRY_SIZE: usize = 1024;
+
+/// Time after accepting a connection within which it must be completed (ms)
+pub const CONNECTION_TIMEOUT: u32 = 5_000;
+
+/// Capacity for the request head and body in bytes.
+const REQUEST_SIZE: usize = 512;
+
+/// Capacity for the response in bytes.
+const RESPONSE_SIZE: usize = METRICS_SIZE + 128;
+
+/// Prometheus text exposition format
+const METRICS: &str = "text/plain; version=0.0.4";
+const TEXT: &str = "text/plain";
+const JSON: &str = "application/json";
+const EMPTY: &[u8] = b"";
+
+/// Connection state, each with the time the connection was accepted
+enum State<S> {
+    Receiving(S, u32),
+    /// Sending the response. Upgrade to a WebSocket once sent if set.
+    Sending(S, usize, bool, u32),
+}
+
+impl<S> State<S> {
+    fn accepted(&self) -> u32 {
+        match self {
+            Self::Receiving(_, accepted) | Self::Sending(.., accepted) => {
+                *accepted
+            }
+        }
+    }
+
+    fn into_socket(self) -> S {
+        match self {
+            Self::Receiving(socket, _) | Self::Sending(socket, ..) => socket,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Outcome {
+    Done,
+    Changed,
+    Upgrade,
+}
+
+/// HTTP server.
+pub struct HttpServer<S: TcpFullStack> {
+    stack: S,
+    port: u16,
+    token: String<32>,
+    metadata: &'static ApplicationMetadata,
+    listener: Option<S::TcpSocket>,
+    connection: Option<State<S::TcpSocket>>,
+    request: Vec<u8, REQUEST_SIZE>,
+    response: Vec<u8, RESPONSE_SIZE>,
+    metrics: String<METRICS_SIZE>,
+    telemetry: Vec<u8, TELEMETRY_SIZE>,
+    websocket: Option<WebSocket<S::TcpSocket>>,
+    live: Vec<u8, LIVE_SIZE>,
+}
+
+impl<S: TcpFullStack> HttpServer<S> {
+    /// Construct a new HTTP server.
+    ///
+    /// # Args
+    /// * `stack` - The network stack to use.
+    /// * `port` - The TCP port to listen on.
+    /// * `token` - The bearer token authorizing settings writes. Empty to refuse writes.
+    /// * `metadata` - The application metadata.
+    pub fn new(
+        stack: S,
+        port: u16,
+        token: &str,
+        metadata: &'static ApplicationMetadata,
+    ) -> Self {
+        Self {
+            stack,
+            port,
+            // Note(unwrap): The token setting has the same capacity.
+            token: String::try_from(token).unwrap(),
+            metadata,
+            listener: None,
+            connection: None,
+            request: Vec::new(),
+            response: Vec::new(),
+            metrics: String::new(),
+            telemetry: Vec::new(),
+            websocket: None,
+            live: Vec::new(),
+        }
+    }
+
+    /// Discard all served metrics.
+    pub fn clear_metrics(&mut self) {
+        self.metrics.clear();
+    }
+
+    /// Append metrics to those served.
+    ///
+    /// # Args
+    /// * `name` - The metric name prefix.
+    /// * `value` - The value to render as metrics.
+    pub fn add_metrics<T: Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &T,
+    ) -> Result<(), metrics::Error> {
+        let len = self.metrics.len();
+        metrics::render(&mut self.metrics, name, value).inspect_err(|_| {
+            // Don't serve partial metric lines.
+            self.metrics.truncate(len);
+        })
+    }
+
+    /// Replace the served telemetry.
+    ///
+    /// # Args
+    /// * `telemetry` - The latest telemetry.
+    pub fn set_telemetry<T: Serialize>(
+        &mut self,
+        telemetry: &T,
+    ) -> Result<(), serde_json_core::ser::Error> {
+        // Note(unwrap): Resizing within capacity.
+        self.telemetry.resize_default(TELEMETRY_SIZE).unwrap();
+        let len = serde_json_core::to_slice(telemetry, &mut self.telemetry)
+            .inspect_err(|_| self.telemetry.clear())?;
+        self.telemetry.truncate(len);
+        Ok(())
+    }
+
+    fn listen(&mut self) -> Result<(), S::Error> {
+        let mut socket = self.stack.socket()?;
+        self.stack.bind(&mut socket, self.port)?;
+        self.stack.listen(&mut socket)?;
+        self.listener.replace(socket);
+        Ok(())
+    }
+
+    /// Check whether a complete request has been received.
+    ///
+    /// # Returns
+    /// The length of the request head including the terminating empty line if the head and the
+    /// body are complete.
+    fn complete(&self) -> Option<usize> {
+        let head = self.request.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+        let length = header(
+            core::str::from_utf8(&self.request[..head]).ok()?,
+            "content-length",
+        )
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0usize);
+        (self.request.len() >= head + length).then_some(head)
+    }
+
+    /// Handle the received request and prepare the response.
+    fn respond<T: TreeSerialize + TreeDeserializeOwned>(
+        &mut self,
+        head: Option<usize>,
+        settings: &mut T,
+    ) -> Outcome {
+        let Self {
+            request,
+            response,
+            metrics,
+            telemetry,
+            token,
+            metadata,
+            ..
+        } = self;
+        let mut outcome = Outcome::Done;
+        let mut value = [0u8; 512];
+        let complete = head.is_some();
+        let head = head.unwrap_or(request.len());
+        let body = &request[head..];
+        let head = core::str::from_utf8(&request[..head]).unwrap_or("");
+        let mut start = head.split("\r\n").next().unwrap_or("").split(' ');
+        let (method, target) =
+            (start.next().unwrap_or(""), start.next().unwrap_or(""));
+        // The token is compared in constant time.
+        let authorized = !token.is_empty()
+            && header(head, "authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|value| {
+                    value.as_bytes().ct_eq(token.as_bytes()).into()
+                });
+
+        let (status, content, body) =
+            match (method, target.strip_prefix("/settings")) {
+                _ if !complete => ("413 Content Too Large", TEXT, EMPTY),
+                ("GET", _) if target == "/live" => {
+                    if let Some(key) = header(head, "sec-websocket-key") {
+                        response.clear();
+                        // Note(unwrap): The response is short and fits.
+                        write!(
+                            Writer(response),
+                            "HTTP/1.1 101 Switching Protocols\r\n\
+                            Upgrade: websocket\r\n\
+                            Connection: Upgrade\r\n\
+                            Sec-WebSocket-Accept: {}\r\n\r\n",
+                            websocket::accept(key)
+                        )
+                        .unwrap();
+                        return Outcome::Upgrade;
+                    }
+                    ("400 Bad Request", TEXT, EMPTY)
+                }
+                ("GET", _) if target == "/metrics" => {
+                    ("200 OK", METRICS, metrics.as_bytes())
+                }
+                ("GET", _) if target == "/telemetry" => {
+                    ("200 OK", JSON, telemetry.as_slice())
+                }
+                ("GET", _) if target == "/meta" => {
+                    match serde_json_core::to_slice(metadata, &mut value) {
+                        Ok(len) => ("200 OK", JSON, &value[..len]),
+                        Err(_) => ("500 Internal Server Error", TEXT, EMPTY),
+                    }
+                }
+                ("GET", Some(path)) => {
+                    match json_core::get_by_key(
+                        settings,
+                        Path::<_, '/'>(path),
+                        &mut value,
+                    ) {
+                        Ok(len) => ("200 OK", JSON, &value[..len]),
+                        Err(_) => ("404 Not Found", TEXT, EMPTY),
+                    }
+                }
+                ("PUT", Some(_)) if !authorized => {
+                    ("403 Forbidden", TEXT, EMPTY)
+                }
+                ("PUT", Some(path)) => {
+                    match json_core::set(settings, path, body) {
+                        Ok(_) => {
+                            outcome = Outcome::Changed;
+                            ("200 OK", TEXT, EMPTY)
+                        }
+                        Err(e) => {
+                            log::info!("HTTP setting `{path}` failed: {e:?}");
+                            ("400 Bad Request", TEXT, EMPTY)
+                        }
+                    }
+                }
+                _ => ("404 Not Found", TEXT, EMPTY),
+            };
+
+        response.clear();
+        // Note(unwrap): The response capacity covers the header and the largest body.
+        write!(
+            Writer(response),
+            "HTTP/1.0 {status}\r\n\
+            Content-Type: {content}\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        response.extend_from_slice(body).unwrap();
+        outcome
+    }
+
+    /// Queue live data for the WebSocket client.
+    ///
+    /// # Args
+    /// * `data` - The data to append to the next binary message.
+    ///
+    /// # Returns
+    /// Whether the data was queued. Data is dropped if no client is connected or the client does
+    /// not keep up.
+    pub fn push_live(&mut self, data: &[u8]) -> bool {
+        self.websocket.is_some() && self.live.extend_from_slice(data).is_ok()
+    }
+
+    fn update_websocket(&mut self) {
+        let Some(websocket) = self.websocket.as_mut() else {
+            return;
+        };
+        if !websocket.update(&mut self.stack, &mut self.live) {
+            // Note(unwrap): Checked above.
+            self.stack.close(self.websocket.take().unwrap().socket).ok();
+            self.live.clear();
+        }
+    }
+
+    /// Process connections.
+    ///
+    /// # Note
+    /// This should be called regularly.
+    ///
+    /// # Args
+    /// * `settings` - The settings to serve and update.
+    /// * `now` - The current wrapping timestamp in milliseconds.
+    ///
+    /// # Returns
+    /// Whether the settings were changed by a request.
+    pub fn update<T: TreeSerialize + TreeDeserializeOwned>(
+        &mut self,
+        settings: &mut T,
+        now: u32,
+    ) -> bool {
+        self.update_websocket();
+
+        if self.listener.is_none() {
+            if let Err(e) = self.listen() {
+                log::warn!("HTTP listen error: {e:?}");
+                return false;
+            }
+        }
+
+        let state = match self.connection.take() {
+            Some(state) => state,
+            None => {
+                // Note(unwrap): The listener was created above.
+                match self.stack.accept(self.listener.as_mut().unwrap()) {
+                    Ok((socket, _remote)) => {
+                        self.request.clear();
+                        State::Receiving(socket, now)
+                    }
+                    Err(nb::Error::WouldBlock) => return false,
+                    Err(nb::Error::Other(e)) => {
+                        log::warn!("HTTP accept error: {e:?}");
+                        if let Some(listener) = self.listener.take() {
+                            self.stack.close(listener).ok();
+                        }
+                        return false;
+                    }
+                }
+            }
+        };
+
+        if now.wrapping_sub(state.accepted()) >= CONNECTION_TIMEOUT {
+            log::info!("HTTP connection timed out");
+            self.stack.close(state.into_socket()).ok();
+            return false;
+        }
+
+        let mut changed = false;
+        self.connection = match state {
+            State::Receiving(mut socket, accepted) => {
+                let mut buf = [0; 64];
+                match self.stack.receive(&mut socket, &mut buf) {
+                    Ok(len) => {
+                        let received = &buf[..len];
+                        let len = received
+                            .len()
+                            .min(self.request.capacity() - self.request.len());
+                        // Note(unwrap): The length is limited to the remaining capacity.
+                        self.request
+                            .extend_from_slice(&received[..len])
+                            .unwrap();
+                        let head = self.complete();
+                        if head.is_some() || self.request.is_full() {
+                            let outcome = self.respond(head, settings);
+                            changed = outcome == Outcome::Changed;
+                            Some(State::Sending(
+                                socket,
+                                0,
+                                outcome == Outcome::Upgrade,
+                                accepted,
+                            ))
+                        } else {
+                            Some(State::Receiving(socket, accepted))
+                        }
+                    }
+                    Err(nb::Error::WouldBlock) => {
+                        Some(State::Receiving(socket, accepted))
+                    }
+                    Err(nb::Error::Other(_)) => {
+                        self.stack.close(socket).ok();
+                        None
+                    }
+                }
+            }
+            State::Sending(mut socket, sent, upgrade, accepted) => {
+                match self.stack.send(&mut socket, &self.response[sent..]) {
+                    Ok(len) if sent + len < self.response.len() => Some(
+                        State::Sending(socket, sent + len, upgrade, accepted),
+                    ),
+                    Err(nb::Error::WouldBlock) => {
+                        Some(State::Sending(socket, sent, upgrade, accepted))
+                    }
+                    Ok(_) if upgrade => {
+                        // Only a single WebSocket client is served.
+                        if let Some(previous) =
+                            self.websocket.replace(WebSocket::new(socket))
+                        {
+                            self.stack.close(previous.socket).ok();
+                        }
+                        self.live.clear();
+                        None
+                    }
+                    _ => {
+                        self.stack.close(socket).ok();
+                        None
+                    }
+                }
+            }
+        };
+        changed
+    }
+}
+
+/// Find a header value in a request head.
+pub(crate) fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// `core::fmt::Write` adapter for byte buffers.
+pub(crate) struct Writer<'a, const N: usize>(pub(crate) &'a mut Vec<u8, N>);
+
+impl<const N: usize> Write for Writer<'_, N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0
+            .extend_from_slice(s.as_bytes())
+            .or(Err(core::fmt::Error))
+    }
+}
@@ -0,0 +1,221 @@
+//! Configurable interlock
+//!
+//! # Design
+//! The interlock combines a set of threshold conditions on monitored signals (digital inputs, ADC
+//! inputs, auxiliary ADC inputs, temperature, RF input power, broker connection timeout) with AND or OR logic. Once the
+//! combination is true the interlock trips and latches. Applications drive their outputs to the
+//! failsafe state while the interlock is tripped. Digital inputs with the interlock role
+//! ([crate::Role::Interlock]) trip the interlock while asserted regardless of the conditions. A trip
+//! must be reset explicitly. The reset has no effect while the combination is still true.
+use crate::{DIGITAL_INPUTS, DigitalInputConfig, Role};
+use heapless::Vec;
+use miniconf::Tree;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of interlock conditions
+pub const INTERLOCK_CONDITIONS: usize = 8;
+
+/// A monitored signal
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Signal {
+    /// Digital input level with the given index (1.0 if high, else 0.0)
+    Di(usize),
+    /// ADC input voltage with the given index (V)
+    Adc(usize),
+    /// Auxiliary ADC input voltage with the given index (V)
+    Aux(usize),
+    /// CPU temperature (°C)
+    Temperature,
+    /// RF input power with the given index (dBm)
+    Power(usize),
+    /// Broker connection watchdog (1.0 if tripped, else 0.0)
+    Timeout,
+}
+
+/// A threshold condition on a signal
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Condition {
+    /// The monitored signal
+    pub signal: Signal,
+    /// The threshold
+    pub threshold: f32,
+    /// The condition is true if the signal is above the threshold, else if the signal is below
+    /// the threshold.
+    pub above: bool,
+}
+
+impl Condition {
+    /// Evaluate the condition.
+    ///
+    /// Unavailable signals (`NaN`) never satisfy a condition.
+    fn evaluate(&self, inputs: &Inputs) -> bool {
+        let value = inputs.get(self.signal);
+        if self.above {
+            value > self.threshold
+        } else {
+            value < self.threshold
+        }
+    }
+}
+
+/// Condition combination
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Logic {
+    /// Trip if any condition is true
+    #[default]
+    Or,
+    /// Trip if all conditions are true
+    And,
+}
+
+/// Interlock configuration
+#[derive(Clone, Debug, Default, Tree)]
+#[tree(meta(doc, typename))]
+pub struct InterlockConfig {
+    /// Conditions (at most 8)
+    ///
+    /// An empty list disables the interlock.
+    #[tree(with=miniconf::leaf)]
+    pub conditions: Vec<Condition, INTERLOCK_CONDITIONS>,
+    /// Condition combination
+    #[tree(with=miniconf::leaf)]
+    pub logic: Logic,
+    /// Reset a trip
+    pub reset: bool,
+}
+
+/// The monitored signal values
+///
+/// Unavailable signals are `NaN`.
+#[derive(Copy, Clone, Debug)]
+pub struct Inputs {
+    /// Digital input levels
+    pub di: [bool; 2],
+    /// ADC input voltages (V)
+    pub adc: [f32; 2],
+    /// Auxiliary ADC input voltages (V)
+    pub aux: [f32; 4],
+    /// CPU temperature (°C)
+    pub temperature: f32,
+    /// RF input power (dBm)
+    pub power: [f32; 2],
+    /// Broker connection watchdog tripped
+    pub timeout: bool,
+    /// A digital input with the interlock role is asserted
+    pub external: bool,
+}
+
+impl Inputs {
+    /// Whether a digital input with the interlock role is asserted.
+    ///
+    /// # Args
+    /// * `config` - The digital input configurations.
+    /// * `levels` - The digital input levels.
+    pub fn external(
+        config: &[DigitalInputConfig; DIGITAL_INPUTS],
+        levels: [bool; DIGITAL_INPUTS],
+    ) -> bool {
+        config
+            .iter()
+            .zip(levels)
+            .any(|(c, level)| c.role == Role::Interlock && c.asserted(level))
+    }
+}
+
+impl Default for Inputs {
+    fn default() -> Self {
+        Self {
+            di: [false; 2],
+            adc: [f32::NAN; 2],
+            aux: [f32::NAN; 4],
+            temperature: f32::NAN,
+            power: [f32::NAN; 2],
+            timeout: false,
+            external: false,
+        }
+    }
+}
+
+impl Inputs {
+    fn get(&self, signal: Signal) -> f32 {
+        match signal {
+            Signal::Di(i) => self
+                .di
+                .get(i)
+                .map(|di| if *di { 1.0 } else { 0.0 })
+                .unwrap_or(f32::NAN),
+            Signal::Adc(i) => self.adc.get(i).copied().unwrap_or(f32::NAN),
+            Signal::Aux(i) => self.aux.get(i).copied().unwrap_or(f32::NAN),
+            Signal::Temperature => self.temperature,
+            Signal::Power(i) => self.power.get(i).copied().unwrap_or(f32::NAN),
+            Signal::Timeout => {
+                if self.timeout {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Interlock state
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Interlock {
+    tripped: bool,
+    /// The conditions were met on the last update
+    active: bool,
+    /// Trips not yet accounted in the usage counters
+    trips: u32,
+}
+
+impl Interlock {
+    /// Update the interlock.
+    ///
+    /// # Args
+    /// * `config` - The interlock configuration.
+    /// * `inputs` - The current signal values.
+    ///
+    /// # Returns
+    /// Whether the interlock is tripped.
+    pub fn update(
+        &mut self,
+        config: &InterlockConfig,
+        inputs: &Inputs,
+    ) -> bool {
+        let mut conditions =
+            config.conditions.iter().map(|c| c.evaluate(inputs));
+        let active = inputs.external
+            || (!config.conditions.is_empty()
+                && match config.logic {
+                    Logic::Or => conditions.any(|c| c),
+                    Logic::And => conditions.all(|c| c),
+                });
+        if active && !self.tripped {
+            log::warn!("Interlock tripped");
+            self.trips += 1;
+        }
+        self.active = active;
+        self.tripped |= active;
+        self.tripped
+    }
+
+    /// Reset a trip.
+    ///
+    /// A trip persists if the conditions are still met.
+    pub fn reset(&mut self) {
+        self.tripped = self.active;
+    }
+
+    /// Whether the interlock is tripped.
+    pub fn tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Take the number of trips since the last call.
+    pub fn take_trips(&mut self) -> u32 {
+        core::mem::take(&mut self.trips)
+    }
+}
@@ -26,3 +26,37 @@ pub use telemetry::*;
 
 mod delay;
 pub use delay::*;
+
+mod watchdog;
+pub use watchdog::*;
+
+mod http;
+pub use http::*;
+
+mod provision;
+pub use provision::*;
+
+pub mod metrics;
+
+mod websocket;
+
+mod command;
+pub use command::*;
+
+mod counters;
+pub use counters::*;
+
+mod interlock;
+pub use interlock::*;
+
+mod digital_input;
+pub use digital_input::*;
+
+mod logger;
+pub use logger::*;
+
+mod validate;
+pub use validate::*;
+
+mod settings_meta;
+pub use settings_meta::*;
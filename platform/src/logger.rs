@@ -0,0 +1,206 @@
+//! Logging with runtime filters and an in-RAM history
+//!
+//! # Design
+//! [Logger] formats each enabled record once into a line and passes the line to a sink (the RTT
+//! print channel) and to a RAM ring buffer of the most recent lines. The `log` command of the USB
+//! serial terminal prints the buffer. Levels are filtered at runtime by module path prefix
+//! ([LogConfig]). The `log` facade maximum level is the most verbose filter.
+//!
+//! The filter lookup and the output of a formatted line run in short critical sections so that
+//! records logged at any priority are neither dropped nor interleaved. Records are formatted
+//! outside of the critical sections. Lines are truncated to [LOG_LINE] bytes.
+use core::{cell::RefCell, fmt::Write};
+use cortex_m::interrupt::Mutex;
+use heapless::{Deque, String, Vec};
+use miniconf::Tree;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of module log filters
+pub const LOG_FILTERS: usize = 4;
+
+/// Size of the log history in bytes
+pub const LOG_HISTORY: usize = 4096;
+
+/// Maximum length of a log line in bytes
+pub const LOG_LINE: usize = 256;
+
+/// Log level
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Level {
+    /// No logging
+    Off,
+    /// Errors
+    Error,
+    /// Warnings and errors
+    Warn,
+    /// Informational messages, warnings and errors
+    #[default]
+    Info,
+    /// Debug messages and above
+    Debug,
+    /// All messages
+    Trace,
+}
+
+impl From<Level> for log::LevelFilter {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Off => Self::Off,
+            Level::Error => Self::Error,
+            Level::Warn => Self::Warn,
+            Level::Info => Self::Info,
+            Level::Debug => Self::Debug,
+            Level::Trace => Self::Trace,
+        }
+    }
+}
+
+/// Log level of a module
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogFilter {
+    /// Module path prefix, e.g. `stabilizer::hardware::pounder`
+    pub module: String<48>,
+    /// Level of records from matching modules
+    pub level: Level,
+}
+
+/// Logging configuration
+#[derive(Clone, Debug, Default, Tree)]
+#[tree(meta(doc, typename))]
+pub struct LogConfig {
+    /// Level of records from modules without a matching filter
+    #[tree(with=miniconf::leaf)]
+    pub level: Level,
+    /// Module levels (at most 4)
+    ///
+    /// The filter with the longest matching module path prefix applies.
+    #[tree(with=miniconf::leaf)]
+    pub filters: Vec<LogFilter, LOG_FILTERS>,
+}
+
+impl LogConfig {
+    fn level(&self, target: &str) -> log::LevelFilter {
+        self.filters
+            .iter()
+            .filter(|f| target.starts_with(f.module.as_str()))
+            .max_by_key(|f| f.module.len())
+            .map(|f| f.level)
+            .unwrap_or(self.level)
+            .into()
+    }
+
+    fn max_level(&self) -> log::LevelFilter {
+        self.filters
+            .iter()
+            .map(|f| log::LevelFilter::from(f.level))
+            .fold(log::LevelFilter::from(self.level), core::cmp::max)
+    }
+}
+
+/// Ring buffer of complete log lines, dropping the oldest lines when full.
+struct History(Deque<u8, LOG_HISTORY>);
+
+impl Write for History {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            if self.0.is_full() {
+                while self.0.pop_front().is_some_and(|b| b != b'\n') {}
+            }
+            // Note(unwrap): There is space after the pop.
+            self.0.push_back(byte).unwrap();
+        }
+        Ok(())
+    }
+}
+
+struct State {
+    config: LogConfig,
+    history: History,
+}
+
+/// Logger with runtime filters and an in-RAM history.
+pub struct Logger {
+    state: Mutex<RefCell<State>>,
+    sink: fn(&str),
+}
+
+impl Logger {
+    /// Construct a logger.
+    ///
+    /// # Args
+    /// * `sink` - Output of the formatted lines of the enabled records, in addition to the
+    ///   history. Called in a critical section.
+    pub const fn new(sink: fn(&str)) -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(State {
+                config: LogConfig {
+                    level: Level::Info,
+                    filters: Vec::new(),
+                },
+                history: History(Deque::new()),
+            })),
+            sink,
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        cortex_m::interrupt::free(|cs| {
+            f(&mut self.state.borrow(cs).borrow_mut())
+        })
+    }
+
+    /// Apply a logging configuration.
+    pub fn configure(&self, config: &LogConfig) {
+        self.with(|state| {
+            state.config = config.clone();
+            log::set_max_level(config.max_level());
+        })
+    }
+
+    /// Copy the log history.
+    ///
+    /// # Returns
+    /// The history contents, oldest first.
+    pub fn history(&self) -> Vec<u8, LOG_HISTORY> {
+        self.with(|state| {
+            let (a, b) = state.history.0.as_slices();
+            let mut history = Vec::new();
+            // Note(unwrap): The history fits its capacity.
+            history.extend_from_slice(a).unwrap();
+            history.extend_from_slice(b).unwrap();
+            history
+        })
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.with(|state| {
+            metadata.level() <= state.config.level(metadata.target())
+        })
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut line: String<LOG_LINE> = String::new();
+        // Formatting stops when the line is full.
+        write!(
+            &mut line,
+            "{} {}: {}",
+            record.level(),
+            record.target(),
+            record.args()
+        )
+        .ok();
+        self.with(|state| {
+            (self.sink)(&line);
+            writeln!(&mut state.history, "{line}").ok();
+        });
+    }
+
+    fn flush(&self) {}
+}
@@ -1,6 +1,8 @@
 use core::fmt;
 use serde::Serialize;
 
+use crate::Counters;
+
 #[derive(Serialize)]
 pub struct ApplicationMetadata {
     pub firmware_version: &'static str,
@@ -10,6 +12,25 @@ pub struct ApplicationMetadata {
     pub features: &'static str,
     pub panic_info: &'static str,
     pub hardware_version: &'static str,
+    pub settings_version: u32,
+    pub buffers: BufferSizes,
+    /// Usage counters at boot
+    pub counters: Counters,
+}
+
+/// Network buffer sizes selected at build time.
+#[derive(Serialize)]
+pub struct BufferSizes {
+    /// TCP socket receive and transmit buffers in bytes
+    pub tcp: [usize; 2],
+    /// UDP socket receive and transmit buffers in bytes
+    pub udp: [usize; 2],
+    /// UDP socket packet metadata slots
+    pub udp_packets: usize,
+    /// MQTT telemetry and settings client buffers in bytes
+    pub mqtt: [usize; 2],
+    /// Stream frame buffers
+    pub stream_frames: usize,
 }
 
 impl fmt::Display for ApplicationMetadata {
@@ -26,8 +47,29 @@ impl fmt::Display for ApplicationMetadata {
             "{:<20}: {}",
             "Rustc Version", self.rust_version
         ))?;
+        f.write_fmt(format_args!(
+            "{:<20}: {}",
+            "Settings Version", self.settings_version
+        ))?;
         f.write_fmt(format_args!("{:<20}: {}", "Features", self.features))?;
+        f.write_fmt(format_args!(
+            "{:<20}: TCP {:?}, UDP {:?}/{}, MQTT {:?}, {} stream frames",
+            "Buffers",
+            self.buffers.tcp,
+            self.buffers.udp,
+            self.buffers.udp_packets,
+            self.buffers.mqtt,
+            self.buffers.stream_frames,
+        ))?;
         f.write_fmt(format_args!("{:<20}: {}", "Panic Info", self.panic_info))?;
+        f.write_fmt(format_args!(
+            "{:<20}: {} s, {} power cycles, {} watchdog resets, {} interlock trips",
+            "Counters",
+            self.counters.uptime,
+            self.counters.power_cycles,
+            self.counters.watchdog_resets,
+            self.counters.interlock_trips,
+        ))?;
         Ok(())
     }
 }
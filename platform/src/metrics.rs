@@ -0,0 +1,386 @@
+//! Prometheus text exposition format
+//!
+//! # Design
+//! Metrics are rendered from any `Serialize` value. Numeric and boolean leaves become samples
+//! named after their path with the struct field names joined by underscores. Sequence elements
+//! are distinguished by an `index` label. Strings, unit values and `None` are skipped. Maps and
+//! enum variants with data are not supported.
+use core::fmt::{self, Write};
+use heapless::String;
+use serde::{Serialize, ser};
+
+/// Metrics rendering error.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The output buffer or a metric name is too short.
+    Capacity,
+    /// The value can not be represented as metrics.
+    Unsupported,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Capacity => f.write_str("Insufficient capacity"),
+            Self::Unsupported => f.write_str("Unsupported value"),
+        }
+    }
+}
+
+impl ser::StdError for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        Self::Unsupported
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Self {
+        Self::Capacity
+    }
+}
+
+/// Render a value as Prometheus metrics.
+///
+/// # Args
+/// * `writer` - The output to append the metrics to.
+/// * `name` - The metric name prefix.
+/// * `value` - The value to render.
+pub fn render<W: Write, T: Serialize + ?Sized>(
+    writer: &mut W,
+    name: &str,
+    value: &T,
+) -> Result<(), Error> {
+    let mut renderer = Renderer {
+        writer,
+        name: String::try_from(name).or(Err(Error::Capacity))?,
+        index: String::new(),
+    };
+    value.serialize(&mut renderer)
+}
+
+struct Renderer<'a, W> {
+    writer: &'a mut W,
+    name: String<96>,
+    index: String<16>,
+}
+
+impl<W: Write> Renderer<'_, W> {
+    fn sample(&mut self, value: impl fmt::Display) -> Result<(), Error> {
+        if self.index.is_empty() {
+            writeln!(self.writer, "{} {}", self.name, value)?;
+        } else {
+            writeln!(
+                self.writer,
+                "{}{{index=\"{}\"}} {}",
+                self.name, self.index, value
+            )?;
+        }
+        Ok(())
+    }
+
+    fn field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let len = self.name.len();
+        self.name.push('_').or(Err(Error::Capacity))?;
+        self.name.push_str(key).or(Err(Error::Capacity))?;
+        let res = value.serialize(&mut *self);
+        self.name.truncate(len);
+        res
+    }
+
+    fn element<T: Serialize + ?Sized>(
+        &mut self,
+        index: usize,
+        value: &T,
+    ) -> Result<(), Error> {
+        let len = self.index.len();
+        if len != 0 {
+            self.index.push('.').or(Err(Error::Capacity))?;
+        }
+        write!(self.index, "{index}")?;
+        let res = value.serialize(&mut *self);
+        self.index.truncate(len);
+        res
+    }
+}
+
+/// Sequence rendering state.
+pub struct Seq<'r, 'a, W> {
+    renderer: &'r mut Renderer<'a, W>,
+    index: usize,
+}
+
+impl<W: Write> Seq<'_, '_, W> {
+    fn element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.renderer.element(self.index, value)?;
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeSeq for Seq<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeTuple for Seq<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeTupleStruct for Seq<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeStruct for &mut Renderer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'r, 'a, W: Write> ser::Serializer for &'r mut Renderer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Seq<'r, 'a, W>;
+    type SerializeTuple = Seq<'r, 'a, W>;
+    type SerializeTupleStruct = Seq<'r, 'a, W>;
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.sample(v as u8)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.sample(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.sample(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.sample(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.sample(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.sample(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.sample(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.sample(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.sample(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        // Prometheus spells infinities differently.
+        if v == f64::INFINITY {
+            self.sample("+Inf")
+        } else if v == f64::NEG_INFINITY {
+            self.sample("-Inf")
+        } else {
+            self.sample(v)
+        }
+    }
+
+    fn serialize_char(self, _v: char) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(variant, value)
+    }
+
+    fn serialize_seq(
+        self,
+        _len: Option<usize>,
+    ) -> Result<Self::SerializeSeq, Error> {
+        Ok(Seq {
+            renderer: self,
+            index: 0,
+        })
+    }
+
+    fn serialize_tuple(
+        self,
+        _len: usize,
+    ) -> Result<Self::SerializeTuple, Error> {
+        Ok(Seq {
+            renderer: self,
+            index: 0,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Ok(Seq {
+            renderer: self,
+            index: 0,
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_map(
+        self,
+        _len: Option<usize>,
+    ) -> Result<Self::SerializeMap, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn collect_str<T: fmt::Display + ?Sized>(
+        self,
+        _value: &T,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -1,8 +1,47 @@
+use crate::LogConfig;
 use core::fmt::Write;
-use heapless::String;
+use heapless::{String, Vec};
 use miniconf::Tree;
+use serde::{Deserialize, Serialize};
 use smoltcp_nal::smoltcp::wire::EthernetAddress;
 
+/// Maximum number of risky settings path prefixes
+pub const PROBATION_PATHS: usize = 4;
+
+/// DSCP values of outgoing traffic
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Tree)]
+#[tree(meta(doc, typename))]
+pub struct Dscp {
+    /// DSCP value (0 to 63) of the UDP data stream
+    pub stream: u8,
+    /// DSCP value (0 to 63) of TCP control traffic (MQTT and HTTP), e.g. 46 (expedited
+    /// forwarding)
+    pub control: u8,
+}
+
+/// 802.1Q VLAN tag
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vlan {
+    /// VLAN identifier (1 to 4094)
+    pub id: u16,
+    /// Priority code point (0 to 7)
+    pub priority: u8,
+}
+
+impl Vlan {
+    /// Whether the identifier and the priority are in range.
+    ///
+    /// The identifiers 0 (priority tagged) and 4095 are reserved.
+    pub fn is_valid(&self) -> bool {
+        (1..=4094).contains(&self.id) && self.priority <= 7
+    }
+
+    /// The tag control information: priority, drop eligible indicator and identifier.
+    pub fn tci(&self) -> u16 {
+        ((self.priority as u16 & 0x7) << 13) | (self.id & 0xfff)
+    }
+}
+
 /// Settings that are used for configuring the network interface to Stabilizer.
 #[derive(Clone, Debug, Tree)]
 #[tree(meta(doc, typename))]
@@ -10,6 +49,13 @@ pub struct NetSettings {
     /// The broker domain name (or IP address) to use for MQTT connections.
     pub broker: String<255>,
 
+    /// The secondary broker domain name (or IP address), e.g. of a monitoring broker.
+    ///
+    /// Telemetry, events, metadata and the settings snapshot are also published
+    /// on this broker. It is read-only: settings changes and remote commands are
+    /// not accepted from it. Empty to disable. Takes effect after a reboot.
+    pub secondary_broker: String<255>,
+
     /// The MQTT ID to use upon connection with a broker.
     pub id: String<23>,
 
@@ -17,6 +63,115 @@ pub struct NetSettings {
     /// use DHCP.
     pub ip: String<15>,
 
+    /// Broker connection watchdog timeout in seconds.
+    ///
+    /// If the broker connection is lost for longer than this, the application
+    /// drives its outputs to a failsafe state until the connection is restored.
+    /// `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    pub watchdog: Option<f32>,
+
+    /// Topic prefix template of telemetry, metadata, events and remote commands.
+    ///
+    /// `{app}` and `{id}` are replaced by the application name and the MQTT ID,
+    /// e.g. `lab3/rack2/{app}/{id}`. Settings remain under
+    /// `dt/sinara/{app}/{id}`. Empty for the settings prefix. Takes effect
+    /// after a reboot.
+    pub telemetry_prefix: String<64>,
+
+    /// Minimum interval in seconds between retained telemetry copies.
+    ///
+    /// The latest telemetry is additionally published as a retained message on
+    /// `<prefix>/telemetry/retained` together with the uptime at publication
+    /// so that clients subscribing later receive the last known state. `null`
+    /// to disable.
+    #[tree(with=miniconf::leaf)]
+    pub retained_telemetry: Option<f32>,
+
+    /// MQTT keep-alive interval in seconds.
+    ///
+    /// The broker disconnects a client that has been silent for one and a half
+    /// intervals. The clients ping the broker if they have nothing else to send.
+    /// `0` to disable. Takes effect after a reboot.
+    pub mqtt_keepalive: u16,
+
+    /// TCP keep-alive interval in seconds.
+    ///
+    /// Idle TCP connections send keep-alive segments at this interval to keep
+    /// NAT and firewall state alive. Connections are aborted if the peer has not
+    /// acknowledged for three intervals. `null` to disable. Takes effect after a
+    /// reboot.
+    #[tree(with=miniconf::leaf)]
+    pub tcp_keepalive: Option<f32>,
+
+    /// DSCP marking of outgoing traffic.
+    ///
+    /// Managed networks can prioritize control traffic over bulk streaming by
+    /// these values. Takes effect after a reboot.
+    pub dscp: Dscp,
+
+    /// 802.1Q VLAN tag of all traffic.
+    ///
+    /// Outgoing frames are tagged. Incoming frames with a different VLAN
+    /// identifier are dropped and the tag of matching frames is removed.
+    /// Untagged incoming frames are accepted. `null` for untagged operation.
+    /// Takes effect after a reboot.
+    #[tree(with=miniconf::leaf)]
+    pub vlan: Option<Vlan>,
+
+    /// TCP port of the HTTP server serving metrics, telemetry, metadata and settings.
+    ///
+    /// `null` to disable. Takes effect after a reboot.
+    #[tree(with=miniconf::leaf)]
+    pub http: Option<u16>,
+
+    /// Bearer token required for settings writes over HTTP.
+    ///
+    /// Empty to refuse all writes. Takes effect after a reboot.
+    pub http_token: String<32>,
+
+    /// Shared secret authenticating remote commands on `<prefix>/command`.
+    ///
+    /// Empty to disable remote commands. Takes effect after a reboot.
+    pub secret: String<64>,
+
+    /// Locally administered unicast MAC address overriding the EUI-48 from the EEPROM,
+    /// e.g. `02-00-00-00-00-01`.
+    ///
+    /// Empty or invalid addresses use the EUI-48. The MQTT ID remains derived
+    /// from the EUI-48. Takes effect after a reboot.
+    pub mac_override: String<17>,
+
+    /// Log levels.
+    ///
+    /// Records are printed over RTT and kept in a RAM history that the `log`
+    /// command of the USB serial terminal prints.
+    pub log: LogConfig,
+
+    /// Probation window of risky remote settings changes in seconds.
+    ///
+    /// After a remote change of a risky setting, the previous settings are
+    /// kept staged. They are restored if the broker connection is lost within
+    /// the window. Remote changes are not persisted: a reboot restores the
+    /// persisted settings. `null` to disable. Takes effect after a reboot.
+    #[tree(with=miniconf::leaf)]
+    pub probation: Option<f32>,
+
+    /// Risky settings path prefixes (at most 4).
+    ///
+    /// Paths are relative to the application settings, e.g. `/stream`.
+    /// Takes effect after a reboot.
+    #[tree(with=miniconf::leaf)]
+    pub probation_paths: Vec<String<64>, PROBATION_PATHS>,
+
+    /// Provisioning server domain name (or IP address).
+    ///
+    /// On a device without other persisted settings, the initial settings are
+    /// fetched from `http://<provisioning>/<app>/<id>`, persisted and applied
+    /// with a reboot. The server is not authenticated: only enable this on a
+    /// trusted network. Empty (the default) to disable.
+    pub provisioning: String<64>,
+
     #[tree(skip)]
     /// The MAC address of Stabilizer, which is used to reinitialize the ID to default settings.
     pub mac: EthernetAddress,
@@ -26,8 +181,24 @@ impl Default for NetSettings {
     fn default() -> Self {
         Self {
             broker: String::try_from("mqtt").unwrap(),
+            secondary_broker: String::new(),
             ip: String::try_from("0.0.0.0").unwrap(),
             id: String::try_from("<mac>").unwrap(),
+            watchdog: None,
+            telemetry_prefix: String::new(),
+            retained_telemetry: None,
+            mqtt_keepalive: 60,
+            tcp_keepalive: None,
+            dscp: Dscp::default(),
+            vlan: None,
+            http: None,
+            http_token: String::new(),
+            secret: String::new(),
+            mac_override: String::new(),
+            log: LogConfig::default(),
+            probation: None,
+            probation_paths: Vec::new(),
+            provisioning: String::new(),
             mac: EthernetAddress::default(),
         }
     }
@@ -44,9 +215,39 @@ impl NetSettings {
             ..Default::default()
         }
     }
+
+    /// The MAC address to use on the network.
+    ///
+    /// # Returns
+    /// The override if it is a valid locally administered unicast address, else the EUI-48.
+    pub fn mac_address(&self) -> EthernetAddress {
+        if self.mac_override.is_empty() {
+            return self.mac;
+        }
+        match self.mac_override.parse::<EthernetAddress>() {
+            Ok(mac) if mac.is_unicast() && mac.is_local() => mac,
+            _ => {
+                log::warn!(
+                    "Invalid MAC address override {}. Using the EUI-48",
+                    self.mac_override
+                );
+                self.mac
+            }
+        }
+    }
 }
 
 pub trait AppSettings {
+    /// The application name, used to scope persisted settings metadata.
+    const NAME: &'static str;
+
+    /// The settings schema version.
+    ///
+    /// Persisted application settings are only loaded if they were stored with the same schema
+    /// version. Increment this whenever existing settings paths change their meaning or
+    /// serialization.
+    const VERSION: u32;
+
     /// Construct the settings given known network settings.
     fn new(net: NetSettings) -> Self;
 
@@ -0,0 +1,235 @@
+//! Provisioning of the initial settings
+//!
+//! # Design
+//! A device without persisted settings fetches its initial settings from a provisioning server so
+//! that fleets can be deployed without configuring each unit over USB. The [Provisioner] requests
+//! `GET /<app>/<id>` from port 80 of the provisioning server with a minimal HTTP/1.0 client. The
+//! response body lists settings one per line, each an absolute settings path followed by a space
+//! and the JSON value (the format of the USB serial terminal macros), e.g.:
+//!
+//! ```text
+//! /net/broker "10.0.0.2"
+//! /net/telemetry_prefix "lab3/{app}/{id}"
+//! /dual_iir/stream/target {"ip":[10,0,0,3],"port":9293}
+//! ```
+//!
+//! The application applies and persists the received settings and reboots for the static
+//! configuration (broker, prefixes) to take effect. Once settings are persisted, the server is not
+//! contacted again. Failed requests are retried. A `404 Not Found` response ends provisioning.
+//!
+//! Provisioning is disabled by default. The server is neither authenticated nor is the transfer
+//! encrypted. It is enabled by setting only `/net/provisioning` (e.g. through the USB serial
+//! terminal) on a unit for a trusted network. The remote command secret, the HTTP token and the
+//! provisioning server itself are never accepted from the server.
+//!
+//! The DHCP client of the network stack does not expose received options. The server is
+//! therefore located by name (resolved via the DNS server provided by DHCP) rather than by a DHCP
+//! option.
+use core::fmt::Write;
+use heapless::{String, Vec};
+use minimq::embedded_nal::{AddrType, Dns, SocketAddr, TcpClientStack, nb};
+
+use crate::http::{Writer, header};
+
+/// Capacity for the provisioning response in bytes.
+pub const PROVISION_SIZE: usize = 2048;
+
+/// TCP port of the provisioning server.
+const PORT: u16 = 80;
+
+/// Interval between failed requests in milliseconds.
+const RETRY_INTERVAL: u32 = 10_000;
+
+enum State<S> {
+    /// Waiting to retry since the given time (ms)
+    Idle(u32),
+    Resolving,
+    Connecting(S, SocketAddr),
+    Sending(S, usize),
+    Receiving(S),
+    /// The settings were received. The response head has the given length.
+    Done(usize),
+    /// The server has no settings for the device
+    Stopped,
+}
+
+/// Provisioning client.
+pub struct Provisioner<S: TcpClientStack> {
+    stack: S,
+    host: String<64>,
+    state: State<S::TcpSocket>,
+    request: Vec<u8, 256>,
+    response: Vec<u8, PROVISION_SIZE>,
+}
+
+impl<S: TcpClientStack + Dns> Provisioner<S> {
+    /// Construct a new provisioning client.
+    ///
+    /// # Args
+    /// * `stack` - The network stack to use.
+    /// * `host` - The provisioning server domain name (or IP address).
+    /// * `app` - The name of the application.
+    /// * `id` - The MQTT ID of the device.
+    pub fn new(stack: S, host: &str, app: &str, id: &str) -> Self {
+        let mut request = Vec::new();
+        // Note(unwrap): The request fits the host and the ID by design.
+        write!(
+            Writer(&mut request),
+            "GET /{app}/{id} HTTP/1.0\r\nHost: {host}\r\n\r\n"
+        )
+        .unwrap();
+        Self {
+            stack,
+            // Note(unwrap): The provisioning setting has the same capacity.
+            host: String::try_from(host).unwrap(),
+            state: State::Resolving,
+            request,
+            response: Vec::new(),
+        }
+    }
+
+    /// The received settings.
+    ///
+    /// # Returns
+    /// The response body once the settings were received.
+    pub fn received(&self) -> Option<&[u8]> {
+        match self.state {
+            State::Done(head) => Some(&self.response[head..]),
+            _ => None,
+        }
+    }
+
+    /// Check whether the response is complete as declared by its `Content-Length`.
+    fn complete(&self) -> bool {
+        let Some(head) =
+            self.response.windows(4).position(|w| w == b"\r\n\r\n")
+        else {
+            return false;
+        };
+        core::str::from_utf8(&self.response[..head + 4])
+            .ok()
+            .and_then(|head| header(head, "content-length"))
+            .and_then(|value| value.parse::<usize>().ok())
+            .is_some_and(|length| self.response.len() >= head + 4 + length)
+    }
+
+    /// Evaluate the complete response.
+    fn finish(&mut self, now: u32) -> State<S::TcpSocket> {
+        let head = self.response.windows(4).position(|w| w == b"\r\n\r\n");
+        let status = core::str::from_utf8(&self.response)
+            .ok()
+            .and_then(|response| response.split("\r\n").next())
+            .and_then(|line| line.split(' ').nth(1));
+        match (head, status) {
+            (Some(head), Some("200")) => {
+                log::info!("Received provisioned settings");
+                State::Done(head + 4)
+            }
+            (_, Some("404")) => {
+                log::info!("No provisioned settings for this device");
+                State::Stopped
+            }
+            _ => {
+                log::warn!("Invalid provisioning response: {status:?}");
+                State::Idle(now)
+            }
+        }
+    }
+
+    /// Abandon an attempt.
+    fn retry(
+        &mut self,
+        socket: S::TcpSocket,
+        now: u32,
+        error: S::Error,
+    ) -> State<S::TcpSocket> {
+        log::warn!("Provisioning request failed: {error:?}");
+        self.stack.close(socket).ok();
+        State::Idle(now)
+    }
+
+    /// Process the request.
+    ///
+    /// # Note
+    /// This should be called regularly until the settings were received.
+    ///
+    /// # Args
+    /// * `now` - The current wrapping timestamp in milliseconds.
+    pub fn update(&mut self, now: u32) {
+        self.state = match core::mem::replace(&mut self.state, State::Stopped) {
+            State::Idle(since) if now.wrapping_sub(since) < RETRY_INTERVAL => {
+                State::Idle(since)
+            }
+            State::Idle(_) => State::Resolving,
+            State::Resolving => {
+                match self.stack.get_host_by_name(&self.host, AddrType::IPv4) {
+                    Ok(ip) => match self.stack.socket() {
+                        Ok(socket) => {
+                            State::Connecting(socket, SocketAddr::new(ip, PORT))
+                        }
+                        Err(e) => {
+                            log::warn!("Provisioning socket error: {e:?}");
+                            State::Idle(now)
+                        }
+                    },
+                    Err(nb::Error::WouldBlock) => State::Resolving,
+                    Err(nb::Error::Other(e)) => {
+                        log::warn!(
+                            "Failed to resolve provisioning server `{}`: {e:?}",
+                            self.host
+                        );
+                        State::Idle(now)
+                    }
+                }
+            }
+            State::Connecting(mut socket, remote) => {
+                match self.stack.connect(&mut socket, remote) {
+                    Ok(()) => State::Sending(socket, 0),
+                    Err(nb::Error::WouldBlock) => {
+                        State::Connecting(socket, remote)
+                    }
+                    Err(nb::Error::Other(e)) => self.retry(socket, now, e),
+                }
+            }
+            State::Sending(mut socket, sent) => {
+                match self.stack.send(&mut socket, &self.request[sent..]) {
+                    Ok(len) if sent + len < self.request.len() => {
+                        State::Sending(socket, sent + len)
+                    }
+                    Ok(_) => {
+                        self.response.clear();
+                        State::Receiving(socket)
+                    }
+                    Err(nb::Error::WouldBlock) => State::Sending(socket, sent),
+                    Err(nb::Error::Other(e)) => self.retry(socket, now, e),
+                }
+            }
+            State::Receiving(mut socket) => {
+                let mut buf = [0; 64];
+                match self.stack.receive(&mut socket, &mut buf) {
+                    Ok(len) => {
+                        if self.response.extend_from_slice(&buf[..len]).is_err()
+                        {
+                            log::error!("Provisioning response too large");
+                            self.stack.close(socket).ok();
+                            State::Stopped
+                        } else if self.complete() {
+                            self.stack.close(socket).ok();
+                            self.finish(now)
+                        } else {
+                            State::Receiving(socket)
+                        }
+                    }
+                    Err(nb::Error::WouldBlock) => State::Receiving(socket),
+                    // Without a `Content-Length`, the response ends when the server closes the
+                    // connection.
+                    Err(nb::Error::Other(_)) => {
+                        self.stack.close(socket).ok();
+                        self.finish(now)
+                    }
+                }
+            }
+            state => state,
+        };
+    }
+}
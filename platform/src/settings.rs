@@ -22,7 +22,18 @@
 //!    settings values
 //! 3. Unknown/unneeded settings values in flash can be actively ignored, facilitating simple flash
 //!    storage sharing.
-use crate::{dfu, metadata::ApplicationMetadata};
+//!
+//! Along with the settings, the application settings schema version ([AppSettings::VERSION]) is
+//! persisted. Application settings stored with a different schema version are rejected on load
+//! instead of being misinterpreted. Network settings are shared among all applications and are
+//! always loaded.
+//!
+//! The persistent usage [Counters] are stored under the `counters` key. Application calibration
+//! data is stored under the `calibration` key.
+use crate::{
+    AppSettings, Counters, Uptime, dfu, metadata::ApplicationMetadata,
+};
+use core::fmt::Write;
 use embassy_futures::block_on;
 use embedded_io::{Read as EioRead, ReadReady, Write as EioWrite, WriteReady};
 use embedded_storage_async::nor_flash::NorFlash;
@@ -34,6 +45,7 @@ use sequential_storage::{
     cache::NoCache,
     map::{SerializationError, fetch_item, store_item},
 };
+use serde::{Serialize, de::DeserializeOwned};
 use serial_settings::{BestEffortInterface, Platform, Settings};
 
 #[derive(
@@ -72,21 +84,273 @@ pub struct SerialSettingsPlatform<C, F, S> {
 
     /// Metadata associated with the application
     pub metadata: &'static ApplicationMetadata,
+
+    /// The persistent usage counters
+    pub counters: Counters,
+
+    /// Operating time not yet accounted in the counters
+    pub uptime: Uptime,
+
+    /// The logger holding the log history
+    pub logger: &'static Logger,
+}
+
+/// Get the flash key of the settings schema version of an application.
+fn version_key<C: AppSettings>() -> SettingsKey {
+    let mut key: String<128> = String::new();
+    write!(&mut key, "version/{}", C::NAME).unwrap();
+    SettingsKey(key.into_bytes())
+}
+
+/// Settings paths that are never accepted from the provisioning server.
+pub const PROVISION_DENIED: [&str; 3] =
+    ["/net/secret", "/net/http_token", "/net/provisioning"];
+
+/// Fetch the persisted settings schema version of an application.
+fn fetch_version<C: AppSettings, F: NorFlash>(
+    storage: &mut F,
+    buffer: &mut [u8],
+) -> Result<Option<u32>, sequential_storage::Error<F::Error>> {
+    let value: Option<&[u8]> = block_on(fetch_item(
+        storage,
+        0..storage.capacity() as _,
+        &mut NoCache::new(),
+        buffer,
+        &version_key::<C>(),
+    ))?;
+    Ok(value.and_then(|v| ::postcard::from_bytes(v).ok()))
+}
+
+/// Erase a setting persisted with a rejected schema version.
+///
+/// Settings that are absent or already erased are not written again.
+fn erase_stale<F: NorFlash>(storage: &mut F, buffer: &mut [u8], path: &str) {
+    let key = SettingsKey(Vec::try_from(path.as_bytes()).unwrap());
+    let range = 0..storage.capacity() as _;
+    let value: Result<Option<&[u8]>, _> = block_on(fetch_item(
+        storage,
+        range.clone(),
+        &mut NoCache::new(),
+        buffer,
+        &key,
+    ));
+    if !matches!(value, Ok(Some(v)) if !v.is_empty()) {
+        return;
+    }
+    let empty: &[u8] = &[];
+    match block_on(store_item(
+        storage,
+        range,
+        &mut NoCache::new(),
+        buffer,
+        &key,
+        &empty,
+    )) {
+        Ok(()) => log::info!("Erased stale `{path}` from flash"),
+        Err(e) => log::warn!("Failed to erase stale `{path}`: {e:?}"),
+    }
+}
+
+/// Get the flash key of the usage counters.
+fn counters_key() -> SettingsKey {
+    SettingsKey(Vec::try_from(&b"counters"[..]).unwrap())
+}
+
+/// Fetch the persisted usage counters.
+///
+/// # Returns
+/// The counters or default counters if none are persisted or they can not be read.
+pub fn fetch_counters<F: NorFlash>(storage: &mut F) -> Counters {
+    let mut buffer = [0u8; 64];
+    let value: Result<Option<&[u8]>, _> = block_on(fetch_item(
+        storage,
+        0..storage.capacity() as _,
+        &mut NoCache::new(),
+        &mut buffer,
+        &counters_key(),
+    ));
+    match value {
+        Ok(value) => value
+            .and_then(|v| ::postcard::from_bytes(v).ok())
+            .unwrap_or_default(),
+        Err(e) => {
+            log::warn!("Failed to fetch counters from flash: {e:?}");
+            Counters::default()
+        }
+    }
+}
+
+/// Persist the usage counters.
+pub fn store_counters<F: NorFlash>(storage: &mut F, counters: &Counters) {
+    let mut value = [0u8; 32];
+    // Note(unwrap): The buffer fits the counters.
+    let value = ::postcard::to_slice(counters, &mut value).unwrap();
+    let mut buffer = [0u8; 64];
+    if let Err(e) = block_on(store_item(
+        storage,
+        0..storage.capacity() as _,
+        &mut NoCache::new(),
+        &mut buffer,
+        &counters_key(),
+        &&value[..],
+    )) {
+        log::warn!("Failed to store counters to flash: {e:?}");
+    }
+}
+
+/// Get the flash key of the calibration data.
+fn calibration_key() -> SettingsKey {
+    SettingsKey(Vec::try_from(&b"calibration"[..]).unwrap())
+}
+
+/// Fetch the persisted calibration data.
+///
+/// # Returns
+/// The calibration or the default calibration if none is persisted or it can not be read.
+pub fn fetch_calibration<F: NorFlash, T: DeserializeOwned + Default>(
+    storage: &mut F,
+) -> T {
+    let mut buffer = [0u8; 128];
+    let value: Result<Option<&[u8]>, _> = block_on(fetch_item(
+        storage,
+        0..storage.capacity() as _,
+        &mut NoCache::new(),
+        &mut buffer,
+        &calibration_key(),
+    ));
+    match value {
+        Ok(value) => value
+            .and_then(|v| ::postcard::from_bytes(v).ok())
+            .unwrap_or_default(),
+        Err(e) => {
+            log::warn!("Failed to fetch calibration from flash: {e:?}");
+            T::default()
+        }
+    }
+}
+
+/// Persist the calibration data.
+pub fn store_calibration<F: NorFlash, T: Serialize>(
+    storage: &mut F,
+    calibration: &T,
+) {
+    let mut value = [0u8; 96];
+    let value = match ::postcard::to_slice(calibration, &mut value) {
+        Ok(value) => value,
+        Err(e) => {
+            log::error!("Failed to serialize calibration: {e:?}");
+            return;
+        }
+    };
+    let mut buffer = [0u8; 128];
+    if let Err(e) = block_on(store_item(
+        storage,
+        0..storage.capacity() as _,
+        &mut NoCache::new(),
+        &mut buffer,
+        &calibration_key(),
+        &&value[..],
+    )) {
+        log::warn!("Failed to store calibration to flash: {e:?}");
+    }
+}
+
+impl<C, F: NorFlash, S> SerialSettingsPlatform<C, F, S> {
+    /// Account the operating time and persist it and the interlock trips
+    /// periodically.
+    ///
+    /// # Note
+    /// This should be called regularly.
+    ///
+    /// # Args
+    /// * `now` - The current wrapping timestamp in milliseconds.
+    pub fn update_counters(&mut self, now: u32) {
+        if let Some(seconds) = self.uptime.update(now) {
+            self.counters.uptime += seconds;
+            store_counters(&mut self.storage, &self.counters);
+        }
+    }
+
+    /// Record interlock trips.
+    ///
+    /// The trips are accumulated in RAM and persisted with the operating time
+    /// by [Self::update_counters] to limit flash wear from a chattering
+    /// interlock.
+    ///
+    /// # Args
+    /// * `trips` - The number of trips since the last call.
+    pub fn record_interlock_trips(&mut self, trips: u32) {
+        self.counters.interlock_trips =
+            self.counters.interlock_trips.saturating_add(trips);
+    }
+
+    /// Persist calibration data.
+    pub fn store_calibration<T: Serialize>(&mut self, calibration: &T) {
+        store_calibration(&mut self.storage, calibration);
+    }
 }
 
 impl<C, F, S> SerialSettingsPlatform<C, F, S>
 where
-    C: TreeDeserializeOwned + TreeSerialize + TreeSchema,
+    C: TreeDeserializeOwned + TreeSerialize + TreeSchema + AppSettings,
     F: NorFlash,
 {
-    pub fn load(structure: &mut C, storage: &mut F) {
-        // Loop over flash and read settings
+    /// Load the persisted settings.
+    ///
+    /// Application settings persisted with a different schema version are erased so that storing
+    /// a setting with the current version does not revalidate them.
+    ///
+    /// # Args
+    /// * `structure` - The settings to load into.
+    /// * `storage` - The settings storage.
+    /// * `safe` - Safe boot: only load the network settings.
+    ///
+    /// # Returns
+    /// Whether any settings other than `/net/provisioning` were loaded.
+    pub fn load(structure: &mut C, storage: &mut F, safe: bool) -> bool {
         let mut buffer = [0u8; 512];
+        let mut loaded = false;
+
+        // Settings persisted before schema versioning was introduced carry no version and are
+        // assumed to be compatible.
+        let rejected = match fetch_version::<C, F>(storage, &mut buffer) {
+            Ok(Some(version)) if version != C::VERSION => {
+                log::warn!(
+                    "Rejecting persisted settings with schema version {version} (expected {})",
+                    C::VERSION
+                );
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                log::warn!(
+                    "Failed to fetch settings version from flash: {e:?}"
+                );
+                false
+            }
+        };
+        let stale = safe || rejected;
+        if safe {
+            log::warn!(
+                "Safe boot: skipping the persisted application settings"
+            );
+        }
+
+        // Loop over flash and read settings
         for path in C::SCHEMA
             .nodes::<Path<String<128>, '/'>, { serial_settings::MAX_DEPTH }>()
         {
             let path = path.unwrap();
 
+            // Network settings are shared among applications and not covered by the schema
+            // version.
+            if stale && !path.0.as_str().starts_with("/net/") {
+                if rejected {
+                    erase_stale(storage, &mut buffer, path.0.as_str());
+                }
+                continue;
+            }
+
             // Try to fetch the setting from flash.
             let value: &[u8] = match block_on(fetch_item(
                 storage,
@@ -113,6 +377,8 @@ where
             }
 
             log::info!("Loading initial `{}` from flash", path.0.as_str());
+            // Enabling provisioning alone leaves the device unprovisioned.
+            loaded |= path.0.as_str() != "/net/provisioning";
 
             let flavor = ::postcard::de_flavors::Slice::new(value);
             if let Err(e) = postcard::set_by_key(structure, &path, flavor) {
@@ -122,12 +388,80 @@ where
                 );
             }
         }
+        loaded
+    }
+}
+
+impl<C, F, S> SerialSettingsPlatform<C, F, S>
+where
+    C: Settings + AppSettings,
+    F: NorFlash,
+    S: EioWrite + WriteReady + ReadReady + EioRead,
+{
+    /// Apply and persist provisioned settings.
+    ///
+    /// The credentials and the provisioning server itself are refused
+    /// ([PROVISION_DENIED]) since the server is not authenticated.
+    ///
+    /// # Args
+    /// * `settings` - The settings to update.
+    /// * `provisioned` - The settings, one `<path> <JSON value>` per line.
+    ///
+    /// # Returns
+    /// The number of settings applied and persisted.
+    pub fn provision(&mut self, settings: &mut C, provisioned: &[u8]) -> usize {
+        let mut buffer = [0u8; 512];
+        let mut count = 0;
+        let lines = core::str::from_utf8(provisioned).unwrap_or_else(|e| {
+            log::warn!("Provisioned settings are not UTF-8: {e}");
+            ""
+        });
+        for line in lines.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let Some((path, value)) = line.split_once(' ') else {
+                log::warn!("Invalid provisioned setting: `{line}`");
+                continue;
+            };
+            if PROVISION_DENIED.contains(&path) {
+                log::warn!("Refusing to provision `{path}`");
+                continue;
+            }
+            if let Err(e) = miniconf::json_core::set(
+                settings,
+                path,
+                value.trim().as_bytes(),
+            ) {
+                log::warn!("Failed to provision `{path}`: {e:?}");
+                continue;
+            }
+            let flavor = ::postcard::ser_flavors::Slice::new(&mut buffer);
+            let value = match postcard::get_by_key(
+                settings,
+                Path::<_, '/'>(path),
+                flavor,
+            ) {
+                Ok(value) => value,
+                Err(e) => {
+                    log::warn!("Failed to serialize `{path}`: {e:?}");
+                    continue;
+                }
+            };
+            let len = value.len();
+            let (value, rest) = buffer.split_at_mut(len);
+            match self.store(rest, path.as_bytes(), value) {
+                Ok(()) => {
+                    log::info!("Provisioned `{path}`");
+                    count += 1;
+                }
+                Err(e) => log::warn!("Failed to store `{path}`: {e:?}"),
+            }
+        }
+        count
     }
 }
 
 impl<C, F, S> Platform for SerialSettingsPlatform<C, F, S>
 where
-    C: Settings,
+    C: Settings + AppSettings,
     F: NorFlash,
     S: EioWrite + WriteReady + ReadReady + EioRead,
 {
@@ -160,12 +494,28 @@ where
         let range = 0..self.storage.capacity() as _;
         block_on(store_item(
             &mut self.storage,
-            range,
+            range.clone(),
             &mut NoCache::new(),
             buf,
             &SettingsKey(Vec::try_from(key).unwrap()),
             &value,
-        ))
+        ))?;
+
+        // Record the schema version the settings were stored with.
+        if fetch_version::<C, F>(&mut self.storage, buf)? != Some(C::VERSION) {
+            let mut version = [0u8; 5];
+            let version =
+                ::postcard::to_slice(&C::VERSION, &mut version).unwrap();
+            block_on(store_item(
+                &mut self.storage,
+                range,
+                &mut NoCache::new(),
+                buf,
+                &version_key::<C>(),
+                &&version[..],
+            ))?;
+        }
+        Ok(())
     }
 
     fn clear(&mut self, buf: &mut [u8], key: &[u8]) -> Result<(), Self::Error> {
@@ -179,10 +529,14 @@ where
             "service" => {
                 write!(&mut self.interface, "{}", &self.metadata).unwrap();
             }
+            "log" => {
+                let history = self.logger.history();
+                self.interface.write_all(&history).ok();
+            }
             _ => {
                 writeln!(
                     self.interface_mut(),
-                    "Invalid platform command: `{cmd}` not in [`dfu`, `reboot`, `service`]"
+                    "Invalid platform command: `{cmd}` not in [`dfu`, `log`, `reboot`, `service`]"
                 )
                 .ok();
             }
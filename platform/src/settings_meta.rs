@@ -0,0 +1,99 @@
+//! Settings metadata discovery
+//!
+//! # Design
+//! Clients discover the settings tree and its documentation at run time. A request to
+//! `<prefix>/meta/settings` carries a settings path (e.g. `/dual_iir/ch/0`, empty for the root)
+//! and is answered on the response topic of the request with its correlation data, like the
+//! replies to settings changes. The reply is the [SettingsMeta] of the node or `null` if the path
+//! does not exist. Clients walk the tree from the root.
+//!
+//! The metadata are the `Tree` annotations compiled into the firmware
+//! (`#[tree(meta(doc, typename))]`): the documentation of the node in its parent (e.g. of a struct
+//! field) and the documentation and name of its type. Units and limits are part of the
+//! documentation.
+use miniconf::{Internal, Meta, Named, Schema};
+use serde::{Serialize, Serializer};
+
+/// Names of the children of a settings node
+#[derive(Copy, Clone, Debug)]
+pub struct Names(&'static [Named]);
+
+impl Serialize for Names {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|named| named.name))
+    }
+}
+
+/// Children of a settings node
+#[derive(Copy, Clone, Debug, Serialize)]
+pub enum Children {
+    /// Named children (e.g. struct fields)
+    Named(Names),
+    /// Number of indexed children (e.g. array items)
+    Numbered(usize),
+}
+
+/// Metadata of a settings node
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct SettingsMeta {
+    /// Metadata of the node in its parent, e.g. the field documentation
+    pub item: Option<Meta>,
+    /// Metadata of the node type, e.g. the type documentation and name
+    pub meta: Option<Meta>,
+    /// The children. `None` for a leaf.
+    pub children: Option<Children>,
+}
+
+impl SettingsMeta {
+    /// Look up the metadata of a settings node.
+    ///
+    /// # Args
+    /// * `schema` - The settings schema.
+    /// * `path` - The `/`-separated node path. Empty for the root.
+    ///
+    /// # Returns
+    /// The node metadata or `None` if the path does not exist.
+    pub fn new(mut schema: &'static Schema, path: &str) -> Option<Self> {
+        let mut item = None;
+        for key in path.split('/').filter(|key| !key.is_empty()) {
+            let (meta, child) = match schema.internal.as_ref()? {
+                Internal::Named(named) => {
+                    let named = named.iter().find(|named| named.name == key)?;
+                    (named.meta, named.schema)
+                }
+                Internal::Numbered(numbered) => {
+                    let numbered = numbered.get(key.parse::<usize>().ok()?)?;
+                    (numbered.meta, numbered.schema)
+                }
+                Internal::Homogeneous(homogeneous) => {
+                    if key.parse::<usize>().ok()?
+                        >= usize::from(homogeneous.len)
+                    {
+                        return None;
+                    }
+                    (homogeneous.meta, homogeneous.schema)
+                }
+            };
+            item = meta;
+            schema = child;
+        }
+        let children =
+            schema.internal.as_ref().map(|internal| match internal {
+                Internal::Named(named) => Children::Named(Names(named)),
+                Internal::Numbered(numbered) => {
+                    Children::Numbered(numbered.len())
+                }
+                Internal::Homogeneous(homogeneous) => {
+                    Children::Numbered(usize::from(homogeneous.len))
+                }
+            });
+        Some(Self {
+            item,
+            meta: schema.meta,
+            children,
+        })
+    }
+}
@@ -10,8 +10,26 @@
 //! sampling frequency. Instead, the raw codes are stored and the telemetry is generated as
 //! required immediately before transmission. This ensures that any slower computation required
 //! for unit conversion can be off-loaded to lower priority tasks.
-use crate::ApplicationMetadata;
+//!
+//! After connecting to the broker and after settings changes, the complete settings tree is
+//! published as a retained JSON object of path-value pairs on `<prefix>/settings_snapshot` so that
+//! clients can learn the device state with a single subscription. The snapshot must fit into the
+//! MQTT telemetry buffer (`STABILIZER_MQTT_TELEMETRY_BUFFER`).
+//!
+//! Sites with separate monitoring and control brokers can configure a secondary broker. Telemetry,
+//! events, metadata and the settings snapshot are published on both brokers concurrently. The
+//! secondary broker is read-only: settings changes and remote commands are only accepted from the
+//! primary broker. The connection state of both brokers is published as a retained
+//! [BrokerState] on `<prefix>/brokers` on each connected broker.
+//!
+//! Settings validation requests on `<prefix>/validate` (see [crate::Validate]) and settings
+//! metadata requests on `<prefix>/meta/settings` (see [SettingsMeta]) are answered on the primary
+//! broker.
+use crate::{
+    ApplicationMetadata, Command, CommandAuth, Invalid, SettingsMeta, Validate,
+};
 use heapless::String;
+use miniconf::{Path, SerdeError, TreeSchema, TreeSerialize, json_core};
 use minimq::{
     PubError, Publication,
     embedded_nal::{Dns, TcpClientStack},
@@ -23,12 +41,149 @@ use smoltcp_nal::NetworkError;
 /// Default metadata message if formatting errors occur.
 const DEFAULT_METADATA: &str = "{\"message\":\"Truncated: See USB terminal\"}";
 
+/// An MQTT client of the telemetry client.
+type Mqtt<C, S> = minimq::Minimq<'static, S, C, minimq::broker::NamedBroker<S>>;
+
 /// The telemetry client for reporting telemetry data over MQTT.
 pub struct TelemetryClient<C: Clock, S: TcpClientStack> {
-    mqtt: minimq::Minimq<'static, S, C, minimq::broker::NamedBroker<S>>,
+    mqtt: Mqtt<C, S>,
     prefix: &'static str,
     meta_published: bool,
     metadata: &'static ApplicationMetadata,
+    auth: Option<CommandAuth>,
+    subscribed: bool,
+    nonce_published: bool,
+    snapshot_published: bool,
+    /// Time of the last retained telemetry publication (ms)
+    retained_at: Option<u32>,
+    secondary: Option<Secondary<C, S>>,
+    /// The last published broker connection state
+    brokers: BrokerState,
+    brokers_published: bool,
+}
+
+/// The read-only client of the secondary broker
+struct Secondary<C: Clock, S: TcpClientStack> {
+    mqtt: Mqtt<C, S>,
+    meta_published: bool,
+    snapshot_published: bool,
+    brokers_published: bool,
+}
+
+/// Broker connection state
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BrokerState {
+    /// Whether the primary broker connection is established
+    pub primary: bool,
+    /// Whether the secondary broker connection is established, `None` if not configured
+    pub secondary: Option<bool>,
+}
+
+/// A reply to a request
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Reply {
+    Validate(Option<Invalid>),
+    Meta(Option<SettingsMeta>),
+}
+
+/// A retained telemetry copy
+#[derive(Serialize)]
+struct Retained<'a, T> {
+    /// Time since boot at publication (s)
+    uptime: f32,
+    telemetry: &'a T,
+}
+
+/// Publish the application metadata.
+fn publish_metadata<C: Clock, S: TcpClientStack<Error = NetworkError> + Dns>(
+    mqtt: &mut Mqtt<C, S>,
+    topic: &str,
+    metadata: &ApplicationMetadata,
+) {
+    if mqtt
+        .client()
+        .publish(Publication::new(topic, |buf: &mut [u8]| {
+            serde_json_core::to_slice(&metadata, buf)
+        }))
+        .is_err()
+    {
+        // Note(unwrap): We can guarantee that this message will be sent because the caller checked
+        // for ability to publish.
+        mqtt.client()
+            .publish(Publication::new(topic, DEFAULT_METADATA.as_bytes()))
+            .unwrap();
+    }
+}
+
+/// Publish the retained broker connection state.
+fn publish_brokers<C: Clock, S: TcpClientStack<Error = NetworkError> + Dns>(
+    mqtt: &mut Mqtt<C, S>,
+    prefix: &str,
+    brokers: &BrokerState,
+) -> bool {
+    let mut topic: String<128> = prefix.try_into().unwrap();
+    topic.push_str("/brokers").unwrap();
+    mqtt.client()
+        .publish(
+            Publication::new(&topic, |buf: &mut [u8]| {
+                serde_json_core::to_slice(brokers, buf)
+            })
+            .retain(),
+        )
+        .is_ok()
+}
+
+/// Serialize the settings leaves as a JSON object of path-value pairs.
+fn serialize_settings<T: TreeSerialize + TreeSchema>(
+    settings: &T,
+    buf: &mut [u8],
+) -> Result<usize, serde_json_core::ser::Error> {
+    fn push(
+        buf: &mut [u8],
+        len: &mut usize,
+        data: &[u8],
+    ) -> Result<(), serde_json_core::ser::Error> {
+        let end = *len + data.len();
+        buf.get_mut(*len..end)
+            .ok_or(serde_json_core::ser::Error::BufferFull)?
+            .copy_from_slice(data);
+        *len = end;
+        Ok(())
+    }
+
+    let mut len = 0;
+    push(buf, &mut len, b"{")?;
+    let mut first = true;
+    for path in T::SCHEMA
+        .nodes::<Path<String<128>, '/'>, { serial_settings::MAX_DEPTH }>()
+    {
+        // Note(unwrap): The paths fit by design.
+        let path = path.unwrap();
+        let start = len;
+        if !first {
+            push(buf, &mut len, b",")?;
+        }
+        push(buf, &mut len, b"\"")?;
+        push(buf, &mut len, path.0.as_bytes())?;
+        push(buf, &mut len, b"\":")?;
+        match json_core::get_by_key(
+            settings,
+            Path::<_, '/'>(path.0.as_str()),
+            &mut buf[len..],
+        ) {
+            Ok(value) => len += value,
+            // Absent leaves are omitted.
+            Err(SerdeError::Value(_)) => {
+                len = start;
+                continue;
+            }
+            Err(_) => return Err(serde_json_core::ser::Error::BufferFull),
+        }
+        first = false;
+    }
+    push(buf, &mut len, b"}")?;
+    Ok(len)
 }
 
 impl<C: Clock, S: TcpClientStack<Error = smoltcp_nal::NetworkError> + Dns>
@@ -39,19 +194,150 @@ impl<C: Clock, S: TcpClientStack<Error = smoltcp_nal::NetworkError> + Dns>
     /// # Args
     /// * `mqtt` - The MQTT client
     /// * `prefix` - The device prefix to use for MQTT telemetry reporting.
+    /// * `metadata` - The application metadata.
+    /// * `auth` - The remote command authenticator. `None` to disable remote commands.
+    /// * `secondary` - The MQTT client of the read-only secondary broker. `None` to disable.
     ///
     /// # Returns
     /// A new telemetry client.
     pub fn new(
-        mqtt: minimq::Minimq<'static, S, C, minimq::broker::NamedBroker<S>>,
+        mqtt: Mqtt<C, S>,
         prefix: &'static str,
         metadata: &'static ApplicationMetadata,
+        auth: Option<CommandAuth>,
+        secondary: Option<Mqtt<C, S>>,
     ) -> Self {
         Self {
             mqtt,
             meta_published: false,
             prefix,
             metadata,
+            auth,
+            subscribed: false,
+            nonce_published: false,
+            snapshot_published: false,
+            retained_at: None,
+            brokers: BrokerState {
+                primary: false,
+                secondary: secondary.as_ref().map(|_| false),
+            },
+            brokers_published: false,
+            secondary: secondary.map(|mqtt| Secondary {
+                mqtt,
+                meta_published: false,
+                snapshot_published: false,
+                brokers_published: false,
+            }),
+        }
+    }
+
+    /// The broker connection state.
+    pub fn brokers(&self) -> BrokerState {
+        self.brokers
+    }
+
+    /// Publish a retained copy of telemetry at a reduced rate.
+    ///
+    /// The copy is published on `<prefix><suffix>/retained`.
+    ///
+    /// # Args
+    /// * `suffix` - The telemetry topic suffix.
+    /// * `telemetry` - The telemetry to report.
+    /// * `now` - The current time in milliseconds since boot.
+    /// * `interval` - The minimum interval between copies in seconds. `None` to disable.
+    pub fn publish_retained<T: Serialize>(
+        &mut self,
+        suffix: &str,
+        telemetry: &T,
+        now: u32,
+        interval: Option<f32>,
+    ) {
+        let Some(interval) = interval else {
+            return;
+        };
+        if self
+            .retained_at
+            .is_some_and(|t| (now.wrapping_sub(t) as f32) < interval * 1e3)
+        {
+            return;
+        }
+        let mut topic: String<128> = self.prefix.try_into().unwrap();
+        topic.push_str(suffix).unwrap();
+        topic.push_str("/retained").unwrap();
+        let retained = Retained {
+            uptime: now as f32 * 1e-3,
+            telemetry,
+        };
+        let publication = || {
+            Publication::new(&topic, |buf: &mut [u8]| {
+                serde_json_core::to_slice(&retained, buf)
+            })
+            .retain()
+        };
+        if let Some(secondary) = self.secondary.as_mut() {
+            if secondary.mqtt.client().can_publish(minimq::QoS::AtMostOnce) {
+                secondary.mqtt.client().publish(publication()).ok();
+            }
+        }
+        match self.mqtt.client().publish(publication()) {
+            Ok(()) => self.retained_at = Some(now),
+            Err(e) => log::error!("Retained telemetry publishing error: {e:?}"),
+        }
+    }
+
+    /// Publish the retained settings snapshot if it is outdated.
+    ///
+    /// # Args
+    /// * `settings` - The current settings.
+    pub fn publish_settings<T: TreeSerialize + TreeSchema>(
+        &mut self,
+        settings: &T,
+    ) {
+        let mut topic: String<128> = self.prefix.try_into().unwrap();
+        topic.push_str("/settings_snapshot").unwrap();
+        if let Some(secondary) = self.secondary.as_mut() {
+            if !secondary.snapshot_published
+                && secondary.mqtt.client().can_publish(minimq::QoS::AtMostOnce)
+            {
+                secondary
+                    .mqtt
+                    .client()
+                    .publish(
+                        Publication::new(&topic, |buf: &mut [u8]| {
+                            serialize_settings(settings, buf)
+                        })
+                        .retain(),
+                    )
+                    .ok();
+                secondary.snapshot_published = true;
+            }
+        }
+        if self.snapshot_published
+            || !self.mqtt.client().can_publish(minimq::QoS::AtMostOnce)
+        {
+            return;
+        }
+        self.mqtt
+            .client()
+            .publish(
+                Publication::new(&topic, |buf: &mut [u8]| {
+                    serialize_settings(settings, buf)
+                })
+                .retain(),
+            )
+            .map_err(|e| {
+                log::error!("Settings snapshot publishing error: {e:?}")
+            })
+            .ok();
+        // Failures are not retried until the settings change.
+        self.snapshot_published = true;
+    }
+
+    /// Mark the settings snapshot as outdated, e.g. after a settings change.
+    pub fn invalidate_settings(&mut self) {
+        self.snapshot_published = false;
+        if let Some(secondary) = self.secondary.as_mut() {
+            secondary.snapshot_published = false;
         }
     }
 
@@ -75,11 +361,27 @@ impl<C: Clock, S: TcpClientStack<Error = smoltcp_nal::NetworkError> + Dns>
             .ok();
     }
 
+    ///
+    /// Telemetry is also published on the secondary broker if it is connected.
     pub fn publish<T: Serialize>(
         &mut self,
         topic: &str,
         payload: &T,
     ) -> Result<(), PubError<NetworkError, serde_json_core::ser::Error>> {
+        if let Some(secondary) = self.secondary.as_mut() {
+            if secondary.mqtt.client().can_publish(minimq::QoS::AtMostOnce) {
+                secondary
+                    .mqtt
+                    .client()
+                    .publish(minimq::Publication::new(
+                        &topic,
+                        |buf: &mut [u8]| {
+                            serde_json_core::to_slice(payload, buf)
+                        },
+                    ))
+                    .ok();
+            }
+        }
         self.mqtt
             .client()
             .publish(minimq::Publication::new(&topic, |buf: &mut [u8]| {
@@ -87,14 +389,83 @@ impl<C: Clock, S: TcpClientStack<Error = smoltcp_nal::NetworkError> + Dns>
             }))
     }
 
+    /// Whether the telemetry client is connected to the primary broker.
+    pub fn is_connected(&mut self) -> bool {
+        self.mqtt.client().is_connected()
+    }
+
     /// Update the telemetry client
     ///
     /// # Note
     /// This function is provided to force the underlying MQTT state machine to process incoming
     /// and outgoing messages. Without this, the client will never connect to the broker. This
     /// should be called regularly.
-    pub fn update(&mut self) {
-        match self.mqtt.poll(|_client, _topic, _message, _properties| {}) {
+    ///
+    /// # Args
+    /// * `settings` - The settings to check on validation and metadata requests.
+    ///
+    /// # Returns
+    /// An authenticated remote command to be executed.
+    pub fn update<T: Validate + TreeSchema>(
+        &mut self,
+        settings: &T,
+    ) -> Option<Command> {
+        let mut topic: String<128> = self.prefix.try_into().unwrap();
+        topic.push_str("/command").unwrap();
+        let mut validate: String<128> = self.prefix.try_into().unwrap();
+        validate.push_str("/validate").unwrap();
+        let mut meta: String<128> = self.prefix.try_into().unwrap();
+        meta.push_str("/meta/settings").unwrap();
+
+        let Self {
+            mqtt,
+            auth,
+            nonce_published,
+            ..
+        } = self;
+        let mut command = None;
+        match mqtt.poll(|client, t, message, properties| {
+            let reply = if t == validate.as_str() {
+                let result = settings.validate();
+                if let Err(invalid) = &result {
+                    log::warn!("Invalid settings: {invalid:?}");
+                }
+                Some(Reply::Validate(result.err()))
+            } else if t == meta.as_str() {
+                let node = core::str::from_utf8(message)
+                    .ok()
+                    .and_then(|path| SettingsMeta::new(T::SCHEMA, path));
+                Some(Reply::Meta(node))
+            } else {
+                None
+            };
+            if let Some(reply) = reply {
+                let publication = Publication::respond(
+                    None,
+                    properties,
+                    |buf: &mut [u8]| serde_json_core::to_slice(&reply, buf),
+                );
+                match publication {
+                    Ok(publication) => {
+                        if let Err(e) = client.publish(publication) {
+                            log::warn!("Reply error on {t}: {e:?}");
+                        }
+                    }
+                    Err(_) => log::warn!("Request on {t} without response topic"),
+                }
+            } else if let Some(auth) =
+                auth.as_mut().filter(|_| t == topic.as_str())
+            {
+                match auth.verify(message) {
+                    Ok(cmd) => {
+                        log::warn!("Remote command: {cmd:?}");
+                        command = Some(cmd);
+                    }
+                    Err(e) => log::warn!("Rejected remote command: {e:?}"),
+                }
+                *nonce_published = false;
+            }
+        }) {
             Err(minimq::Error::Network(
                 smoltcp_nal::NetworkError::TcpConnectionFailure(
                     smoltcp_nal::smoltcp::socket::tcp::ConnectError::Unaddressable
@@ -105,38 +476,126 @@ impl<C: Clock, S: TcpClientStack<Error = smoltcp_nal::NetworkError> + Dns>
             _ => {}
         }
 
+        self.update_secondary();
+
         if !self.mqtt.client().is_connected() {
             self.meta_published = false;
-            return;
+            self.subscribed = false;
+            self.nonce_published = false;
+            self.snapshot_published = false;
+            self.brokers_published = false;
+            return command;
+        }
+
+        if !self.subscribed && !self.mqtt.client().subscriptions_pending() {
+            let filters = [
+                minimq::types::TopicFilter::new(&validate),
+                minimq::types::TopicFilter::new(&meta),
+                minimq::types::TopicFilter::new(&topic),
+            ];
+            // Remote commands are only subscribed with an authenticator.
+            let filters = if self.auth.is_some() {
+                &filters[..]
+            } else {
+                &filters[..2]
+            };
+            self.subscribed =
+                self.mqtt.client().subscribe(filters, &[]).is_ok();
+        }
+
+        if let Some(auth) = self.auth.as_ref() {
+            if !self.nonce_published
+                && self.mqtt.client().can_publish(minimq::QoS::AtMostOnce)
+            {
+                topic.push_str("/nonce").unwrap();
+                let nonce = auth.nonce();
+                self.nonce_published = self
+                    .mqtt
+                    .client()
+                    .publish(
+                        Publication::new(&topic, |buf: &mut [u8]| {
+                            serde_json_core::to_slice(&nonce, buf)
+                        })
+                        .retain(),
+                    )
+                    .is_ok();
+            }
         }
 
         // Publish application metadata
         if !self.meta_published
             && self.mqtt.client().can_publish(minimq::QoS::AtMostOnce)
         {
-            let Self { mqtt, metadata, .. } = self;
-
             let mut topic: String<128> = self.prefix.try_into().unwrap();
             topic.push_str("/meta").unwrap();
+            publish_metadata(&mut self.mqtt, &topic, self.metadata);
+            self.meta_published = true;
+        }
+
+        if !self.brokers_published
+            && self.mqtt.client().can_publish(minimq::QoS::AtMostOnce)
+        {
+            self.brokers_published =
+                publish_brokers(&mut self.mqtt, self.prefix, &self.brokers);
+        }
 
-            if mqtt
-                .client()
-                .publish(Publication::new(&topic, |buf: &mut [u8]| {
-                    serde_json_core::to_slice(&metadata, buf)
-                }))
-                .is_err()
+        command
+    }
+
+    /// Process the secondary broker connection and track the broker connection state.
+    fn update_secondary(&mut self) {
+        let primary = self.mqtt.client().is_connected();
+        let secondary = self.secondary.as_mut().map(|secondary| {
+            // The secondary broker is read-only: no messages are subscribed.
+            match secondary.mqtt.poll(|_, _, _, _| {}) {
+                Err(minimq::Error::Network(
+                    smoltcp_nal::NetworkError::TcpConnectionFailure(
+                        smoltcp_nal::smoltcp::socket::tcp::ConnectError::Unaddressable
+                    ),
+                )) => {}
+                Err(error) => {
+                    log::info!("Unexpected secondary broker error: {:?}", error)
+                }
+                _ => {}
+            }
+
+            if !secondary.mqtt.client().is_connected() {
+                secondary.meta_published = false;
+                secondary.snapshot_published = false;
+                secondary.brokers_published = false;
+                return false;
+            }
+
+            if !secondary.meta_published
+                && secondary.mqtt.client().can_publish(minimq::QoS::AtMostOnce)
             {
-                // Note(unwrap): We can guarantee that this message will be sent because we checked
-                // for ability to publish above.
-                mqtt.client()
-                    .publish(Publication::new(
-                        &topic,
-                        DEFAULT_METADATA.as_bytes(),
-                    ))
-                    .unwrap();
+                let mut topic: String<128> = self.prefix.try_into().unwrap();
+                topic.push_str("/meta").unwrap();
+                publish_metadata(&mut secondary.mqtt, &topic, self.metadata);
+                secondary.meta_published = true;
             }
+            true
+        });
 
-            self.meta_published = true;
+        let brokers = BrokerState { primary, secondary };
+        if brokers != self.brokers {
+            log::info!("Broker connection state: {brokers:?}");
+            self.brokers = brokers;
+            self.brokers_published = false;
+            if let Some(secondary) = self.secondary.as_mut() {
+                secondary.brokers_published = false;
+            }
+        }
+        if let Some(secondary) = self.secondary.as_mut() {
+            if !secondary.brokers_published
+                && secondary.mqtt.client().can_publish(minimq::QoS::AtMostOnce)
+            {
+                secondary.brokers_published = publish_brokers(
+                    &mut secondary.mqtt,
+                    self.prefix,
+                    &self.brokers,
+                );
+            }
         }
     }
 }
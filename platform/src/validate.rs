@@ -0,0 +1,42 @@
+//! Settings validation
+//!
+//! # Design
+//! Applications check their settings with [Validate]. A client can have the current settings
+//! checked without applying anything by publishing a request to `<prefix>/validate`. The outcome,
+//! `null` if the settings are valid or the [Invalid] reason, is published to the response topic of
+//! the request with its correlation data, like the replies to settings changes. Requests without a
+//! response topic are ignored.
+use core::fmt::{Display, Write};
+use heapless::String;
+use serde::Serialize;
+
+/// Reason for invalid settings
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Invalid {
+    /// The settings path of the offending value, e.g. `/urukul/ch/0/frequency`
+    pub path: String<64>,
+    /// The violated constraint
+    pub reason: String<64>,
+}
+
+impl Invalid {
+    /// Construct a validation error.
+    ///
+    /// # Note
+    /// Formatting stops at the capacity of the path and the reason.
+    pub fn new(path: impl Display, reason: impl Display) -> Self {
+        let mut invalid = Self {
+            path: String::new(),
+            reason: String::new(),
+        };
+        write!(invalid.path, "{path}").ok();
+        write!(invalid.reason, "{reason}").ok();
+        invalid
+    }
+}
+
+/// Settings that can be validated
+pub trait Validate {
+    /// Check the settings without applying them.
+    fn validate(&self) -> Result<(), Invalid>;
+}
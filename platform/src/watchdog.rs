@@ -0,0 +1,33 @@
+/// Broker connection watchdog.
+///
+/// # Design
+/// The watchdog trips once the broker connection has been lost for longer than a timeout and
+/// recovers as soon as the connection is restored. The connection is also considered lost before
+/// it has been established for the first time. Applications use the watchdog state to drive
+/// their outputs to a failsafe state while unreachable.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ConnectionWatchdog {
+    /// Duration since the connection was lost in seconds
+    lost: f32,
+}
+
+impl ConnectionWatchdog {
+    /// Update the watchdog.
+    ///
+    /// # Args
+    /// * `connected` - Whether the broker connection is established.
+    /// * `elapsed` - The time since the last update in seconds.
+    /// * `timeout` - The watchdog timeout in seconds. `None` disables the watchdog.
+    ///
+    /// # Returns
+    /// Whether the watchdog is tripped.
+    pub fn update(
+        &mut self,
+        connected: bool,
+        elapsed: f32,
+        timeout: Option<f32>,
+    ) -> bool {
+        self.lost = if connected { 0.0 } else { self.lost + elapsed };
+        timeout.is_some_and(|timeout| self.lost > timeout)
+    }
+}
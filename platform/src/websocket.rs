@@ -0,0 +1,100 @@
+//! WebSocket live data
+//!
+//! # Design
+//! A WebSocket connection upgraded by the HTTP server receives the live data queued by the
+//! application as unfragmented binary messages. Messages from the client are discarded. The
+//! connection is closed once the client sends a close frame or an error occurs.
+use base64::Engine;
+use heapless::{String, Vec};
+use minimq::embedded_nal::{TcpClientStack, nb};
+use sha1::{Digest, Sha1};
+
+/// Capacity for the live data of a message in bytes.
+pub const LIVE_SIZE: usize = 1024;
+
+/// The WebSocket handshake GUID (RFC 6455).
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Compute the `Sec-WebSocket-Accept` handshake response.
+///
+/// # Args
+/// * `key` - The `Sec-WebSocket-Key` of the client.
+pub fn accept(key: &str) -> String<28> {
+    let digest = Sha1::new()
+        .chain_update(key.as_bytes())
+        .chain_update(GUID.as_bytes())
+        .finalize();
+    let mut buf = [0; 28];
+    // Note(unwrap): The 20 byte digest encodes to 28 characters.
+    let len = base64::engine::general_purpose::STANDARD
+        .encode_slice(digest, &mut buf)
+        .unwrap();
+    String::try_from(core::str::from_utf8(&buf[..len]).unwrap()).unwrap()
+}
+
+/// A connected WebSocket client.
+pub struct WebSocket<T> {
+    pub socket: T,
+    frame: Vec<u8, { LIVE_SIZE + 4 }>,
+    sent: usize,
+}
+
+impl<T> WebSocket<T> {
+    /// Construct a WebSocket on a connection that completed the handshake.
+    pub fn new(socket: T) -> Self {
+        Self {
+            socket,
+            frame: Vec::new(),
+            sent: 0,
+        }
+    }
+
+    /// Receive and discard client messages and send queued data.
+    ///
+    /// # Args
+    /// * `stack` - The network stack owning the socket.
+    /// * `live` - The queued data. It is cleared once it has been framed.
+    ///
+    /// # Returns
+    /// Whether the connection is still open.
+    pub fn update<S: TcpClientStack<TcpSocket = T>>(
+        &mut self,
+        stack: &mut S,
+        live: &mut Vec<u8, LIVE_SIZE>,
+    ) -> bool {
+        let mut buf = [0; 64];
+        match stack.receive(&mut self.socket, &mut buf) {
+            // Client frames are small. A close frame is detected at the start of a read.
+            Ok(len) if len > 0 && buf[0] & 0x0f == 0x8 => return false,
+            Ok(_) | Err(nb::Error::WouldBlock) => {}
+            Err(nb::Error::Other(_)) => return false,
+        }
+
+        if self.sent == self.frame.len() && !live.is_empty() {
+            self.frame.clear();
+            self.sent = 0;
+            // Note(unwrap): The frame capacity covers the header and the data.
+            // FIN, binary opcode, unmasked
+            self.frame.push(0x82).unwrap();
+            if live.len() < 126 {
+                self.frame.push(live.len() as u8).unwrap();
+            } else {
+                self.frame.push(126).unwrap();
+                self.frame
+                    .extend_from_slice(&(live.len() as u16).to_be_bytes())
+                    .unwrap();
+            }
+            self.frame.extend_from_slice(live).unwrap();
+            live.clear();
+        }
+
+        if self.sent < self.frame.len() {
+            match stack.send(&mut self.socket, &self.frame[self.sent..]) {
+                Ok(len) => self.sent += len,
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(_)) => return false,
+            }
+        }
+        true
+    }
+}
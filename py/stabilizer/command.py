@@ -0,0 +1,86 @@
+#!/usr/bin/python3
+"""Stabilizer authenticated remote commands
+
+Critical platform commands (`reboot`, `dfu`) are authenticated with an
+HMAC-SHA256 over the command and the current device nonce, keyed with the
+device secret configured as `net/secret` through the USB serial terminal.
+
+A `dfu` reboot is armed by the `dfu` command and performed by a subsequent
+`confirm` command within 10 seconds. Both are sent in sequence.
+"""
+
+import argparse
+import asyncio
+import hashlib
+import hmac
+import json
+import logging
+import sys
+import os
+
+import miniconf
+from miniconf.common import MQTTv5, one
+
+logger = logging.getLogger(__name__)
+
+if sys.platform.lower() == "win32" or os.name.lower() == "nt":
+    from asyncio import set_event_loop_policy, WindowsSelectorEventLoopPolicy
+
+    set_event_loop_policy(WindowsSelectorEventLoopPolicy())
+
+
+def sign(secret, command, nonce):
+    """Compute the authentication code of a command"""
+    return hmac.new(
+        secret.encode(), f"{command}:{nonce}".encode(), hashlib.sha256
+    ).hexdigest()
+
+
+async def _send(client, prefix, secret, command, nonce):
+    logger.info("Sending `%s` with nonce %s", command, nonce)
+    await client.publish(
+        f"{prefix}/command",
+        json.dumps({"command": command, "mac": sign(secret, command, nonce)}),
+        qos=1,
+    )
+
+
+async def _main():
+    parser = argparse.ArgumentParser(description="Issue a Stabilizer remote command")
+    parser.add_argument("prefix", help="The MQTT topic prefix of the target")
+    parser.add_argument(
+        "--broker", "-b", default="mqtt", type=str, help="The MQTT broker address"
+    )
+    parser.add_argument("command", choices=["reboot", "dfu"], help="The command")
+    parser.add_argument(
+        "--secret",
+        default=os.environ.get("STABILIZER_SECRET"),
+        help="The device secret (default: STABILIZER_SECRET environment variable)",
+    )
+    args = parser.parse_args()
+    logging.basicConfig(level=logging.INFO)
+    if not args.secret:
+        parser.error("No device secret given")
+
+    async with miniconf.Client(
+        args.broker,
+        protocol=MQTTv5,
+        logger=logging.getLogger("aiomqtt-client"),
+    ) as client:
+        prefix, _alive = one(await miniconf.discover(client, args.prefix))
+        commands = ["dfu", "confirm"] if args.command == "dfu" else [args.command]
+        # The current nonce is retained and republished after every command.
+        await client.subscribe(f"{prefix}/command/nonce")
+        nonce = None
+        async for message in client.messages:
+            if json.loads(message.payload) == nonce:
+                continue
+            nonce = json.loads(message.payload)
+            await _send(client, prefix, args.secret, commands.pop(0), nonce)
+            if not commands:
+                break
+        await client.unsubscribe(f"{prefix}/command/nonce")
+
+
+if __name__ == "__main__":
+    asyncio.run(_main())
@@ -0,0 +1,113 @@
+#!/usr/bin/python3
+"""Stabilizer settings metadata discovery
+
+The firmware applications emit their settings JSON schema when run on the host
+(e.g. `cargo run --target x86_64-unknown-linux-gnu --bin dual-iir`). The schema
+is derived from the `Tree` documentation and type annotations.
+
+This tool flattens the schema into per-path metadata (type, description, limits)
+and publishes it as retained messages below `<prefix>/schema` so that
+graphical clients can be generated without hard-coding the settings layout.
+"""
+
+import argparse
+import asyncio
+import json
+import logging
+import sys
+import os
+
+import miniconf
+from miniconf.common import MQTTv5, one
+
+logger = logging.getLogger(__name__)
+
+if sys.platform.lower() == "win32" or os.name.lower() == "nt":
+    from asyncio import set_event_loop_policy, WindowsSelectorEventLoopPolicy
+
+    set_event_loop_policy(WindowsSelectorEventLoopPolicy())
+
+# JSON schema keywords forwarded as path metadata
+KEYWORDS = (
+    "type",
+    "title",
+    "description",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "enum",
+    "const",
+)
+
+
+def load(text):
+    """Load the settings schema from the application host output.
+
+    The host output consists of the default settings followed by the schema.
+    The last JSON document is used."""
+    decoder = json.JSONDecoder()
+    doc = None
+    idx = 0
+    text = text.strip()
+    while idx < len(text):
+        doc, idx = decoder.raw_decode(text, idx)
+        while idx < len(text) and text[idx].isspace():
+            idx += 1
+    return doc
+
+
+def flatten(schema, root=None, path=""):
+    """Yield `(path, metadata)` for all leaf nodes of the schema"""
+    if root is None:
+        root = schema
+    ref = schema.get("$ref")
+    if ref is not None and ref.startswith("#/"):
+        target = root
+        for key in ref[2:].split("/"):
+            target = target[key]
+        schema = {**target, **{k: v for k, v in schema.items() if k != "$ref"}}
+    if "properties" in schema:
+        for name, sub in schema["properties"].items():
+            yield from flatten(sub, root, f"{path}/{name}")
+    elif "prefixItems" in schema:
+        for idx, sub in enumerate(schema["prefixItems"]):
+            yield from flatten(sub, root, f"{path}/{idx}")
+    elif isinstance(schema.get("items"), dict) and "maxItems" in schema:
+        for idx in range(schema["maxItems"]):
+            yield from flatten(schema["items"], root, f"{path}/{idx}")
+    else:
+        yield path, {k: schema[k] for k in KEYWORDS if k in schema}
+
+
+async def _main():
+    parser = argparse.ArgumentParser(description="Publish Stabilizer settings metadata")
+    parser.add_argument("prefix", help="The MQTT topic prefix of the target")
+    parser.add_argument(
+        "--broker", "-b", default="mqtt", type=str, help="The MQTT broker address"
+    )
+    parser.add_argument(
+        "schema",
+        type=argparse.FileType("r"),
+        help="Application host output containing the settings schema",
+    )
+    args = parser.parse_args()
+    logging.basicConfig(level=logging.INFO)
+
+    schema = load(args.schema.read())
+
+    async with miniconf.Client(
+        args.broker,
+        protocol=MQTTv5,
+        logger=logging.getLogger("aiomqtt-client"),
+    ) as client:
+        prefix, _alive = one(await miniconf.discover(client, args.prefix))
+        for path, meta in flatten(schema):
+            logger.info("Publishing metadata for %s", path)
+            await client.publish(
+                f"{prefix}/schema{path}", json.dumps(meta), qos=1, retain=True
+            )
+
+
+if __name__ == "__main__":
+    asyncio.run(_main())
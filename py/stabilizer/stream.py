@@ -14,7 +14,7 @@ from dataclasses import dataclass
 
 import numpy as np
 
-from . import DAC_VOLTS_PER_LSB
+from . import ADC_VOLTS_PER_LSB, DAC_VOLTS_PER_LSB
 
 logger = logging.getLogger(__name__)
 
@@ -79,6 +79,175 @@ class AdcDac:
         ]
 
 
+class AdcDacVolts:
+    """Stabilizer ADC/DAC data calibrated to volts"""
+
+    format_id = 4
+
+    def __init__(self, header, body):
+        self.header = header
+        self.body = body
+
+    def size(self):
+        """Return the data size of the frame in bytes"""
+        return len(self.body)
+
+    def to_si(self):
+        """Return the data in SI units"""
+        data = np.frombuffer(self.body, "<f4")
+        # batch, channel, sample
+        data = data.reshape(self.header.batches, 4, -1)
+        data = data.swapaxes(0, 1).reshape(4, -1)
+        return {
+            "adc": data[:2],
+            "dac": data[2:],
+        }
+
+    def to_traces(self):
+        """Convert the data to labelled Trace instances"""
+        data = self.to_si()
+        return [
+            Trace(data["adc"][0], scale=1, label="ADC0"),
+            Trace(data["adc"][1], scale=1, label="ADC1"),
+            Trace(data["dac"][0], scale=1, label="DAC0"),
+            Trace(data["dac"][1], scale=1, label="DAC1"),
+        ]
+
+
+class LockinIq:
+    """Lockin demodulated in-phase/quadrature format"""
+
+    format_id = 5
+
+    # Sample rate of the lockin application
+    sample_rate = 100e6 / 128
+
+    def __init__(self, header, body):
+        self.header = header
+        self.body = body
+
+    def size(self):
+        """Return the data size of the frame in bytes"""
+        return len(self.body)
+
+    def to_mu(self):
+        """Return the raw data in machine units"""
+        return np.frombuffer(
+            self.body, np.dtype([("frequency", "<i4"), ("i", "<i4"), ("q", "<i4")])
+        )
+
+    def to_si(self):
+        """Convert the raw data to SI units"""
+        data = self.to_mu()
+        # ADC full scale in volts
+        scale = ADC_VOLTS_PER_LSB * (1 << 15) / (1 << 31)
+        return {
+            "frequency": data["frequency"] * self.sample_rate / (1 << 32),
+            "i": data["i"] * scale,
+            "q": data["q"] * scale,
+        }
+
+    def to_traces(self):
+        """Convert the raw data to labelled Trace instances"""
+        data = self.to_mu()
+        scale = ADC_VOLTS_PER_LSB * (1 << 15) / (1 << 31)
+        return [
+            Trace(data["i"], scale=scale, label="I"),
+            Trace(data["q"], scale=scale, label="Q"),
+        ]
+
+
+class Probe:
+    """Application-defined internal signals in volts"""
+
+    format_id = 6
+
+    def __init__(self, header, body):
+        self.header = header
+        self.body = body
+
+    def size(self):
+        """Return the data size of the frame in bytes"""
+        return len(self.body)
+
+    def to_si(self):
+        """Return the data in SI units"""
+        data = np.frombuffer(self.body, "<f4")
+        # batch, channel, sample
+        data = data.reshape(self.header.batches, 2, -1)
+        return data.swapaxes(0, 1).reshape(2, -1)
+
+    def to_traces(self):
+        """Convert the data to labelled Trace instances"""
+        data = self.to_si()
+        return [
+            Trace(data[0], scale=1, label="PROBE0"),
+            Trace(data[1], scale=1, label="PROBE1"),
+        ]
+
+
+class Spectrum:
+    """Averaged frequency domain estimates, one batch per frequency bin"""
+
+    format_id = 7
+
+    def __init__(self, header, body):
+        self.header = header
+        self.body = body
+
+    def size(self):
+        """Return the data size of the frame in bytes"""
+        return len(self.body)
+
+    def to_si(self):
+        """Return the parsed data in SI units"""
+        data = np.frombuffer(
+            self.body,
+            np.dtype(
+                [
+                    ("frequency", "<f4"),
+                    ("psd", "<f4"),
+                    ("re", "<f4"),
+                    ("im", "<f4"),
+                ]
+            ),
+        )
+        return {
+            "frequency": data["frequency"],
+            "psd": data["psd"],
+            "transfer": data["re"] + 1j * data["im"],
+        }
+
+
+class PounderPhase:
+    """Pounder DDS clock phase, one batch per sample batch"""
+
+    format_id = 8
+
+    # Pounder timestamp tick period in seconds
+    tick = 32e-9
+
+    def __init__(self, header, body):
+        self.header = header
+        self.body = body
+
+    def size(self):
+        """Return the data size of the frame in bytes"""
+        return len(self.body)
+
+    def to_mu(self):
+        """Return the raw phase in timestamp ticks"""
+        return np.frombuffer(self.body, "<i4")
+
+    def to_si(self):
+        """Convert the phase to seconds"""
+        return {"phase": self.to_mu() * self.tick}
+
+    def to_traces(self):
+        """Convert the data to labelled Trace instances"""
+        return [Trace(self.to_mu(), scale=self.tick, label="PHASE")]
+
+
 class ThermostatEem:
     """Thermostat-EEM format"""
 
@@ -108,6 +277,11 @@ class Frame:
     header = namedtuple("Header", "magic format_id batches sequence")
     parsers = {
         AdcDac.format_id: AdcDac,
+        AdcDacVolts.format_id: AdcDacVolts,
+        LockinIq.format_id: LockinIq,
+        Probe.format_id: Probe,
+        Spectrum.format_id: Spectrum,
+        PounderPhase.format_id: PounderPhase,
         ThermostatEem.format_id: ThermostatEem,
     }
 
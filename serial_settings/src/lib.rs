@@ -62,10 +62,32 @@ pub trait Platform {
     fn interface_mut(&mut self) -> &mut Self::Interface;
 }
 
+/// Maximum size of a settings macro (all `path value` lines)
+pub const MACRO_SIZE: usize = 256;
+
+/// A settings macro being recorded
+struct Recording {
+    /// Storage key of the macro
+    key: String<128>,
+    /// Recorded `path value` lines
+    steps: String<MACRO_SIZE>,
+}
+
+/// Get the storage key of a settings macro.
+///
+/// Settings paths start with `/`, macro keys can not collide with them.
+fn macro_key(name: &str) -> Option<String<128>> {
+    let mut key = String::new();
+    key.push_str("macro/").ok()?;
+    key.push_str(name).ok()?;
+    Some(key)
+}
+
 struct Interface<'a, P> {
     platform: P,
     buffer: &'a mut [u8],
     updated: bool,
+    recording: Option<Recording>,
 }
 
 impl<'a, P: Platform> Interface<'a, P> {
@@ -497,12 +519,144 @@ impl<'a, P: Platform> Interface<'a, P> {
                 )
             }
             Err(e) => {
-                writeln!(interface, "Failed to set `{key}`: {e:?}")
+                writeln!(interface, "Failed to set `{key}`: {e:?}").unwrap();
+                return;
+            }
+        }
+        .unwrap();
+
+        if let Some(recording) = interface.recording.as_mut() {
+            let steps = &mut recording.steps;
+            if steps.len() + key.len() + value.len() + 2 > MACRO_SIZE {
+                writeln!(interface, "Macro full. `{key}` not recorded")
+                    .unwrap();
+                return;
+            }
+            // Note(unwrap): Capacity checked above.
+            steps.push_str(key).unwrap();
+            steps.push(' ').unwrap();
+            steps.push_str(value).unwrap();
+            steps.push('\n').unwrap();
+        }
+    }
+
+    fn handle_record(
+        _menu: &menu::Menu<Self, P::Settings>,
+        item: &menu::Item<Self, P::Settings>,
+        args: &[&str],
+        interface: &mut Self,
+        _settings: &mut P::Settings,
+    ) {
+        let name = menu::argument_finder(item, args, "name").unwrap().unwrap();
+        let Some(key) = macro_key(name) else {
+            writeln!(interface, "Macro name too long: `{name}`").unwrap();
+            return;
+        };
+        if let Some(recording) = interface.recording.take() {
+            writeln!(
+                interface,
+                "Discarding unfinished recording `{}`",
+                recording.key
+            )
+            .unwrap();
+        }
+        interface.recording = Some(Recording {
+            key,
+            steps: String::new(),
+        });
+        writeln!(
+            interface,
+            "Recording `set` commands into macro `{name}`. Finish with `stop`."
+        )
+        .unwrap();
+    }
+
+    fn handle_stop(
+        _menu: &menu::Menu<Self, P::Settings>,
+        _item: &menu::Item<Self, P::Settings>,
+        _args: &[&str],
+        interface: &mut Self,
+        _settings: &mut P::Settings,
+    ) {
+        let Some(recording) = interface.recording.take() else {
+            writeln!(interface, "Not recording").unwrap();
+            return;
+        };
+        // An empty macro is stored as the empty value and thus removes the macro.
+        match interface.platform.store(
+            interface.buffer,
+            recording.key.as_bytes(),
+            recording.steps.as_bytes(),
+        ) {
+            Ok(()) if recording.steps.is_empty() => {
+                writeln!(interface, "Removed `{}`", recording.key)
             }
+            Ok(()) => writeln!(interface, "`{}` stored", recording.key),
+            Err(e) => writeln!(
+                interface,
+                "Failed to store `{}`: {e:?}",
+                recording.key
+            ),
         }
         .unwrap();
     }
 
+    fn handle_play(
+        _menu: &menu::Menu<Self, P::Settings>,
+        item: &menu::Item<Self, P::Settings>,
+        args: &[&str],
+        interface: &mut Self,
+        settings: &mut P::Settings,
+    ) {
+        let name = menu::argument_finder(item, args, "name").unwrap().unwrap();
+        let Some(key) = macro_key(name) else {
+            writeln!(interface, "Macro name too long: `{name}`").unwrap();
+            return;
+        };
+        let steps: String<MACRO_SIZE> =
+            match interface.platform.fetch(interface.buffer, key.as_bytes()) {
+                Ok(Some(steps)) => match core::str::from_utf8(steps)
+                    .ok()
+                    .and_then(|steps| String::try_from(steps).ok())
+                {
+                    Some(steps) => steps,
+                    None => {
+                        writeln!(interface, "Invalid macro `{key}`").unwrap();
+                        return;
+                    }
+                },
+                Ok(None) => {
+                    writeln!(interface, "No macro `{key}`").unwrap();
+                    return;
+                }
+                Err(e) => {
+                    writeln!(interface, "Failed to fetch `{key}`: {e:?}")
+                        .unwrap();
+                    return;
+                }
+            };
+
+        for step in steps.lines() {
+            let Some((path, value)) = step.split_once(' ') else {
+                writeln!(interface, "Invalid macro step: `{step}`").unwrap();
+                continue;
+            };
+            match json_core::set(settings, path, value.as_bytes()) {
+                Ok(_) => {
+                    interface.updated = true;
+                    writeln!(interface, "Set `{path}`: {value}")
+                }
+                Err(e) => writeln!(interface, "Failed to set `{path}`: {e:?}"),
+            }
+            .unwrap();
+        }
+        writeln!(
+            interface,
+            "Set but not stored. May require store and reboot to activate."
+        )
+        .unwrap();
+    }
+
     fn menu() -> menu::Menu<'a, Self, P::Settings> {
         menu::Menu {
             label: "settings",
@@ -577,6 +731,40 @@ impl<'a, P: Platform> Interface<'a, P> {
                         }],
                     },
                 },
+                &menu::Item {
+                    command: "record",
+                    help: Some(
+                        "Start recording subsequent `set` commands into a macro",
+                    ),
+                    item_type: menu::ItemType::Callback {
+                        function: Self::handle_record,
+                        parameters: &[menu::Parameter::Mandatory {
+                            parameter_name: "name",
+                            help: Some("The name of the macro"),
+                        }],
+                    },
+                },
+                &menu::Item {
+                    command: "stop",
+                    help: Some(
+                        "Stop recording and store the macro (an empty macro is removed)",
+                    ),
+                    item_type: menu::ItemType::Callback {
+                        function: Self::handle_stop,
+                        parameters: &[],
+                    },
+                },
+                &menu::Item {
+                    command: "play",
+                    help: Some("Replay the `set` commands of a stored macro"),
+                    item_type: menu::ItemType::Callback {
+                        function: Self::handle_play,
+                        parameters: &[menu::Parameter::Mandatory {
+                            parameter_name: "name",
+                            help: Some("The name of the macro"),
+                        }],
+                    },
+                },
                 &menu::Item {
                     command: "platform",
                     help: Some("Platform specific commands"),
@@ -654,6 +842,7 @@ impl<'a, P: Platform> Runner<'a, P> {
                 platform,
                 buffer: serialize_buf,
                 updated: false,
+                recording: None,
             },
             settings,
         )))
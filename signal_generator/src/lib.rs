@@ -243,4 +243,40 @@ impl Config {
             },
         })
     }
+
+    /// Build a source previewing the signal.
+    ///
+    /// Periodic signals are generated with `points` samples per signal period, starting at the
+    /// initial phase and repeating indefinitely. Sweeps and noise are generated at the output
+    /// sample period.
+    ///
+    /// # Args
+    /// * `period` - The output sample period as for [Config::build].
+    /// * `points` - The number of samples per period of periodic signals.
+    /// * `scale` - The output scale as for [Config::build].
+    ///
+    /// # Returns
+    /// The source and its sample period.
+    pub fn preview(
+        &self,
+        period: f32,
+        points: usize,
+        scale: f32,
+    ) -> Result<(Source, f32), Error> {
+        // Validate at the output sample period.
+        let source = self.build(period, scale)?;
+        match self.signal {
+            Signal::Cosine | Signal::Square | Signal::Triangle
+                if self.frequency > 0.0 =>
+            {
+                let period = 1.0 / (self.frequency * points.max(2) as f32);
+                let config = Self {
+                    length: u32::MAX,
+                    ..self.clone()
+                };
+                Ok((config.build(period, scale)?, period))
+            }
+            _ => Ok((source, period)),
+        }
+    }
 }
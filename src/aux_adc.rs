@@ -0,0 +1,166 @@
+//! Auxiliary analog inputs
+//!
+//! # Design
+//! External slow ADCs (e.g. an ADS1115 on the I2C bus) provide additional analog inputs for
+//! monitoring and slow loops. A driver implements [AuxAdc]: single-shot conversions are started on
+//! one channel at a time and polled for completion. [AuxInputs] cycles through the enabled
+//! channels and holds the most recent value of each. The values are published with telemetry and
+//! are available as interlock signals and as slow loop inputs of an application.
+//!
+//! Inputs that are disabled, not converted yet, or whose device is absent read `NaN`.
+use miniconf::Tree;
+use serde::{Deserialize, Serialize};
+
+/// Number of auxiliary analog inputs
+pub const AUX_CHANNELS: usize = 4;
+
+/// Input voltage range (full scale, V)
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Range {
+    /// ±6.144 V
+    V6_144,
+    /// ±4.096 V
+    V4_096,
+    /// ±2.048 V
+    #[default]
+    V2_048,
+    /// ±1.024 V
+    V1_024,
+    /// ±0.512 V
+    V0_512,
+    /// ±0.256 V
+    V0_256,
+}
+
+impl Range {
+    /// The full scale voltage.
+    pub fn full_scale(&self) -> f32 {
+        match self {
+            Self::V6_144 => 6.144,
+            Self::V4_096 => 4.096,
+            Self::V2_048 => 2.048,
+            Self::V1_024 => 1.024,
+            Self::V0_512 => 0.512,
+            Self::V0_256 => 0.256,
+        }
+    }
+}
+
+/// Auxiliary input configuration
+#[derive(Clone, Debug, Default, Tree)]
+#[tree(meta(doc, typename))]
+pub struct AuxConfig {
+    /// Input range of each channel. `null` disables the channel.
+    #[tree(with=miniconf::leaf)]
+    pub channels: [Option<Range>; AUX_CHANNELS],
+}
+
+/// A slow external ADC with single-shot conversions
+pub trait AuxAdc {
+    type Error;
+
+    /// Number of input channels
+    const CHANNELS: usize;
+
+    /// Start a conversion.
+    ///
+    /// # Args
+    /// * `channel` - The input channel.
+    /// * `range` - The input voltage range.
+    fn start(
+        &mut self,
+        channel: usize,
+        range: Range,
+    ) -> Result<(), Self::Error>;
+
+    /// Read the result of the last conversion.
+    ///
+    /// # Returns
+    /// The input voltage or `None` if the conversion is still in progress.
+    fn read(&mut self) -> Result<Option<f32>, Self::Error>;
+}
+
+/// Sequencer of conversions on the enabled auxiliary inputs
+pub struct AuxInputs<A> {
+    adc: A,
+    /// The channel being converted
+    pending: Option<usize>,
+    values: [f32; AUX_CHANNELS],
+    /// Failed reads not yet taken
+    errors: u32,
+}
+
+impl<A: AuxAdc> AuxInputs<A> {
+    /// Construct the sequencer.
+    ///
+    /// # Args
+    /// * `adc` - The ADC driver.
+    pub fn new(adc: A) -> Self {
+        Self {
+            adc,
+            pending: None,
+            values: [f32::NAN; AUX_CHANNELS],
+            errors: 0,
+        }
+    }
+
+    /// Complete a pending conversion and start the next one.
+    ///
+    /// This should be called periodically at an interval longer than the ADC conversion time.
+    ///
+    /// # Args
+    /// * `config` - The input configuration.
+    pub fn poll(&mut self, config: &AuxConfig) {
+        let channels = A::CHANNELS.min(AUX_CHANNELS);
+        let last = if let Some(channel) = self.pending {
+            match self.adc.read() {
+                Ok(None) => return,
+                Ok(Some(value)) => {
+                    self.values[channel] = value;
+                }
+                Err(_) => {
+                    log::warn!("Aux ADC read failed");
+                    self.errors += 1;
+                    self.values[channel] = f32::NAN;
+                }
+            }
+            channel
+        } else {
+            channels - 1
+        };
+        self.pending = None;
+
+        // Invalidate disabled inputs.
+        for (value, range) in self.values.iter_mut().zip(&config.channels) {
+            if range.is_none() {
+                *value = f32::NAN;
+            }
+        }
+
+        // Start a conversion on the next enabled channel in turn.
+        let next = (1..=channels)
+            .map(|i| (last + i) % channels)
+            .find_map(|ch| config.channels[ch].map(|range| (ch, range)));
+        if let Some((channel, range)) = next {
+            if self.adc.start(channel, range).is_ok() {
+                self.pending = Some(channel);
+            } else {
+                log::warn!("Aux ADC conversion start failed");
+                self.errors += 1;
+                self.values[channel] = f32::NAN;
+            }
+        }
+    }
+
+    /// The most recent input voltages.
+    pub fn values(&self) -> [f32; AUX_CHANNELS] {
+        self.values
+    }
+
+    /// Take the number of failed ADC accesses since the last call.
+    pub fn take_errors(&mut self) -> u32 {
+        core::mem::take(&mut self.errors)
+    }
+}
@@ -3,8 +3,12 @@
 //! This requires the alternate direction EEM transceiver configuration.
 //! It exposes the Urukul CPLD and DDS settings via miniconf (MQTT and USB).
 //!
-//! Note that several values are not range checked and out-of-range values
-//! will lead to panics.
+//! Settings are validated before being applied. Invalid channel settings are not applied and
+//! an error is logged. A request on the `validate` topic runs the checks on the current settings
+//! without applying anything and answers with the outcome (see [platform::Validate]).
+//!
+//! After channel settings have been applied, the actual (quantized) values are published to the
+//! `applied` topic.
 #![cfg_attr(target_os = "none", no_std)]
 #![cfg_attr(target_os = "none", no_main)]
 
@@ -12,8 +16,9 @@ use arbitrary_int::{u2, u5};
 use fugit::ExtU32;
 use miniconf::Tree;
 use rtic_monotonics::Monotonic;
+use serde::Serialize;
 
-use platform::{AppSettings, NetSettings};
+use platform::{AppSettings, Invalid, NetSettings, Validate};
 
 #[derive(Clone, Debug, Tree, Default)]
 #[tree(meta(doc, typename))]
@@ -23,6 +28,10 @@ pub struct Settings {
 }
 
 impl AppSettings for Settings {
+    const NAME: &'static str = env!("CARGO_BIN_NAME");
+    /// Settings have only been added since schema versioning was introduced.
+    const VERSION: u32 = 1;
+
     fn new(net: NetSettings) -> Self {
         Self {
             net,
@@ -35,6 +44,12 @@ impl AppSettings for Settings {
     }
 }
 
+impl Validate for Settings {
+    fn validate(&self) -> Result<(), Invalid> {
+        self.urukul.validate()
+    }
+}
+
 impl serial_settings::Settings for Settings {
     fn reset(&mut self) {
         *self = Self {
@@ -44,6 +59,28 @@ impl serial_settings::Settings for Settings {
     }
 }
 
+/// AD9912 maximum system clock frequency
+const SYSCLK_MAX: f64 = 1e9;
+/// AD9912 PLL VCO minimum frequency
+const VCO_MIN: f64 = 700e6;
+/// Full scale current range of the AD9912 DAC
+const FULL_SCALE_CURRENT: core::ops::RangeInclusive<f32> = 8.6e-3..=31.7e-3;
+
+/// Attenuation range in dB covered by the Urukul attenuator codes
+const ATTENUATION: core::ops::RangeInclusive<f32> =
+    urukul::mu_to_att(u8::MAX)..=urukul::mu_to_att(0);
+
+/// Channel settings as applied to the hardware after quantization
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Applied {
+    ch: u8,
+    sysclk: f64,
+    frequency: f64,
+    phase: f32,
+    full_scale_current: f32,
+    attenuation: f32,
+}
+
 #[derive(Clone, Debug, Tree)]
 #[tree(meta(doc, typename))]
 pub struct Channel {
@@ -58,6 +95,45 @@ pub struct Channel {
     update: bool,
 }
 
+impl Channel {
+    /// Get the system clock frequency for a given reference clock frequency.
+    fn sysclk(&self, refclk: f64) -> f64 {
+        if let Some(pll_n) = self.pll_n {
+            ad9912::Pll::default()
+                .with_ref_doubler(self.pll_doubler)
+                .set_refclk(pll_n, refclk)
+        } else {
+            refclk
+        }
+    }
+
+    /// Validate the channel settings.
+    ///
+    /// # Args
+    /// * `ch` - The channel index
+    /// * `refclk` - The reference clock frequency after the divider
+    fn validate(&self, ch: u8, refclk: f64) -> Result<(), Invalid> {
+        let sysclk = self.sysclk(refclk);
+        let min = if self.pll_n.is_some() { VCO_MIN } else { 0.0 };
+        let invalid = |field: &str, reason: &str| {
+            Invalid::new(format_args!("/urukul/ch/{ch}/{field}"), reason)
+        };
+        if !(min..=SYSCLK_MAX).contains(&sysclk) {
+            return Err(invalid("pll_n", "System clock out of range"));
+        }
+        if !(0.0..=sysclk / 2.0).contains(&self.frequency) {
+            return Err(invalid("frequency", "Beyond Nyquist"));
+        }
+        if !FULL_SCALE_CURRENT.contains(&self.full_scale_current) {
+            return Err(invalid("full_scale_current", "Out of range"));
+        }
+        if !ATTENUATION.contains(&self.attenuation) {
+            return Err(invalid("attenuation", "Out of range"));
+        }
+        Ok(())
+    }
+}
+
 impl Default for Channel {
     fn default() -> Self {
         Self {
@@ -85,6 +161,24 @@ pub struct App {
     ch: [Channel; 4],
 }
 
+impl App {
+    /// Get the reference clock frequency after the divider.
+    fn refclk(&self) -> f64 {
+        self.refclk / self.div_sel.divider() as f64
+    }
+
+    /// Validate all settings.
+    fn validate(&self) -> Result<(), Invalid> {
+        if self.refclk <= 0.0 {
+            return Err(Invalid::new("/urukul/refclk", "Not positive"));
+        }
+        for (i, ch) in self.ch.iter().enumerate() {
+            ch.validate(i as _, self.refclk())?;
+        }
+        Ok(())
+    }
+}
+
 impl Default for App {
     fn default() -> Self {
         Self {
@@ -117,8 +211,10 @@ fn main() {
 mod app {
     use super::*;
 
+    use platform::ConnectionWatchdog;
     use stabilizer::hardware::{
-        self, SerialTerminal, SystemTimer, Systick, Urukul, UsbDevice, hal,
+        self, SerialTerminal, SystemTimer, Systick, Urukul, UsbDevice,
+        UsbEthernet, hal,
         net::{NetworkState, NetworkUsers},
     };
 
@@ -127,12 +223,14 @@ mod app {
         usb: UsbDevice,
         network: NetworkUsers<App>,
         settings: Settings,
+        failsafe: bool,
     }
 
     #[local]
     struct Local {
         urukul: Urukul,
         usb_terminal: SerialTerminal<Settings>,
+        usb_ethernet: UsbEthernet,
     }
 
     #[init]
@@ -154,26 +252,32 @@ mod app {
         let network = NetworkUsers::new(
             stabilizer.network_devices.stack,
             stabilizer.network_devices.phy,
+            stabilizer.network_devices.nonce,
             clock,
             env!("CARGO_BIN_NAME"),
             &stabilizer.settings.net,
             stabilizer.metadata,
+            stabilizer.network_devices.provision,
+            stabilizer.network_devices.safe_boot,
         );
 
         let shared = Shared {
             usb: stabilizer.usb,
             network,
             settings: stabilizer.settings,
+            failsafe: false,
         };
 
         let local = Local {
             urukul,
             usb_terminal: stabilizer.usb_serial,
+            usb_ethernet: stabilizer.usb_ethernet,
         };
 
         // Spawn a settings update for default settings.
         settings_update::spawn().unwrap();
         ethernet_link::spawn().unwrap();
+        watchdog::spawn().unwrap();
         usb::spawn().unwrap();
 
         (shared, local)
@@ -202,11 +306,23 @@ mod app {
         }
     }
 
-    #[task(priority = 1, shared=[settings], local=[urukul])]
+    #[task(priority = 1, shared=[network, settings, failsafe], local=[urukul])]
     async fn settings_update(mut c: settings_update::Context) {
         let u = c.local.urukul;
+        if c.shared.failsafe.lock(|failsafe| *failsafe) {
+            // Disable RF. Pending updates are applied once the connection is restored.
+            for i in 0..4 {
+                u.set_rf_sw(u2::new(i), false).unwrap();
+            }
+            return;
+        }
         c.shared.settings.lock(|s| {
+            hardware::LOGGER.configure(&s.net.log);
             let s = &mut s.urukul;
+            if s.refclk <= 0.0 {
+                log::error!("Invalid reference clock: {}", s.refclk);
+                return;
+            }
             if s.update {
                 s.update = false;
                 u.set_cfg(
@@ -222,10 +338,14 @@ mod app {
                 .with_cmos_en(false)
                 .with_hstl_pd(true)
                 .build();
+            let refclk = s.refclk();
             for (i, ch) in s.ch.iter_mut().enumerate() {
                 if ch.update {
                     ch.update = false;
-                    let refclk = s.refclk / s.div_sel.divider() as f64;
+                    if let Err(err) = ch.validate(i as _, refclk) {
+                        log::error!("Invalid settings: {:?}", err);
+                        continue;
+                    }
                     let i = u2::new(i as _);
                     let sysclk = if let Some(pll_n) = ch.pll_n {
                         u.dds(i).set_power(power.with_pll_pd(false)).unwrap();
@@ -240,28 +360,42 @@ mod app {
                         u.dds(i).set_power(power.with_pll_pd(true)).unwrap();
                         refclk
                     };
-                    u.dds(i).set_frequency(ch.frequency, sysclk).unwrap();
-                    u.dds(i).set_phase(ch.phase).unwrap();
+                    let ftw =
+                        u.dds(i).set_frequency(ch.frequency, sysclk).unwrap();
+                    let pow = u.dds(i).set_phase(ch.phase).unwrap();
                     u.io_update().unwrap();
-                    u.dds(i)
+                    let fsc = u
+                        .dds(i)
                         .set_full_scale_current(ch.full_scale_current, 10e3)
                         .unwrap();
-                    u.set_att(i, urukul::att_to_mu(ch.attenuation)).unwrap();
+                    let att = urukul::att_to_mu(ch.attenuation);
+                    u.set_att(i, att).unwrap();
                     u.set_rf_sw(i, ch.enable).unwrap();
+
+                    let applied = Applied {
+                        ch: i.value(),
+                        sysclk,
+                        frequency: ad9912::ftw_to_frequency(ftw, sysclk),
+                        phase: ad9912::pow_to_phase(pow),
+                        full_scale_current: ad9912::fsc_to_dac_fs(fsc, 10e3),
+                        attenuation: urukul::mu_to_att(att),
+                    };
+                    c.shared.network.lock(|net| {
+                        net.telemetry.publish_telemetry("/applied", &applied)
+                    });
                 }
             }
         });
     }
 
-    #[task(priority = 1, shared=[usb, settings], local=[usb_terminal])]
+    #[task(priority = 1, shared=[usb, network, settings], local=[usb_terminal, usb_ethernet])]
     async fn usb(mut c: usb::Context) {
         loop {
             c.shared.usb.lock(|usb| {
-                usb.poll(&mut [c
-                    .local
-                    .usb_terminal
-                    .interface_mut()
-                    .inner_mut()]);
+                usb.poll(&mut [
+                    c.local.usb_terminal.interface_mut().inner_mut(),
+                    c.local.usb_ethernet,
+                ]);
             });
 
             c.shared.settings.lock(|settings| {
@@ -270,7 +404,64 @@ mod app {
                 }
             });
 
-            Systick::delay(10.millis()).await;
+            let provisioned = (&mut c.shared.network, &mut c.shared.settings)
+                .lock(|net, settings| {
+                    net.take_provisioned(|provisioned| {
+                        c.local
+                            .usb_terminal
+                            .platform_mut()
+                            .provision(settings, provisioned)
+                    })
+                });
+            match provisioned {
+                Some(0) => log::warn!("No settings provisioned"),
+                Some(count) => {
+                    log::info!("Provisioned {count} settings. Rebooting");
+                    cortex_m::peripheral::SCB::sys_reset();
+                }
+                None => {}
+            }
+
+            c.local
+                .usb_terminal
+                .platform_mut()
+                .update_counters(Systick::now().ticks());
+
+            // Poll faster while the host exchanges frames over the USB network interface.
+            let period = if c.local.usb_ethernet.is_active() {
+                1
+            } else {
+                10
+            };
+            Systick::delay(period.millis()).await;
+        }
+    }
+
+    /// Disable RF while the broker is unreachable.
+    #[task(priority = 1, shared=[network, settings, failsafe])]
+    async fn watchdog(mut c: watchdog::Context) {
+        let mut watchdog = ConnectionWatchdog::default();
+        loop {
+            let timeout =
+                c.shared.settings.lock(|settings| settings.net.watchdog);
+            let connected = c.shared.network.lock(|net| net.is_connected());
+            let tripped = watchdog.update(connected, 0.1, timeout);
+            let changed = c.shared.failsafe.lock(|failsafe| {
+                core::mem::replace(failsafe, tripped) != tripped
+            });
+            if changed {
+                log::warn!("Connection watchdog tripped: {tripped}");
+                if !tripped {
+                    // Restore the RF switch states.
+                    c.shared.settings.lock(|settings| {
+                        for ch in settings.urukul.ch.iter_mut() {
+                            ch.update = true;
+                        }
+                    });
+                }
+                settings_update::spawn().ok();
+            }
+            Systick::delay(100.millis()).await;
         }
     }
 
@@ -23,8 +23,12 @@
 //! Refer to [stabilizer::telemetry::Telemetry] for information about telemetry reported by this application.
 //!
 //! ## Stream
-//! This application streams raw ADC and DAC data over UDP. Refer to
-//! [stream] for more information.
+//! This application streams raw ADC and DAC data over UDP. Alternatively the data can be streamed
+//! as calibrated volts using the [stream::Format::AdcDacVolts] format. The internal signal of each
+//! channel selected by [Probe] is streamed using the [stream::Format::Probe] format. With Pounder,
+//! the DDS clock phase relative to the sampling clock is streamed using the
+//! [stream::Format::PounderPhase] format and its frequency offset is reported on `/pounder/phase`
+//! every telemetry period. Refer to [stream] for more information.
 #![cfg_attr(target_os = "none", no_std)]
 #![cfg_attr(target_os = "none", no_main)]
 
@@ -32,14 +36,46 @@ use miniconf::Tree;
 
 use idsp::iir;
 
-use platform::{AppSettings, NetSettings};
+use platform::{
+    AppSettings, DIGITAL_INPUTS, DigitalInputConfig, InterlockConfig, Invalid,
+    NetSettings, Role, Validate,
+};
 use serde::{Deserialize, Serialize};
 use signal_generator::{self, Source};
-use stabilizer::convert::{AdcCode, DacCode, Gain};
+use stabilizer::{
+    aux_adc::{AUX_CHANNELS, AuxConfig},
+    calibration::InputCalibration,
+    convert::{AdcCode, DacCode, Gain},
+    dsp::{
+        AllPass, Analysis, Block, Capture, DcBlock, GRAPH_BLOCKS, Graph,
+        HumCanceller, HumParameters, Kalman, KalmanModel, Loopback,
+        LoopbackResult, NOISE_BANDS, NoiseRecord, NoiseShaper, PlantModel, Rms,
+        SPECTRUM_SIZE, SmithPredictor, Spectrum, coarse_asd_db, decibel,
+        group_delay,
+    },
+    faults::{Fault, Faults},
+    pounder_config::{BODE_POINTS, PounderConfig},
+    schedule::{Entry, SCHEDULE_ENTRIES, Scheduler},
+    telemetry::{Occupancy, Scaling},
+    timebase::Timebase,
+};
 
 // The number of cascaded IIR biquads per channel. Select 1 or 2!
 const IIR_CASCADE_LENGTH: usize = 1;
 
+// The number of all-pass sections per channel.
+const ALLPASS_SECTIONS: usize = 2;
+
+// The maximum number of summed sources per DAC of the output routing.
+const DAC_TERMS: usize = 4;
+
+// The number of biquad presets per channel selectable by the digital inputs.
+const PRESETS: usize = 3;
+
+// The number of batches from sampling an ADC sample to the output of the corresponding DAC
+// sample (see `stabilizer::hardware::dac`).
+const PIPELINE_BATCHES: usize = 3;
+
 // The number of samples in each batch process
 const BATCH_SIZE: usize = 8;
 
@@ -50,6 +86,28 @@ const SAMPLE_TICKS: u32 = 1 << SAMPLE_TICKS_LOG2;
 const SAMPLE_PERIOD: f32 =
     SAMPLE_TICKS as f32 * stabilizer::design_parameters::TIMER_PERIOD;
 
+// Time to wait for the confirmation that the inputs are shorted during an
+// input offset calibration (ms).
+const CALIBRATION_TIMEOUT: u32 = 60_000;
+
+// The number of points of a signal generator waveform preview.
+const PREVIEW_POINTS: usize = 64;
+
+// The maximum number of spectrum bins streamed per batch.
+const SPECTRUM_BINS_PER_BATCH: usize = 8;
+
+// The number of external reference edges per timestamp.
+const TIMEBASE_PRESCALER: f32 = 8.0;
+
+// The nominal number of Pounder timestamp ticks (DDS SYNC_CLK / 4) per batch.
+const SYNC_TICKS: u32 = (BATCH_SIZE as u64
+    * SAMPLE_TICKS as u64
+    * (stabilizer::design_parameters::DDS_SYSTEM_CLK.to_Hz()
+        / (stabilizer::design_parameters::DDS_SYNC_CLK_DIV as u32 * 4))
+        as u64
+    / stabilizer::design_parameters::TIMER_FREQUENCY.to_Hz() as u64)
+    as u32;
+
 #[derive(Clone, Debug, Tree, Default)]
 #[tree(meta(doc, typename))]
 pub struct Settings {
@@ -58,6 +116,14 @@ pub struct Settings {
 }
 
 impl AppSettings for Settings {
+    const NAME: &'static str = env!("CARGO_BIN_NAME");
+    /// Schema versions:
+    /// * 2: `run = External` follows the digital inputs with the `Hold` role
+    ///   and polarity (`di`), `probe = Input` is taken after the input
+    ///   conversions and `pwm/ch/n/source` selects a signal instead of a
+    ///   channel.
+    const VERSION: u32 = 2;
+
     fn new(net: NetSettings) -> Self {
         Self {
             net,
@@ -70,6 +136,15 @@ impl AppSettings for Settings {
     }
 }
 
+impl Validate for Settings {
+    fn validate(&self) -> Result<(), Invalid> {
+        for (ch, channel) in self.dual_iir.ch.iter().enumerate() {
+            channel.validate(ch)?;
+        }
+        Ok(())
+    }
+}
+
 impl serial_settings::Settings for Settings {
     fn reset(&mut self) {
         *self = Self {
@@ -100,27 +175,241 @@ impl Default for BiquadRepr {
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, Default)]
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default,
+)]
 pub enum Run {
     #[default]
     /// Run
     Run,
     /// Hold
     Hold,
-    /// Hold controlled by corresponding digital input
+    /// Hold controlled by the digital inputs with the `Hold` role of this
+    /// channel
     External,
 }
 
 impl Run {
-    fn run(&self, di: bool) -> bool {
+    fn run(&self, hold: bool) -> bool {
         match self {
             Self::Run => true,
             Self::Hold => false,
-            Self::External => di,
+            Self::External => !hold,
+        }
+    }
+}
+
+/// Channel signal routing
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default,
+)]
+pub enum Route {
+    /// ADC0 and ADC1 are the inputs of channel 0 and 1
+    #[default]
+    Parallel,
+    /// The biquad output of channel 0 (DAC LSB, ahead of quantization and the
+    /// signal generator) is the biquad input of channel 1 instead of ADC1. The
+    /// DC blocker and logarithmic conversion of channel 1 are bypassed.
+    Series,
+}
+
+/// DAC output routing source
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DacSource {
+    /// Processing output of the channel with the given index (V), ahead of
+    /// the signal generator: the biquad and all-pass output or the processing
+    /// chain output
+    Chain(usize),
+    /// Signal generator of the channel with the given index (V)
+    Generator(usize),
+    /// Offset corrected ADC input with the given index (V at the ADC)
+    Input(usize),
+    /// Constant 1 V
+    Constant,
+}
+
+/// DAC output routing term
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct DacTerm {
+    /// The source
+    pub source: DacSource,
+    /// Gain (V/V)
+    pub gain: f32,
+}
+
+impl DacTerm {
+    /// The term value for a sample in DAC LSB.
+    ///
+    /// # Args
+    /// * `chain` - The processing outputs (DAC LSB).
+    /// * `source` - The signal generator outputs (DAC LSB).
+    /// * `input` - The offset corrected ADC inputs (ADC LSB).
+    fn value(&self, chain: [f32; 2], source: [i16; 2], input: [i32; 2]) -> f32 {
+        let x = match self.source {
+            DacSource::Chain(i) => chain.get(i).copied().unwrap_or_default(),
+            DacSource::Generator(i) => {
+                source.get(i).copied().unwrap_or_default() as f32
+            }
+            DacSource::Input(i) => {
+                input.get(i).copied().unwrap_or_default() as f32
+                    * (AdcCode::VOLT_PER_LSB * DacCode::LSB_PER_VOLT)
+            }
+            DacSource::Constant => DacCode::LSB_PER_VOLT,
+        };
+        self.gain * x
+    }
+}
+
+/// Error deadband
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Deadband {
+    /// Center of the band: the setpoint (V at the AFE input, dB with
+    /// logarithmic conversion)
+    pub center: f32,
+    /// Half width of the band (V at the AFE input, dB with logarithmic
+    /// conversion)
+    pub width: f32,
+}
+
+/// Kalman filter state estimator configuration
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct KalmanConfig {
+    /// Signal model
+    pub model: KalmanModel,
+    /// Process noise per sample relative to the measurement noise (standard
+    /// deviations), e.g. `1e-3`
+    pub ratio: f32,
+}
+
+/// Smith predictor plant model
+///
+/// The plant from the DAC output to the AFE input is modeled as a first order
+/// lowpass followed by a pure delay.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct SmithConfig {
+    /// Static plant gain (V/V, dB/V with logarithmic conversion)
+    pub gain: f32,
+    /// Plant time constant (s)
+    pub time_constant: f32,
+    /// Plant pure delay (s), at most 512 samples
+    pub delay: f32,
+}
+
+/// Internal signal of a channel to be streamed
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default,
+)]
+pub enum Probe {
+    /// ADC input after the DC blocker and logarithmic conversion, referred to
+    /// the AFE input
+    #[default]
+    Input,
+    /// Output of the biquad with the given index in the cascade
+    Biquad(usize),
+    /// Signal generator contribution
+    Source,
+    /// DAC output
+    Output,
+}
+
+impl Probe {
+    /// The probed signal scale in volts per unit.
+    fn scale(&self, gain: Gain) -> f32 {
+        match self {
+            Self::Input => AdcCode::VOLT_PER_LSB / gain.gain(),
+            _ => DacCode::VOLT_PER_LSB,
         }
     }
 }
 
+/// Biquad coefficient issue report
+#[derive(Copy, Clone, Debug, Serialize)]
+struct BiquadIssue<'a> {
+    /// Channel index
+    ch: usize,
+    /// Stage index in the cascade
+    stage: usize,
+    analysis: &'a Analysis,
+}
+
+/// Line-synchronous hum rejection configuration
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct HumConfig {
+    /// Nominal line frequency (Hz), e.g. 50 or 60
+    pub frequency: f32,
+    /// Waveform averaging time constant (s)
+    pub time_constant: f32,
+}
+
+/// Signal generator waveform preview
+#[derive(Copy, Clone, Debug, Serialize)]
+struct Preview {
+    /// Output channel
+    channel: usize,
+    /// Time between points (s)
+    period: f32,
+    /// Signal generator output (V)
+    output: [f32; PREVIEW_POINTS],
+}
+
+/// Loop group delay report
+#[derive(Copy, Clone, Debug, Serialize)]
+struct LoopDelay {
+    /// Evaluation frequency (Hz)
+    frequency: f32,
+    /// Delay from ADC sampling to DAC output (s)
+    pipeline: f32,
+    /// Filter group delay of each channel (s)
+    ///
+    /// `None` if it is not finite, e.g. at DC with an integrator.
+    filter: [Option<f32>; 2],
+    /// Total group delay of each channel (s)
+    total: [Option<f32>; 2],
+}
+
+impl LoopDelay {
+    /// The delay from sampling to output: the sample pipeline, the delay
+    /// between the ADC and DAC conversion triggers and the DAC transfer.
+    fn pipeline() -> f32 {
+        (PIPELINE_BATCHES * BATCH_SIZE) as f32 * SAMPLE_PERIOD
+            + 2.0 * stabilizer::design_parameters::TIMER_PERIOD
+            + 16.0
+                / stabilizer::design_parameters::ADC_DAC_SCK_MAX.to_Hz() as f32
+    }
+}
+
+/// Signal generator amplitude modulation source
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AmSource {
+    /// DAC output of the channel with the given index (V), updated once per
+    /// sample batch
+    Dac(usize),
+    /// Auxiliary ADC input with the given index (V)
+    Aux(usize),
+    /// The modulation signal generator of this channel (V)
+    Generator,
+}
+
+/// Signal generator amplitude modulation
+#[derive(Clone, Debug, Tree, Default)]
+#[tree(meta(doc, typename))]
+pub struct AmConfig {
+    /// Modulation signal.
+    ///
+    /// The signal generator output is scaled by `1 + depth * signal`. An
+    /// unavailable auxiliary input holds the unmodulated amplitude. `null` to
+    /// disable.
+    #[tree(with=miniconf::leaf)]
+    source: Option<AmSource>,
+    /// Modulation depth per signal voltage (1/V)
+    #[tree(with=miniconf::leaf)]
+    depth: f32,
+    /// Modulation signal generator configuration for the `Generator` source.
+    ///
+    /// It is applied and restarted along with the signal generator.
+    generator: signal_generator::Config,
+}
+
 /// A ADC-DAC channel
 #[derive(Clone, Debug, Tree, Default)]
 #[tree(meta(doc, typename))]
@@ -128,24 +417,309 @@ pub struct Channel {
     /// Analog Front End (AFE) gain.
     #[tree(with=miniconf::leaf)]
     gain: Gain,
+    /// Number of samples to hold the channel after an AFE gain change.
+    ///
+    /// The biquad input history and the DC blocker estimate are rescaled to
+    /// the new gain on a change so that the output does not step. While held,
+    /// the DC blocker and biquad states are frozen and the output remains
+    /// constant, rejecting the switching transient and the samples in flight
+    /// with the previous gain. 0 to switch without holding.
+    #[tree(with=miniconf::leaf)]
+    gain_blanking: u32,
+    /// DC blocker corner frequency (Hz) ahead of the biquads.
+    ///
+    /// `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    dc_block: Option<f32>,
+    /// Line-synchronous hum rejection.
+    ///
+    /// The line-synchronous input waveform (mains hum and harmonics, without
+    /// its mean) is estimated by synchronous averaging over the line period
+    /// and subtracted from the input after the DC blocker. The line phase
+    /// follows a digital input with the `LineSync` role if configured (e.g. a
+    /// mains synchronization source), else it runs at the nominal frequency.
+    /// `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    hum: Option<HumConfig>,
+    /// Logarithmic conversion reference level (V) at the AFE input.
+    ///
+    /// If set, the input is converted to a level in dB relative to this
+    /// reference ahead of the biquads and the controller operates on the
+    /// dB-scaled error. The converted signal has a slope of 0.1 V/dB referred
+    /// to the ADC (like a logarithmic amplifier), i.e. 0 dB corresponds to
+    /// 0 V and the input range is about ±100 dB. Inputs smaller than 1 LSB are
+    /// clamped. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    log: Option<f32>,
+    /// Telemetry scaling of the input (V at the AFE input) to user units.
+    ///
+    /// The input is additionally reported in these units as
+    /// `scaled_adcs` in the telemetry, e.g. `{"scale": 2.5, "offset": 0,
+    /// "unit": "µW"}` for a photodetector. Units are one of `V`, `mV`, `A`,
+    /// `mA`, `µA`, `W`, `mW`, `µW`, `nW`, `Hz`, `kHz`, `MHz`, `Pa`, `kPa`,
+    /// `°C`, `K`, `m`, `µm`, `nm`, `dB`, `%`. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    input_unit: Option<Scaling>,
+    /// Telemetry scaling of the output (V at the DAC output) to user units.
+    ///
+    /// The output is additionally reported in these units as `scaled_dacs`
+    /// in the telemetry. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    output_unit: Option<Scaling>,
+    /// Kalman filter state estimator.
+    ///
+    /// If set, the steady-state Kalman estimate of the input (after the DC
+    /// blocker and logarithmic conversion) is the feedback signal instead of
+    /// the raw sample. It attenuates measurement noise without the phase lag
+    /// of a low-pass filter for signals following the model. `null` to
+    /// disable.
+    #[tree(with=miniconf::leaf)]
+    kalman: Option<KalmanConfig>,
+    /// Smith predictor delay compensation.
+    ///
+    /// An internal plant model with delay is driven by the biquad output
+    /// (ahead of the signal generator). The biquads act on the input corrected
+    /// by the difference between the undelayed and the delayed model output.
+    /// With an accurate model the loop can be tuned as if the plant had no
+    /// delay, e.g. for fiber links or AOM acoustic delays. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    smith: Option<SmithConfig>,
+    /// Error deadband ahead of the biquads.
+    ///
+    /// Inputs within the band are replaced by its center such that the error
+    /// relative to a setpoint at the center is zero and noise near the setpoint
+    /// does not continuously dither an actuator. Inputs outside the band are
+    /// moved towards the center by the half width such that the response is
+    /// continuous. The fraction of time within the band is reported in
+    /// telemetry. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    deadband: Option<Deadband>,
     /// Biquad
     biquad: [BiquadRepr; IIR_CASCADE_LENGTH],
+    /// Biquad presets selected by the digital inputs.
+    ///
+    /// The profile selection of the digital inputs with the `Profile` role
+    /// selects the biquad configuration: 0 for `biquad`, `n` for preset
+    /// `n - 1`. The coefficients of all presets are computed when settings are
+    /// applied such that a selection takes effect with the next sample batch
+    /// (cross-faded if configured). Invalid selections and presets rejected as
+    /// invalid are ignored.
+    preset: [[BiquadRepr; IIR_CASCADE_LENGTH]; PRESETS],
+    /// All-pass phase compensation sections following the biquads.
+    ///
+    /// Each section has unity gain and shifts the phase around its corner
+    /// frequency: first order sections by up to -180 degrees, second order
+    /// sections (with a quality factor) by up to -360 degrees. `null` to
+    /// disable a section.
+    #[tree(with=miniconf::leaf)]
+    allpass: [Option<AllPass>; ALLPASS_SECTIONS],
+    /// User-configurable processing chain (at most 8 blocks).
+    ///
+    /// If set, the chain replaces the processing from the DC blocker to the
+    /// noise shaping and the signal generator sum. Its input is the offset
+    /// corrected ADC input (V at the ADC, or the channel 0 output with series
+    /// routing) and its output the DAC output (V). The blocks are evaluated in
+    /// order, e.g. `[{"Fir": [0.25, 0.25, 0.25, 0.25]}, {"Biquad": [...]},
+    /// {"Limit": [-1, 1]}, {"Generator": 1}]`. Biquads can be designed on
+    /// the device for the sample rate with the representation of the biquad
+    /// cascade in volts, e.g. `{"Design": {"Pid": {...}}}`. While held, the
+    /// output is held.
+    /// Reconfiguring retains the states of blocks whose type is unchanged.
+    /// `null` to use the fixed processing.
+    #[tree(with=miniconf::leaf)]
+    graph: Option<heapless::Vec<Block, GRAPH_BLOCKS>>,
+    /// DAC quantization noise shaping corner frequency (Hz).
+    ///
+    /// If set, the quantization error of the biquad output is shaped such that
+    /// the noise below the corner is suppressed (by 20 dB per decade towards
+    /// DC) and the output resolves fractions of an LSB on average. The noise
+    /// above the corner is raised by up to 6 dB. The corner is limited to
+    /// about 0.16 times the sample rate. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    noise_shaping: Option<f32>,
     /// Run/Hold behavior
     #[tree(with=miniconf::leaf)]
     run: Run,
     /// Signal generator configuration to add to the DAC0/DAC1 outputs
     source: signal_generator::Config,
+    /// Signal generator amplitude modulation
+    am: AmConfig,
+    /// Internal signal to stream with the probe stream format
+    #[tree(with=miniconf::leaf)]
+    probe: Probe,
+    /// Number of samples to cross-fade from the previous to new biquad coefficients.
+    ///
+    /// 0 for instantaneous updates.
+    #[tree(with=miniconf::leaf)]
+    crossfade: u32,
+    /// Clear the biquad states without changing the coefficients
+    #[tree(with=miniconf::leaf)]
+    clear: bool,
 }
 
 impl Channel {
+    /// The biquad input units (LSB) per V at the AFE input, or per dB with
+    /// logarithmic conversion.
+    fn input_scale(&self) -> f32 {
+        if self.log.is_some() {
+            LOG_SLOPE * AdcCode::LSB_PER_VOLT
+        } else {
+            self.gain.gain() * AdcCode::LSB_PER_VOLT
+        }
+    }
+
+    /// The deadband center and half width in biquad input units.
+    fn deadband(&self) -> Option<(f32, f32)> {
+        let scale = self.input_scale();
+        self.deadband
+            .map(|band| (band.center * scale, band.width.max(0.0) * scale))
+    }
+
+    /// The hum canceller parameters.
+    fn hum(&self) -> Option<HumParameters> {
+        self.hum.map(|hum| {
+            HumParameters::new(hum.frequency, hum.time_constant, SAMPLE_PERIOD)
+        })
+    }
+
+    /// The all-pass section coefficients.
+    fn allpass(&self) -> [Option<iir::Biquad<f32>>; ALLPASS_SECTIONS] {
+        self.allpass
+            .map(|ap| ap.map(|ap| ap.biquad(SAMPLE_PERIOD, i16::MAX as _)))
+    }
+
+    /// The group delay of the DC blocker, the biquads and the all-pass
+    /// sections.
+    ///
+    /// # Args
+    /// * `frequency` - The frequency in Hz.
+    ///
+    /// # Returns
+    /// The group delay in samples.
+    fn group_delay(&self, frequency: f32) -> f32 {
+        let omega = core::f32::consts::TAU * frequency * SAMPLE_PERIOD;
+        let dc_block = self.dc_block.map(|corner| {
+            let pole = 1.0
+                - DcBlock::gain(corner, SAMPLE_PERIOD) as f32
+                    / (1u64 << 32) as f32;
+            iir::Biquad::from([1.0, -1.0, 0.0, pole, 0.0])
+        });
+        self.biquad
+            .iter()
+            .map(|b| {
+                b.repr
+                    .build::<f32>(SAMPLE_PERIOD, 1.0, DacCode::LSB_PER_VOLT)
+            })
+            .chain(dc_block)
+            .chain(self.allpass().into_iter().flatten())
+            .map(|b| group_delay(&b, omega))
+            .sum()
+    }
+
+    /// The Smith predictor plant model in biquad input and output units.
+    fn smith(&self) -> Option<PlantModel> {
+        self.smith.map(|smith| {
+            PlantModel::new(
+                smith.gain * self.input_scale() / DacCode::LSB_PER_VOLT,
+                smith.time_constant,
+                smith.delay,
+                SAMPLE_PERIOD,
+            )
+        })
+    }
+
+    /// The amplitude modulation source and depth.
+    fn am(&self) -> Option<(AmSource, f32)> {
+        self.am.source.map(|source| (source, self.am.depth))
+    }
+
+    /// Validate the channel settings.
+    ///
+    /// The signal generators must be valid and the biquad cascade must not
+    /// be unstable or exceed the output limits.
+    fn validate(&self, ch: usize) -> Result<(), Invalid> {
+        let scale = DacCode::FULL_SCALE.recip();
+        if let Err(err) = self.source.build(SAMPLE_PERIOD, scale) {
+            return Err(Invalid::new(
+                format_args!("/dual_iir/ch/{ch}/source"),
+                err,
+            ));
+        }
+        if let Err(err) = self.am.generator.build(SAMPLE_PERIOD, scale) {
+            return Err(Invalid::new(
+                format_args!("/dual_iir/ch/{ch}/am/generator"),
+                err,
+            ));
+        }
+        let biquad = self.biquad.each_ref().map(|b| {
+            b.repr
+                .build::<f32>(SAMPLE_PERIOD, 1.0, DacCode::LSB_PER_VOLT)
+        });
+        // The last stage output is converted to DAC codes.
+        let analysis = Analysis::cascade(
+            &biquad,
+            i16::MAX as _,
+            (i16::MIN as _, i16::MAX as _),
+        );
+        match analysis.iter().position(Analysis::invalid) {
+            // Note(unwrap): Invalid stages have an issue.
+            Some(i) => Err(Invalid::new(
+                format_args!("/dual_iir/ch/{ch}/biquad/{i}"),
+                format_args!("{:?}", analysis[i].issue.unwrap()),
+            )),
+            None => Ok(()),
+        }
+    }
+
     fn build(&self) -> Result<Active, signal_generator::Error> {
+        let source = self
+            .source
+            .build(SAMPLE_PERIOD, DacCode::FULL_SCALE.recip())
+            .unwrap();
+        let am_source = self
+            .am
+            .generator
+            .build(SAMPLE_PERIOD, DacCode::FULL_SCALE.recip())?;
         Ok(Active {
-            source: self
-                .source
-                .build(SAMPLE_PERIOD, DacCode::FULL_SCALE.recip())
-                .unwrap(),
+            offset: 0,
+            graph: self
+                .graph
+                .as_deref()
+                .map(|blocks| Graph::new(blocks, SAMPLE_PERIOD)),
+            restart: source.clone(),
+            source,
+            am: self.am(),
+            am_restart: am_source.clone(),
+            am_source,
             state: Default::default(),
+            previous: self.biquad.each_ref().map(|_| iir::Biquad::IDENTITY),
+            previous_state: Default::default(),
+            fade: 0,
+            crossfade: self.crossfade,
+            blank: 0,
+            profiles: Default::default(),
+            profile: 0,
             run: self.run,
+            gain: self.gain,
+            probe: self.probe,
+            dc_block: self
+                .dc_block
+                .map(|corner| DcBlock::gain(corner, SAMPLE_PERIOD)),
+            dc: Default::default(),
+            log: self
+                .log
+                .map(|reference| log_reference(reference, self.gain)),
+            deadband: self.deadband(),
+            hum: self.hum(),
+            hum_canceller: Default::default(),
+            allpass: self.allpass(),
+            allpass_state: Default::default(),
+            kalman_gain: self
+                .kalman
+                .map(|kalman| Kalman::gain(kalman.model, kalman.ratio)),
+            kalman: Default::default(),
+            smith_model: self.smith(),
+            smith: Default::default(),
             biquad: self.biquad.each_ref().map(|biquad| {
                 biquad.repr.build::<f32>(
                     SAMPLE_PERIOD,
@@ -153,131 +727,1193 @@ impl Channel {
                     DacCode::LSB_PER_VOLT,
                 )
             }),
+            noise_shaping: self
+                .noise_shaping
+                .map(|corner| NoiseShaper::pole(corner, SAMPLE_PERIOD)),
+            shaper: Default::default(),
         })
     }
 }
 
+/// Spectrum telemetry configuration
 #[derive(Clone, Debug, Tree)]
 #[tree(meta(doc, typename))]
-pub struct DualIir {
-    /// Channel configuration
-    ch: [Channel; 2],
-    /// Trigger both signal sources
-    #[tree(with=miniconf::leaf)]
-    trigger: bool,
-    /// Telemetry output period in seconds.
+pub struct SpectrumConfig {
+    /// Channel whose probe signal is analyzed.
+    ///
+    /// The amplitude spectral density is published on `/spectrum` at most once
+    /// per telemetry period. With the `Spectrum` stream format, the power
+    /// spectral density and the transfer function from the channel's signal
+    /// generator (excitation) to the probe signal are also streamed.
+    /// `null` to disable.
     #[tree(with=miniconf::leaf)]
-    telemetry_period: f32,
-    /// Target IP and port for UDP streaming.
+    channel: Option<usize>,
+    /// Number of sample batches averaged per spectrum sample.
     ///
-    /// Can be multicast.
+    /// The spectrum sample rate is the batch rate divided by this.
+    #[tree(with=miniconf::leaf)]
+    decimation: u32,
+    /// Number of spectra averaged per report.
     #[tree(with=miniconf::leaf)]
-    stream: stream::Target,
+    average: u32,
 }
 
-impl Default for DualIir {
+impl Default for SpectrumConfig {
     fn default() -> Self {
         Self {
-            telemetry_period: 10.0,
-            trigger: false,
-            stream: Default::default(),
-            ch: Default::default(),
+            channel: None,
+            decimation: 1,
+            average: 1,
         }
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Active {
-    run: Run,
-    biquad: [iir::Biquad<f32>; IIR_CASCADE_LENGTH],
-    state: [[f32; 4]; IIR_CASCADE_LENGTH],
-    source: Source,
+impl SpectrumConfig {
+    /// The spectrum sample rate in Hz.
+    fn sample_rate(&self) -> f32 {
+        1.0 / (SAMPLE_PERIOD
+            * (BATCH_SIZE as u32 * self.decimation.max(1)) as f32)
+    }
 }
 
-#[cfg(not(target_os = "none"))]
-fn main() {
-    use miniconf::{json::to_json_value, json_schema::TreeJsonSchema};
-    let s = Settings::default();
-    println!(
-        "{}",
-        serde_json::to_string_pretty(&to_json_value(&s).unwrap()).unwrap()
-    );
-    let mut schema = TreeJsonSchema::new(Some(&s)).unwrap();
-    schema
-        .root
-        .insert("title".to_string(), "Stabilizer dual-iir".into());
-    println!("{}", serde_json::to_string_pretty(&schema.root).unwrap());
+/// Input noise floor characterization
+///
+/// Terminate both inputs (e.g. short them or connect 50 Ω) before starting.
+/// Both DAC outputs are held at 0 V during the characterization. For each AFE
+/// gain, records of consecutive raw samples of each input are captured. The
+/// input-referred RMS noise and the coarse amplitude spectral density are
+/// published on `/noise` per gain. The configured AFE gains are restored
+/// afterwards.
+#[derive(Clone, Debug, Tree)]
+#[tree(meta(doc, typename))]
+pub struct NoiseFloorConfig {
+    /// Number of records of 512 samples averaged per input and gain
+    average: u32,
+    /// Settling time after each gain change (ms)
+    settle: u32,
+    /// Start a characterization
+    run: bool,
 }
 
-#[cfg(target_os = "none")]
-#[cfg_attr(target_os = "none", rtic::app(device = stabilizer::hardware::hal::stm32, peripherals = true, dispatchers=[DCMI, JPEG, LTDC, SDMMC]))]
-mod app {
-    use super::*;
-    use core::sync::atomic::{Ordering, fence};
-    use fugit::ExtU32 as _;
-    use rtic_monotonics::Monotonic;
+impl Default for NoiseFloorConfig {
+    fn default() -> Self {
+        Self {
+            average: 32,
+            settle: 10,
+            run: false,
+        }
+    }
+}
 
-    use stabilizer::{
-        hardware::{
-            self, DigitalInput0, DigitalInput1, Pgia, SerialTerminal,
-            SystemTimer, Systick, UsbDevice,
-            adc::{Adc0Input, Adc1Input},
-            dac::{Dac0Output, Dac1Output},
-            hal,
-            net::{NetworkState, NetworkUsers},
-            timers::SamplingTimer,
-        },
-        telemetry::TelemetryBuffer,
-    };
-    use stream::FrameGenerator;
+/// Input offset calibration
+///
+/// Setting `run` starts a guided calibration. Instructions are published on
+/// `/calibration`: short both inputs, then set `confirm` within 60 s. Both
+/// DAC outputs are then held at 0 V, the offsets of both inputs are measured
+/// at each AFE gain, stored in the settings flash and published. The offsets
+/// of the configured gains are subtracted from the inputs of the control
+/// path and from the telemetry.
+#[derive(Clone, Debug, Tree)]
+#[tree(meta(doc, typename))]
+pub struct CalibrationConfig {
+    /// Number of records of 512 samples averaged per input and gain
+    average: u32,
+    /// Settling time after each gain change (ms)
+    settle: u32,
+    /// Start a calibration
+    run: bool,
+    /// Confirm that the inputs are shorted
+    confirm: bool,
+    /// Remove the stored calibration
+    clear: bool,
+}
 
-    #[shared]
-    struct Shared {
-        usb: UsbDevice,
-        network: NetworkUsers<DualIir>,
-        settings: Settings,
-        active: [Active; 2],
-        telemetry: TelemetryBuffer,
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            average: 32,
+            settle: 10,
+            run: false,
+            confirm: false,
+            clear: false,
+        }
     }
+}
 
-    #[local]
-    struct Local {
-        usb_terminal: SerialTerminal<Settings>,
-        sampling_timer: SamplingTimer,
-        digital_inputs: (DigitalInput0, DigitalInput1),
-        afes: [Pgia; 2],
-        adcs: (Adc0Input, Adc1Input),
-        dacs: (Dac0Output, Dac1Output),
-        generator: FrameGenerator,
-        cpu_temp_sensor: stabilizer::hardware::cpu_temp_sensor::CpuTempSensor,
+/// Scheduled maintenance action
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Maintenance {
+    /// Unattended input offset calibration (`calibration`) without
+    /// confirmation. The inputs must be shorted externally at that time, e.g.
+    /// by a relay. The outputs are held during the calibration.
+    AutoZero,
+    /// Publish a retained copy of the next telemetry on
+    /// `/telemetry/retained`
+    Snapshot,
+    /// Re-apply the settings, including the Pounder DDS profiles and
+    /// attenuations
+    Resync,
+}
+
+/// Maintenance schedule
+#[derive(Clone, Debug, Default, Tree)]
+#[tree(meta(doc, typename))]
+pub struct ScheduleConfig {
+    /// Scheduled actions (at most 8)
+    ///
+    /// Each entry runs an action either at a fixed interval in seconds
+    /// (`{"action": "Snapshot", "trigger": {"Every": 3600}}`) or daily at a
+    /// time of day in seconds since midnight
+    /// (`{"action": "AutoZero", "trigger": {"Daily": 7200}}`).
+    #[tree(with=miniconf::leaf)]
+    entries: heapless::Vec<Entry<Maintenance>, SCHEDULE_ENTRIES>,
+    /// Set the current time of day (s since midnight)
+    ///
+    /// The device has no wall clock. Daily entries are inactive until the
+    /// time of day was set. It is kept with the local timebase afterwards.
+    #[tree(with=miniconf::leaf)]
+    time: Option<u32>,
+}
+
+/// Loopback latency measurement
+///
+/// Loop a DAC output back to an ADC input before starting. The output
+/// alternates between `-amplitude` and `amplitude` and the delay from each
+/// step to the input response is measured in samples. The delay statistics
+/// (including the jitter) are published on `/loopback`. The delay includes
+/// the processing pipeline, the converters and the analog signal path.
+#[derive(Clone, Debug, Tree)]
+#[tree(meta(doc, typename))]
+pub struct LoopbackConfig {
+    /// Stimulus output channel (0 or 1)
+    output: usize,
+    /// Response input channel (0 or 1)
+    input: usize,
+    /// Step amplitude (V)
+    amplitude: f32,
+    /// Number of samples per level
+    half_period: u32,
+    /// Number of steps to measure
+    steps: u32,
+    /// Start a measurement
+    run: bool,
+}
+
+impl Default for LoopbackConfig {
+    fn default() -> Self {
+        Self {
+            output: 0,
+            input: 0,
+            amplitude: 1.0,
+            half_period: 256,
+            steps: 100,
+            run: false,
+        }
     }
+}
 
-    #[init]
-    fn init(c: init::Context) -> (Shared, Local) {
-        let clock = SystemTimer::new(|| Systick::now().ticks());
+/// PWM duty cycle source
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PwmSource {
+    /// DAC output of the channel with the given index (V)
+    Dac(usize),
+    /// Auxiliary ADC input with the given index (V)
+    Aux(usize),
+}
 
-        // Configure the microcontroller
-        let (stabilizer, _mezzanine, _eem) = hardware::setup::setup::<Settings>(
-            c.core,
-            c.device,
-            clock,
-            BATCH_SIZE,
-            SAMPLE_TICKS,
-        );
+/// PWM output configuration
+#[derive(Copy, Clone, Debug, Tree, Default)]
+#[tree(meta(doc, typename))]
+pub struct PwmChannel {
+    /// Duty cycle (0 to 1).
+    ///
+    /// This is the offset if the duty cycle is driven by a channel.
+    #[tree(with=miniconf::leaf)]
+    duty: f32,
+    /// Signal driving the duty cycle.
+    ///
+    /// The duty cycle is `duty + gain * signal` (signal in V), e.g. to drive a
+    /// heater from a slow control loop. The duty cycle is 0 while an auxiliary
+    /// input is unavailable. `null` for a fixed duty cycle.
+    #[tree(with=miniconf::leaf)]
+    source: Option<PwmSource>,
+    /// Duty cycle change per signal voltage (1/V)
+    #[tree(with=miniconf::leaf)]
+    gain: f32,
+}
 
-        let mut network = NetworkUsers::new(
-            stabilizer.network_devices.stack,
-            stabilizer.network_devices.phy,
-            clock,
-            env!("CARGO_BIN_NAME"),
-            &stabilizer.settings.net,
-            stabilizer.metadata,
-        );
+impl PwmChannel {
+    /// The duty cycle for the given DAC outputs and auxiliary inputs.
+    fn duty(&self, dac: [f32; 2], aux: [f32; AUX_CHANNELS]) -> f32 {
+        let signal = match self.source {
+            Some(PwmSource::Dac(ch)) => dac.get(ch).copied(),
+            Some(PwmSource::Aux(ch)) => aux.get(ch).copied(),
+            None => None,
+        };
+        // NaN maps to a duty cycle of 0.
+        match signal {
+            Some(signal) => self.duty + self.gain * signal,
+            None => self.duty,
+        }
+    }
+}
 
-        let generator = network.configure_streaming(stream::Format::AdcDacData);
+/// Shutter/relay output configuration
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Shutter {
+    /// PWM output used as the shutter output (0 or 1)
+    output: usize,
+    /// Invert the polarity: the output is low while open
+    invert: bool,
+    /// Delay from the end of the failsafe state to opening (ms)
+    open_delay: u32,
+    /// Delay from the start of the failsafe state to closing (ms)
+    close_delay: u32,
+}
 
-        let shared = Shared {
-            usb: stabilizer.usb,
+/// Shutter/relay output state
+#[derive(Copy, Clone, Debug, Default)]
+struct ShutterState {
+    open: bool,
+    /// Time since the requested state differs (ms)
+    pending: u32,
+}
+
+impl ShutterState {
+    /// Update the state once per millisecond.
+    ///
+    /// # Args
+    /// * `config` - The shutter configuration.
+    /// * `failsafe` - The outputs are in the failsafe state.
+    ///
+    /// # Returns
+    /// The output level.
+    fn update(&mut self, config: &Shutter, failsafe: bool) -> bool {
+        if self.open == failsafe {
+            self.pending += 1;
+            let delay = if failsafe {
+                config.close_delay
+            } else {
+                config.open_delay
+            };
+            if self.pending > delay {
+                self.open = !failsafe;
+                self.pending = 0;
+            }
+        } else {
+            self.pending = 0;
+        }
+        self.open != config.invert
+    }
+}
+
+/// PWM outputs configuration
+///
+/// The PWM outputs are the LVDS6/LVDS7 signals of the EEM connector with the
+/// `Gpio` EEM population variant. Their duty cycle is 0 while the outputs are
+/// in the failsafe state.
+#[derive(Copy, Clone, Debug, Tree)]
+#[tree(meta(doc, typename))]
+pub struct PwmConfig {
+    /// PWM frequency (Hz) common to both outputs, at most a few kHz
+    #[tree(with=miniconf::leaf)]
+    frequency: f32,
+    /// PWM output configuration
+    ch: [PwmChannel; 2],
+    /// Shutter/relay output.
+    ///
+    /// The selected output is a static level following the output enable
+    /// state instead of a PWM signal: it opens (high unless inverted) while the
+    /// outputs are enabled and closes while they are in the failsafe state
+    /// (interlock trip or broker connection timeout), each after the
+    /// respective delay, e.g. to drive a shutter or an RF switch. The output
+    /// is closed at startup. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    shutter: Option<Shutter>,
+}
+
+impl Default for PwmConfig {
+    fn default() -> Self {
+        Self {
+            frequency: 1e3,
+            ch: Default::default(),
+            shutter: None,
+        }
+    }
+}
+
+/// Input noise floor report of one AFE gain
+#[derive(Serialize)]
+struct NoiseFloorTelemetry {
+    gain: Gain,
+    /// Input-referred RMS noise of input 0/1 in V
+    rms: [f32; 2],
+    /// Band width in Hz
+    resolution: f32,
+    /// Input-referred amplitude spectral density of input 0/1 in dB relative
+    /// to 1 V/sqrt(Hz) in bands of equal width, starting at DC
+    asd: [[i16; NOISE_BANDS]; 2],
+}
+
+/// Input offset calibration state
+#[derive(Copy, Clone, Debug, Serialize)]
+enum Calibration {
+    /// Short both inputs and confirm
+    ShortInputs,
+    /// The confirmation timed out without a change
+    TimedOut,
+    /// The offsets were measured and stored
+    Done,
+    /// The calibration was removed
+    Cleared,
+}
+
+/// Input offset calibration report
+#[derive(Serialize)]
+struct CalibrationTelemetry {
+    calibration: Calibration,
+    /// Input-referred offsets of input 0/1 in V at the gains G1, G2, G5 and
+    /// G10
+    offset: [[f32; 4]; 2],
+}
+
+impl CalibrationTelemetry {
+    fn new(calibration: Calibration, input: &InputCalibration) -> Self {
+        let gains = [Gain::G1, Gain::G2, Gain::G5, Gain::G10];
+        Self {
+            calibration,
+            offset: core::array::from_fn(|ch| {
+                gains.map(|gain| {
+                    input.offset(ch, gain) as f32 * AdcCode::VOLT_PER_LSB
+                        / gain.gain()
+                })
+            }),
+        }
+    }
+}
+
+/// Loopback latency report
+#[derive(Serialize)]
+struct LoopbackTelemetry {
+    /// Delay statistics in samples
+    steps: LoopbackResult,
+    /// Mean delay in samples
+    mean: f32,
+    /// Standard deviation of the delay (jitter) in samples
+    jitter: f32,
+    /// Mean delay in seconds
+    delay: f32,
+}
+
+/// Amplitude spectral density report
+#[derive(Serialize)]
+struct SpectrumTelemetry<'a> {
+    /// Frequency bin spacing in Hz
+    resolution: f32,
+    /// Amplitude spectral density in dB relative to 1 V/sqrt(Hz), starting at DC
+    asd: &'a [i16],
+}
+
+#[derive(Clone, Debug, Tree)]
+#[tree(meta(doc, typename))]
+pub struct DualIir {
+    /// Channel configuration
+    ch: [Channel; 2],
+    /// Trigger both signal sources
+    #[tree(with=miniconf::leaf)]
+    trigger: bool,
+    /// Telemetry output period in seconds.
+    #[tree(with=miniconf::leaf)]
+    telemetry_period: f32,
+    /// Clear the latched faults reported in telemetry (`faults`).
+    ///
+    /// The fault counters are retained.
+    #[tree(with=miniconf::leaf)]
+    clear_faults: bool,
+    /// Channel signal routing.
+    ///
+    /// `Series` cascades the two channels into a single filter chain, e.g. to
+    /// implement deeper filters or a plant model in the loop: DAC0 outputs
+    /// channel 0 and DAC1 outputs the cascade.
+    #[tree(with=miniconf::leaf)]
+    route: Route,
+    /// DAC output routing matrix.
+    ///
+    /// If set, each DAC outputs the sum of its terms (at most 4) instead of
+    /// the processing output and the signal generator of its channel, e.g.
+    /// `[[{"source": {"Chain": 0}, "gain": 1}, {"source": {"Generator": 1},
+    /// "gain": 0.5}], [{"source": "Constant", "gain": -2.5}]]`. The digital
+    /// input gate does not apply. The monitor output, the failsafe state and
+    /// measurements take precedence. There is no lock-in or scan source in
+    /// this application. `null` for the fixed routing.
+    #[tree(with=miniconf::leaf)]
+    dac_route: Option<[heapless::Vec<DacTerm, DAC_TERMS>; 2]>,
+    /// Monitor output gain (V/V).
+    ///
+    /// If set, DAC1 is used as a monitor output mirroring the channel 0 probe
+    /// signal scaled by this gain. `null` for normal operation.
+    #[tree(with=miniconf::leaf)]
+    monitor: Option<f32>,
+    /// UDP stream target and framing.
+    stream: stream::Config,
+    /// External frequency reference (Hz) on DI0.
+    ///
+    /// If set, the sampling timebase is measured against the reference. The
+    /// fractional frequency offset of the timebase (positive if fast) is
+    /// published on `/timebase` every telemetry period. It is used to correct
+    /// the signal generator frequencies when they are triggered and allows
+    /// relating stream sample numbers to the reference. The role of DI0 is
+    /// ignored while a timebase is set. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    timebase: Option<f32>,
+    /// Spectrum telemetry
+    spectrum: SpectrumConfig,
+    /// Input noise floor characterization
+    noise_floor: NoiseFloorConfig,
+    /// Loopback latency measurement
+    loopback: LoopbackConfig,
+    /// Input offset calibration
+    calibration: CalibrationConfig,
+    /// Scheduled maintenance actions
+    schedule: ScheduleConfig,
+    /// WebSocket live data decimation.
+    ///
+    /// If set, the probe signals of both channels in volts, averaged over this
+    /// number of sample batches, are sent to the WebSocket client of the HTTP
+    /// server (`GET /live`) as pairs of little-endian `f32`. Samples are
+    /// dropped if the client does not keep up. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    live: Option<u32>,
+    /// Reject invalid biquad coefficients.
+    ///
+    /// Biquad coefficients are analyzed when settings are applied. The
+    /// analysis (pole radius, DC gain, peak gain, output bound) of every stage
+    /// that is unstable, has invalid output limits, or can saturate for a full
+    /// range input is published on `/biquad`. If set, the coefficients of a
+    /// channel with an unstable stage or invalid limits are not applied and
+    /// the previous coefficients remain active.
+    #[tree(with=miniconf::leaf)]
+    reject_invalid: bool,
+    /// Enter a low-power standby while the outputs and streaming are disabled.
+    ///
+    /// The outputs are disabled while both channels are held (`run` is
+    /// `Hold`). Streaming is disabled while the stream target is unspecified
+    /// and live data and the spectrum are disabled. In standby, sampling
+    /// stops: the converters idle, the processing does not run, the outputs
+    /// remain at their last value and the signal generators stop. The network
+    /// remains active for wake-up. Measurements resume sampling while they
+    /// run. The core clock is not reduced since the network, timer and
+    /// converter clocks derive from it. The state is reported as `standby` in
+    /// the telemetry.
+    #[tree(with=miniconf::leaf)]
+    standby: bool,
+    /// Loop group delay evaluation frequency (Hz).
+    ///
+    /// The end-to-end group delay of each channel at this frequency is
+    /// published on `/delay` when settings are applied: the delay from ADC
+    /// sampling to DAC output for the sample rate and batch size and the
+    /// group delay of the DC blocker, biquads and all-pass sections. The
+    /// analog front end and output filters are not included.
+    #[tree(with=miniconf::leaf)]
+    delay_frequency: f32,
+    /// Publish a preview of the signal generator waveform of an output channel.
+    ///
+    /// One period of a periodic signal generator waveform of the channel is
+    /// published on `/preview` as 64 points (V at the DAC output), starting at
+    /// the initial phase. Sweeps and noise are published as their first 64
+    /// samples. The outputs are not affected. Cleared once published.
+    #[tree(with=miniconf::leaf)]
+    preview: Option<usize>,
+    /// Pounder RF configuration.
+    ///
+    /// Applied if Pounder is detected.
+    pounder: PounderConfig,
+    /// Interlock
+    ///
+    /// While tripped both DAC outputs are driven to 0 V. The RF input
+    /// power signals are those of Pounder.
+    interlock: InterlockConfig,
+    /// Digital input roles and polarities.
+    ///
+    /// By default DI0/DI1 hold channel 0/1 while low if its run mode is
+    /// `External`. A `LineSync` input is the hum rejection phase reference.
+    /// `Profile` roles select the biquad presets (`ch/n/preset`).
+    di: [DigitalInputConfig; DIGITAL_INPUTS],
+    /// PWM outputs
+    pwm: PwmConfig,
+    /// Sampling delay of channel 1 relative to channel 0 in timer ticks
+    /// (10 ns).
+    ///
+    /// The ADC1 and DAC1 conversion instants are delayed, e.g. to stagger or
+    /// align actuator updates. The delay is limited to leave time for the ADC1
+    /// transfer within the sample period (61 ticks).
+    #[tree(with=miniconf::leaf)]
+    skew: u32,
+    /// Auxiliary analog inputs.
+    ///
+    /// An external ADS1115 ADC on the I2C bus provides four slow single-ended
+    /// inputs. The enabled inputs are converted in turn every 10 ms. Their
+    /// voltages are published on `/aux` every telemetry period and are
+    /// available as interlock signals and PWM duty cycle sources.
+    aux: AuxConfig,
+}
+
+impl Default for DualIir {
+    fn default() -> Self {
+        Self {
+            telemetry_period: 10.0,
+            clear_faults: false,
+            trigger: false,
+            route: Route::Parallel,
+            dac_route: None,
+            monitor: None,
+            stream: Default::default(),
+            timebase: None,
+            spectrum: Default::default(),
+            noise_floor: Default::default(),
+            loopback: Default::default(),
+            calibration: Default::default(),
+            schedule: Default::default(),
+            live: None,
+            reject_invalid: false,
+            standby: false,
+            delay_frequency: 1e3,
+            preview: None,
+            pounder: Default::default(),
+            interlock: Default::default(),
+            di: core::array::from_fn(|ch| DigitalInputConfig {
+                role: Role::Hold(ch),
+                invert: true,
+            }),
+            pwm: Default::default(),
+            skew: 0,
+            aux: Default::default(),
+            ch: Default::default(),
+        }
+    }
+}
+
+/// Logarithmic conversion slope in volt at the ADC per dB
+const LOG_SLOPE: f32 = 0.1;
+
+impl DualIir {
+    /// The effective digital input configuration.
+    ///
+    /// DI0 has no role while it is the timebase reference.
+    fn di(&self) -> [DigitalInputConfig; DIGITAL_INPUTS] {
+        let mut di = self.di;
+        if self.timebase.is_some() {
+            di[0].role = Role::None;
+        }
+        di
+    }
+}
+
+/// Convert a logarithmic conversion reference level at the AFE input to ADC LSB.
+fn log_reference(reference: f32, gain: Gain) -> f32 {
+    reference * gain.gain() * AdcCode::LSB_PER_VOLT
+}
+
+#[derive(Clone, Debug)]
+pub struct Active {
+    run: Run,
+    gain: Gain,
+    /// Input offset at the current gain in ADC LSB
+    offset: i16,
+    probe: Probe,
+    /// DC blocker gain
+    dc_block: Option<u32>,
+    dc: DcBlock,
+    /// Logarithmic conversion reference level in ADC LSB
+    log: Option<f32>,
+    /// Deadband center and half width
+    deadband: Option<(f32, f32)>,
+    /// Hum canceller parameters
+    hum: Option<HumParameters>,
+    hum_canceller: HumCanceller,
+    /// All-pass sections
+    allpass: [Option<iir::Biquad<f32>>; ALLPASS_SECTIONS],
+    allpass_state: [[f32; 4]; ALLPASS_SECTIONS],
+    /// Kalman filter gain
+    kalman_gain: Option<[f32; 3]>,
+    kalman: Kalman,
+    /// Smith predictor plant model
+    smith_model: Option<PlantModel>,
+    smith: SmithPredictor,
+    biquad: [iir::Biquad<f32>; IIR_CASCADE_LENGTH],
+    state: [[f32; 4]; IIR_CASCADE_LENGTH],
+    /// Previous biquad coefficients during a cross-fade
+    previous: [iir::Biquad<f32>; IIR_CASCADE_LENGTH],
+    previous_state: [[f32; 4]; IIR_CASCADE_LENGTH],
+    /// Remaining cross-fade samples
+    fade: u32,
+    /// Cross-fade length
+    crossfade: u32,
+    /// Remaining samples to hold after a gain change
+    blank: u32,
+    /// Biquad coefficients of the regular configuration and the presets
+    profiles: [Option<[iir::Biquad<f32>; IIR_CASCADE_LENGTH]>; PRESETS + 1],
+    /// Selected profile
+    profile: usize,
+    source: Source,
+    /// Signal generator state to restart from on a trigger input
+    restart: Source,
+    /// Amplitude modulation source and depth (1/V)
+    am: Option<(AmSource, f32)>,
+    /// Amplitude modulation signal generator
+    am_source: Source,
+    /// Amplitude modulation signal generator state to restart from
+    am_restart: Source,
+    /// Noise shaping error feedback pole
+    noise_shaping: Option<f32>,
+    shaper: NoiseShaper,
+    /// User-configurable processing chain
+    graph: Option<Graph>,
+}
+
+impl Active {
+    /// Update the biquad coefficients.
+    ///
+    /// If cross-fading is enabled, the previous coefficients continue to be
+    /// evaluated in parallel and the output is faded to the new coefficients.
+    fn set_biquad(&mut self, biquad: [iir::Biquad<f32>; IIR_CASCADE_LENGTH]) {
+        if self.crossfade > 0 {
+            self.previous = core::mem::replace(&mut self.biquad, biquad);
+            self.previous_state = self.state;
+            self.fade = self.crossfade;
+        } else {
+            self.biquad = biquad;
+            self.fade = 0;
+        }
+    }
+
+    /// Select the biquad coefficients of a profile.
+    ///
+    /// Unavailable profiles are ignored.
+    fn select(&mut self, profile: usize) {
+        if profile == self.profile {
+            return;
+        }
+        if let Some(Some(biquad)) = self.profiles.get(profile) {
+            let biquad = biquad.clone();
+            self.profile = profile;
+            self.set_biquad(biquad);
+        }
+    }
+
+    /// Update the input offset.
+    fn set_offset(&mut self, offset: i16) {
+        self.offset = offset;
+    }
+
+    /// Update the AFE gain.
+    ///
+    /// On a change, the states are rescaled to the new gain and the channel is
+    /// held for the given number of samples.
+    fn set_gain(&mut self, gain: Gain, blanking: u32) {
+        let ratio = gain.gain() / self.gain.gain();
+        if ratio == 1.0 {
+            return;
+        }
+        self.gain = gain;
+        self.dc.scale(ratio);
+        // Logarithmic conversion references are scaled with the gain: the
+        // converted input is independent of the gain.
+        if self.log.is_none() {
+            for state in [&mut self.state[0], &mut self.previous_state[0]] {
+                state[0] *= ratio;
+                state[1] *= ratio;
+            }
+        }
+        self.blank = blanking;
+    }
+
+    /// Process a batch of a channel.
+    ///
+    /// The stage configuration is resolved once per batch such that disabled
+    /// stages stay out of the sample loops. The stages are inlined into the
+    /// processing task in ITCM.
+    ///
+    /// # Args
+    /// * `adc` - The ADC codes.
+    /// * `source` - The signal generator output in DAC LSB.
+    /// * `hold` - Whether the digital inputs with the `Hold` role are asserted.
+    /// * `gate` - Whether the digital inputs with the `Gate` role are asserted.
+    /// * `link` - The channel 0 processing output with series routing.
+    /// * `deadband` - The deadband occupancy.
+    ///
+    /// # Returns
+    /// The processing output in DAC LSB, the DAC output and the probed signal.
+    #[inline(always)]
+    fn process(
+        &mut self,
+        adc: &[u16; BATCH_SIZE],
+        source: &[i16; BATCH_SIZE],
+        hold: bool,
+        gate: bool,
+        link: Option<[f32; BATCH_SIZE]>,
+        deadband: &mut Occupancy,
+    ) -> ([f32; BATCH_SIZE], [i16; BATCH_SIZE], [f32; BATCH_SIZE]) {
+        let blanked = (self.blank as usize).min(BATCH_SIZE);
+        self.blank -= blanked as u32;
+        let run = self.run.run(hold);
+        let offset = self.offset as i32;
+        let input = adc.map(|x| x as i16 as i32 - offset);
+        if let Some(y) =
+            self.process_graph(&input, source, blanked, run, gate, link)
+        {
+            let probe = match self.probe {
+                Probe::Input => input.map(|x| x as f32),
+                Probe::Source => source.map(|s| s as f32),
+                _ => y.map(|y| y as f32),
+            };
+            return (y.map(|y| y as f32), y, probe);
+        }
+
+        let mut x = self.dc_block(&input, blanked);
+        self.cancel_hum(&mut x);
+        if let Some(link) = link {
+            x = link;
+        } else {
+            self.convert_log(&mut x);
+        }
+        self.estimate(&mut x);
+        let (output, tap) = self.filter(&mut x, run, blanked, deadband);
+        let y = self.quantize(&output);
+        let y = if gate {
+            core::array::from_fn(|i| y[i].saturating_add(source[i]))
+        } else {
+            y
+        };
+        let probe = match self.probe {
+            Probe::Input => x,
+            Probe::Biquad(_) => tap,
+            Probe::Source => source.map(|s| s as f32),
+            Probe::Output => y.map(|y| y as f32),
+        };
+        (output, y, probe)
+    }
+
+    /// Process a batch through the user-configurable chain.
+    ///
+    /// The chain input is in volts at the ADC, or the channel 0 output with
+    /// series routing. While blanked or held, the output is held.
+    ///
+    /// # Returns
+    /// The output in DAC LSB or `None` without a chain.
+    #[inline(always)]
+    fn process_graph(
+        &mut self,
+        input: &[i32; BATCH_SIZE],
+        source: &[i16; BATCH_SIZE],
+        blanked: usize,
+        run: bool,
+        gate: bool,
+        link: Option<[f32; BATCH_SIZE]>,
+    ) -> Option<[i16; BATCH_SIZE]> {
+        let graph = self.graph.as_mut()?;
+        Some(core::array::from_fn(|i| {
+            let x = match link {
+                Some(link) => link[i] * DacCode::VOLT_PER_LSB,
+                None => input[i] as f32 * AdcCode::VOLT_PER_LSB,
+            };
+            let y = if i < blanked || !run {
+                graph.output()
+            } else {
+                graph.update(x, source[i] as f32 * DacCode::VOLT_PER_LSB, gate)
+            };
+            // The float to integer conversion saturates.
+            (y * DacCode::LSB_PER_VOLT) as i16
+        }))
+    }
+
+    /// Remove the DC component of the input.
+    ///
+    /// The DC estimate is frozen while blanked.
+    #[inline(always)]
+    fn dc_block(
+        &mut self,
+        input: &[i32; BATCH_SIZE],
+        blanked: usize,
+    ) -> [f32; BATCH_SIZE] {
+        let Some(gain) = self.dc_block else {
+            return input.map(|x| x as f32);
+        };
+        core::array::from_fn(|i| {
+            let x = if i < blanked {
+                input[i] - self.dc.dc()
+            } else {
+                self.dc.update(input[i], gain)
+            };
+            x as f32
+        })
+    }
+
+    /// Cancel the line frequency hum.
+    #[inline(always)]
+    fn cancel_hum(&mut self, x: &mut [f32; BATCH_SIZE]) {
+        if let Some(hum) = &self.hum {
+            for x in x.iter_mut() {
+                *x = self.hum_canceller.update(*x, hum);
+            }
+        }
+    }
+
+    /// Convert the input to a logarithmic scale.
+    #[inline(always)]
+    fn convert_log(&self, x: &mut [f32; BATCH_SIZE]) {
+        if let Some(reference) = self.log {
+            for x in x.iter_mut() {
+                *x = decibel(*x, reference, 1.0)
+                    * (LOG_SLOPE * AdcCode::LSB_PER_VOLT);
+            }
+        }
+    }
+
+    /// Estimate the input with the Kalman filter.
+    #[inline(always)]
+    fn estimate(&mut self, x: &mut [f32; BATCH_SIZE]) {
+        if let Some(gain) = &self.kalman_gain {
+            for x in x.iter_mut() {
+                *x = self.kalman.update(*x, gain);
+            }
+        }
+    }
+
+    /// Filter the input through the deadband, the biquad cascade with the
+    /// cross-fade and the all-pass sections.
+    ///
+    /// The Smith predictor feedback closes a loop around the filters, sample
+    /// by sample. The input is replaced by the filter input after the
+    /// feedback and the deadband.
+    ///
+    /// # Returns
+    /// The output and the probed biquad output.
+    #[inline(always)]
+    fn filter(
+        &mut self,
+        x: &mut [f32; BATCH_SIZE],
+        run: bool,
+        blanked: usize,
+        deadband: &mut Occupancy,
+    ) -> ([f32; BATCH_SIZE], [f32; BATCH_SIZE]) {
+        let allpass = self.allpass.iter().any(Option::is_some);
+        let mut tap = [0.0; BATCH_SIZE];
+        let mut y = [0.0; BATCH_SIZE];
+        for (i, ((x, tap), y)) in x
+            .iter_mut()
+            .zip(tap.iter_mut())
+            .zip(y.iter_mut())
+            .enumerate()
+        {
+            let blank = i < blanked;
+            if let Some(model) = &self.smith_model {
+                *x = self.smith.feedback(*x, model);
+            }
+            if let Some(band) = self.deadband {
+                *x = apply_deadband(*x, band, deadband);
+            }
+            *y = self.biquad(*x, run, blank, tap);
+            *y = self.fade_previous(*x, *y, run, blank);
+            if allpass {
+                *y = self.allpass(*y, blank);
+            }
+            if let Some(model) = &self.smith_model {
+                self.smith.update(*y, model);
+            }
+        }
+        (y, tap)
+    }
+
+    /// Evaluate the biquad cascade.
+    ///
+    /// While blanked, the stage outputs are held. While not running, the
+    /// states are held.
+    #[inline(always)]
+    fn biquad(&mut self, x: f32, run: bool, blank: bool, tap: &mut f32) -> f32 {
+        let probe = self.probe;
+        self.biquad
+            .iter()
+            .zip(self.state.iter_mut())
+            .enumerate()
+            .fold(x, |y, (i, (ch, state))| {
+                let filter = if run { ch } else { &iir::Biquad::HOLD };
+                // Hold the last stage output.
+                let y = if blank {
+                    state[2]
+                } else {
+                    filter.update(state, y)
+                };
+                if probe == Probe::Biquad(i) {
+                    *tap = y;
+                }
+                y
+            })
+    }
+
+    /// Cross-fade from the previous biquad cascade output during a
+    /// coefficient update.
+    #[inline(always)]
+    fn fade_previous(&mut self, x: f32, y: f32, run: bool, blank: bool) -> f32 {
+        if self.fade == 0 {
+            return y;
+        }
+        let y0 = self
+            .previous
+            .iter()
+            .zip(self.previous_state.iter_mut())
+            .fold(x, |y, (ch, state)| {
+                let filter = if run { ch } else { &iir::Biquad::HOLD };
+                if blank {
+                    state[2]
+                } else {
+                    filter.update(state, y)
+                }
+            });
+        self.fade -= 1;
+        let a = self.fade as f32 / self.crossfade as f32;
+        y + a * (y0 - y)
+    }
+
+    /// Evaluate the all-pass sections.
+    #[inline(always)]
+    fn allpass(&mut self, y: f32, blank: bool) -> f32 {
+        self.allpass.iter().zip(self.allpass_state.iter_mut()).fold(
+            y,
+            |y, (ap, state)| match ap {
+                Some(_) if blank => state[2],
+                Some(ap) => ap.update(state, y),
+                None => y,
+            },
+        )
+    }
+
+    /// Quantize the output to DAC LSB, with noise shaping if enabled.
+    #[inline(always)]
+    fn quantize(&mut self, y: &[f32; BATCH_SIZE]) -> [i16; BATCH_SIZE] {
+        if let Some(pole) = self.noise_shaping {
+            y.map(|y| self.shaper.update(y, pole))
+        } else {
+            // Note(unsafe): The filter limits must ensure that the value is in range.
+            // The truncation introduces 1/2 LSB distortion.
+            y.map(|y| unsafe { y.to_int_unchecked() })
+        }
+    }
+}
+
+/// Apply the error deadband `(center, width)` and record its occupancy.
+#[inline(always)]
+fn apply_deadband(
+    x: f32,
+    (center, width): (f32, f32),
+    occupancy: &mut Occupancy,
+) -> f32 {
+    let e = x - center;
+    let inside = -width <= e && e <= width;
+    occupancy.update(inside);
+    if inside {
+        center
+    } else if e > 0.0 {
+        x - width
+    } else {
+        x + width
+    }
+}
+
+/// The nominal number of timer ticks between timestamps of an external reference.
+fn timebase_period(frequency: f32) -> f32 {
+    TIMEBASE_PRESCALER
+        / (frequency * stabilizer::design_parameters::TIMER_PERIOD)
+}
+
+#[cfg(not(target_os = "none"))]
+fn main() {
+    use miniconf::{json::to_json_value, json_schema::TreeJsonSchema};
+    let s = Settings::default();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&to_json_value(&s).unwrap()).unwrap()
+    );
+    let mut schema = TreeJsonSchema::new(Some(&s)).unwrap();
+    schema
+        .root
+        .insert("title".to_string(), "Stabilizer dual-iir".into());
+    println!("{}", serde_json::to_string_pretty(&schema.root).unwrap());
+}
+
+#[cfg(target_os = "none")]
+#[cfg_attr(target_os = "none", rtic::app(device = stabilizer::hardware::hal::stm32, peripherals = true, dispatchers=[DCMI, JPEG, LTDC, SDMMC]))]
+mod app {
+    use super::*;
+    use core::sync::atomic::{Ordering, fence};
+    use fugit::ExtU32 as _;
+    use rtic_monotonics::Monotonic;
+
+    use heapless::spsc::{Consumer, Producer, Queue};
+    use platform::{ConnectionWatchdog, DigitalInputs, Inputs, Interlock};
+    use stabilizer::{
+        aux_adc::AuxInputs,
+        hardware::{
+            self, AuxAdc, DigitalInput0, DigitalInput1, Eem, Pgia,
+            SerialTerminal, SystemTimer, Systick, UsbDevice, UsbEthernet,
+            adc::{Adc0Input, Adc1Input},
+            dac::{Dac0Output, Dac1Output},
+            hal,
+            input_stamper::InputStamper,
+            net::{NetworkState, NetworkUsers},
+            pounder::{
+                Channel as PounderChannel, DdsUpdates, FaultEvent, Peripheral,
+                control::PounderControl, dds_output::DdsOutput,
+                sync_phase::SyncPhase,
+            },
+            pwm::Pwm,
+            setup::Mezzanine,
+            timers::{self, SamplingTimer},
+        },
+        telemetry::TelemetryBuffer,
+    };
+    use stream::FrameGenerator;
+
+    #[cfg(not(feature = "pounder_v1_0"))]
+    use stabilizer::hardware::pounder::timestamp::Timestamper as PounderStamper;
+
+    // Queue of spectrum bins to be streamed.
+    const SPECTRUM_QUEUE_SIZE: usize = SPECTRUM_SIZE / 2 + 1;
+
+    // Queue of live data samples for the WebSocket client.
+    const LIVE_QUEUE_SIZE: usize = 128;
+
+    #[shared]
+    struct Shared {
+        usb: UsbDevice,
+        network: NetworkUsers<DualIir>,
+        settings: Settings,
+        active: [Active; 2],
+        stream_format: stream::Format,
+        monitor: Option<f32>,
+        route: Route,
+        dac_route: Option<[heapless::Vec<DacTerm, DAC_TERMS>; 2]>,
+        timebase: Option<Timebase>,
+        /// Pounder DDS clock phase
+        sync_phase: Option<SyncPhase>,
+        spectrum_channel: Option<usize>,
+        capture: Capture,
+        noise: NoiseRecord,
+        /// Loopback output and input channels and measurement
+        loopback: (usize, usize, Loopback),
+        /// Input offset calibration and whether it is to be persisted
+        calibration: (InputCalibration, bool),
+        live: Option<u32>,
+        failsafe: bool,
+        telemetry: TelemetryBuffer,
+        pounder: Option<PounderControl>,
+        dds: Option<DdsOutput>,
+        /// DDS updates written by the processing task
+        dds_scan: DdsUpdates,
+        interlock: Interlock,
+        di: [DigitalInputConfig; DIGITAL_INPUTS],
+        pwm: Option<Pwm>,
+        pwm_channels: [PwmChannel; 2],
+        /// Shutter output index and level
+        shutter: Option<(usize, bool)>,
+        aux: [f32; AUX_CHANNELS],
+        faults: Faults,
+        /// Publish a retained telemetry copy with the next report
+        snapshot: bool,
+        /// The low-power standby is active
+        standby: bool,
+        sampling_timer: SamplingTimer,
+        afes: [Pgia; 2],
+    }
+
+    #[local]
+    struct Local {
+        usb_terminal: SerialTerminal<Settings>,
+        usb_ethernet: UsbEthernet,
+        digital_inputs: (DigitalInput0, DigitalInput1),
+        adcs: (Adc0Input, Adc1Input),
+        dacs: (Dac0Output, Dac1Output),
+        generator: FrameGenerator,
+        cpu_temp_sensor: stabilizer::hardware::cpu_temp_sensor::CpuTempSensor,
+        timestamper: InputStamper,
+        spectrum: Spectrum,
+        spectrum_producer: Producer<'static, [f32; 4], SPECTRUM_QUEUE_SIZE>,
+        spectrum_consumer: Consumer<'static, [f32; 4], SPECTRUM_QUEUE_SIZE>,
+        live_producer: Producer<'static, [f32; 2], LIVE_QUEUE_SIZE>,
+        live_consumer: Consumer<'static, [f32; 2], LIVE_QUEUE_SIZE>,
+        pounder_detected: bool,
+        aux_inputs: Option<AuxInputs<AuxAdc>>,
+        aux_detected: bool,
+        #[cfg(not(feature = "pounder_v1_0"))]
+        pounder_stamper: Option<PounderStamper>,
+    }
+
+    #[init]
+    fn init(c: init::Context) -> (Shared, Local) {
+        let clock = SystemTimer::new(|| Systick::now().ticks());
+
+        // Configure the microcontroller
+        let (mut stabilizer, mezzanine, eem) = hardware::setup::setup::<Settings>(
+            c.core,
+            c.device,
+            clock,
+            BATCH_SIZE,
+            SAMPLE_TICKS,
+        );
+
+        // Safe boot: The outputs are held at zero.
+        if stabilizer.network_devices.safe_boot {
+            for ch in stabilizer.settings.dual_iir.ch.iter_mut() {
+                ch.run = Run::Hold;
+            }
+        }
+
+        let mut network = NetworkUsers::new(
+            stabilizer.network_devices.stack,
+            stabilizer.network_devices.phy,
+            stabilizer.network_devices.nonce,
+            clock,
+            env!("CARGO_BIN_NAME"),
+            &stabilizer.settings.net,
+            stabilizer.metadata,
+            stabilizer.network_devices.provision,
+            stabilizer.network_devices.safe_boot,
+        );
+
+        let generator = network.configure_streaming(stream::Format::AdcDacData);
+
+        let (spectrum_producer, spectrum_consumer) = cortex_m::singleton!(
+            : Queue<[f32; 4], SPECTRUM_QUEUE_SIZE> = Queue::new())
+        .unwrap()
+        .split();
+
+        let (live_producer, live_consumer) = cortex_m::singleton!(
+            : Queue<[f32; 2], LIVE_QUEUE_SIZE> = Queue::new())
+        .unwrap()
+        .split();
+
+        let (pounder, dds, stamper) = match mezzanine {
+            Mezzanine::Pounder(pounder) => {
+                #[cfg(not(feature = "pounder_v1_0"))]
+                let stamper = Some(pounder.timestamper);
+                #[cfg(feature = "pounder_v1_0")]
+                let stamper: Option<()> = None;
+                (
+                    pounder.pounder.map(PounderControl::new),
+                    Some(pounder.dds_output),
+                    stamper,
+                )
+            }
+            Mezzanine::None => (None, None, None),
+        };
+
+        let pwm = match eem {
+            Eem::Gpio(gpio) => Some(gpio.pwm),
+            _ => None,
+        };
+
+        let shared = Shared {
+            usb: stabilizer.usb,
             network,
             active: stabilizer
                 .settings
@@ -285,19 +1921,56 @@ mod app {
                 .ch
                 .each_ref()
                 .map(|a| a.build().unwrap()),
+            stream_format: stabilizer.settings.dual_iir.stream.format,
+            monitor: stabilizer.settings.dual_iir.monitor,
+            route: stabilizer.settings.dual_iir.route,
+            dac_route: stabilizer.settings.dual_iir.dac_route.clone(),
+            timebase: None,
+            sync_phase: stamper.is_some().then(|| SyncPhase::new(SYNC_TICKS)),
+            spectrum_channel: stabilizer.settings.dual_iir.spectrum.channel,
+            capture: Capture::default(),
+            noise: NoiseRecord::default(),
+            loopback: (0, 0, Loopback::default()),
+            calibration: (stabilizer.calibration, false),
+            live: stabilizer.settings.dual_iir.live,
+            failsafe: false,
             telemetry: TelemetryBuffer::default(),
+            pounder,
+            dds,
+            dds_scan: DdsUpdates::default(),
+            interlock: Interlock::default(),
+            di: stabilizer.settings.dual_iir.di(),
+            pwm,
+            pwm_channels: stabilizer.settings.dual_iir.pwm.ch,
+            shutter: None,
+            aux: [f32::NAN; AUX_CHANNELS],
+            faults: Faults::default(),
+            snapshot: false,
+            standby: false,
+            sampling_timer: stabilizer.sampling_timer,
+            afes: stabilizer.afes,
             settings: stabilizer.settings,
         };
 
         let mut local = Local {
             usb_terminal: stabilizer.usb_serial,
-            sampling_timer: stabilizer.sampling_timer,
+            usb_ethernet: stabilizer.usb_ethernet,
             digital_inputs: stabilizer.digital_inputs,
-            afes: stabilizer.afes,
             adcs: stabilizer.adcs,
             dacs: stabilizer.dacs,
             generator,
             cpu_temp_sensor: stabilizer.temperature_sensor,
+            timestamper: stabilizer.input_stamper,
+            spectrum: Spectrum::new(),
+            spectrum_producer,
+            spectrum_consumer,
+            live_producer,
+            live_consumer,
+            pounder_detected: shared.dds.is_some(),
+            aux_detected: stabilizer.aux_adc.is_some(),
+            aux_inputs: stabilizer.aux_adc.map(AuxInputs::new),
+            #[cfg(not(feature = "pounder_v1_0"))]
+            pounder_stamper: stamper,
         };
 
         // Enable ADC/DAC events
@@ -306,21 +1979,48 @@ mod app {
         local.dacs.0.start();
         local.dacs.1.start();
 
+        // Start timestamping external reference edges on DI0. Without filtering and with
+        // prescaling the timestamper supports reference frequencies up to about 10 MHz.
+        stabilizer.timestamp_timer.start();
+        local
+            .timestamper
+            .configure(timers::InputFilter::Div1N1, timers::Prescaler::Div8);
+        local.timestamper.start();
+        #[cfg(not(feature = "pounder_v1_0"))]
+        if let Some(stamper) = &mut local.pounder_stamper {
+            stamper.start();
+        }
+
         // Spawn a settings update for default settings.
         settings_update::spawn().unwrap();
         telemetry::spawn().unwrap();
         ethernet_link::spawn().unwrap();
+        watchdog::spawn().unwrap();
         usb::spawn().unwrap();
         start::spawn().unwrap();
+        dither::spawn().unwrap();
+        shutter::spawn().unwrap();
+        pounder_service::spawn().unwrap();
+        maintenance::spawn().unwrap();
+        standby::spawn().unwrap();
+        if local.aux_inputs.is_some() {
+            aux::spawn().unwrap();
+        }
 
         (shared, local)
     }
 
-    #[task(priority = 1, local=[sampling_timer])]
-    async fn start(c: start::Context) {
+    #[task(priority = 1, shared=[sampling_timer, standby])]
+    async fn start(mut c: start::Context) {
         Systick::delay(100.millis()).await;
-        // Start sampling ADCs and DACs.
-        c.local.sampling_timer.start();
+        // Start sampling ADCs and DACs unless in standby.
+        (&mut c.shared.sampling_timer, &mut c.shared.standby).lock(
+            |timer, standby| {
+                if !*standby {
+                    timer.start()
+                }
+            },
+        );
     }
 
     /// Main DSP processing routine.
@@ -341,96 +2041,500 @@ mod app {
     /// the same time bounds, meeting one also means the other is also met.
     #[task(
         binds=DMA1_STR4,
-        local=[digital_inputs, adcs, dacs, generator, source: [[i16; BATCH_SIZE]; 2] = [[0; BATCH_SIZE]; 2]],
-        shared=[active, telemetry],
+        local=[digital_inputs, adcs, dacs, generator, timestamper, pounder_stamper, spectrum_consumer, live_producer, source: [[i16; BATCH_SIZE]; 2] = [[0; BATCH_SIZE]; 2], live_accu: ([f32; 2], u32) = ([0.0; 2], 0), di_functions: DigitalInputs = DigitalInputs::new()],
+        shared=[active, stream_format, monitor, route, dac_route, timebase, sync_phase, spectrum_channel, capture, noise, loopback, live, failsafe, telemetry, dds, dds_scan, di, pwm, pwm_channels, shutter, aux],
         priority=3)]
     #[unsafe(link_section = ".itcm.process")]
     fn process(c: process::Context) {
+        let start = cortex_m::peripheral::DWT::cycle_count();
         let process::SharedResources {
-            active, telemetry, ..
+            active,
+            stream_format,
+            monitor,
+            mut route,
+            mut dac_route,
+            mut timebase,
+            mut sync_phase,
+            mut spectrum_channel,
+            mut capture,
+            mut noise,
+            mut loopback,
+            mut live,
+            mut failsafe,
+            telemetry,
+            mut dds,
+            mut dds_scan,
+            mut di,
+            mut pwm,
+            mut pwm_channels,
+            mut shutter,
+            mut aux,
+            ..
         } = c.shared;
 
+        let failsafe = failsafe.lock(|failsafe| *failsafe);
+        let series = route.lock(|route| *route == Route::Series);
+
+        // Timestamps are expected to be overwritten between batches.
+        #[cfg(not(feature = "pounder_v1_0"))]
+        let timestamp = c.local.pounder_stamper.as_mut().and_then(|stamper| {
+            let (Ok(t) | Err(t)) = stamper.latest_timestamp();
+            t
+        });
+        #[cfg(feature = "pounder_v1_0")]
+        let timestamp = None;
+        let phase = sync_phase.lock(|sync| {
+            sync.as_mut().zip(timestamp).map(|(sync, t)| sync.update(t))
+        });
+
         let process::LocalResources {
             digital_inputs,
             adcs: (adc0, adc1),
             dacs: (dac0, dac1),
             generator,
+            timestamper,
+            spectrum_consumer,
+            live_producer,
             source,
+            live_accu,
+            di_functions,
             ..
         } = c.local;
 
-        (active, telemetry).lock(|active, telemetry| {
-            (adc0, adc1, dac0, dac1).lock(|adc0, adc1, dac0, dac1| {
-                // Preserve instruction and data ordering w.r.t. DMA flag access before and after.
-                fence(Ordering::SeqCst);
-                let adc: [&[u16; BATCH_SIZE]; 2] = [
-                    (**adc0).try_into().unwrap(),
-                    (**adc1).try_into().unwrap(),
-                ];
-                let mut dac: [&mut [u16; BATCH_SIZE]; 2] =
-                    [(*dac0).try_into().unwrap(), (*dac1).try_into().unwrap()];
-
-                for ((((adc, dac), active), di), source) in adc
-                    .into_iter()
-                    .zip(dac.iter_mut())
-                    .zip(active.iter_mut())
-                    .zip(telemetry.digital_inputs)
-                    .zip(source.iter())
+        let levels = [digital_inputs.0.is_high(), digital_inputs.1.is_high()];
+        let functions = di.lock(|di| di_functions.update(di, levels));
+
+        timebase.lock(|timebase| {
+            if let Some(timebase) = timebase {
+                // Timestamps are expected to be overwritten between batches.
+                if let Ok(Some(t)) | Err(Some(t)) =
+                    timestamper.latest_timestamp()
                 {
-                    for ((adc, dac), source) in
-                        adc.iter().zip(dac.iter_mut()).zip(source)
+                    timebase.update(t);
+                }
+            }
+        });
+
+        (active, stream_format, monitor, telemetry).lock(
+            |active, format, monitor, telemetry| {
+                // Last DAC outputs of the batch (V)
+                let mut outputs = [0.0; 2];
+                // Last processing outputs of the batch (DAC LSB)
+                let mut processed = [0.0; 2];
+                (adc0, adc1, dac0, dac1).lock(|adc0, adc1, dac0, dac1| {
+                    // Preserve instruction and data ordering w.r.t. DMA flag access before and after.
+                    fence(Ordering::SeqCst);
+                    let adc: [&[u16; BATCH_SIZE]; 2] = [
+                        (**adc0).try_into().unwrap(),
+                        (**adc1).try_into().unwrap(),
+                    ];
+                    let mut dac: [&mut [u16; BATCH_SIZE]; 2] = [
+                        (*dac0).try_into().unwrap(),
+                        (*dac1).try_into().unwrap(),
+                    ];
+                    // Probed signals in ADC (input) or DAC (all others) LSB
+                    let mut probe = [[0.0f32; BATCH_SIZE]; 2];
+                    // Processing outputs for the DAC routing
+                    let mut chain = [[0.0f32; BATCH_SIZE]; 2];
+
+                    for active in active.iter_mut() {
+                        active.select(functions.profile);
+                        if functions.line_sync {
+                            active.hum_canceller.sync();
+                        }
+                    }
+
+                    for (
+                        ch,
+                        (
+                            ((((adc, dac), active), (hold, gate)), source),
+                            (probe, deadband),
+                        ),
+                    ) in adc
+                        .into_iter()
+                        .zip(dac.iter_mut())
+                        .zip(active.iter_mut())
+                        .zip(functions.hold.into_iter().zip(functions.gate))
+                        .zip(source.iter())
+                        .zip(
+                            probe.iter_mut().zip(telemetry.deadband.iter_mut()),
+                        )
+                        .enumerate()
                     {
-                        let x = f32::from(*adc as i16);
-                        let y = active
-                            .biquad
-                            .iter()
-                            .zip(active.state.iter_mut())
-                            .fold(x, |y, (ch, state)| {
-                                let filter = if active.run.run(di) {
-                                    ch
-                                } else {
-                                    &iir::Biquad::HOLD
-                                };
-                                filter.update(state, y)
+                        let link = (series && ch == 1).then_some(chain[0]);
+                        let (output, y, probed) = active
+                            .process(adc, source, hold, gate, link, deadband);
+                        for (dac, y) in dac.iter_mut().zip(y) {
+                            *dac = DacCode::from(y).0;
+                        }
+                        *probe = probed;
+                        chain[ch] = output;
+                    }
+
+                    dac_route.lock(|route| {
+                        let Some(route) = route else {
+                            return;
+                        };
+                        let offset = active.each_ref().map(|a| a.offset as i32);
+                        for (dac, terms) in dac.iter_mut().zip(route.iter()) {
+                            for (i, dac) in dac.iter_mut().enumerate() {
+                                let chain = [chain[0][i], chain[1][i]];
+                                let source = [source[0][i], source[1][i]];
+                                let input = [0, 1].map(|ch| {
+                                    adc[ch][i] as i16 as i32 - offset[ch]
+                                });
+                                let y: f32 = terms
+                                    .iter()
+                                    .map(|t| t.value(chain, source, input))
+                                    .sum();
+                                // The float to integer conversion saturates.
+                                *dac = DacCode::from(y as i16).0;
+                            }
+                        }
+                    });
+                    processed = chain.map(|chain| chain[BATCH_SIZE - 1]);
+                    let replaced =
+                        dds_scan.lock(|updates| updates.replaced_dacs());
+                    for (dac, replaced) in dac.iter_mut().zip(replaced) {
+                        if replaced {
+                            dac.fill(DacCode::from(0i16).0);
+                        }
+                    }
+
+                    (&mut spectrum_channel, &mut capture).lock(
+                        |channel, capture| {
+                            if let Some(ch) = channel.filter(|ch| *ch < 2) {
+                                capture.update([
+                                    probe[ch].iter().sum::<f32>()
+                                        / BATCH_SIZE as f32,
+                                    source[ch]
+                                        .iter()
+                                        .map(|x| *x as f32)
+                                        .sum::<f32>()
+                                        / BATCH_SIZE as f32,
+                                ]);
+                            }
+                        },
+                    );
+
+                    live.lock(|live| {
+                        let Some(decimation) = *live else {
+                            return;
+                        };
+                        let (accu, count) = live_accu;
+                        for ((accu, probe), a) in
+                            accu.iter_mut().zip(probe.iter()).zip(active.iter())
+                        {
+                            *accu += probe.iter().sum::<f32>()
+                                * a.probe.scale(a.gain);
+                        }
+                        *count += 1;
+                        if *count >= decimation {
+                            let n = (*count * BATCH_SIZE as u32) as f32;
+                            // Samples are dropped if the queue is full.
+                            live_producer.enqueue(accu.map(|x| x / n)).ok();
+                            *live_accu = Default::default();
+                        }
+                    });
+
+                    if let Some(gain) = *monitor {
+                        let scale = gain
+                            * match active[0].probe {
+                                Probe::Input => {
+                                    AdcCode::VOLT_PER_LSB
+                                        / active[0].gain.gain()
+                                        * DacCode::LSB_PER_VOLT
+                                }
+                                _ => 1.0,
+                            };
+                        for (dac, probe) in dac[1].iter_mut().zip(probe[0]) {
+                            // The float to integer conversion saturates.
+                            *dac = DacCode::from((probe * scale) as i16).0;
+                        }
+                    }
+                    loopback.lock(|(output, input, loopback)| {
+                        let (Some(x), Some(y)) =
+                            (adc.get(*input), dac.get_mut(*output))
+                        else {
+                            return;
+                        };
+                        for (x, y) in x.iter().zip(y.iter_mut()) {
+                            if let Some(code) =
+                                loopback.update(AdcCode(*x).into())
+                            {
+                                *y = DacCode::from(code).0;
+                            }
+                        }
+                    });
+                    let held = noise.lock(|noise| {
+                        noise.update(adc);
+                        noise.is_held()
+                    });
+                    if failsafe || held {
+                        for dac in dac.iter_mut() {
+                            dac.fill(DacCode::from(0i16).0);
+                        }
+                        processed = [0.0; 2];
+                    }
+                    outputs = [0, 1]
+                        .map(|i| f32::from(DacCode(dac[i][BATCH_SIZE - 1])));
+                    (&mut pwm, &mut pwm_channels, &mut shutter, &mut aux).lock(
+                        |pwm, channels, shutter, aux| {
+                            if let Some(pwm) = pwm {
+                                for (i, ch) in channels.iter().enumerate() {
+                                    let duty = if let Some((_, level)) =
+                                        shutter.filter(|(s, _)| *s == i)
+                                    {
+                                        if level { 1.0 } else { 0.0 }
+                                    } else if failsafe {
+                                        0.0
+                                    } else {
+                                        ch.duty(outputs, *aux)
+                                    };
+                                    pwm.set_duty(i, duty);
+                                }
+                            }
+                        },
+                    );
+                    telemetry.latency.update(
+                        cortex_m::peripheral::DWT::cycle_count()
+                            .wrapping_sub(start),
+                    );
+                    for (rms, adc) in telemetry.rms.iter_mut().zip(adc.iter()) {
+                        for x in adc.iter() {
+                            rms.update(*x as i16);
+                        }
+                    }
+                    telemetry.update(
+                        [0, 1].map(|i| {
+                            AdcCode::from(
+                                (adc[i][0] as i16)
+                                    .saturating_sub(active[i].offset),
+                            )
+                        }),
+                        [DacCode(dac[0][0]), DacCode(dac[1][0])],
+                        levels,
+                    );
+                    // The biquad output limits are symmetric: the lowest code is 1.
+                    telemetry.saturation += dac
+                        .iter()
+                        .flat_map(|d| d.iter())
+                        .any(|code| *code <= 1 || *code == u16::MAX)
+                        as u32;
+
+                    match format {
+                        stream::Format::PounderPhase => {
+                            generator.configure(stream::Format::PounderPhase);
+                            if let Some(phase) = phase {
+                                const N: usize = size_of::<i32>();
+                                generator.add(|buf| {
+                                    buf[..N].copy_from_slice(
+                                        bytemuck::cast_slice(&[phase]),
+                                    );
+                                    N
+                                });
+                            }
+                        }
+                        stream::Format::Spectrum => {
+                            generator.configure(stream::Format::Spectrum);
+                            const N: usize = size_of::<[f32; 4]>();
+                            for bin in core::iter::from_fn(|| {
+                                spectrum_consumer.dequeue()
+                            })
+                            .take(SPECTRUM_BINS_PER_BATCH)
+                            {
+                                generator.add(|buf| {
+                                    buf[..N].copy_from_slice(
+                                        bytemuck::cast_slice(&bin),
+                                    );
+                                    N
+                                });
+                            }
+                        }
+                        stream::Format::Probe => {
+                            generator.configure(stream::Format::Probe);
+                            let scale = active
+                                .each_ref()
+                                .map(|a| a.probe.scale(a.gain));
+                            let data: [[f32; BATCH_SIZE]; 2] = [
+                                probe[0].map(|x| x * scale[0]),
+                                probe[1].map(|x| x * scale[1]),
+                            ];
+                            const N: usize = BATCH_SIZE * size_of::<f32>();
+                            generator.add(|buf| {
+                                data.iter()
+                                    .zip(buf.chunks_exact_mut(N))
+                                    .map(|(data, buf)| {
+                                        buf.copy_from_slice(
+                                            bytemuck::cast_slice(data),
+                                        )
+                                    })
+                                    .count()
+                                    * N
                             });
+                        }
+                        stream::Format::AdcDacVolts => {
+                            generator.configure(stream::Format::AdcDacVolts);
+                            let scale = active
+                                .each_ref()
+                                .map(|a| AdcCode::VOLT_PER_LSB / a.gain.gain());
+                            let data: [[f32; BATCH_SIZE]; 4] = [
+                                adc[0].map(|x| (x as i16) as f32 * scale[0]),
+                                adc[1].map(|x| (x as i16) as f32 * scale[1]),
+                                dac[0].map(|x| f32::from(DacCode(x))),
+                                dac[1].map(|x| f32::from(DacCode(x))),
+                            ];
+                            const N: usize = BATCH_SIZE * size_of::<f32>();
+                            generator.add(|buf| {
+                                data.iter()
+                                    .zip(buf.chunks_exact_mut(N))
+                                    .map(|(data, buf)| {
+                                        buf.copy_from_slice(
+                                            bytemuck::cast_slice(data),
+                                        )
+                                    })
+                                    .count()
+                                    * N
+                            });
+                        }
+                        _ => {
+                            generator.configure(stream::Format::AdcDacData);
+                            const N: usize = BATCH_SIZE * size_of::<i16>();
+                            generator.add(|buf| {
+                                [adc[0], adc[1], dac[0], dac[1]]
+                                    .into_iter()
+                                    .zip(buf.chunks_exact_mut(N))
+                                    .map(|(data, buf)| {
+                                        buf.copy_from_slice(
+                                            bytemuck::cast_slice(data),
+                                        )
+                                    })
+                                    .count()
+                                    * N
+                            });
+                        }
+                    }
 
-                        // Note(unsafe): The filter limits must ensure that the value is in range.
-                        // The truncation introduces 1/2 LSB distortion.
-                        let y: i16 = unsafe { y.to_int_unchecked() };
-                        *dac = DacCode::from(y.saturating_add(*source)).0;
+                    fence(Ordering::SeqCst);
+                });
+                (&mut dds, &mut dds_scan).lock(|dds, updates| {
+                    if let Some(dds) = dds {
+                        updates.write(dds, source.map(|s| s[0]), processed);
+                    }
+                });
+                for (ch, trigger) in active.iter_mut().zip(functions.trigger) {
+                    if trigger {
+                        ch.source = ch.restart.clone();
+                        ch.am_source = ch.am_restart.clone();
                     }
                 }
-                telemetry.adcs = [AdcCode(adc[0][0]), AdcCode(adc[1][0])];
-                telemetry.dacs = [DacCode(dac[0][0]), DacCode(dac[1][0])];
-
-                const N: usize = BATCH_SIZE * size_of::<i16>();
-                generator.add(|buf| {
-                    [adc[0], adc[1], dac[0], dac[1]]
-                        .into_iter()
-                        .zip(buf.chunks_exact_mut(N))
-                        .map(|(data, buf)| {
-                            buf.copy_from_slice(bytemuck::cast_slice(data))
-                        })
-                        .count()
-                        * N
+                let aux = aux.lock(|aux| *aux);
+                *source = active.each_mut().map(|ch| {
+                    let am = ch.am.map(|(kind, depth)| {
+                        let signal = match kind {
+                            AmSource::Dac(i) => outputs.get(i).copied(),
+                            AmSource::Aux(i) => aux.get(i).copied(),
+                            AmSource::Generator => None,
+                        };
+                        (kind, depth, signal.filter(|s| s.is_finite()))
+                    });
+                    core::array::from_fn(|_| {
+                        let x = (ch.source.next().unwrap() >> 16) as i16;
+                        let signal = match am {
+                            Some((AmSource::Generator, depth, _)) => Some((
+                                depth,
+                                (ch.am_source.next().unwrap() >> 16) as f32
+                                    * DacCode::VOLT_PER_LSB,
+                            )),
+                            Some((_, depth, signal)) => {
+                                signal.map(|s| (depth, s))
+                            }
+                            None => None,
+                        };
+                        match signal {
+                            // The float to integer conversion saturates.
+                            Some((depth, s)) => {
+                                (x as f32 * (1.0 + depth * s)) as i16
+                            }
+                            None => x,
+                        }
+                    })
                 });
-
-                fence(Ordering::SeqCst);
-            });
-            *source = active.each_mut().map(|ch| {
-                core::array::from_fn(|_| (ch.source.next().unwrap() >> 16) as _)
-            });
-            telemetry.digital_inputs =
-                [digital_inputs.0.is_high(), digital_inputs.1.is_high()];
-        });
+            },
+        );
     }
 
-    #[idle(shared=[network, settings, usb])]
+    #[idle(shared=[network, settings, usb, capture], local=[spectrum, spectrum_producer, live_consumer])]
     fn idle(mut c: idle::Context) -> ! {
         loop {
-            match (&mut c.shared.network, &mut c.shared.settings)
-                .lock(|net, settings| net.update(&mut settings.dual_iir))
+            // Compute and publish the spectrum once a capture is complete.
+            if let Some(samples) =
+                c.shared.capture.lock(|capture| capture.take())
             {
+                let (config, scale, streaming) =
+                    c.shared.settings.lock(|settings| {
+                        let config = settings.dual_iir.spectrum.clone();
+                        let scale = config
+                            .channel
+                            .and_then(|ch| settings.dual_iir.ch.get(ch))
+                            .map(|ch| {
+                                [ch.probe.scale(ch.gain), DacCode::VOLT_PER_LSB]
+                            });
+                        let streaming = settings.dual_iir.stream.format
+                            == stream::Format::Spectrum;
+                        (config, scale, streaming)
+                    });
+                if let Some(scale) = scale {
+                    let spectrum = &mut c.local.spectrum;
+                    spectrum.update(&samples, scale);
+                    if spectrum.count() >= config.average {
+                        let estimate = spectrum.finish(config.sample_rate());
+                        c.shared.network.lock(|net| {
+                            net.telemetry.publish_telemetry(
+                                "/spectrum",
+                                &SpectrumTelemetry {
+                                    resolution: estimate.resolution,
+                                    asd: &estimate.asd_db(),
+                                },
+                            )
+                        });
+                        if streaming {
+                            for (k, (psd, h)) in estimate
+                                .psd
+                                .iter()
+                                .zip(estimate.transfer.iter())
+                                .enumerate()
+                            {
+                                // Bins are dropped if the previous estimate
+                                // has not been streamed yet.
+                                c.local
+                                    .spectrum_producer
+                                    .enqueue([
+                                        k as f32 * estimate.resolution,
+                                        *psd,
+                                        h.re,
+                                        h.im,
+                                    ])
+                                    .ok();
+                            }
+                        }
+                    } else {
+                        c.shared
+                            .capture
+                            .lock(|capture| capture.start(config.decimation));
+                    }
+                }
+            }
+
+            let live_consumer = &mut c.local.live_consumer;
+            match (&mut c.shared.network, &mut c.shared.settings).lock(
+                |net, settings| {
+                    while let Some(sample) = live_consumer.dequeue() {
+                        net.push_live(bytemuck::cast_slice(&sample));
+                    }
+                    net.update(&mut settings.dual_iir)
+                },
+            ) {
                 NetworkState::SettingsChanged => {
                     settings_update::spawn().unwrap();
                 }
@@ -448,18 +2552,74 @@ mod app {
         }
     }
 
-    #[task(priority = 1, local=[afes], shared=[network, settings, active])]
+    #[task(priority = 1, shared=[network, settings, afes, noise, calibration, active, stream_format, monitor, route, dac_route, timebase, spectrum_channel, live, pounder, dds, dds_scan, di, pwm, pwm_channels, sampling_timer])]
     async fn settings_update(mut c: settings_update::Context) {
         c.shared.settings.lock(|settings| {
-            c.local.afes[0].set_gain(settings.dual_iir.ch[0].gain);
-            c.local.afes[1].set_gain(settings.dual_iir.ch[1].gain);
+            hardware::LOGGER.configure(&settings.net.log);
+            if core::mem::replace(
+                &mut settings.dual_iir.calibration.clear,
+                false,
+            ) {
+                c.shared.calibration.lock(|calibration| {
+                    *calibration = (InputCalibration::default(), true)
+                });
+                log::info!("Input calibration removed");
+                c.shared.network.lock(|net| {
+                    net.telemetry.publish_telemetry(
+                        "/calibration",
+                        &CalibrationTelemetry::new(
+                            Calibration::Cleared,
+                            &InputCalibration::default(),
+                        ),
+                    )
+                });
+            }
+            let calibration = c.shared.calibration.lock(|(c, _)| *c);
+            // Hold the channels before switching the gain.
+            c.shared.active.lock(|active| {
+                for (i, (a, ch)) in
+                    active.iter_mut().zip(&settings.dual_iir.ch).enumerate()
+                {
+                    a.set_gain(ch.gain, ch.gain_blanking);
+                    a.set_offset(calibration.offset(i, ch.gain));
+                }
+            });
+            // The noise floor characterization restores the gains when done.
+            if !c.shared.noise.lock(|noise| noise.is_held()) {
+                c.shared.afes.lock(|afes| {
+                    for (afe, ch) in afes.iter_mut().zip(&settings.dual_iir.ch)
+                    {
+                        afe.set_gain(ch.gain);
+                    }
+                });
+            }
+
+            let period = settings.dual_iir.timebase.map(timebase_period);
+            let offset = c.shared.timebase.lock(|timebase| {
+                if timebase.map(|t| t.period()) != period {
+                    *timebase = period.map(Timebase::new);
+                }
+                timebase.and_then(|t| t.offset())
+            });
 
             if settings.dual_iir.trigger {
                 settings.dual_iir.trigger = false;
+                // Correct the sample period for the measured timebase offset.
+                let sample_period =
+                    SAMPLE_PERIOD / (1.0 + offset.unwrap_or(0.0));
                 let s = settings.dual_iir.ch.each_ref().map(|ch| {
                     let s = ch
                         .source
-                        .build(SAMPLE_PERIOD, DacCode::FULL_SCALE.recip());
+                        .build(sample_period, DacCode::FULL_SCALE.recip())
+                        .and_then(|s| {
+                            Ok((
+                                s,
+                                ch.am.generator.build(
+                                    sample_period,
+                                    DacCode::FULL_SCALE.recip(),
+                                )?,
+                            ))
+                        });
                     if let Err(err) = &s {
                         log::error!("Failed to update source: {:?}", err);
                     }
@@ -467,15 +2627,28 @@ mod app {
                 });
                 c.shared.active.lock(|ch| {
                     for (ch, s) in ch.iter_mut().zip(s) {
-                        if let Ok(s) = s {
+                        if let Ok((s, am)) = s {
+                            ch.restart = s.clone();
                             ch.source = s;
+                            ch.am_restart = am.clone();
+                            ch.am_source = am;
                         }
                     }
                 });
             }
+            let clear = settings
+                .dual_iir
+                .ch
+                .each_mut()
+                .map(|ch| core::mem::replace(&mut ch.clear, false));
             let b = settings.dual_iir.ch.each_ref().map(|ch| {
                 (
                     ch.run,
+                    ch.probe,
+                    ch.crossfade,
+                    ch.dc_block
+                        .map(|corner| DcBlock::gain(corner, SAMPLE_PERIOD)),
+                    ch.log.map(|reference| log_reference(reference, ch.gain)),
                     ch.biquad.each_ref().map(|b| {
                         b.repr.build::<f32>(
                             SAMPLE_PERIOD,
@@ -483,58 +2656,446 @@ mod app {
                             DacCode::LSB_PER_VOLT,
                         )
                     }),
+                    ch.noise_shaping
+                        .map(|corner| NoiseShaper::pole(corner, SAMPLE_PERIOD)),
+                    ch.deadband(),
+                    ch.smith(),
+                    ch.kalman
+                        .map(|kalman| Kalman::gain(kalman.model, kalman.ratio)),
+                    ch.allpass(),
+                    ch.hum(),
+                    ch.am(),
+                )
+            });
+            // The last stage output is converted to DAC codes.
+            let analysis = b.each_ref().map(|b| {
+                Analysis::cascade(
+                    &b.5,
+                    i16::MAX as _,
+                    (i16::MIN as _, i16::MAX as _),
                 )
             });
+            for (ch, analysis) in analysis.iter().enumerate() {
+                for (stage, analysis) in analysis.iter().enumerate() {
+                    if analysis.issue.is_some() {
+                        log::warn!("Biquad {ch}/{stage}: {analysis:?}");
+                        c.shared.network.lock(|net| {
+                            net.telemetry.publish_telemetry(
+                                "/biquad",
+                                &BiquadIssue {
+                                    ch,
+                                    stage,
+                                    analysis,
+                                },
+                            )
+                        });
+                    }
+                }
+            }
+            let frequency = settings.dual_iir.delay_frequency;
+            let pipeline = LoopDelay::pipeline();
+            let filter = settings.dual_iir.ch.each_ref().map(|ch| {
+                Some(ch.group_delay(frequency) * SAMPLE_PERIOD)
+                    .filter(|delay| delay.is_finite())
+            });
+            let delay = LoopDelay {
+                frequency,
+                pipeline,
+                filter,
+                total: filter.map(|filter| filter.map(|f| f + pipeline)),
+            };
+            c.shared
+                .network
+                .lock(|net| net.telemetry.publish_telemetry("/delay", &delay));
+
+            if let Some(channel) = settings.dual_iir.preview.take() {
+                let preview = settings.dual_iir.ch.get(channel).map(|ch| {
+                    ch.source.preview(
+                        SAMPLE_PERIOD,
+                        PREVIEW_POINTS,
+                        DacCode::FULL_SCALE.recip(),
+                    )
+                });
+                match preview {
+                    Some(Ok((mut source, period))) => {
+                        let preview = Preview {
+                            channel,
+                            period,
+                            output: core::array::from_fn(|_| {
+                                // Note(unwrap): The source is infinite.
+                                ((source.next().unwrap() >> 16) as i16) as f32
+                                    * DacCode::VOLT_PER_LSB
+                            }),
+                        };
+                        c.shared.network.lock(|net| {
+                            net.telemetry
+                                .publish_telemetry("/preview", &preview)
+                        });
+                    }
+                    Some(Err(err)) => {
+                        log::error!("Failed to preview source: {:?}", err)
+                    }
+                    None => log::error!("Invalid preview channel {channel}"),
+                }
+            }
+            let reject = analysis.each_ref().map(|analysis| {
+                settings.dual_iir.reject_invalid
+                    && analysis.iter().any(Analysis::invalid)
+            });
+            let presets = settings.dual_iir.ch.each_ref().map(|ch| {
+                ch.preset.each_ref().map(|preset| {
+                    let biquad = preset.each_ref().map(|b| {
+                        b.repr.build::<f32>(
+                            SAMPLE_PERIOD,
+                            1.0,
+                            DacCode::LSB_PER_VOLT,
+                        )
+                    });
+                    let invalid = Analysis::cascade(
+                        &biquad,
+                        i16::MAX as _,
+                        (i16::MIN as _, i16::MAX as _),
+                    )
+                    .iter()
+                    .any(Analysis::invalid);
+                    if settings.dual_iir.reject_invalid && invalid {
+                        log::error!("Rejecting invalid biquad preset");
+                        None
+                    } else {
+                        Some(biquad)
+                    }
+                })
+            });
             c.shared.active.lock(|active| {
-                for (a, b) in active.iter_mut().zip(b) {
-                    (a.run, a.biquad) = b;
+                for (((a, b), reject), presets) in
+                    active.iter_mut().zip(b).zip(reject).zip(presets)
+                {
+                    let biquad;
+                    (
+                        a.run,
+                        a.probe,
+                        a.crossfade,
+                        a.dc_block,
+                        a.log,
+                        biquad,
+                        a.noise_shaping,
+                        a.deadband,
+                        a.smith_model,
+                        a.kalman_gain,
+                        a.allpass,
+                        a.hum,
+                        a.am,
+                    ) = b;
+                    if reject {
+                        log::error!("Rejecting invalid biquad coefficients");
+                    }
+                    a.profiles[0] = (!reject).then_some(biquad);
+                    for (profile, preset) in
+                        a.profiles[1..].iter_mut().zip(presets)
+                    {
+                        *profile = preset;
+                    }
+                    if let Some(biquad) = a.profiles[a.profile].clone() {
+                        a.set_biquad(biquad);
+                    }
                 }
+                for (a, ch) in active.iter_mut().zip(&settings.dual_iir.ch) {
+                    match (&mut a.graph, &ch.graph) {
+                        (Some(graph), Some(blocks)) => {
+                            graph.set(blocks, SAMPLE_PERIOD)
+                        }
+                        (graph, blocks) => {
+                            *graph = blocks
+                                .as_deref()
+                                .map(|blocks| Graph::new(blocks, SAMPLE_PERIOD))
+                        }
+                    }
+                }
+                for (a, clear) in active.iter_mut().zip(clear) {
+                    if clear {
+                        a.state = Default::default();
+                        a.previous_state = Default::default();
+                        a.smith = Default::default();
+                        a.kalman = Default::default();
+                        a.allpass_state = Default::default();
+                        a.hum_canceller = Default::default();
+                        if let Some(graph) = &mut a.graph {
+                            graph.clear();
+                        }
+                    }
+                }
+            });
+            c.shared
+                .stream_format
+                .lock(|format| *format = settings.dual_iir.stream.format);
+            c.shared
+                .monitor
+                .lock(|monitor| *monitor = settings.dual_iir.monitor);
+            c.shared
+                .route
+                .lock(|route| *route = settings.dual_iir.route);
+            c.shared
+                .dac_route
+                .lock(|route| route.clone_from(&settings.dual_iir.dac_route));
+            c.shared
+                .spectrum_channel
+                .lock(|channel| *channel = settings.dual_iir.spectrum.channel);
+            c.shared.live.lock(|live| *live = settings.dual_iir.live);
+            let skew = settings.dual_iir.skew;
+            if c.shared.sampling_timer.lock(|timer| timer.set_skew(skew))
+                != skew
+            {
+                log::warn!("Channel skew limited");
+            }
+            if settings.dual_iir.timebase.is_some()
+                && settings.dual_iir.di[0].role != Role::None
+            {
+                log::warn!("DI0 role ignored: DI0 is the timebase reference");
+            }
+            c.shared.di.lock(|di| *di = settings.dual_iir.di());
+            c.shared.pwm.lock(|pwm| {
+                if let Some(pwm) = pwm {
+                    pwm.set_frequency(settings.dual_iir.pwm.frequency);
+                }
+            });
+            c.shared
+                .pwm_channels
+                .lock(|channels| *channels = settings.dual_iir.pwm.ch);
+            let requests = settings.dual_iir.pounder.take_requests();
+            if requests.bode {
+                bode::spawn().ok();
+            }
+            if core::mem::replace(&mut settings.dual_iir.noise_floor.run, false)
+            {
+                noise_floor::spawn().ok();
+            }
+            if core::mem::replace(&mut settings.dual_iir.loopback.run, false) {
+                loopback::spawn().ok();
+            }
+            if core::mem::replace(&mut settings.dual_iir.calibration.run, false)
+            {
+                calibrate::spawn(true).ok();
+            }
+            let followup = c.shared.pounder.lock(|pounder| {
+                Some(pounder.as_mut()?.update(
+                    &settings.dual_iir.pounder,
+                    requests,
+                    Systick::now().ticks(),
+                    BATCH_SIZE as f32 * SAMPLE_PERIOD,
+                    &mut c.shared.dds,
+                    &mut c.shared.dds_scan,
+                ))
             });
+            if let Some(followup) = followup {
+                if followup.clock_check {
+                    clock_check::spawn().ok();
+                }
+                if followup.attenuation_ramp {
+                    attenuation_ramp::spawn().ok();
+                }
+                if followup.sync {
+                    dds_sync::spawn().ok();
+                }
+                if followup.verify {
+                    dds_verify::spawn().ok();
+                }
+                if let Some(response) = followup.register {
+                    c.shared.network.lock(|net| {
+                        net.telemetry
+                            .publish_telemetry("/dds/register", &response)
+                    });
+                }
+            }
             c.shared
                 .network
-                .lock(|net| net.direct_stream(settings.dual_iir.stream));
+                .lock(|net| net.direct_stream(&settings.dual_iir.stream));
         });
     }
 
-    #[task(priority = 1, shared=[network, settings, telemetry], local=[cpu_temp_sensor])]
+    #[task(priority = 1, shared=[network, settings, timebase, sync_phase, capture, telemetry, interlock, pounder, aux, faults, snapshot, standby], local=[pounder_detected, aux_detected])]
     async fn telemetry(mut c: telemetry::Context) {
+        // Pounder peripheral fault states: devices, temperature, power
+        let mut faults = [false; 4];
+        // Stream losses and interlock state at the last report
+        let mut stream_losses = 0u32;
+        let mut tripped = false;
         loop {
+            // Capture the sampled quantities at one sample instant. The
+            // capture completes with the next batch unless processing is
+            // stalled.
+            c.shared.telemetry.lock(|telemetry| telemetry.request());
+            for _ in 0..10 {
+                Systick::delay(1.millis()).await;
+                if c.shared.telemetry.lock(|telemetry| telemetry.captured()) {
+                    break;
+                }
+            }
+            let pounder = c.shared.pounder.lock(|pounder| {
+                pounder.as_mut().map(|p| p.devices().telemetry())
+            });
             let telemetry =
-                c.shared.telemetry.lock(|telemetry| telemetry.clone());
+                c.shared.telemetry.lock(|telemetry| telemetry.snapshot());
+            let offset = c
+                .shared
+                .timebase
+                .lock(|timebase| timebase.as_mut().map(|t| t.finish()));
+            let sync_phase = c
+                .shared
+                .sync_phase
+                .lock(|sync| sync.as_mut().map(|s| s.finish()));
 
-            let (gains, telemetry_period) =
-                c.shared.settings.lock(|settings| {
-                    (
-                        settings.dual_iir.ch.each_ref().map(|ch| ch.gain),
-                        settings.dual_iir.telemetry_period,
-                    )
+            let (
+                gains,
+                log,
+                units,
+                spectrum,
+                telemetry_period,
+                retained,
+                clear,
+            ) = c.shared.settings.lock(|settings| {
+                (
+                    settings.dual_iir.ch.each_ref().map(|ch| ch.gain),
+                    settings.dual_iir.ch.each_ref().map(|ch| ch.log),
+                    settings
+                        .dual_iir
+                        .ch
+                        .each_ref()
+                        .map(|ch| (ch.input_unit, ch.output_unit)),
+                    settings.dual_iir.spectrum.clone(),
+                    settings.dual_iir.telemetry_period,
+                    settings.net.retained_telemetry,
+                    core::mem::replace(
+                        &mut settings.dual_iir.clear_faults,
+                        false,
+                    ),
+                )
+            });
+
+            // Start a new spectrum capture. Averaging captures are started
+            // from idle.
+            if spectrum.channel.is_some() {
+                c.shared.capture.lock(|capture| {
+                    if capture.is_idle() {
+                        capture.start(spectrum.decimation)
+                    }
+                });
+            }
+
+            let aux = c.shared.aux.lock(|aux| *aux);
+            // A scheduled snapshot bypasses the retained copy interval.
+            let retained = if c
+                .shared
+                .snapshot
+                .lock(|snapshot| core::mem::replace(snapshot, false))
+            {
+                Some(0.0)
+            } else {
+                retained
+            };
+            let saturation = telemetry.saturation;
+
+            let mut telemetry = telemetry.finalize(gains[0], gains[1]);
+            telemetry.scale(units.map(|u| u.0), units.map(|u| u.1));
+            telemetry.interlock =
+                c.shared.interlock.lock(|interlock| interlock.tripped());
+            telemetry.standby = c.shared.standby.lock(|standby| *standby);
+
+            let stats = c.shared.network.lock(|net| net.stream_statistics());
+            let losses = stats.dropped.wrapping_add(stats.errors);
+            let trip = telemetry.interlock && !tripped;
+            tripped = telemetry.interlock;
+            telemetry.faults = c.shared.faults.lock(|faults| {
+                if clear {
+                    faults.clear();
+                }
+                faults.record(Fault::Dma, hardware::take_dma_errors());
+                faults
+                    .record(Fault::Stream, losses.wrapping_sub(stream_losses));
+                faults.record(Fault::Saturation, saturation);
+                faults.record(Fault::Interlock, trip as u32);
+                faults.record(
+                    Fault::I2c,
+                    (*c.local.pounder_detected && pounder.is_none()) as u32,
+                );
+                *faults
+            });
+            stream_losses = losses;
+            for ((db, adc), log) in
+                telemetry.db.iter_mut().zip(telemetry.adcs).zip(log)
+            {
+                *db = log.map(|reference| {
+                    decibel(adc, reference, AdcCode::VOLT_PER_LSB)
                 });
+            }
 
             c.shared.network.lock(|net| {
-                net.telemetry.publish_telemetry(
+                net.telemetry.publish_telemetry("/telemetry", &telemetry);
+                net.telemetry.publish_retained(
                     "/telemetry",
-                    &telemetry.finalize(
-                        gains[0],
-                        gains[1],
-                        c.local.cpu_temp_sensor.get_temperature().unwrap(),
-                    ),
-                )
+                    &telemetry,
+                    Systick::now().ticks(),
+                    retained,
+                );
+                net.serve_telemetry(&telemetry);
+
+                net.telemetry.publish_telemetry("/stream", &stats);
+
+                if let Some(offset) = offset {
+                    net.telemetry.publish_telemetry("/timebase", &offset);
+                }
+
+                // Without timestamps the phase is serialized as `null`.
+                if let Some(phase) = sync_phase {
+                    net.telemetry.publish_telemetry("/pounder/phase", &phase);
+                }
+
+                // Unavailable inputs are serialized as `null`.
+                if *c.local.aux_detected {
+                    net.telemetry.publish_telemetry("/aux", &aux);
+                }
+
+                if *c.local.pounder_detected {
+                    let telemetry = pounder.unwrap_or_default();
+                    net.telemetry.publish_telemetry("/pounder", &telemetry);
+
+                    // Report fault state changes once they can be delivered.
+                    if net.is_connected() {
+                        let events = [FaultEvent {
+                            peripheral: Peripheral::Devices,
+                            fault: pounder.is_none(),
+                        }]
+                        .into_iter()
+                        .chain(telemetry.faults());
+                        for (event, fault) in events.zip(faults.iter_mut()) {
+                            if core::mem::replace(fault, event.fault)
+                                != event.fault
+                            {
+                                log::warn!(
+                                    "Pounder {:?} fault: {}",
+                                    event.peripheral,
+                                    event.fault
+                                );
+                                net.telemetry
+                                    .publish_telemetry("/event", &event);
+                            }
+                        }
+                    }
+                }
             });
 
             Systick::delay(((telemetry_period * 1000.0) as u32).millis()).await;
         }
     }
 
-    #[task(priority = 1, shared=[usb, settings], local=[usb_terminal])]
+    #[task(priority = 1, shared=[usb, network, settings, interlock, calibration], local=[usb_terminal, usb_ethernet])]
     async fn usb(mut c: usb::Context) {
         loop {
-            // Handle the USB serial terminal.
+            // Handle the USB serial terminal and network interface.
             c.shared.usb.lock(|usb| {
-                usb.poll(&mut [c
-                    .local
-                    .usb_terminal
-                    .interface_mut()
-                    .inner_mut()]);
+                usb.poll(&mut [
+                    c.local.usb_terminal.interface_mut().inner_mut(),
+                    c.local.usb_ethernet,
+                ]);
             });
 
             c.shared.settings.lock(|settings| {
@@ -543,6 +3104,603 @@ mod app {
                 }
             });
 
+            let provisioned = (&mut c.shared.network, &mut c.shared.settings)
+                .lock(|net, settings| {
+                    net.take_provisioned(|provisioned| {
+                        c.local
+                            .usb_terminal
+                            .platform_mut()
+                            .provision(settings, provisioned)
+                    })
+                });
+            match provisioned {
+                Some(0) => log::warn!("No settings provisioned"),
+                Some(count) => {
+                    log::info!("Provisioned {count} settings. Rebooting");
+                    cortex_m::peripheral::SCB::sys_reset();
+                }
+                None => {}
+            }
+
+            let platform = c.local.usb_terminal.platform_mut();
+            platform.update_counters(Systick::now().ticks());
+            platform.record_interlock_trips(
+                c.shared.interlock.lock(|i| i.take_trips()),
+            );
+            if let Some(calibration) =
+                c.shared.calibration.lock(|(calibration, unsaved)| {
+                    core::mem::replace(unsaved, false).then_some(*calibration)
+                })
+            {
+                platform.store_calibration(&calibration);
+            }
+
+            // Poll faster while the host exchanges frames over the USB network interface.
+            let period = if c.local.usb_ethernet.is_active() {
+                1
+            } else {
+                10
+            };
+            Systick::delay(period.millis()).await;
+        }
+    }
+
+    /// Drive the outputs to the failsafe state while the broker is unreachable
+    /// or the interlock is tripped.
+    #[task(priority = 1, shared=[network, settings, failsafe, telemetry, pounder, interlock, aux], local=[cpu_temp_sensor])]
+    async fn watchdog(mut c: watchdog::Context) {
+        let mut watchdog = ConnectionWatchdog::default();
+        loop {
+            let (timeout, config, reset, gains, di_config) =
+                c.shared.settings.lock(|settings| {
+                    let s = &mut settings.dual_iir;
+                    (
+                        settings.net.watchdog,
+                        s.interlock.clone(),
+                        core::mem::replace(&mut s.interlock.reset, false),
+                        s.ch.each_ref().map(|ch| ch.gain),
+                        s.di(),
+                    )
+                });
+            let connected = c.shared.network.lock(|net| net.is_connected());
+            let timeout = watchdog.update(connected, 0.1, timeout);
+
+            let temperature =
+                c.local.cpu_temp_sensor.get_temperature().unwrap();
+            let (di, adc) = c.shared.telemetry.lock(|telemetry| {
+                telemetry.cpu_temp = temperature;
+                (telemetry.digital_inputs, telemetry.adcs)
+            });
+            let power = c.shared.pounder.lock(|pounder| {
+                pounder.as_ref().map_or([f32::NAN; 2], |p| p.power())
+            });
+            let inputs = Inputs {
+                di,
+                adc: [0, 1].map(|i| f32::from(adc[i]) / gains[i].gain()),
+                aux: c.shared.aux.lock(|aux| *aux),
+                temperature,
+                power,
+                timeout,
+                external: Inputs::external(&di_config, di),
+            };
+            let interlock = c.shared.interlock.lock(|interlock| {
+                if reset {
+                    interlock.reset();
+                }
+                interlock.update(&config, &inputs)
+            });
+
+            let tripped = timeout || interlock;
+            let changed = c.shared.failsafe.lock(|failsafe| {
+                core::mem::replace(failsafe, tripped) != tripped
+            });
+            if changed {
+                log::warn!(
+                    "Failsafe: {tripped} (watchdog: {timeout}, interlock: {interlock})"
+                );
+            }
+            Systick::delay(100.millis()).await;
+        }
+    }
+
+    /// Drive the shutter output from the output enable state.
+    #[task(priority = 1, shared=[settings, failsafe, shutter])]
+    async fn shutter(mut c: shutter::Context) {
+        let mut state = ShutterState::default();
+        loop {
+            let config = c.shared.settings.lock(|s| s.dual_iir.pwm.shutter);
+            let failsafe = c.shared.failsafe.lock(|failsafe| *failsafe);
+            let level = config
+                .map(|config| (config.output, state.update(&config, failsafe)));
+            if config.is_none() {
+                state = Default::default();
+            }
+            c.shared.shutter.lock(|shutter| *shutter = level);
+            Systick::delay(1.millis()).await;
+        }
+    }
+
+    /// Measure the frequency response from a Pounder output to an input.
+    #[task(priority = 1, shared=[network, settings, pounder, dds_scan])]
+    async fn bode(mut c: bode::Context) {
+        let config =
+            c.shared.settings.lock(|s| s.dual_iir.pounder.bode.clone());
+        let channels = (
+            [PounderChannel::Out0, PounderChannel::Out1].get(config.output),
+            [PounderChannel::In0, PounderChannel::In1].get(config.input),
+        );
+        let (Some(_), Some(&input)) = channels else {
+            log::error!("Invalid Bode channels");
+            return;
+        };
+        if c.shared.pounder.lock(|pounder| pounder.is_none()) {
+            log::error!("Pounder unavailable");
+            return;
+        }
+        let mut response: heapless::Vec<[f32; 2], BODE_POINTS> =
+            heapless::Vec::new();
+        for frequency in config.frequencies() {
+            let Some(ftw) = ad9959::frequency_to_ftw(
+                frequency,
+                stabilizer::design_parameters::DDS_SYSTEM_CLK.to_Hz() as f64,
+            ) else {
+                log::error!("Invalid Bode frequency: {frequency}");
+                break;
+            };
+            // The output channels follow the input channels in the DDS channel order.
+            c.shared
+                .dds_scan
+                .lock(|updates| updates.queue_ftw(2 + config.output, ftw));
+            Systick::delay(config.settle.millis()).await;
+            let power = c.shared.pounder.lock(|pounder| {
+                pounder.as_mut().unwrap().devices().measure_power(input)
+            });
+            match power {
+                // Note(ok): The number of points is limited to the capacity.
+                Ok(power) => response.push([frequency as f32, power]).ok(),
+                Err(err) => {
+                    log::error!("Failed to measure power: {:?}", err);
+                    break;
+                }
+            };
+        }
+        c.shared
+            .network
+            .lock(|net| net.telemetry.publish_telemetry("/bode", &response));
+        // Restore the DDS configuration.
+        settings_update::spawn().ok();
+    }
+
+    /// Characterize the input noise floor for each AFE gain.
+    #[task(priority = 1, shared=[network, settings, afes, noise])]
+    async fn noise_floor(mut c: noise_floor::Context) {
+        let config = c.shared.settings.lock(|s| s.dual_iir.noise_floor.clone());
+        log::info!("Noise floor characterization started");
+        c.shared.noise.lock(|noise| noise.hold(true));
+        let mut spectrum = Spectrum::new();
+        for gain in [Gain::G1, Gain::G2, Gain::G5, Gain::G10] {
+            c.shared.afes.lock(|afes| {
+                for afe in afes.iter_mut() {
+                    afe.set_gain(gain);
+                }
+            });
+            Systick::delay(config.settle.millis()).await;
+            // Input-referred volts per ADC LSB
+            let scale = AdcCode::VOLT_PER_LSB / gain.gain();
+            let mut report = NoiseFloorTelemetry {
+                gain,
+                rms: [0.0; 2],
+                resolution: 0.0,
+                asd: [[0; NOISE_BANDS]; 2],
+            };
+            for ch in 0..2 {
+                let mut rms = Rms::default();
+                for _ in 0..config.average.max(1) {
+                    let record = record(&mut c.shared.noise, ch).await;
+                    for x in record {
+                        rms.update(x);
+                    }
+                    spectrum.update(
+                        &[record.map(f32::from), [0.0; SPECTRUM_SIZE]],
+                        [scale, 0.0],
+                    );
+                }
+                let estimate = spectrum.finish(SAMPLE_PERIOD.recip());
+                report.rms[ch] = rms.rms() * scale;
+                report.resolution = estimate.resolution
+                    * (SPECTRUM_SIZE / 2 / NOISE_BANDS) as f32;
+                report.asd[ch] = coarse_asd_db(&estimate.psd);
+            }
+            c.shared
+                .network
+                .lock(|net| net.telemetry.publish_telemetry("/noise", &report));
+        }
+        c.shared.noise.lock(|noise| noise.hold(false));
+        log::info!("Noise floor characterization done");
+        // Restore the AFE gains.
+        settings_update::spawn().ok();
+    }
+
+    /// Input offset calibration.
+    ///
+    /// An attended calibration waits for the confirmation that the inputs are
+    /// shorted.
+    #[task(priority = 1, shared=[network, settings, afes, noise, calibration])]
+    async fn calibrate(mut c: calibrate::Context, attended: bool) {
+        let config = c.shared.settings.lock(|s| {
+            s.dual_iir.calibration.confirm = false;
+            s.dual_iir.calibration.clone()
+        });
+        if attended {
+            let current = c.shared.calibration.lock(|(c, _)| *c);
+            log::info!(
+                "Input calibration: short both inputs and set `calibration/confirm`"
+            );
+            c.shared.network.lock(|net| {
+                net.telemetry.publish_telemetry(
+                    "/calibration",
+                    &CalibrationTelemetry::new(
+                        Calibration::ShortInputs,
+                        &current,
+                    ),
+                )
+            });
+            let start = Systick::now();
+            while !c.shared.settings.lock(|s| {
+                core::mem::replace(&mut s.dual_iir.calibration.confirm, false)
+            }) {
+                if Systick::now() - start >= CALIBRATION_TIMEOUT.millis() {
+                    log::warn!("Input calibration confirmation timed out");
+                    c.shared.network.lock(|net| {
+                        net.telemetry.publish_telemetry(
+                            "/calibration",
+                            &CalibrationTelemetry::new(
+                                Calibration::TimedOut,
+                                &current,
+                            ),
+                        )
+                    });
+                    return;
+                }
+                Systick::delay(100.millis()).await;
+            }
+        }
+
+        c.shared.noise.lock(|noise| noise.hold(true));
+        let mut calibration = InputCalibration::default();
+        for (g, gain) in [Gain::G1, Gain::G2, Gain::G5, Gain::G10]
+            .into_iter()
+            .enumerate()
+        {
+            c.shared.afes.lock(|afes| {
+                for afe in afes.iter_mut() {
+                    afe.set_gain(gain);
+                }
+            });
+            Systick::delay(config.settle.millis()).await;
+            for (ch, offset) in calibration.offset.iter_mut().enumerate() {
+                let mut sum = 0i64;
+                let records = config.average.max(1);
+                for _ in 0..records {
+                    let record = record(&mut c.shared.noise, ch).await;
+                    sum += record.iter().map(|x| *x as i64).sum::<i64>();
+                }
+                offset[g] = (sum / (records as i64 * SPECTRUM_SIZE as i64))
+                    .clamp(i16::MIN as _, i16::MAX as _)
+                    as i16;
+            }
+        }
+        c.shared.noise.lock(|noise| noise.hold(false));
+        log::info!("Input calibration done: {:?}", calibration.offset);
+        c.shared
+            .calibration
+            .lock(|stored| *stored = (calibration, true));
+        c.shared.network.lock(|net| {
+            net.telemetry.publish_telemetry(
+                "/calibration",
+                &CalibrationTelemetry::new(Calibration::Done, &calibration),
+            )
+        });
+        // Restore the AFE gains and apply the offsets.
+        settings_update::spawn().ok();
+    }
+
+    /// Capture a record of raw input samples.
+    async fn record(
+        noise: &mut impl rtic::Mutex<T = NoiseRecord>,
+        channel: usize,
+    ) -> [i16; SPECTRUM_SIZE] {
+        noise.lock(|noise| noise.start(channel));
+        loop {
+            Systick::delay(1.millis()).await;
+            if let Some(record) = noise.lock(|noise| noise.take()) {
+                return record;
+            }
+        }
+    }
+
+    /// Measure the loop delay from a DAC output looped back to an ADC input.
+    #[task(priority = 1, shared=[network, settings, loopback])]
+    async fn loopback(mut c: loopback::Context) {
+        let config = c.shared.settings.lock(|s| s.dual_iir.loopback.clone());
+        if config.output >= 2 || config.input >= 2 {
+            log::error!("Invalid loopback channels");
+            return;
+        }
+        // The float to integer conversion saturates.
+        let amplitude = (config.amplitude * DacCode::LSB_PER_VOLT) as i16;
+        c.shared.loopback.lock(|(output, input, loopback)| {
+            *output = config.output;
+            *input = config.input;
+            loopback.start(
+                [amplitude.saturating_neg(), amplitude],
+                config.half_period,
+                config.steps,
+            );
+        });
+        let steps = loop {
+            Systick::delay(10.millis()).await;
+            if let Some(steps) =
+                c.shared.loopback.lock(|(_, _, loopback)| loopback.take())
+            {
+                break steps;
+            }
+        };
+        if steps.steps == 0 {
+            log::warn!("No loopback response");
+        }
+        let report = LoopbackTelemetry {
+            steps,
+            mean: steps.mean(),
+            jitter: steps.jitter(),
+            delay: steps.mean() * SAMPLE_PERIOD,
+        };
+        c.shared
+            .network
+            .lock(|net| net.telemetry.publish_telemetry("/loopback", &report));
+    }
+
+    /// Dither the Pounder DDS frequency tuning words.
+    /// Run the scheduled maintenance actions.
+    #[task(priority = 1, shared=[settings, snapshot])]
+    async fn maintenance(mut c: maintenance::Context) {
+        let mut scheduler = Scheduler::default();
+        loop {
+            let (entries, time) = c.shared.settings.lock(|s| {
+                (
+                    s.dual_iir.schedule.entries.clone(),
+                    s.dual_iir.schedule.time.take(),
+                )
+            });
+            if let Some(time) = time {
+                scheduler.set_time(time);
+                log::info!("Time of day set: {time} s");
+            }
+            scheduler.update(&entries, Systick::now().ticks(), |action| {
+                log::info!("Scheduled maintenance: {action:?}");
+                let spawned = match action {
+                    Maintenance::AutoZero => calibrate::spawn(false).is_ok(),
+                    Maintenance::Snapshot => {
+                        c.shared.snapshot.lock(|snapshot| *snapshot = true);
+                        true
+                    }
+                    Maintenance::Resync => settings_update::spawn().is_ok(),
+                };
+                if !spawned {
+                    log::warn!("Scheduled maintenance {action:?} is busy");
+                }
+            });
+            Systick::delay(1.secs()).await;
+        }
+    }
+
+    /// Stop and resume sampling for the low-power standby.
+    #[task(priority = 1, shared=[settings, sampling_timer, noise, loopback, standby])]
+    async fn standby(mut c: standby::Context) {
+        // Let the start task start sampling first.
+        Systick::delay(200.millis()).await;
+        loop {
+            let idle = c.shared.settings.lock(|settings| {
+                let s = &settings.dual_iir;
+                s.standby
+                    && s.stream.target.0.ip().is_unspecified()
+                    && s.live.is_none()
+                    && s.spectrum.channel.is_none()
+                    && s.ch.iter().all(|ch| ch.run == Run::Hold)
+            });
+            let busy = c.shared.noise.lock(|noise| noise.is_held())
+                || c.shared
+                    .loopback
+                    .lock(|(_, _, loopback)| loopback.is_active());
+            let standby = idle && !busy;
+            (&mut c.shared.sampling_timer, &mut c.shared.standby).lock(
+                |timer, state| {
+                    if core::mem::replace(state, standby) != standby {
+                        log::info!("Standby: {standby}");
+                        if standby {
+                            timer.pause();
+                        } else {
+                            timer.start();
+                        }
+                    }
+                },
+            );
+            Systick::delay(100.millis()).await;
+        }
+    }
+
+    #[task(priority = 1, shared=[pounder, dds_scan])]
+    async fn dither(mut c: dither::Context) {
+        if c.shared.pounder.lock(|pounder| pounder.is_none()) {
+            return;
+        }
+        loop {
+            (&mut c.shared.pounder, &mut c.shared.dds_scan).lock(
+                |pounder, updates| {
+                    if let Some(pounder) = pounder {
+                        pounder.devices().dither(updates);
+                    }
+                },
+            );
+            Systick::delay(1.millis()).await;
+        }
+    }
+
+    /// Service the Pounder amplitude controls, RF power servos and the
+    /// temperature watch.
+    #[task(priority = 1, shared=[settings, pounder, dds, dds_scan, telemetry, interlock])]
+    async fn pounder_service(mut c: pounder_service::Context) {
+        if c.shared.pounder.lock(|pounder| pounder.is_none()) {
+            return;
+        }
+        const PERIOD: f32 = 0.01;
+        loop {
+            Systick::delay(10.millis()).await;
+            let codes = c.shared.telemetry.lock(|telemetry| telemetry.adcs);
+            let tripped =
+                c.shared.interlock.lock(|interlock| interlock.tripped());
+            (&mut c.shared.settings, &mut c.shared.pounder).lock(
+                |settings, pounder| {
+                    let Some(pounder) = pounder else {
+                        return;
+                    };
+                    let s = &settings.dual_iir;
+                    let adc = [0, 1]
+                        .map(|i| f32::from(codes[i]) / s.ch[i].gain.gain());
+                    pounder.service(
+                        &s.pounder,
+                        adc,
+                        tripped,
+                        PERIOD,
+                        &mut c.shared.dds,
+                        &mut c.shared.dds_scan,
+                    );
+                },
+            );
+        }
+    }
+
+    /// Verify the presence of the external Pounder reference clock.
+    ///
+    /// The DDS SYNC_CLK advances the Pounder timestamps. Without progress the on-board oscillator
+    /// is selected again.
+    #[task(priority = 1, shared=[pounder, sync_phase, standby])]
+    async fn clock_check(mut c: clock_check::Context) {
+        // Allow the DDS PLL to lock.
+        Systick::delay(10.millis()).await;
+        let start = c.shared.sync_phase.lock(|s| s.as_ref().map(|s| s.total()));
+        Systick::delay(100.millis()).await;
+        let end = c.shared.sync_phase.lock(|s| s.as_ref().map(|s| s.total()));
+        let standby = c.shared.standby.lock(|standby| *standby);
+        match start.zip(end) {
+            Some((start, end)) if !standby && start == end => {
+                log::warn!(
+                    "External Pounder clock absent, using the oscillator"
+                );
+                c.shared.pounder.lock(|pounder| {
+                    if let Some(pounder) = pounder {
+                        if let Err(err) = pounder.devices().set_ext_clk(false) {
+                            log::error!(
+                                "Failed to select the oscillator: {err:?}"
+                            );
+                        }
+                    }
+                });
+            }
+            Some(_) if !standby => {
+                log::info!("External Pounder reference clock detected")
+            }
+            _ => log::warn!("External Pounder reference clock not verified"),
+        }
+    }
+
+    /// Synchronize the phases of the Pounder DDS channels.
+    #[task(priority = 1, shared=[pounder, dds])]
+    async fn dds_sync(mut c: dds_sync::Context) {
+        for clear in [true, false] {
+            (&mut c.shared.pounder, &mut c.shared.dds).lock(|pounder, dds| {
+                if let (Some(pounder), Some(dds)) = (pounder, dds) {
+                    pounder.devices().sync(clear, dds);
+                }
+            });
+            // Release only after the IO_Update of the hold.
+            Systick::delay(1.millis()).await;
+        }
+    }
+
+    /// Read back and verify the Pounder DDS profiles one channel at a time.
+    #[task(priority = 1, shared=[pounder, dds])]
+    async fn dds_verify(mut c: dds_verify::Context) {
+        loop {
+            // Verify after the IO_Update of the profiles.
+            Systick::delay(1.millis()).await;
+            let pending = (&mut c.shared.pounder, &mut c.shared.dds).lock(
+                |pounder, dds| match (pounder, dds) {
+                    (Some(pounder), Some(dds)) => {
+                        pounder.devices().verify_dds(dds)
+                    }
+                    _ => Ok(false),
+                },
+            );
+            match pending {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    log::error!("DDS readback failed: {err:?}");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Ramp the Pounder attenuators to their targets.
+    #[task(priority = 1, shared=[settings, pounder])]
+    async fn attenuation_ramp(mut c: attenuation_ramp::Context) {
+        loop {
+            let ramping = c.shared.pounder.lock(|pounder| {
+                pounder.as_mut().map(|p| {
+                    p.devices().ramp_attenuators(Systick::now().ticks())
+                })
+            });
+            match ramping {
+                Some(Ok(true)) => {}
+                Some(Err(err)) => {
+                    log::error!("Failed to ramp attenuation: {err:?}");
+                    break;
+                }
+                _ => break,
+            }
+            Systick::delay(1.millis()).await;
+        }
+        // The attenuation status LEDs follow the completed ramps.
+        (&mut c.shared.settings, &mut c.shared.pounder).lock(
+            |settings, pounder| {
+                if let Some(pounder) = pounder {
+                    pounder
+                        .devices()
+                        .update_leds(&settings.dual_iir.pounder)
+                        .ok();
+                }
+            },
+        );
+    }
+
+    /// Convert the enabled auxiliary inputs in turn.
+    #[task(priority = 1, shared=[settings, aux, faults], local=[aux_inputs])]
+    async fn aux(mut c: aux::Context) {
+        let inputs = c.local.aux_inputs.as_mut().unwrap();
+        loop {
+            let config = c
+                .shared
+                .settings
+                .lock(|settings| settings.dual_iir.aux.clone());
+            inputs.poll(&config);
+            let values = inputs.values();
+            c.shared.aux.lock(|aux| *aux = values);
+            let errors = inputs.take_errors();
+            c.shared
+                .faults
+                .lock(|faults| faults.record(Fault::I2c, errors));
             Systick::delay(10.millis()).await;
         }
     }
@@ -555,6 +3713,16 @@ mod app {
         }
     }
 
+    /// Drive the PWM outputs.
+    #[task(binds = TIM4, priority = 3, shared=[pwm])]
+    fn pwm(mut c: pwm::Context) {
+        c.shared.pwm.lock(|pwm| {
+            if let Some(pwm) = pwm {
+                pwm.update();
+            }
+        });
+    }
+
     #[task(binds = ETH, priority = 1)]
     fn eth(_: eth::Context) {
         unsafe { hal::ethernet::interrupt_handler() }
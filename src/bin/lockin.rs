@@ -10,7 +10,7 @@
 //!     1. Internal: Generate reference internally and output on one of the channel outputs
 //!     2. External: Reciprocal PLL, reference input applied to DI0.
 //! * Adjustable PLL and locking time constants
-//! * Adjustable phase offset and harmonic index
+//! * Adjustable phase offset and harmonic index, including fractional and sub-harmonic ratios
 //! * Run-time configurable output modes (in-phase, quadrature, magnitude, log2 power, phase, frequency)
 //! * Input/output data streamng via UDP
 //!
@@ -22,8 +22,9 @@
 //! Refer to [stabilizer::telemetry::Telemetry] for information about telemetry reported by this application.
 //!
 //! ## Stream
-//! This application streams raw ADC and DAC data over UDP. Refer to
-//! [stream] for more information.
+//! This application streams raw ADC and DAC data over UDP. Alternatively the demodulated
+//! in-phase and quadrature components can be streamed at the decimated rate using the
+//! [stream::Format::LockinIq] format. Refer to [stream] for more information.
 #![cfg_attr(target_os = "none", no_std)]
 #![cfg_attr(target_os = "none", no_main)]
 
@@ -39,9 +40,16 @@ use miniconf::{Leaf, Tree};
 use rtic_monotonics::Monotonic;
 use serde::{Deserialize, Serialize};
 
-use stabilizer::convert::{AdcCode, DacCode, Gain};
+use stabilizer::{
+    convert::{AdcCode, DacCode, Gain},
+    dsp::PhaseRatio,
+    faults::{Fault, Faults},
+};
 
-use platform::{AppSettings, NetSettings};
+use platform::{
+    AppSettings, DIGITAL_INPUTS, DigitalInputConfig, InterlockConfig, Invalid,
+    NetSettings, Validate,
+};
 
 // The logarithm of the number of samples in each batch process. This corresponds with 2^3 samples
 // per batch = 8 samples
@@ -62,6 +70,10 @@ pub struct Settings {
 }
 
 impl AppSettings for Settings {
+    const NAME: &'static str = env!("CARGO_BIN_NAME");
+    /// Settings have only been added since schema versioning was introduced.
+    const VERSION: u32 = 1;
+
     fn new(net: NetSettings) -> Self {
         Self {
             net,
@@ -74,6 +86,12 @@ impl AppSettings for Settings {
     }
 }
 
+impl Validate for Settings {
+    fn validate(&self) -> Result<(), Invalid> {
+        self.lockin.validate()
+    }
+}
+
 impl serial_settings::Settings for Settings {
     fn reset(&mut self) {
         *self = Self {
@@ -133,6 +151,14 @@ pub struct Lockin {
     /// Harmonic index of the LO. -1 to _de_modulate the fundamental (complex conjugate)
     lockin_harmonic: i32,
 
+    /// Specifies the sub-harmonic divisor of the LO.
+    ///
+    /// The LO frequency is `lockin_harmonic / lockin_divisor` times the reference frequency,
+    /// e.g. `-1 / 2` to demodulate at half the reference frequency. With a divisor `q` above
+    /// one, the LO phase relative to the reference is ambiguous by multiples of `1 / q` turns
+    /// and is fixed when the lockin starts tracking.
+    lockin_divisor: u16,
+
     /// Specifies the LO phase offset.
     ///
     /// Demodulation LO phase offset. Units are in terms of i32, where [i32::MIN] is equivalent to
@@ -145,9 +171,23 @@ pub struct Lockin {
     /// Specifies the telemetry output period in seconds.
     telemetry_period: u16,
 
-    /// Specifies the target for data streaming.
-    #[tree(with=miniconf::leaf)]
-    stream: stream::Target,
+    /// Clear the latched faults reported in telemetry (`faults`).
+    ///
+    /// The fault counters are retained.
+    clear_faults: bool,
+
+    /// Specifies the target and framing for data streaming.
+    stream: stream::Config,
+
+    /// Interlock
+    ///
+    /// While tripped both DAC outputs are driven to 0 V.
+    interlock: InterlockConfig,
+
+    /// Digital input roles and polarities.
+    ///
+    /// Only the `Interlock` role is supported.
+    di: [DigitalInputConfig; DIGITAL_INPUTS],
 }
 
 impl Default for Lockin {
@@ -161,14 +201,38 @@ impl Default for Lockin {
 
             lockin_k: [0x8_0000, -0x400_0000], // lockin lowpass gains
             lockin_harmonic: -1, // Harmonic index of the LO: -1 to _de_modulate the fundamental (complex conjugate)
+            lockin_divisor: 1,   // Sub-harmonic divisor of the LO
             lockin_phase: 0,     // Demodulation LO phase offset
 
             output_conf: [Leaf(Conf::InPhase), Leaf(Conf::Quadrature)],
             // The default telemetry period in seconds.
             telemetry_period: 10,
+            clear_faults: false,
 
             stream: Default::default(),
+
+            interlock: Default::default(),
+
+            di: Default::default(),
+        }
+    }
+}
+
+impl Lockin {
+    /// Validate the settings.
+    fn validate(&self) -> Result<(), Invalid> {
+        for (i, tc) in self.pll_tc.iter().enumerate() {
+            if !(1..=31).contains(tc) {
+                return Err(Invalid::new(
+                    format_args!("/lockin/pll_tc/{i}"),
+                    "Out of range (1-31)",
+                ));
+            }
+        }
+        if self.telemetry_period == 0 {
+            return Err(Invalid::new("/lockin/telemetry_period", "Zero"));
         }
+        Ok(())
     }
 }
 
@@ -191,10 +255,11 @@ fn main() {
 #[rtic::app(device = stabilizer::hardware::hal::stm32, peripherals = true, dispatchers=[DCMI, JPEG, SDMMC])]
 mod app {
     use super::*;
+    use platform::{ConnectionWatchdog, Inputs, Interlock};
     use stabilizer::{
         hardware::{
             self, DigitalInput0, DigitalInput1, Pgia, SerialTerminal,
-            SystemTimer, Systick, UsbDevice,
+            SystemTimer, Systick, UsbDevice, UsbEthernet,
             adc::{Adc0Input, Adc1Input},
             dac::{Dac0Output, Dac1Output},
             hal,
@@ -212,12 +277,15 @@ mod app {
         network: NetworkUsers<Lockin>,
         settings: Settings,
         active_settings: Lockin,
+        failsafe: bool,
         telemetry: TelemetryBuffer,
+        interlock: Interlock,
     }
 
     #[local]
     struct Local {
         usb_terminal: SerialTerminal<Settings>,
+        usb_ethernet: UsbEthernet,
         sampling_timer: SamplingTimer,
         digital_inputs: (DigitalInput0, DigitalInput1),
         timestamper: InputStamper,
@@ -248,10 +316,13 @@ mod app {
         let mut network = NetworkUsers::new(
             stabilizer.network_devices.stack,
             stabilizer.network_devices.phy,
+            stabilizer.network_devices.nonce,
             clock,
             env!("CARGO_BIN_NAME"),
             &stabilizer.settings.net,
             stabilizer.metadata,
+            stabilizer.network_devices.provision,
+            stabilizer.network_devices.safe_boot,
         );
 
         let generator = network.configure_streaming(stream::Format::AdcDacData);
@@ -261,11 +332,14 @@ mod app {
             usb: stabilizer.usb,
             telemetry: TelemetryBuffer::default(),
             active_settings: stabilizer.settings.lockin.clone(),
+            failsafe: false,
+            interlock: Interlock::default(),
             settings: stabilizer.settings,
         };
 
         let mut local = Local {
             usb_terminal: stabilizer.usb_serial,
+            usb_ethernet: stabilizer.usb_ethernet,
             sampling_timer: stabilizer.sampling_timer,
             digital_inputs: stabilizer.digital_inputs,
             afes: stabilizer.afes,
@@ -293,6 +367,7 @@ mod app {
         settings_update::spawn().unwrap();
         telemetry::spawn().unwrap();
         ethernet_link::spawn().unwrap();
+        watchdog::spawn().unwrap();
         start::spawn().unwrap();
         usb::spawn().unwrap();
 
@@ -319,16 +394,21 @@ mod app {
     /// This is an implementation of a externally (DI0) referenced PLL lockin on the ADC0 signal.
     /// It outputs either I/Q or power/phase on DAC0/DAC1. Data is normalized to full scale.
     /// PLL bandwidth, filter bandwidth, slope, and x/y or power/phase post-filters are available.
-    #[task(binds=DMA1_STR4, shared=[active_settings, telemetry], local=[adcs, dacs, lockin, timestamper, pll, generator, source], priority=3)]
+    #[task(binds=DMA1_STR4, shared=[active_settings, failsafe, telemetry], local=[digital_inputs, adcs, dacs, lockin, timestamper, pll, generator, source, phase_ratio: PhaseRatio = PhaseRatio::new()], priority=3)]
     #[unsafe(link_section = ".itcm.process")]
     fn process(c: process::Context) {
+        let start = cortex_m::peripheral::DWT::cycle_count();
         let process::SharedResources {
             active_settings,
+            mut failsafe,
             telemetry,
             ..
         } = c.shared;
 
+        let failsafe = failsafe.lock(|failsafe| *failsafe);
+
         let process::LocalResources {
+            digital_inputs,
             timestamper,
             adcs: (adc0, adc1),
             dacs: (dac0, dac1),
@@ -336,9 +416,12 @@ mod app {
             lockin,
             source,
             generator,
+            phase_ratio,
             ..
         } = c.local;
 
+        let levels = [digital_inputs.0.is_high(), digital_inputs.1.is_high()];
+
         (active_settings, telemetry).lock(|settings, telemetry| {
             let (reference_phase, reference_frequency) =
                 match settings.lockin_mode {
@@ -358,11 +441,22 @@ mod app {
                     }
                 };
 
-            let sample_frequency =
-                reference_frequency.wrapping_mul(settings.lockin_harmonic);
-            let sample_phase = settings.lockin_phase.wrapping_add(
-                reference_phase.wrapping_mul(settings.lockin_harmonic),
-            );
+            let (sample_phase, sample_frequency) = if settings.lockin_divisor
+                > 1
+            {
+                phase_ratio.update(
+                    reference_phase,
+                    reference_frequency,
+                    settings.lockin_harmonic,
+                    settings.lockin_divisor,
+                )
+            } else {
+                (
+                    reference_phase.wrapping_mul(settings.lockin_harmonic),
+                    reference_frequency.wrapping_mul(settings.lockin_harmonic),
+                )
+            };
+            let sample_phase = settings.lockin_phase.wrapping_add(sample_phase);
 
             (adc0, adc1, dac0, dac1).lock(|adc0, adc1, dac0, dac1| {
                 let adc_samples = [adc0, adc1];
@@ -405,32 +499,74 @@ mod app {
                     }
                 }
 
+                if failsafe {
+                    for dac in dac_samples.iter_mut() {
+                        dac.fill(DacCode::from(0i16).0);
+                    }
+                }
+
+                telemetry.latency.update(
+                    cortex_m::peripheral::DWT::cycle_count()
+                        .wrapping_sub(start),
+                );
+
                 // Stream the data.
-                const N: usize = BATCH_SIZE * size_of::<i16>()
-                    / size_of::<MaybeUninit<u8>>();
-                generator.add(|buf| {
-                    for (data, buf) in adc_samples
-                        .iter()
-                        .chain(dac_samples.iter())
-                        .zip(buf.chunks_exact_mut(N))
-                    {
-                        let data = unsafe {
-                            core::slice::from_raw_parts(
-                                data.as_ptr() as *const MaybeUninit<u8>,
-                                N,
-                            )
-                        };
-                        buf.copy_from_slice(data)
+                match settings.stream.format {
+                    stream::Format::LockinIq => {
+                        generator.configure(stream::Format::LockinIq);
+                        generator.add(|buf| {
+                            let data = [sample_frequency, output.re, output.im];
+                            for (byte, buf) in data
+                                .iter()
+                                .flat_map(|x| x.to_le_bytes())
+                                .zip(buf.iter_mut())
+                            {
+                                buf.write(byte);
+                            }
+                            size_of_val(&data)
+                        });
                     }
-                    N * 4
-                });
+                    _ => {
+                        generator.configure(stream::Format::AdcDacData);
+                        const N: usize = BATCH_SIZE * size_of::<i16>()
+                            / size_of::<MaybeUninit<u8>>();
+                        generator.add(|buf| {
+                            for (data, buf) in adc_samples
+                                .iter()
+                                .chain(dac_samples.iter())
+                                .zip(buf.chunks_exact_mut(N))
+                            {
+                                let data = unsafe {
+                                    core::slice::from_raw_parts(
+                                        data.as_ptr() as *const MaybeUninit<u8>,
+                                        N,
+                                    )
+                                };
+                                buf.copy_from_slice(data)
+                            }
+                            N * 4
+                        });
+                    }
+                }
 
                 // Update telemetry measurements.
-                telemetry.adcs =
-                    [AdcCode(adc_samples[0][0]), AdcCode(adc_samples[1][0])];
-
-                telemetry.dacs =
-                    [DacCode(dac_samples[0][0]), DacCode(dac_samples[1][0])];
+                for (rms, adc) in
+                    telemetry.rms.iter_mut().zip(adc_samples.iter())
+                {
+                    for x in adc.iter() {
+                        rms.update(*x as i16);
+                    }
+                }
+                telemetry.update(
+                    [AdcCode(adc_samples[0][0]), AdcCode(adc_samples[1][0])],
+                    [DacCode(dac_samples[0][0]), DacCode(dac_samples[1][0])],
+                    levels,
+                );
+                telemetry.saturation += dac_samples
+                    .iter()
+                    .flat_map(|d| d.iter())
+                    .any(|code| *code == 0 || *code == u16::MAX)
+                    as u32;
 
                 // Preserve instruction and data ordering w.r.t. DMA flag access.
                 fence(Ordering::SeqCst);
@@ -464,12 +600,13 @@ mod app {
     #[task(priority = 1, local=[afes], shared=[network, settings, active_settings])]
     async fn settings_update(mut c: settings_update::Context) {
         c.shared.settings.lock(|settings| {
+            hardware::LOGGER.configure(&settings.net.log);
             c.local.afes[0].set_gain(*settings.lockin.afe[0]);
             c.local.afes[1].set_gain(*settings.lockin.afe[1]);
 
             c.shared
                 .network
-                .lock(|net| net.direct_stream(settings.lockin.stream));
+                .lock(|net| net.direct_stream(&settings.lockin.stream));
 
             c.shared
                 .active_settings
@@ -477,31 +614,71 @@ mod app {
         });
     }
 
-    #[task(priority = 1, local=[digital_inputs, cpu_temp_sensor], shared=[network, settings, telemetry])]
+    #[task(priority = 1, shared=[network, settings, telemetry, interlock])]
     async fn telemetry(mut c: telemetry::Context) {
+        let mut faults = Faults::default();
+        // Stream losses and interlock state at the last report
+        let mut stream_losses = 0u32;
+        let mut tripped = false;
         loop {
-            let mut telemetry =
-                c.shared.telemetry.lock(|telemetry| telemetry.clone());
-
-            telemetry.digital_inputs = [
-                c.local.digital_inputs.0.is_high(),
-                c.local.digital_inputs.1.is_high(),
-            ];
+            // Capture the sampled quantities at one sample instant. The
+            // capture completes with the next batch unless processing is
+            // stalled.
+            c.shared.telemetry.lock(|telemetry| telemetry.request());
+            for _ in 0..10 {
+                Systick::delay(1.millis()).await;
+                if c.shared.telemetry.lock(|telemetry| telemetry.captured()) {
+                    break;
+                }
+            }
+            let telemetry =
+                c.shared.telemetry.lock(|telemetry| telemetry.snapshot());
 
-            let (gains, telemetry_period) =
+            let (gains, telemetry_period, retained, clear) =
                 c.shared.settings.lock(|settings| {
-                    (settings.lockin.afe, settings.lockin.telemetry_period)
+                    (
+                        settings.lockin.afe,
+                        settings.lockin.telemetry_period,
+                        settings.net.retained_telemetry,
+                        core::mem::replace(
+                            &mut settings.lockin.clear_faults,
+                            false,
+                        ),
+                    )
                 });
+            let saturation = telemetry.saturation;
+
+            let mut telemetry = telemetry.finalize(*gains[0], *gains[1]);
+            telemetry.interlock =
+                c.shared.interlock.lock(|interlock| interlock.tripped());
+
+            let stats = c.shared.network.lock(|net| net.stream_statistics());
+            let losses = stats.dropped.wrapping_add(stats.errors);
+            if clear {
+                faults.clear();
+            }
+            faults.record(Fault::Dma, hardware::take_dma_errors());
+            faults.record(Fault::Stream, losses.wrapping_sub(stream_losses));
+            faults.record(Fault::Saturation, saturation);
+            faults.record(
+                Fault::Interlock,
+                (telemetry.interlock && !tripped) as u32,
+            );
+            stream_losses = losses;
+            tripped = telemetry.interlock;
+            telemetry.faults = faults;
 
             c.shared.network.lock(|net| {
-                net.telemetry.publish_telemetry(
+                net.telemetry.publish_telemetry("/telemetry", &telemetry);
+                net.telemetry.publish_retained(
                     "/telemetry",
-                    &telemetry.finalize(
-                        *gains[0],
-                        *gains[1],
-                        c.local.cpu_temp_sensor.get_temperature().unwrap(),
-                    ),
-                )
+                    &telemetry,
+                    Systick::now().ticks(),
+                    retained,
+                );
+                net.serve_telemetry(&telemetry);
+
+                net.telemetry.publish_telemetry("/stream", &stats);
             });
 
             // Schedule the telemetry task in the future.
@@ -509,16 +686,15 @@ mod app {
         }
     }
 
-    #[task(priority = 1, shared=[usb, settings], local=[usb_terminal])]
+    #[task(priority = 1, shared=[usb, network, settings, interlock], local=[usb_terminal, usb_ethernet])]
     async fn usb(mut c: usb::Context) {
         loop {
-            // Handle the USB serial terminal.
+            // Handle the USB serial terminal and network interface.
             c.shared.usb.lock(|usb| {
-                usb.poll(&mut [c
-                    .local
-                    .usb_terminal
-                    .interface_mut()
-                    .inner_mut()]);
+                usb.poll(&mut [
+                    c.local.usb_terminal.interface_mut().inner_mut(),
+                    c.local.usb_ethernet,
+                ]);
             });
 
             c.shared.settings.lock(|settings| {
@@ -527,7 +703,91 @@ mod app {
                 }
             });
 
-            Systick::delay(10.millis()).await;
+            let provisioned = (&mut c.shared.network, &mut c.shared.settings)
+                .lock(|net, settings| {
+                    net.take_provisioned(|provisioned| {
+                        c.local
+                            .usb_terminal
+                            .platform_mut()
+                            .provision(settings, provisioned)
+                    })
+                });
+            match provisioned {
+                Some(0) => log::warn!("No settings provisioned"),
+                Some(count) => {
+                    log::info!("Provisioned {count} settings. Rebooting");
+                    cortex_m::peripheral::SCB::sys_reset();
+                }
+                None => {}
+            }
+
+            let platform = c.local.usb_terminal.platform_mut();
+            platform.update_counters(Systick::now().ticks());
+            platform.record_interlock_trips(
+                c.shared.interlock.lock(|i| i.take_trips()),
+            );
+
+            // Poll faster while the host exchanges frames over the USB network interface.
+            let period = if c.local.usb_ethernet.is_active() {
+                1
+            } else {
+                10
+            };
+            Systick::delay(period.millis()).await;
+        }
+    }
+
+    /// Drive the outputs to the failsafe state while the broker is unreachable
+    /// or the interlock is tripped.
+    #[task(priority = 1, shared=[network, settings, failsafe, telemetry, interlock], local=[cpu_temp_sensor])]
+    async fn watchdog(mut c: watchdog::Context) {
+        let mut watchdog = ConnectionWatchdog::default();
+        loop {
+            let (timeout, config, reset, gains, di_config) =
+                c.shared.settings.lock(|settings| {
+                    let s = &mut settings.lockin;
+                    (
+                        settings.net.watchdog,
+                        s.interlock.clone(),
+                        core::mem::replace(&mut s.interlock.reset, false),
+                        s.afe,
+                        s.di,
+                    )
+                });
+            let connected = c.shared.network.lock(|net| net.is_connected());
+            let timeout = watchdog.update(connected, 0.1, timeout);
+
+            let temperature =
+                c.local.cpu_temp_sensor.get_temperature().unwrap();
+            let (di, adc) = c.shared.telemetry.lock(|telemetry| {
+                telemetry.cpu_temp = temperature;
+                (telemetry.digital_inputs, telemetry.adcs)
+            });
+            let inputs = Inputs {
+                di,
+                adc: [0, 1].map(|i| f32::from(adc[i]) / gains[i].gain()),
+                temperature,
+                timeout,
+                external: Inputs::external(&di_config, di),
+                ..Default::default()
+            };
+            let interlock = c.shared.interlock.lock(|interlock| {
+                if reset {
+                    interlock.reset();
+                }
+                interlock.update(&config, &inputs)
+            });
+
+            let tripped = timeout || interlock;
+            let changed = c.shared.failsafe.lock(|failsafe| {
+                core::mem::replace(failsafe, tripped) != tripped
+            });
+            if changed {
+                log::warn!(
+                    "Failsafe: {tripped} (watchdog: {timeout}, interlock: {interlock})"
+                );
+            }
+            Systick::delay(100.millis()).await;
         }
     }
 
@@ -0,0 +1,33 @@
+//! Input offset calibration
+//!
+//! # Design
+//! The ADC input offsets of each channel are measured at each AFE gain with shorted inputs. They
+//! are persisted in the settings flash next to the settings and are not part of the settings.
+//! Applications subtract the offset of the configured gain from the ADC samples before processing
+//! and reporting them.
+use crate::convert::Gain;
+use serde::{Deserialize, Serialize};
+
+/// Input offset calibration
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InputCalibration {
+    /// ADC offsets of input 0/1 in LSB at the gains G1, G2, G5 and G10
+    pub offset: [[i16; 4]; 2],
+}
+
+impl InputCalibration {
+    /// The ADC offset of an input.
+    ///
+    /// # Args
+    /// * `channel` - The input channel.
+    /// * `gain` - The AFE gain.
+    ///
+    /// # Returns
+    /// The offset in LSB, zero for invalid channels.
+    pub fn offset(&self, channel: usize, gain: Gain) -> i16 {
+        self.offset
+            .get(channel)
+            .map(|offset| offset[gain as usize])
+            .unwrap_or(0)
+    }
+}
@@ -11,6 +11,17 @@ pub const ADC_SETUP_TIME: f32 = 220e-9;
 /// The maximum DAC/ADC serial clock line frequency. This is a hardware limit.
 pub const ADC_DAC_SCK_MAX: MegaHertz = MegaHertz::MHz(50);
 
+/// Network buffer sizes in bytes (or UDP packets) selected at build time.
+///
+/// Set the `STABILIZER_<NAME>` environment variables when building to override the defaults.
+pub mod net_buffers {
+    include!(concat!(env!("OUT_DIR"), "/net_buffers.rs"));
+}
+
+/// The number of sampling timer ticks reserved for an ADC conversion and transfer after its
+/// trigger: the setup time and 16 bits at the maximum serial clock, with margin.
+pub const ADC_TRANSFER_TICKS: u32 = 64;
+
 /// The optimal counting frequency of the hardware timers used for timestamping and sampling.
 pub const TIMER_FREQUENCY: MegaHertz = MegaHertz::MHz(100);
 pub const TIMER_PERIOD: f32 = 1. / (TIMER_FREQUENCY.to_Hz() as f32);
@@ -0,0 +1,116 @@
+use idsp::iir::Biquad;
+use num_traits::Float;
+use serde::{Deserialize, Serialize};
+
+/// All-pass section
+///
+/// # Design
+/// An all-pass section has unity magnitude response at all frequencies and shapes the phase only.
+/// The first order section shifts the phase from 0 at DC to -180 degrees at Nyquist, passing -90
+/// degrees at the corner frequency. The second order section shifts the phase from 0 to -360
+/// degrees, passing -180 degrees at the corner frequency. Its quality factor sets the width of the
+/// transition. This allows shaping the loop phase near the crossover without changing the loop
+/// gain. The coefficients are those of the bilinear transform of the analog prototypes.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct AllPass {
+    /// Corner frequency (Hz)
+    pub frequency: f32,
+    /// Quality factor of a second order section, `null` for a first order section
+    pub q: Option<f32>,
+}
+
+impl AllPass {
+    /// Compute the section coefficients.
+    ///
+    /// # Args
+    /// * `period` - The sample period in seconds.
+    /// * `limit` - The output magnitude limit.
+    pub fn biquad(&self, period: f32, limit: f32) -> Biquad<f32> {
+        let w0 = core::f32::consts::TAU * self.frequency * period;
+        // `idsp` sign convention: `a1` and `a2` are negated.
+        let ba = if let Some(q) = self.q {
+            let alpha = w0.sin() / (2.0 * q);
+            let a0 = 1.0 + alpha;
+            let c = 2.0 * w0.cos() / a0;
+            let r = (1.0 - alpha) / a0;
+            [r, -c, 1.0, c, -r]
+        } else {
+            let k = (w0 / 2.0).tan();
+            let c = (k - 1.0) / (k + 1.0);
+            [c, 1.0, 0.0, -c, 0.0]
+        };
+        let mut biquad = Biquad::from(ba);
+        biquad.set_min(-limit);
+        biquad.set_max(limit);
+        biquad
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Magnitude and phase of the biquad at an angular frequency
+    fn response(biquad: &Biquad<f32>, omega: f32) -> (f32, f32) {
+        let &[b0, b1, b2, a1, a2] = biquad.ba();
+        let (s, c) = omega.sin_cos();
+        let (s2, c2) = (2.0 * omega).sin_cos();
+        let num = (b0 + b1 * c + b2 * c2, -b1 * s - b2 * s2);
+        let den = (1.0 - a1 * c - a2 * c2, a1 * s + a2 * s2);
+        let mag = (num.0.hypot(num.1)) / den.0.hypot(den.1);
+        (mag, num.1.atan2(num.0) - den.1.atan2(den.0))
+    }
+
+    fn sections() -> [AllPass; 2] {
+        [
+            AllPass {
+                frequency: 1e3,
+                q: None,
+            },
+            AllPass {
+                frequency: 1e3,
+                q: Some(1.0),
+            },
+        ]
+    }
+
+    #[test]
+    fn unity_magnitude() {
+        for ap in sections() {
+            let biquad = ap.biquad(1e-5, 1e6);
+            for omega in [0.0, 0.01, 0.3, 1.0, 2.0, 3.0] {
+                assert!((response(&biquad, omega).0 - 1.0).abs() < 1e-4);
+            }
+            // Unity DC gain
+            assert!(response(&biquad, 0.0).1.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn corner_phase() {
+        let omega = core::f32::consts::TAU * 1e3 * 1e-5;
+        let [first, second] = sections().map(|ap| ap.biquad(1e-5, 1e6));
+        let wrap = |p: f32| p.sin().atan2(p.cos());
+        let phase = wrap(response(&first, omega).1);
+        assert!((phase + core::f32::consts::FRAC_PI_2).abs() < 1e-3);
+        let phase = wrap(response(&second, omega).1);
+        assert!(core::f32::consts::PI - phase.abs() < 1e-3);
+    }
+
+    #[test]
+    fn impulse_response() {
+        for ap in sections() {
+            let biquad = ap.biquad(1e-5, 1e6);
+            let mut state = [0.0; 4];
+            let mut energy = 0.0;
+            let mut y = 0.0;
+            for n in 0..5000 {
+                y = biquad.update(&mut state, if n == 0 { 1.0 } else { 0.0 });
+                energy += y * y;
+            }
+            // Stable and lossless
+            assert!(y.abs() < 1e-6);
+            assert!((energy - 1.0).abs() < 1e-3);
+        }
+    }
+}
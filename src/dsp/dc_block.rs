@@ -0,0 +1,93 @@
+/// Single-pole DC blocker (first order highpass).
+///
+/// # Design
+/// The DC estimate is tracked by a first order lowpass and subtracted from the input. The
+/// lowpass is implemented in fixed point with a 64 bit accumulator (32 fractional bits) so that
+/// corner frequencies far below 1 Hz do not suffer from the coefficient and state precision
+/// issues of a floating point biquad.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DcBlock {
+    state: i64,
+}
+
+impl DcBlock {
+    /// Compute the lowpass gain for a given corner frequency.
+    ///
+    /// # Args
+    /// * `corner` - The corner frequency in Hz.
+    /// * `period` - The sample period in seconds.
+    ///
+    /// # Returns
+    /// The lowpass gain as a fraction of `1 << 32`.
+    pub fn gain(corner: f32, period: f32) -> u32 {
+        // The float to integer conversion saturates.
+        (core::f32::consts::TAU * corner * period * (1u64 << 32) as f32) as u32
+    }
+
+    /// Get the current DC estimate.
+    pub fn dc(&self) -> i32 {
+        (self.state >> 32) as i32
+    }
+
+    /// Scale the DC estimate, e.g. after a change of the input gain.
+    ///
+    /// # Args
+    /// * `factor` - The scale factor.
+    pub fn scale(&mut self, factor: f32) {
+        // The float to integer conversion saturates.
+        self.state = (self.state as f64 * factor as f64) as i64;
+    }
+
+    /// Process a sample.
+    ///
+    /// # Args
+    /// * `x` - The input sample.
+    /// * `gain` - The lowpass gain as computed by [DcBlock::gain].
+    ///
+    /// # Returns
+    /// The input with the DC estimate removed.
+    pub fn update(&mut self, x: i32, gain: u32) -> i32 {
+        let y = x - self.dc();
+        self.state += y as i64 * gain as i64;
+        y
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn step_response() {
+        let gain = DcBlock::gain(1.0, 1e-3);
+        let mut dc = DcBlock::default();
+        // The step passes initially.
+        assert_eq!(dc.update(1000, gain), 1000);
+        let mut last = 1000;
+        for _ in 0..10_000 {
+            let y = dc.update(1000, gain);
+            // The response decays monotonically without overshoot.
+            assert!((0..=last).contains(&y));
+            last = y;
+        }
+        assert!(last <= 1);
+        assert!((999..=1000).contains(&dc.dc()));
+    }
+
+    #[test]
+    fn scale() {
+        let gain = DcBlock::gain(10.0, 1e-3);
+        let mut dc = DcBlock::default();
+        for _ in 0..10_000 {
+            dc.update(-2000, gain);
+        }
+        dc.scale(0.5);
+        assert!((-1001..=-999).contains(&dc.dc()));
+    }
+
+    #[test]
+    fn gain_saturates() {
+        assert_eq!(DcBlock::gain(0.0, 1e-3), 0);
+        assert_eq!(DcBlock::gain(1e9, 1.0), u32::MAX);
+    }
+}
@@ -0,0 +1,373 @@
+use heapless::Vec;
+use idsp::iir::{self, Biquad};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of blocks of a processing chain.
+pub const GRAPH_BLOCKS: usize = 8;
+
+/// Maximum number of FIR filter taps.
+pub const FIR_TAPS: usize = 16;
+
+/// Maximum number of lookup table points.
+pub const LUT_POINTS: usize = 16;
+
+/// Processing chain block
+///
+/// Signals are in volts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Block {
+    /// Biquad section with normalized coefficients `[b0, b1, b2, a1, a2]`
+    ///
+    /// `idsp` sign convention: `y0 = b0 x0 + b1 x1 + b2 x2 + a1 y1 + a2 y2`. Consecutive sections
+    /// form a cascade.
+    Biquad([f32; 5]),
+    /// Biquad section designed on-device by `idsp` for the sample period
+    ///
+    /// Same representation as the biquad cascade (`Raw`, `Pid` or a filter design) with input and
+    /// output in volts.
+    Design(iir::BiquadRepr<f32, f32>),
+    /// FIR filter taps, the tap of the current sample first
+    Fir(Vec<f32, FIR_TAPS>),
+    /// Output limits `[min, max]`
+    Limit([f32; 2]),
+    /// Lookup table of `[input, output]` points sorted by input
+    ///
+    /// The output is linearly interpolated between points and held constant beyond the first and
+    /// the last point. An empty table passes the input.
+    Lut(Vec<[f32; 2], LUT_POINTS>),
+    /// Add the signal generator output scaled by the given factor
+    Generator(f32),
+    /// Pass while the digital input gate is asserted, else output zero
+    Gate,
+}
+
+/// Instantiated block with its state
+#[derive(Clone, Debug)]
+enum Stage {
+    Biquad(Biquad<f32>, [f32; 4]),
+    /// Taps, delay line, index of the current sample in the delay line
+    Fir(Vec<f32, FIR_TAPS>, [f32; FIR_TAPS], usize),
+    Limit([f32; 2]),
+    Lut(Vec<[f32; 2], LUT_POINTS>),
+    Generator(f32),
+    Gate,
+}
+
+impl Stage {
+    fn new(block: &Block, period: f32) -> Self {
+        match *block {
+            Block::Biquad(ba) => {
+                let mut biquad = Biquad::from(ba);
+                biquad.set_min(f32::MIN);
+                biquad.set_max(f32::MAX);
+                Self::Biquad(biquad, Default::default())
+            }
+            Block::Design(ref repr) => Self::Biquad(
+                repr.build::<f32>(period, 1.0, 1.0),
+                Default::default(),
+            ),
+            Block::Fir(ref taps) => Self::Fir(taps.clone(), [0.0; FIR_TAPS], 0),
+            Block::Limit(limits) => Self::Limit(limits),
+            Block::Lut(ref points) => Self::Lut(points.clone()),
+            Block::Generator(gain) => Self::Generator(gain),
+            Block::Gate => Self::Gate,
+        }
+    }
+
+    /// Update the block parameters, retaining the state if the stage type is unchanged.
+    fn set(&mut self, block: &Block, period: f32) {
+        let new = Self::new(block, period);
+        *self = match (core::mem::replace(self, Self::Gate), new) {
+            (Self::Biquad(_, state), Self::Biquad(biquad, _)) => {
+                Self::Biquad(biquad, state)
+            }
+            (Self::Fir(_, delay, index), Self::Fir(taps, ..)) => {
+                Self::Fir(taps, delay, index)
+            }
+            (_, new) => new,
+        };
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Self::Biquad(_, state) => *state = Default::default(),
+            Self::Fir(_, delay, _) => *delay = [0.0; FIR_TAPS],
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, x: f32, generator: f32, gate: bool) -> f32 {
+        match self {
+            Self::Biquad(biquad, state) => biquad.update(state, x),
+            Self::Fir(taps, delay, index) => {
+                *index = (*index + FIR_TAPS - 1) % FIR_TAPS;
+                delay[*index] = x;
+                taps.iter()
+                    .enumerate()
+                    .map(|(i, tap)| tap * delay[(*index + i) % FIR_TAPS])
+                    .sum()
+            }
+            Self::Limit([min, max]) => x.max(*min).min(*max),
+            Self::Lut(points) => {
+                let i = points.partition_point(|[p, _]| *p < x);
+                match (points.get(i.wrapping_sub(1)), points.get(i)) {
+                    (None, None) => x,
+                    (Some([_, y]), None) | (None, Some([_, y])) => *y,
+                    (Some([x0, y0]), Some([x1, y1])) => {
+                        y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+                    }
+                }
+            }
+            Self::Generator(gain) => x + *gain * generator,
+            Self::Gate if gate => x,
+            Self::Gate => 0.0,
+        }
+    }
+}
+
+/// User-configurable processing chain.
+///
+/// # Design
+/// The chain is an ordered list of blocks from a fixed library, instantiated from the settings.
+/// This covers topologies that otherwise require a modified application, e.g. a FIR pre-filter
+/// ahead of a biquad cascade with a nonlinear output map. Blocks are evaluated in order, each on
+/// the output of the previous block. When the chain is reconfigured, the states of blocks whose
+/// type is unchanged are retained so that coefficient updates do not disturb the output. Biquad
+/// blocks are either raw coefficients or designed on-device by `idsp` for the sample period of
+/// the chain so that they remain valid when the sample rate changes.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    stages: Vec<Stage, GRAPH_BLOCKS>,
+    /// Last output
+    y: f32,
+}
+
+impl Graph {
+    /// Instantiate a chain.
+    ///
+    /// # Args
+    /// * `blocks` - The blocks. Blocks beyond [GRAPH_BLOCKS] are ignored.
+    /// * `period` - The sample period in seconds.
+    pub fn new(blocks: &[Block], period: f32) -> Self {
+        let mut graph = Self::default();
+        graph.set(blocks, period);
+        graph
+    }
+
+    /// Reconfigure the chain.
+    ///
+    /// # Args
+    /// * `blocks` - The blocks. Blocks beyond [GRAPH_BLOCKS] are ignored.
+    /// * `period` - The sample period in seconds.
+    pub fn set(&mut self, blocks: &[Block], period: f32) {
+        let blocks = &blocks[..blocks.len().min(GRAPH_BLOCKS)];
+        self.stages.truncate(blocks.len());
+        for (stage, block) in self.stages.iter_mut().zip(blocks) {
+            stage.set(block, period);
+        }
+        for block in &blocks[self.stages.len()..] {
+            // Note(unwrap): The blocks are truncated to the capacity.
+            self.stages.push(Stage::new(block, period)).unwrap();
+        }
+    }
+
+    /// Clear the block states without changing the configuration.
+    pub fn clear(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.clear();
+        }
+        self.y = 0.0;
+    }
+
+    /// The last output.
+    pub fn output(&self) -> f32 {
+        self.y
+    }
+
+    /// Process a sample.
+    ///
+    /// # Args
+    /// * `x` - The input sample.
+    /// * `generator` - The signal generator output.
+    /// * `gate` - The digital input gate state.
+    ///
+    /// # Returns
+    /// The output sample.
+    pub fn update(&mut self, x: f32, generator: f32, gate: bool) -> f32 {
+        self.y = self
+            .stages
+            .iter_mut()
+            .fold(x, |x, stage| stage.update(x, generator, gate));
+        self.y
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use miniconf::json_core;
+
+    const PERIOD: f32 = 1e-5;
+
+    /// PID representation with the given settings
+    fn pid(settings: &[(&str, &str)]) -> iir::BiquadRepr<f32, f32> {
+        let mut repr = iir::BiquadRepr::Pid(Default::default());
+        for (path, value) in settings {
+            json_core::set(&mut repr, path, value.as_bytes()).unwrap();
+        }
+        repr
+    }
+
+    #[test]
+    fn design() {
+        for repr in [
+            iir::BiquadRepr::Raw(Biquad::from([0.5, 0.25, 0.0, 0.5, -0.25])),
+            pid(&[("/Pid/gain/p", "-0.1")]),
+            pid(&[
+                ("/Pid/order", "\"I\""),
+                ("/Pid/gain/p", "-0.1"),
+                ("/Pid/gain/i", "-1000"),
+                ("/Pid/min", "-10"),
+                ("/Pid/max", "10"),
+            ]),
+            pid(&[
+                ("/Pid/order", "\"I\""),
+                ("/Pid/gain/i", "20"),
+                ("/Pid/setpoint", "0.5"),
+            ]),
+        ] {
+            let reference = repr.build::<f32>(PERIOD, 1.0, 1.0);
+            let Stage::Biquad(biquad, _) =
+                Stage::new(&Block::Design(repr.clone()), PERIOD)
+            else {
+                panic!("not a biquad stage");
+            };
+            assert_eq!(biquad.ba(), reference.ba());
+            assert_eq!(biquad.u(), reference.u());
+            assert_eq!(biquad.min(), reference.min());
+            assert_eq!(biquad.max(), reference.max());
+
+            // The chain evaluates the design like the cascade
+            let mut graph = Graph::new(&[Block::Design(repr)], PERIOD);
+            let mut state = [0.0; 4];
+            for x in [1.0, 1.0, -0.5, 0.0, 0.25] {
+                assert_eq!(
+                    graph.update(x, 0.0, true),
+                    reference.update(&mut state, x)
+                );
+            }
+        }
+    }
+
+    fn run(blocks: &[Block], x: &[f32]) -> [f32; 8] {
+        let mut graph = Graph::new(blocks, PERIOD);
+        let mut y = [0.0; 8];
+        for (y, x) in y.iter_mut().zip(x) {
+            *y = graph.update(*x, 0.0, true);
+        }
+        y
+    }
+
+    #[test]
+    fn fir() {
+        let taps = Vec::from_slice(&[0.5, 0.25, -0.25]).unwrap();
+        assert_eq!(
+            run(
+                &[Block::Fir(taps)],
+                &[1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0]
+            ),
+            [0.5, 0.25, -0.25, 0.0, 1.0, 0.5, -0.5, 0.0]
+        );
+        let mut taps = Vec::new();
+        taps.resize(FIR_TAPS, 0.0).unwrap();
+        taps[FIR_TAPS - 1] = 1.0;
+        let mut graph = Graph::new(&[Block::Fir(taps)], PERIOD);
+        for i in 0..2 * FIR_TAPS {
+            let x = if i == 3 { 1.0 } else { 0.0 };
+            let y = if i == 3 + FIR_TAPS - 1 { 1.0 } else { 0.0 };
+            assert_eq!(graph.update(x, 0.0, true), y);
+        }
+    }
+
+    #[test]
+    fn lut() {
+        let lut = Block::Lut(
+            Vec::from_slice(&[[-1.0, -2.0], [0.0, 0.0], [1.0, 1.0]]).unwrap(),
+        );
+        let mut graph = Graph::new(&[lut], PERIOD);
+        for (x, y) in [
+            (-3.0, -2.0),
+            (-1.0, -2.0),
+            (-0.5, -1.0),
+            (0.0, 0.0),
+            (0.25, 0.25),
+            (1.0, 1.0),
+            (5.0, 1.0),
+        ] {
+            assert_eq!(graph.update(x, 0.0, true), y);
+        }
+        assert_eq!(run(&[Block::Lut(Vec::new())], &[0.5])[0], 0.5);
+    }
+
+    #[test]
+    fn blocks() {
+        let mut graph = Graph::new(
+            &[
+                Block::Limit([-1.0, 0.5]),
+                Block::Generator(2.0),
+                Block::Gate,
+            ],
+            PERIOD,
+        );
+        assert_eq!(graph.update(-3.0, 0.0, true), -1.0);
+        assert_eq!(graph.update(3.0, 0.0, true), 0.5);
+        assert_eq!(graph.update(0.25, 0.5, true), 1.25);
+        assert_eq!(graph.output(), 1.25);
+        assert_eq!(graph.update(0.25, 0.5, false), 0.0);
+        assert_eq!(run(&[], &[0.75])[0], 0.75);
+    }
+
+    #[test]
+    fn order() {
+        let limit = Block::Limit([-1.0, 1.0]);
+        let generator = Block::Generator(1.0);
+        let mut graph = Graph::new(&[limit.clone(), generator.clone()], PERIOD);
+        assert_eq!(graph.update(3.0, 1.0, true), 2.0);
+        graph.set(&[generator, limit], PERIOD);
+        assert_eq!(graph.update(3.0, 1.0, true), 1.0);
+
+        // Blocks beyond the capacity are ignored
+        let blocks: [_; GRAPH_BLOCKS + 1] =
+            core::array::from_fn(|_| Block::Generator(1.0));
+        let mut graph = Graph::new(&blocks, PERIOD);
+        assert_eq!(graph.update(0.0, 1.0, true), GRAPH_BLOCKS as f32);
+    }
+
+    #[test]
+    fn state() {
+        let delay = |gain| Block::Fir(Vec::from_slice(&[0.0, gain]).unwrap());
+        let mut graph = Graph::new(&[delay(1.0)], PERIOD);
+        assert_eq!(graph.update(1.0, 0.0, true), 0.0);
+        // Retained with the same block type
+        graph.set(&[delay(2.0)], PERIOD);
+        assert_eq!(graph.update(0.0, 0.0, true), 2.0);
+        graph.update(1.0, 0.0, true);
+        // Reset with another block type
+        graph.set(&[Block::Gate], PERIOD);
+        graph.set(&[delay(1.0)], PERIOD);
+        assert_eq!(graph.update(0.0, 0.0, true), 0.0);
+        graph.update(1.0, 0.0, true);
+        graph.clear();
+        assert_eq!(graph.output(), 0.0);
+        assert_eq!(graph.update(0.0, 0.0, true), 0.0);
+
+        // Biquad integrator state
+        let integrator = Block::Biquad([1.0, 0.0, 0.0, 1.0, 0.0]);
+        let mut graph = Graph::new(&[integrator], PERIOD);
+        for i in 1..4 {
+            assert_eq!(graph.update(1.0, 0.0, true), i as f32);
+        }
+        graph.set(&[Block::Biquad([0.5, 0.0, 0.0, 1.0, 0.0])], PERIOD);
+        assert_eq!(graph.update(1.0, 0.0, true), 3.5);
+    }
+}
@@ -0,0 +1,77 @@
+use idsp::iir::Biquad;
+use num_traits::Float;
+
+/// Group delay of a polynomial in `z^-1`.
+///
+/// # Args
+/// * `c` - The coefficients of `z^0`, `z^-1`, `z^-2`.
+/// * `omega` - The angular frequency in radians per sample.
+fn polynomial_delay(c: [f32; 3], omega: f32) -> f32 {
+    // With P = sum(c_k exp(-j omega k)), the delay is Re(sum(k c_k exp(-j omega k))/P).
+    let (mut p, mut d) = ([0.0f32; 2], [0.0f32; 2]);
+    for (k, c) in c.into_iter().enumerate() {
+        let (s, co) = (k as f32 * omega).sin_cos();
+        p[0] += c * co;
+        p[1] -= c * s;
+        d[0] += k as f32 * c * co;
+        d[1] -= k as f32 * c * s;
+    }
+    (d[0] * p[0] + d[1] * p[1]) / (p[0] * p[0] + p[1] * p[1])
+}
+
+/// Compute the group delay of a biquad.
+///
+/// # Args
+/// * `biquad` - The biquad. It uses the `idsp` sign convention
+///   `H(z) = (b0 + b1 z^-1 + b2 z^-2)/(1 - a1 z^-1 - a2 z^-2)`.
+/// * `omega` - The angular frequency in radians per sample.
+///
+/// # Returns
+/// The group delay in samples. It is not finite at poles and zeros on the unit circle, e.g. at
+/// DC for integrators.
+pub fn group_delay(biquad: &Biquad<f32>, omega: f32) -> f32 {
+    let &[b0, b1, b2, a1, a2] = biquad.ba();
+    polynomial_delay([b0, b1, b2], omega)
+        - polynomial_delay([1.0, -a1, -a2], omega)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OMEGA: [f32; 4] = [0.0, 0.1, 1.0, 3.0];
+
+    #[test]
+    fn pure_delay() {
+        for (ba, delay) in [
+            ([1.0, 0.0, 0.0, 0.0, 0.0], 0.0),
+            ([0.0, 1.0, 0.0, 0.0, 0.0], 1.0),
+            ([0.0, 0.0, 1.0, 0.0, 0.0], 2.0),
+        ] {
+            for omega in OMEGA {
+                let tau = group_delay(&Biquad::from(ba), omega);
+                assert!((tau - delay).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn lowpass() {
+        let p = 0.5f32;
+        let biquad = Biquad::from([1.0 - p, 0.0, 0.0, p, 0.0]);
+        for omega in OMEGA {
+            let c = omega.cos();
+            let tau = (p * c - p * p) / (1.0 - 2.0 * p * c + p * p);
+            assert!((group_delay(&biquad, omega) - tau).abs() < 1e-5);
+        }
+        // p/(1 - p) at DC
+        assert!((group_delay(&biquad, 0.0) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn integrator() {
+        let biquad = Biquad::from([1.0, 0.0, 0.0, 1.0, 0.0]);
+        assert!(!group_delay(&biquad, 0.0).is_finite());
+        assert!(group_delay(&biquad, 1.0).is_finite());
+    }
+}
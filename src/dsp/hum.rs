@@ -0,0 +1,180 @@
+/// Number of line period waveform bins.
+pub const HUM_BINS: usize = 256;
+
+/// Line-synchronous hum canceller parameters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HumParameters {
+    /// Nominal phase increment per sample (turns as a fraction of `1 << 32`)
+    pub increment: u32,
+    /// Waveform estimate update rate per sample
+    pub rate: f32,
+}
+
+impl HumParameters {
+    /// Compute the parameters.
+    ///
+    /// # Args
+    /// * `frequency` - The nominal line frequency in Hz.
+    /// * `time_constant` - The waveform averaging time constant in seconds.
+    /// * `period` - The sample period in seconds.
+    pub fn new(frequency: f32, time_constant: f32, period: f32) -> Self {
+        Self {
+            // The float to integer conversion saturates.
+            increment: (frequency * period * (1u64 << 32) as f32) as u32,
+            // Each bin is updated on `1/(HUM_BINS period)` samples per second.
+            rate: (HUM_BINS as f32 * period / time_constant).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Line-synchronous hum canceller.
+///
+/// # Design
+/// The line period is divided into [HUM_BINS] bins. A phase accumulator tracks the line phase. The
+/// input is averaged per bin with an exponential moving average, which converges to the
+/// line-synchronous waveform (mains hum and its harmonics) while averaging out asynchronous
+/// signals. The estimate of the current bin, less the waveform mean, is subtracted from the input
+/// such that the DC content of the input is preserved.
+///
+/// The phase runs at the nominal line frequency. With a line synchronization reference, the phase
+/// is reset on each reference edge and the phase increment follows the measured line period such
+/// that the estimate stays locked to the actual line frequency.
+#[derive(Clone, Debug)]
+pub struct HumCanceller {
+    waveform: [f32; HUM_BINS],
+    /// Waveform sum
+    sum: f32,
+    phase: u32,
+    increment: u32,
+    nominal: u32,
+    /// Samples since the last reference edge
+    count: u32,
+}
+
+impl Default for HumCanceller {
+    fn default() -> Self {
+        Self {
+            waveform: [0.0; HUM_BINS],
+            sum: 0.0,
+            phase: 0,
+            increment: 0,
+            nominal: 0,
+            count: 0,
+        }
+    }
+}
+
+impl HumCanceller {
+    /// Synchronize to a line reference edge.
+    ///
+    /// The measured period is accepted if it is within a factor of two of the nominal period.
+    pub fn sync(&mut self) {
+        let period = (1u64 << 32) / self.nominal.max(1) as u64;
+        let count = self.count as u64;
+        if count > period / 2 && count < 2 * period {
+            self.increment = ((1u64 << 32) / count) as u32;
+        }
+        self.phase = 0;
+        self.count = 0;
+        self.sum = self.waveform.iter().sum();
+    }
+
+    /// Process a sample.
+    ///
+    /// # Args
+    /// * `x` - The input sample.
+    /// * `parameters` - The canceller parameters.
+    ///
+    /// # Returns
+    /// The input with the line-synchronous waveform removed.
+    pub fn update(&mut self, x: f32, parameters: &HumParameters) -> f32 {
+        if parameters.increment != self.nominal {
+            self.nominal = parameters.increment;
+            self.increment = parameters.increment;
+        }
+        let bin = &mut self.waveform
+            [(self.phase >> (32 - HUM_BINS.trailing_zeros())) as usize];
+        let delta = parameters.rate * (x - *bin);
+        *bin += delta;
+        self.sum += delta;
+        let y = x - (*bin - self.sum * (1.0 / HUM_BINS as f32));
+        let wrapped;
+        (self.phase, wrapped) = self.phase.overflowing_add(self.increment);
+        if wrapped {
+            // Remove the rounding errors accumulated in the sum once per period.
+            self.sum = self.waveform.iter().sum();
+        }
+        self.count = self.count.saturating_add(1);
+        y
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::f32::consts::TAU;
+    use num_traits::Float;
+
+    /// 1024 samples per line period, four samples per bin
+    const PARAMETERS: HumParameters = HumParameters {
+        increment: 1 << 22,
+        rate: 0.1,
+    };
+
+    #[test]
+    fn parameters() {
+        let p = HumParameters::new(50.0, 1.0, 1.0 / 51200.0);
+        assert!(p.increment.abs_diff(1 << 22) < 8);
+        assert!((p.rate - 0.005).abs() < 1e-6);
+        assert_eq!(HumParameters::new(50.0, 0.0, 1e-5).rate, 1.0);
+    }
+
+    #[test]
+    fn dc_preserved() {
+        let mut hum = HumCanceller::default();
+        let mut y = 0.0;
+        for _ in 0..200 * 1024 {
+            y = hum.update(3.0, &PARAMETERS);
+        }
+        assert!((y - 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cancels_hum() {
+        let mut hum = HumCanceller::default();
+        for n in 0..400 * 1024 {
+            let x = 3.0 + (TAU * (n % 1024) as f32 / 1024.0).sin();
+            let y = hum.update(x, &PARAMETERS);
+            if n >= 300 * 1024 {
+                // Residual of the waveform variation within a bin
+                assert!((y - 3.0).abs() < 0.03);
+            }
+        }
+    }
+
+    #[test]
+    fn sync() {
+        let mut hum = HumCanceller::default();
+        for _ in 0..1000 {
+            hum.update(0.0, &PARAMETERS);
+        }
+        hum.sync();
+        assert_eq!(hum.increment, ((1u64 << 32) / 1000) as u32);
+        assert_eq!((hum.phase, hum.count), (0, 0));
+        // Periods off by more than a factor of two are rejected.
+        for _ in 0..100 {
+            hum.update(0.0, &PARAMETERS);
+        }
+        hum.sync();
+        assert_eq!(hum.increment, ((1u64 << 32) / 1000) as u32);
+        // A change of the nominal frequency resets the increment.
+        hum.update(
+            0.0,
+            &HumParameters {
+                increment: 1 << 21,
+                ..PARAMETERS
+            },
+        );
+        assert_eq!(hum.increment, 1 << 21);
+    }
+}
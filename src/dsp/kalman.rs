@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of Riccati iterations for the steady-state gain.
+const RICCATI_ITERATIONS: usize = 20_000;
+
+/// Kalman filter signal model
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum KalmanModel {
+    /// Two states: value and rate, driven by white noise on the rate of change of the rate
+    #[default]
+    Velocity,
+    /// Three states: value, rate and acceleration, driven by white noise on the rate of change of
+    /// the acceleration
+    Acceleration,
+}
+
+/// Steady-state Kalman filter state estimator.
+///
+/// # Design
+/// The measured signal is modeled as the value state of a chain of two or three integrators driven
+/// by white process noise, observed with white measurement noise. The steady-state Kalman gain is
+/// computed once from the ratio of the process noise to the measurement noise (the tracking
+/// index) by iterating the Riccati equation. At run time, each sample costs one prediction and
+/// one correction (an alpha-beta or alpha-beta-gamma filter). For signals following the model,
+/// the estimate attenuates measurement noise without the phase lag of a low-pass filter.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Kalman {
+    /// Value, rate and acceleration estimates
+    state: [f32; 3],
+}
+
+impl Kalman {
+    /// Compute the steady-state Kalman gain.
+    ///
+    /// # Args
+    /// * `model` - The signal model.
+    /// * `ratio` - The process noise per sample relative to the measurement noise (standard
+    ///   deviations). Smaller values attenuate noise more but track model deviations slower.
+    ///
+    /// # Returns
+    /// The gain for the value, rate and acceleration states.
+    pub fn gain(model: KalmanModel, ratio: f32) -> [f32; 3] {
+        let n = match model {
+            KalmanModel::Velocity => 2,
+            KalmanModel::Acceleration => 3,
+        };
+        // State transition with unit sample period
+        let f = [[1.0, 1.0, 0.5], [0.0, 1.0, 1.0], [0.0, 0.0, 1.0]];
+        // Process noise input of the highest derivative
+        let g: [f64; 3] = if n == 2 {
+            [0.5, 1.0, 0.0]
+        } else {
+            [1.0 / 6.0, 0.5, 1.0]
+        };
+        let q = (ratio as f64) * (ratio as f64);
+        let mut p = [[0.0f64; 3]; 3];
+        let mut k = [0.0f64; 3];
+        for _ in 0..RICCATI_ITERATIONS {
+            // Predict: P = F P F' + G G' q
+            let mut fp = [[0.0f64; 3]; 3];
+            for i in 0..n {
+                for j in 0..n {
+                    fp[i][j] = (0..n).map(|l| f[i][l] * p[l][j]).sum();
+                }
+            }
+            for i in 0..n {
+                for j in 0..n {
+                    p[i][j] = (0..n).map(|l| fp[i][l] * f[j][l]).sum::<f64>()
+                        + g[i] * g[j] * q;
+                }
+            }
+            // Correct with a unit variance measurement of the value
+            let s = p[0][0] + 1.0;
+            let mut next = [0.0f64; 3];
+            for i in 0..n {
+                next[i] = p[i][0] / s;
+            }
+            let mut pc = p;
+            for i in 0..n {
+                for j in 0..n {
+                    pc[i][j] = p[i][j] - next[i] * p[0][j];
+                }
+            }
+            p = pc;
+            // Relative change below 1e-9
+            let converged = (0..n).all(|i| {
+                let d = next[i] - k[i];
+                d * d <= 1e-18 * next[i] * next[i]
+            });
+            k = next;
+            if converged {
+                break;
+            }
+        }
+        k.map(|k| k as f32)
+    }
+
+    /// Process a measurement.
+    ///
+    /// # Args
+    /// * `z` - The measurement.
+    /// * `gain` - The gain as computed by [Kalman::gain].
+    ///
+    /// # Returns
+    /// The value estimate.
+    pub fn update(&mut self, z: f32, gain: &[f32; 3]) -> f32 {
+        let [x, v, a] = self.state;
+        let predicted = [x + v + 0.5 * a, v + a, a];
+        let innovation = z - predicted[0];
+        for ((state, predicted), gain) in
+            self.state.iter_mut().zip(predicted).zip(gain)
+        {
+            *state = predicted + gain * innovation;
+        }
+        self.state[0]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn track(model: KalmanModel, z: impl Fn(f32) -> f32) -> f32 {
+        let gain = Kalman::gain(model, 0.01);
+        let mut kalman = Kalman::default();
+        let mut error = 0.0;
+        for n in 0..10_000 {
+            let z = z(n as f32);
+            error = kalman.update(z, &gain) - z;
+            assert!(error.is_finite());
+        }
+        error
+    }
+
+    #[test]
+    fn gain() {
+        for ratio in [1e-3, 0.1, 10.0] {
+            let g = Kalman::gain(KalmanModel::Velocity, ratio);
+            assert!(g[0] > 0.0 && g[0] < 1.0 && g[1] > 0.0);
+            assert_eq!(g[2], 0.0);
+            let g = Kalman::gain(KalmanModel::Acceleration, ratio);
+            assert!(g[0] > 0.0 && g[0] < 1.0 && g[1] > 0.0 && g[2] > 0.0);
+        }
+        // Less process noise attenuates the measurement noise more.
+        let [a, b] =
+            [1e-3, 0.1].map(|r| Kalman::gain(KalmanModel::Velocity, r));
+        assert!(a[0] < b[0]);
+    }
+
+    #[test]
+    fn step() {
+        for model in [KalmanModel::Velocity, KalmanModel::Acceleration] {
+            assert!(track(model, |_| 5.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn ramp() {
+        for model in [KalmanModel::Velocity, KalmanModel::Acceleration] {
+            assert!(track(model, |n| 1e-3 * n).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn parabola() {
+        assert!(
+            track(KalmanModel::Acceleration, |n| 1e-7 * n * n).abs() < 1e-3
+        );
+    }
+}
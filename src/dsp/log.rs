@@ -0,0 +1,42 @@
+/// Convert an amplitude to decibel relative to a reference level.
+///
+/// # Args
+/// * `x` - The amplitude. Its magnitude is used.
+/// * `reference` - The amplitude corresponding to 0 dB.
+/// * `floor` - The minimum magnitude. This bounds the output for vanishing inputs.
+///
+/// # Returns
+/// The level `20 log10(max(|x|, floor)/reference)` in dB.
+pub fn decibel(x: f32, reference: f32, floor: f32) -> f32 {
+    20.0 * num_traits::Float::log10(x.abs().max(floor) / reference)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        assert_eq!(decibel(1.0, 1.0, 1e-6), 0.0);
+        assert!((decibel(10.0, 1.0, 1e-6) - 20.0).abs() < 1e-5);
+        assert!((decibel(0.5, 5.0, 1e-6) + 20.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn negative() {
+        assert_eq!(decibel(-10.0, 1.0, 1e-6), decibel(10.0, 1.0, 1e-6));
+    }
+
+    #[test]
+    fn floor() {
+        assert!((decibel(0.0, 1.0, 1e-3) + 60.0).abs() < 1e-4);
+        assert!((decibel(-0.0, 1.0, 1e-3) + 60.0).abs() < 1e-4);
+        assert_eq!(decibel(1e-9, 1.0, 1e-3), decibel(0.0, 1.0, 1e-3));
+    }
+
+    #[test]
+    fn zero_floor() {
+        assert_eq!(decibel(0.0, 1.0, 0.0), f32::NEG_INFINITY);
+        assert!(decibel(f32::NAN, 1.0, 1e-3).is_finite());
+    }
+}
@@ -0,0 +1,225 @@
+use num_traits::Float;
+use serde::Serialize;
+
+/// Minimum difference of the settled input levels in LSB for a valid loopback.
+const MIN_SWING: i64 = 64;
+
+/// Loopback latency measurement.
+///
+/// # Design
+/// With a DAC output looped back to an ADC input, the output alternates between two levels every
+/// half period. The delay of each step is the number of samples from the step output sample to
+/// the first input sample beyond the threshold. The threshold is the midpoint between the settled
+/// input levels measured during the first period, which is not counted. Steps without a detected
+/// response within the half period are counted as missed. The measurement ends without any steps
+/// if the settled input levels do not differ.
+#[derive(Clone, Debug, Default)]
+pub struct Loopback {
+    /// Output codes of the low and high levels
+    code: [i16; 2],
+    /// Samples per level
+    half_period: u32,
+    /// Remaining steps
+    steps: u32,
+    /// Current level
+    high: bool,
+    /// Samples since the last step
+    count: u32,
+    /// Sum and number of the settled input samples of the low and high levels
+    settled: [(i64, u32); 2],
+    /// Threshold and response polarity
+    threshold: Option<(i32, bool)>,
+    /// Delay of the current step
+    delay: Option<u32>,
+    result: LoopbackResult,
+    active: bool,
+}
+
+/// Loopback latency measurement result
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct LoopbackResult {
+    /// Number of steps with a detected response
+    pub steps: u32,
+    /// Number of steps without a detected response
+    pub missed: u32,
+    /// Minimum delay in samples
+    pub min: u32,
+    /// Maximum delay in samples
+    pub max: u32,
+    /// Sum of delays, sum of squared delays
+    #[serde(skip)]
+    sum: (u64, u64),
+}
+
+impl LoopbackResult {
+    fn add(&mut self, delay: u32) {
+        if self.steps == 0 {
+            self.min = delay;
+            self.max = delay;
+        }
+        self.min = self.min.min(delay);
+        self.max = self.max.max(delay);
+        self.sum.0 += delay as u64;
+        self.sum.1 += delay as u64 * delay as u64;
+        self.steps += 1;
+    }
+
+    /// Mean delay in samples.
+    pub fn mean(&self) -> f32 {
+        self.sum.0 as f32 / self.steps.max(1) as f32
+    }
+
+    /// Standard deviation of the delay (jitter) in samples.
+    pub fn jitter(&self) -> f32 {
+        let n = self.steps.max(1) as f64;
+        let mean = self.sum.0 as f64 / n;
+        (self.sum.1 as f64 / n - mean * mean).max(0.0).sqrt() as f32
+    }
+}
+
+impl Loopback {
+    /// Start a measurement.
+    ///
+    /// # Args
+    /// * `code` - The output codes of the low and high levels.
+    /// * `half_period` - The number of samples per level. It must exceed the loop delay.
+    /// * `steps` - The number of steps to measure.
+    pub fn start(&mut self, code: [i16; 2], half_period: u32, steps: u32) {
+        *self = Self {
+            code,
+            half_period: half_period.max(4),
+            // The first period determines the threshold.
+            steps: steps.saturating_add(2),
+            active: true,
+            ..Default::default()
+        };
+    }
+
+    /// Whether a measurement is in progress.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Take the result of a completed measurement.
+    pub fn take(&mut self) -> Option<LoopbackResult> {
+        (!self.active && self.half_period > 0).then(|| {
+            self.half_period = 0;
+            self.result
+        })
+    }
+
+    /// Process an input sample and generate the next output sample.
+    ///
+    /// # Args
+    /// * `x` - The input sample.
+    ///
+    /// # Returns
+    /// The output sample if a measurement is in progress.
+    pub fn update(&mut self, x: i16) -> Option<i16> {
+        if !self.active {
+            return None;
+        }
+        let x = x as i32;
+        let level = self.high as usize;
+        if self.delay.is_none()
+            && self.threshold.is_some_and(|(threshold, rising)| {
+                (x >= threshold) == (rising == self.high)
+            })
+        {
+            self.delay = Some(self.count + 1);
+        }
+        // The last quarter of each level is settled.
+        if self.count >= self.half_period - self.half_period / 4 {
+            let (sum, n) = &mut self.settled[level];
+            *sum += x as i64;
+            *n += 1;
+        }
+        self.count += 1;
+        if self.count >= self.half_period {
+            if self.threshold.is_some() {
+                match self.delay.take() {
+                    Some(delay) => self.result.add(delay),
+                    None => self.result.missed += 1,
+                }
+            } else if self.high {
+                let [low, high] =
+                    self.settled.map(|(sum, n)| sum / n.max(1) as i64);
+                if (high - low).abs() < MIN_SWING {
+                    self.steps = 1;
+                }
+                self.threshold = Some((((low + high) / 2) as i32, high > low));
+            }
+            self.high = !self.high;
+            self.count = 0;
+            self.steps -= 1;
+            self.active = self.steps > 0;
+        }
+        Some(self.code[self.high as usize])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Run a measurement with the output looped back through a delay and a gain.
+    ///
+    /// The input is stuck low after `cut` samples.
+    fn measure(delay: usize, gain: i16, cut: usize) -> LoopbackResult {
+        let mut loopback = Loopback::default();
+        loopback.start([-1000, 1000], 50, 10);
+        // Past outputs, starting at the low level
+        let mut line = [-1000i16; 64];
+        let mut n = 0;
+        let input = |line: &[i16; 64], n: usize| {
+            if n < cut {
+                line[n % delay] * gain
+            } else {
+                -1000
+            }
+        };
+        while let Some(y) = loopback.update(input(&line, n)) {
+            line[n % delay] = y;
+            n += 1;
+        }
+        assert!(!loopback.is_active());
+        loopback.take().unwrap()
+    }
+
+    #[test]
+    fn delay() {
+        for delay in [1, 2, 7, 30] {
+            for gain in [1, -1] {
+                let result = measure(delay, gain, usize::MAX);
+                assert_eq!((result.steps, result.missed), (10, 0));
+                assert_eq!((result.min, result.max), (delay as _, delay as _));
+                assert!((result.mean() - delay as f32).abs() < 1e-6);
+                assert_eq!(result.jitter(), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn missed() {
+        // With the loop cut after the first period, only the falling steps are
+        // detected, immediately.
+        let result = measure(5, 1, 100);
+        assert_eq!((result.steps, result.missed), (5, 5));
+        assert_eq!((result.min, result.max), (1, 1));
+    }
+
+    #[test]
+    fn no_swing() {
+        let mut loopback = Loopback::default();
+        loopback.start([-1000, 1000], 50, 10);
+        let mut n = 0;
+        while loopback.update(0).is_some() {
+            n += 1;
+        }
+        // The measurement ends after the first period.
+        assert_eq!(n, 100);
+        let result = loopback.take().unwrap();
+        assert_eq!((result.steps, result.missed), (0, 0));
+        assert!(loopback.take().is_none());
+    }
+}
@@ -0,0 +1,46 @@
+//! Signal processing blocks used by the applications in addition to those provided by `idsp`.
+
+mod allpass;
+pub use allpass::*;
+
+mod dc_block;
+pub use dc_block::*;
+
+mod graph;
+pub use graph::*;
+
+mod group_delay;
+pub use group_delay::*;
+
+mod hum;
+pub use hum::*;
+
+mod kalman;
+pub use kalman::*;
+
+mod log;
+pub use log::*;
+
+mod loopback;
+pub use loopback::*;
+
+mod noise;
+pub use noise::*;
+
+mod noise_shaping;
+pub use noise_shaping::*;
+
+mod phase_ratio;
+pub use phase_ratio::*;
+
+mod rms;
+pub use rms::*;
+
+mod smith;
+pub use smith::*;
+
+mod spectrum;
+pub use spectrum::*;
+
+mod stability;
+pub use stability::*;
@@ -0,0 +1,161 @@
+use super::SPECTRUM_SIZE;
+use crate::convert::AdcCode;
+use num_traits::Float;
+
+/// The number of bands of the coarse noise spectrum.
+pub const NOISE_BANDS: usize = 16;
+
+/// Raw input record for noise floor characterization.
+///
+/// # Design
+/// While the characterization is running, the outputs are held and records of consecutive raw
+/// ADC samples of one channel are captured at interrupt priority. Complete records are handed
+/// over to a lower priority context for analysis.
+#[derive(Clone, Debug)]
+pub struct NoiseRecord {
+    buffer: [i16; SPECTRUM_SIZE],
+    /// Number of captured samples, `None` if idle
+    index: Option<usize>,
+    channel: usize,
+    hold: bool,
+}
+
+impl Default for NoiseRecord {
+    fn default() -> Self {
+        Self {
+            buffer: [0; SPECTRUM_SIZE],
+            index: None,
+            channel: 0,
+            hold: false,
+        }
+    }
+}
+
+impl NoiseRecord {
+    /// Hold or release the outputs for a characterization.
+    pub fn hold(&mut self, hold: bool) {
+        self.hold = hold;
+        if !hold {
+            self.index = None;
+        }
+    }
+
+    /// Whether the outputs are held.
+    pub fn is_held(&self) -> bool {
+        self.hold
+    }
+
+    /// Start a new record, discarding any samples captured previously.
+    ///
+    /// # Args
+    /// * `channel` - The input channel to record.
+    pub fn start(&mut self, channel: usize) {
+        self.channel = channel;
+        self.index = Some(0);
+    }
+
+    /// Add a batch of ADC samples.
+    ///
+    /// # Args
+    /// * `adc` - The ADC codes of both channels.
+    pub fn update<const N: usize>(&mut self, adc: [&[u16; N]; 2]) {
+        let Some(index) = self.index.as_mut() else {
+            return;
+        };
+        let Some(adc) = adc.get(self.channel) else {
+            return;
+        };
+        for (buffer, x) in self.buffer[*index..].iter_mut().zip(adc.iter()) {
+            *buffer = AdcCode(*x).into();
+            *index += 1;
+        }
+    }
+
+    /// Take the recorded samples once the record is complete.
+    ///
+    /// # Returns
+    /// The ADC codes if the record is complete. The record is then idle.
+    pub fn take(&mut self) -> Option<[i16; SPECTRUM_SIZE]> {
+        if self.index == Some(SPECTRUM_SIZE) {
+            self.index = None;
+            Some(self.buffer)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reduce a power spectral density to a coarse amplitude spectral density.
+///
+/// # Args
+/// * `psd` - The one-sided power spectral density in V²/Hz.
+///
+/// # Returns
+/// The amplitude spectral density in dB relative to 1 V/sqrt(Hz) of [NOISE_BANDS] bands of equal
+/// width, starting at DC, rounded to integers.
+pub fn coarse_asd_db(psd: &[f32; SPECTRUM_SIZE / 2]) -> [i16; NOISE_BANDS] {
+    let width = psd.len() / NOISE_BANDS;
+    core::array::from_fn(|band| {
+        let power = psd[band * width..(band + 1) * width].iter().sum::<f32>()
+            / width as f32;
+        // The float to integer conversion saturates.
+        (10.0 * power.max(f32::MIN_POSITIVE).log10()).round() as i16
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let mut record = NoiseRecord::default();
+        record.hold(true);
+        assert!(record.is_held());
+        // Idle records ignore samples.
+        record.update([&[1; 8], &[2; 8]]);
+        assert_eq!(record.take(), None);
+        record.start(1);
+        for i in 0..SPECTRUM_SIZE / 8 {
+            assert_eq!(record.take(), None);
+            let x = core::array::from_fn(|j| (i * 8 + j) as u16);
+            record.update([&[0xffff; 8], &x]);
+        }
+        // Samples beyond the record length are discarded.
+        record.update([&[0; 8], &[0xffff; 8]]);
+        let buffer = record.take().unwrap();
+        assert!(buffer.iter().enumerate().all(|(i, x)| *x == i as i16));
+        assert_eq!(record.take(), None);
+    }
+
+    #[test]
+    fn release() {
+        let mut record = NoiseRecord::default();
+        record.hold(true);
+        record.start(0);
+        for _ in 0..SPECTRUM_SIZE / 8 {
+            record.update([&[0x8000; 8], &[0; 8]]);
+        }
+        record.hold(false);
+        assert!(!record.is_held());
+        assert_eq!(record.take(), None);
+    }
+
+    #[test]
+    fn flat() {
+        assert_eq!(coarse_asd_db(&[1e-2; SPECTRUM_SIZE / 2]), [-20; 16]);
+        assert_eq!(coarse_asd_db(&[1.0; SPECTRUM_SIZE / 2]), [0; 16]);
+    }
+
+    #[test]
+    fn bands() {
+        let mut psd = [0.0; SPECTRUM_SIZE / 2];
+        // A single line is averaged over the band width of 16 bins.
+        psd[20] = 16e-4;
+        let asd = coarse_asd_db(&psd);
+        assert_eq!(asd[1], -40);
+        // Empty bands saturate at the smallest positive power.
+        assert_eq!(asd[0], -379);
+        assert_eq!(asd[15], -379);
+    }
+}
@@ -0,0 +1,79 @@
+use num_traits::Float;
+
+/// First order noise shaping quantizer.
+///
+/// # Design
+/// The quantization error is fed back through a first order filter such that the noise transfer
+/// function is the highpass `NTF(z) = (1 - z^-1)/(1 - p z^-1)`. Quantization noise is suppressed
+/// below the corner frequency, by 20 dB per decade towards DC, and moved to higher frequencies
+/// where it is removed by the bandwidth of the load or of an output filter. A slow output then
+/// resolves fractions of an LSB on average. Above the corner the noise is raised by up to 6 dB.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoiseShaper {
+    state: f32,
+}
+
+impl NoiseShaper {
+    /// Compute the error feedback pole for a given corner frequency.
+    ///
+    /// # Args
+    /// * `corner` - The corner frequency in Hz. It is limited to `1/(2 pi period)`.
+    /// * `period` - The sample period in seconds.
+    pub fn pole(corner: f32, period: f32) -> f32 {
+        (1.0 - core::f32::consts::TAU * corner * period).clamp(0.0, 1.0)
+    }
+
+    /// Quantize a sample.
+    ///
+    /// # Args
+    /// * `x` - The input sample in LSB.
+    /// * `pole` - The error feedback pole as computed by [NoiseShaper::pole].
+    ///
+    /// # Returns
+    /// The quantized sample. It saturates at the output range.
+    pub fn update(&mut self, x: f32, pole: f32) -> i16 {
+        let v = x + self.state;
+        let q = (v + 0.5).floor();
+        self.state = pole * self.state + (pole - 1.0) * (q - v);
+        // The float to integer conversion saturates.
+        q as i16
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pole() {
+        assert_eq!(NoiseShaper::pole(0.0, 1e-5), 1.0);
+        assert_eq!(NoiseShaper::pole(1e9, 1e-5), 0.0);
+    }
+
+    #[test]
+    fn integer() {
+        let mut shaper = NoiseShaper::default();
+        for _ in 0..100 {
+            assert_eq!(shaper.update(-5.0, 0.9), -5);
+        }
+    }
+
+    #[test]
+    fn dc_resolution() {
+        for pole in [0.0, NoiseShaper::pole(100.0, 1e-5)] {
+            let mut shaper = NoiseShaper::default();
+            let n = 100_000;
+            let sum: i32 =
+                (0..n).map(|_| shaper.update(0.25, pole) as i32).sum();
+            assert!((sum as f32 / n as f32 - 0.25).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn saturation() {
+        let mut shaper = NoiseShaper::default();
+        assert_eq!(shaper.update(1e6, 0.5), i16::MAX);
+        let mut shaper = NoiseShaper::default();
+        assert_eq!(shaper.update(-1e6, 0.5), i16::MIN);
+    }
+}
@@ -0,0 +1,127 @@
+/// Rational multiple of a reference phase.
+///
+/// # Design
+/// A sub-harmonic or fractional multiple `p/q` of a wrapping reference phase is only defined
+/// with knowledge of the reference cycle count modulo `q`. The reference phase is unwrapped by
+/// accumulating its wrapping increments modulo `q` cycles. The scaled phase is continuous across
+/// reference cycles. Its offset is subject to the inherent `q`-fold ambiguity which depends on the
+/// cycle at which tracking started.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PhaseRatio {
+    last: i32,
+    unwrapped: i64,
+}
+
+impl PhaseRatio {
+    /// Construct a tracker starting at zero phase.
+    pub const fn new() -> Self {
+        Self {
+            last: 0,
+            unwrapped: 0,
+        }
+    }
+
+    /// Update with the next reference phase and compute the scaled LO.
+    ///
+    /// # Args
+    /// * `phase` - The reference phase. A full turn is `1 << 32`.
+    /// * `frequency` - The reference frequency (phase increment per sample).
+    /// * `numerator` - The ratio numerator `p`. Negative values demodulate.
+    /// * `denominator` - The ratio denominator `q`. Zero is treated as one.
+    ///
+    /// # Returns
+    /// The scaled phase and frequency.
+    pub fn update(
+        &mut self,
+        phase: i32,
+        frequency: i32,
+        numerator: i32,
+        denominator: u16,
+    ) -> (i32, i32) {
+        let q = denominator.max(1) as i64;
+        let delta = phase.wrapping_sub(self.last) as i64;
+        self.last = phase;
+        self.unwrapped = (self.unwrapped + delta).rem_euclid(q << 32);
+        // The unwrapped phase is below 2**48. The product with the numerator requires 128 bits.
+        let phase =
+            (self.unwrapped as i128 * numerator as i128 / q as i128) as i32;
+        let frequency = (frequency as i64 * numerator as i64 / q) as i32;
+        (phase, frequency)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Track a reference phase ramp and check the scaled phase increments.
+    fn ramp(frequency: i32, numerator: i32, denominator: u16, samples: usize) {
+        let q = denominator.max(1) as i64;
+        let expected = (frequency as i64 * numerator as i64 / q) as i32;
+        let mut ratio = PhaseRatio::new();
+        let mut phase = 0i32;
+        let mut last = 0i32;
+        for _ in 0..samples {
+            phase = phase.wrapping_add(frequency);
+            let (y, f) = ratio.update(phase, frequency, numerator, denominator);
+            assert_eq!(f, expected);
+            // Continuous across reference cycles and accumulator wraps up to
+            // the rounding of the division.
+            assert!((y.wrapping_sub(last).wrapping_sub(expected)).abs() <= 1);
+            last = y;
+        }
+    }
+
+    #[test]
+    fn unity() {
+        let mut ratio = PhaseRatio::new();
+        let mut phase = 0i32;
+        for _ in 0..100 {
+            phase = phase.wrapping_add(0x1234_5678);
+            assert_eq!(ratio.update(phase, 0x1234_5678, 1, 1).0, phase);
+        }
+        // A zero denominator is treated as one.
+        ramp(0x1234_5678, 1, 0, 100);
+        ramp(-0x1234_5678, -1, 1, 100);
+    }
+
+    #[test]
+    fn harmonic() {
+        let mut ratio = PhaseRatio::new();
+        let mut phase = 0i32;
+        for _ in 0..100 {
+            phase = phase.wrapping_add(0x0765_4321);
+            assert_eq!(
+                ratio.update(phase, 0x0765_4321, 3, 1).0,
+                phase.wrapping_mul(3)
+            );
+        }
+    }
+
+    #[test]
+    fn subharmonic() {
+        // 16 samples per reference cycle, many accumulator wraps
+        ramp(0x1000_0000, 1, 2, 1000);
+        ramp(0x1000_0000, -1, 3, 1000);
+        ramp(-0x1000_0000, 1, 2, 1000);
+        ramp(0x0123_4567, 5, 7, 10_000);
+    }
+
+    #[test]
+    fn wrap() {
+        // After q reference cycles the scaled phase returns to its start.
+        let mut ratio = PhaseRatio::new();
+        let mut phase = 0i32;
+        let mut y = [0i32; 3];
+        for cycle in 0..3 {
+            for _ in 0..16 {
+                phase = phase.wrapping_add(0x1000_0000);
+                y[cycle] = ratio.update(phase, 0x1000_0000, 2, 3).0;
+            }
+        }
+        // Two thirds of a turn per reference cycle
+        assert_eq!(y[0], (2i64 * (1 << 32) / 3) as i32);
+        assert_eq!(y[1], (4i64 * (1 << 32) / 3) as i32);
+        assert_eq!(y[2], 0);
+    }
+}
@@ -0,0 +1,71 @@
+/// Root mean square (RMS) accumulator of the AC content of a signal.
+///
+/// # Design
+/// Sum and sum of squares are accumulated in integers so there is no loss of precision over
+/// long intervals. The mean (DC) is removed when computing the RMS.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Rms {
+    sum: i64,
+    sum_sq: u64,
+    count: u32,
+}
+
+impl Rms {
+    /// Add a sample.
+    pub fn update(&mut self, x: i16) {
+        self.sum += x as i64;
+        self.sum_sq += (x as i32 * x as i32) as u64;
+        self.count += 1;
+    }
+
+    /// Get the RMS of the samples with the mean removed.
+    ///
+    /// # Returns
+    /// The RMS in units of the samples or zero if there were no samples.
+    pub fn rms(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let n = self.count as f64;
+        let mean = self.sum as f64 / n;
+        let var = (self.sum_sq as f64 / n - mean * mean).max(0.0);
+        num_traits::Float::sqrt(var) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(Rms::default().rms(), 0.0);
+    }
+
+    #[test]
+    fn dc_removed() {
+        let mut rms = Rms::default();
+        for _ in 0..1000 {
+            rms.update(-1234);
+        }
+        assert_eq!(rms.rms(), 0.0);
+    }
+
+    #[test]
+    fn square_wave() {
+        let mut rms = Rms::default();
+        for i in 0..1000 {
+            rms.update(if i % 2 == 0 { 150 } else { -50 });
+        }
+        assert!((rms.rms() - 100.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn full_scale() {
+        let mut rms = Rms::default();
+        for i in 0..100_000 {
+            rms.update(if i % 2 == 0 { i16::MAX } else { i16::MIN + 1 });
+        }
+        assert!((rms.rms() - i16::MAX as f32).abs() < 1.0);
+    }
+}
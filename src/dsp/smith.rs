@@ -0,0 +1,146 @@
+use num_traits::Float;
+
+/// Maximum plant model delay in samples.
+pub const SMITH_DELAY: usize = 512;
+
+/// First order plus dead time plant model coefficients.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlantModel {
+    /// Static gain from controller output to input units
+    pub gain: f32,
+    /// Lowpass pole
+    pub pole: f32,
+    /// Pure delay in samples
+    pub delay: usize,
+}
+
+impl PlantModel {
+    /// Compute the model coefficients.
+    ///
+    /// # Args
+    /// * `gain` - The static plant gain in input units per output unit.
+    /// * `time_constant` - The plant time constant in seconds.
+    /// * `delay` - The plant pure delay in seconds. It is limited to [SMITH_DELAY] samples.
+    /// * `period` - The sample period in seconds.
+    pub fn new(gain: f32, time_constant: f32, delay: f32, period: f32) -> Self {
+        Self {
+            gain,
+            pole: (-period / time_constant.max(0.0)).exp(),
+            // The float to integer conversion saturates.
+            delay: ((delay / period).round() as usize).min(SMITH_DELAY),
+        }
+    }
+}
+
+/// Smith predictor delay compensation.
+///
+/// # Design
+/// An internal model of the plant, a first order lowpass followed by a pure delay, is driven by the
+/// controller output. The controller acts on the measurement corrected by the difference between
+/// the undelayed and the delayed model output. If the model matches the plant, the delay is
+/// removed from the loop and the controller can be tuned for the delay-free plant. Model errors
+/// are fed back through the measurement as usual.
+#[derive(Clone, Debug)]
+pub struct SmithPredictor {
+    /// Undelayed model output
+    model: f32,
+    /// Model output history
+    history: [f32; SMITH_DELAY],
+    index: usize,
+}
+
+impl Default for SmithPredictor {
+    fn default() -> Self {
+        Self {
+            model: 0.0,
+            history: [0.0; SMITH_DELAY],
+            index: 0,
+        }
+    }
+}
+
+impl SmithPredictor {
+    /// Correct a measurement by the predicted delay-free plant response.
+    ///
+    /// # Args
+    /// * `x` - The measurement.
+    /// * `model` - The plant model.
+    ///
+    /// # Returns
+    /// The measurement with the delayed model output replaced by the undelayed one.
+    pub fn feedback(&self, x: f32, model: &PlantModel) -> f32 {
+        let delayed = if model.delay == 0 {
+            self.model
+        } else {
+            self.history[(self.index + SMITH_DELAY - model.delay) % SMITH_DELAY]
+        };
+        x + self.model - delayed
+    }
+
+    /// Advance the model with a controller output sample.
+    ///
+    /// # Args
+    /// * `u` - The controller output.
+    /// * `model` - The plant model.
+    pub fn update(&mut self, u: f32, model: &PlantModel) {
+        self.history[self.index] = self.model;
+        self.index = (self.index + 1) % SMITH_DELAY;
+        self.model =
+            model.pole * self.model + (1.0 - model.pole) * model.gain * u;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn model() {
+        let model = PlantModel::new(2.0, 1e-3, 1e-4, 1e-5);
+        assert_eq!(model.delay, 10);
+        assert!((model.pole - (-0.01f32).exp()).abs() < 1e-6);
+        let model = PlantModel::new(1.0, 0.0, 1.0, 1e-5);
+        assert_eq!(model.pole, 0.0);
+        assert_eq!(model.delay, SMITH_DELAY);
+    }
+
+    #[test]
+    fn no_delay() {
+        let model = PlantModel::new(1.0, 1e-3, 0.0, 1e-5);
+        let mut smith = SmithPredictor::default();
+        for _ in 0..100 {
+            smith.update(1.0, &model);
+            assert!((smith.feedback(0.5, &model) - 0.5).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn pure_delay() {
+        // Unit gain, no lag, three samples delay
+        let model = PlantModel::new(1.0, 0.0, 3e-5, 1e-5);
+        let mut smith = SmithPredictor::default();
+        for _ in 0..3 {
+            smith.update(1.0, &model);
+            // The undelayed response is fed back before the delayed one arrives.
+            assert_eq!(smith.feedback(0.0, &model), 1.0);
+        }
+        smith.update(1.0, &model);
+        assert_eq!(smith.feedback(0.0, &model), 0.0);
+    }
+
+    #[test]
+    fn step_response() {
+        let model = PlantModel::new(-3.0, 1e-4, 1e-4, 1e-5);
+        let mut smith = SmithPredictor::default();
+        let mut last = 0.0;
+        for _ in 0..2000 {
+            smith.update(1.0, &model);
+            // The model lowpass settles monotonically at the static gain.
+            assert!(smith.model <= last + 1e-6 && smith.model >= -3.0 - 1e-6);
+            last = smith.model;
+        }
+        assert!((last + 3.0).abs() < 1e-4);
+        // Once settled, the prediction matches the delayed response.
+        assert!(smith.feedback(0.0, &model).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,332 @@
+use core::f32::consts::TAU;
+use idsp::Complex;
+use num_traits::Float;
+
+/// The number of points of the spectrum FFT.
+pub const SPECTRUM_SIZE: usize = 512;
+
+/// Sample capture for spectrum analysis.
+///
+/// # Design
+/// The capture is filled at interrupt priority with decimated (boxcar averaged) samples of a
+/// signal and an excitation and then handed over to a lower priority context for the spectrum
+/// computation.
+#[derive(Clone, Debug)]
+pub struct Capture {
+    buffer: [[f32; SPECTRUM_SIZE]; 2],
+    /// Number of captured samples, `None` if idle
+    index: Option<usize>,
+    accu: [f32; 2],
+    count: u32,
+    decimation: u32,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            buffer: [[0.0; SPECTRUM_SIZE]; 2],
+            index: None,
+            accu: [0.0; 2],
+            count: 0,
+            decimation: 1,
+        }
+    }
+}
+
+impl Capture {
+    /// Start a new capture, discarding any samples captured previously.
+    ///
+    /// # Args
+    /// * `decimation` - The number of input samples averaged per captured sample.
+    pub fn start(&mut self, decimation: u32) {
+        self.index = Some(0);
+        self.accu = [0.0; 2];
+        self.count = 0;
+        self.decimation = decimation.max(1);
+    }
+
+    /// Whether no capture is in progress or pending.
+    pub fn is_idle(&self) -> bool {
+        self.index.is_none()
+    }
+
+    /// Add an input sample.
+    ///
+    /// # Args
+    /// * `x` - The signal and excitation samples.
+    pub fn update(&mut self, x: [f32; 2]) {
+        let Some(index) = self.index.as_mut() else {
+            return;
+        };
+        if *index >= SPECTRUM_SIZE {
+            return;
+        }
+        for (accu, x) in self.accu.iter_mut().zip(x) {
+            *accu += x;
+        }
+        self.count += 1;
+        if self.count >= self.decimation {
+            for (buffer, accu) in
+                self.buffer.iter_mut().zip(self.accu.iter_mut())
+            {
+                buffer[*index] = *accu / self.count as f32;
+                *accu = 0.0;
+            }
+            *index += 1;
+            self.count = 0;
+        }
+    }
+
+    /// Take the captured samples once the capture is complete.
+    ///
+    /// # Returns
+    /// The captured signal and excitation samples if the capture is complete. The capture is then
+    /// idle.
+    pub fn take(&mut self) -> Option<[[f32; SPECTRUM_SIZE]; 2]> {
+        if self.index == Some(SPECTRUM_SIZE) {
+            self.index = None;
+            Some(self.buffer)
+        } else {
+            None
+        }
+    }
+}
+
+/// Averaged frequency domain estimates.
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    /// Bin spacing in Hz
+    pub resolution: f32,
+    /// One-sided power spectral density of the signal in V²/Hz
+    pub psd: [f32; SPECTRUM_SIZE / 2],
+    /// Transfer function (H1) from the excitation to the signal
+    ///
+    /// NaN without excitation.
+    pub transfer: [Complex<f32>; SPECTRUM_SIZE / 2],
+}
+
+impl Estimate {
+    /// The amplitude spectral density in dB relative to 1 V/sqrt(Hz), rounded to integers.
+    pub fn asd_db(&self) -> [i16; SPECTRUM_SIZE / 2] {
+        // The float to integer conversion saturates.
+        self.psd.map(|psd| {
+            (10.0 * psd.max(f32::MIN_POSITIVE).log10()).round() as i16
+        })
+    }
+}
+
+/// Welch averaged power spectral density and transfer function estimator.
+///
+/// # Design
+/// A Hann window is applied to the captured samples before a radix-2 FFT. The one-sided power
+/// spectra and cross spectra of multiple non-overlapping captures are averaged. The transfer
+/// function is the H1 estimate: the cross spectrum of excitation and signal divided by the power
+/// spectrum of the excitation.
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    twiddle: [Complex<f32>; SPECTRUM_SIZE / 2],
+    window: [f32; SPECTRUM_SIZE],
+    /// Sum of the squared window
+    window_power: f32,
+    /// Signal power spectrum
+    pyy: [f32; SPECTRUM_SIZE / 2],
+    /// Excitation power spectrum
+    pxx: [f32; SPECTRUM_SIZE / 2],
+    /// Cross spectrum
+    pxy: [Complex<f32>; SPECTRUM_SIZE / 2],
+    count: u32,
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Spectrum {
+    /// Construct a new spectrum estimator.
+    pub fn new() -> Self {
+        let n = SPECTRUM_SIZE as f32;
+        let twiddle: [Complex<f32>; SPECTRUM_SIZE / 2] =
+            core::array::from_fn(|k| {
+                let (s, c) = (-TAU * k as f32 / n).sin_cos();
+                Complex::new(c, s)
+            });
+        let window: [f32; SPECTRUM_SIZE] =
+            core::array::from_fn(|k| 0.5 - 0.5 * (TAU * k as f32 / n).cos());
+        Self {
+            twiddle,
+            window_power: window.iter().map(|w| w * w).sum(),
+            window,
+            pyy: [0.0; SPECTRUM_SIZE / 2],
+            pxx: [0.0; SPECTRUM_SIZE / 2],
+            pxy: [Complex::new(0.0, 0.0); SPECTRUM_SIZE / 2],
+            count: 0,
+        }
+    }
+
+    /// The number of spectra accumulated.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Accumulate the spectra of a capture.
+    ///
+    /// # Args
+    /// * `samples` - The captured signal and excitation samples.
+    /// * `scale` - The scale of the signal and excitation samples in volts per unit.
+    pub fn update(
+        &mut self,
+        samples: &[[f32; SPECTRUM_SIZE]; 2],
+        scale: [f32; 2],
+    ) {
+        let [y, x] = [0, 1].map(|i| {
+            let mut buf: [Complex<f32>; SPECTRUM_SIZE] =
+                core::array::from_fn(|k| {
+                    Complex::new(samples[i][k] * self.window[k] * scale[i], 0.0)
+                });
+            self.fft(&mut buf);
+            buf
+        });
+        for ((((pyy, pxx), pxy), y), x) in self
+            .pyy
+            .iter_mut()
+            .zip(self.pxx.iter_mut())
+            .zip(self.pxy.iter_mut())
+            .zip(y.iter())
+            .zip(x.iter())
+        {
+            *pyy += y.norm_sqr();
+            *pxx += x.norm_sqr();
+            *pxy += x.conj() * y;
+        }
+        self.count += 1;
+    }
+
+    /// Compute the averaged estimates and reset the accumulated spectra.
+    ///
+    /// # Args
+    /// * `sample_rate` - The sample rate of the captures in Hz.
+    pub fn finish(&mut self, sample_rate: f32) -> Estimate {
+        // One-sided power spectral density normalization.
+        let norm =
+            2.0 / (sample_rate * self.window_power * self.count.max(1) as f32);
+        let estimate = Estimate {
+            resolution: sample_rate / SPECTRUM_SIZE as f32,
+            psd: self.pyy.map(|pyy| pyy * norm),
+            transfer: core::array::from_fn(|k| {
+                if self.pxx[k] > 0.0 {
+                    self.pxy[k] / self.pxx[k]
+                } else {
+                    Complex::new(f32::NAN, f32::NAN)
+                }
+            }),
+        };
+        self.pyy = [0.0; SPECTRUM_SIZE / 2];
+        self.pxx = [0.0; SPECTRUM_SIZE / 2];
+        self.pxy = [Complex::new(0.0, 0.0); SPECTRUM_SIZE / 2];
+        self.count = 0;
+        estimate
+    }
+
+    /// In-place radix-2 decimation in time FFT.
+    fn fft(&self, buf: &mut [Complex<f32>; SPECTRUM_SIZE]) {
+        let bits = SPECTRUM_SIZE.trailing_zeros();
+        for i in 0..SPECTRUM_SIZE {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if j > i {
+                buf.swap(i, j);
+            }
+        }
+        let mut len = 2;
+        while len <= SPECTRUM_SIZE {
+            let step = SPECTRUM_SIZE / len;
+            for chunk in buf.chunks_exact_mut(len) {
+                let (a, b) = chunk.split_at_mut(len / 2);
+                for ((a, b), w) in a
+                    .iter_mut()
+                    .zip(b.iter_mut())
+                    .zip(self.twiddle.iter().step_by(step))
+                {
+                    let t = *b * *w;
+                    *b = *a - t;
+                    *a += t;
+                }
+            }
+            len <<= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sine(bin: usize, amplitude: f32) -> [f32; SPECTRUM_SIZE] {
+        core::array::from_fn(|k| {
+            amplitude * (TAU * (bin * k) as f32 / SPECTRUM_SIZE as f32).sin()
+        })
+    }
+
+    #[test]
+    fn capture_decimation() {
+        let mut capture = Capture::default();
+        assert!(capture.is_idle());
+        capture.start(2);
+        for k in 0..2 * SPECTRUM_SIZE {
+            assert!(capture.take().is_none());
+            capture.update([k as f32, 1.0]);
+        }
+        let [y, x] = capture.take().unwrap();
+        assert!(capture.is_idle());
+        for k in 0..SPECTRUM_SIZE {
+            assert_eq!(y[k], 2.0 * k as f32 + 0.5);
+            assert_eq!(x[k], 1.0);
+        }
+    }
+
+    #[test]
+    fn tone_power() {
+        let mut spectrum = Spectrum::new();
+        let y = sine(32, 2.0);
+        spectrum.update(&[y, y], [1.0; 2]);
+        assert_eq!(spectrum.count(), 1);
+        let estimate = spectrum.finish(1e3);
+        assert_eq!(spectrum.count(), 0);
+        assert_eq!(estimate.resolution, 1e3 / SPECTRUM_SIZE as f32);
+        // The integrated PSD is the mean square of the tone.
+        let power = estimate.psd.iter().sum::<f32>() * estimate.resolution;
+        assert!((power - 2.0).abs() < 2e-3);
+        let peak = (0..SPECTRUM_SIZE / 2)
+            .max_by(|i, j| estimate.psd[*i].total_cmp(&estimate.psd[*j]))
+            .unwrap();
+        assert_eq!(peak, 32);
+        let h = estimate.transfer[32];
+        assert!((h.re - 1.0).abs() < 1e-4 && h.im.abs() < 1e-4);
+    }
+
+    #[test]
+    fn averaging() {
+        let mut spectrum = Spectrum::new();
+        let y = sine(7, 1.0);
+        spectrum.update(&[y, y], [1.0; 2]);
+        let single = spectrum.finish(1.0);
+        spectrum.update(&[y, y], [1.0; 2]);
+        spectrum.update(&[y, y], [1.0; 2]);
+        let averaged = spectrum.finish(1.0);
+        for (a, b) in single.psd.iter().zip(averaged.psd.iter()) {
+            assert!((a - b).abs() <= 1e-6 * a.abs().max(1e-12));
+        }
+    }
+
+    #[test]
+    fn no_excitation() {
+        let mut spectrum = Spectrum::new();
+        spectrum.update(&[sine(3, 1.0), [0.0; SPECTRUM_SIZE]], [1.0; 2]);
+        let estimate = spectrum.finish(1.0);
+        assert!(estimate.transfer.iter().all(|h| h.re.is_nan()));
+        let empty = spectrum.finish(1.0);
+        assert!(empty.psd.iter().all(|psd| *psd == 0.0));
+        assert!(empty.asd_db().iter().all(|asd| *asd == -379));
+    }
+}
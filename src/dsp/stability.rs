@@ -0,0 +1,245 @@
+use idsp::iir::Biquad;
+use num_traits::Float;
+use serde::Serialize;
+
+/// Pole radius margin beyond the unit circle tolerated as marginally stable (integrators).
+const POLE_TOLERANCE: f32 = 1e-6;
+
+/// Number of impulse response samples summed for the peak gain estimate.
+const IMPULSE_LENGTH: usize = 1 << 12;
+
+/// Biquad coefficient issue
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Issue {
+    /// A pole lies outside the unit circle. The state diverges until it is limited by the output
+    /// range.
+    Unstable,
+    /// The output limits are inverted, not finite, or exceed the output range.
+    Limits,
+    /// The output for a full range input can exceed the output limits. It will saturate.
+    Saturation,
+}
+
+/// Biquad coefficient analysis
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Analysis {
+    /// Largest pole radius
+    pub pole_radius: f32,
+    /// DC gain
+    ///
+    /// Infinite for integrators.
+    pub dc_gain: f32,
+    /// Peak gain: the largest ratio of output to input magnitude for any input
+    /// (l1 norm of the impulse response).
+    ///
+    /// Infinite for marginally stable or unstable filters.
+    pub peak_gain: f32,
+    /// Output magnitude bound for the given input magnitude bound
+    pub bound: f32,
+    /// The most severe issue found
+    pub issue: Option<Issue>,
+}
+
+impl Analysis {
+    /// Analyze biquad coefficients.
+    ///
+    /// # Args
+    /// * `biquad` - The biquad to analyze. It uses the `idsp` sign convention
+    ///   `H(z) = (b0 + b1 z^-1 + b2 z^-2)/(1 - a1 z^-1 - a2 z^-2)`.
+    /// * `input` - Input magnitude bound.
+    /// * `range` - Allowed output range. The biquad output limits must be within.
+    ///   Limits are otherwise unconstrained for intermediate stages of a cascade.
+    pub fn new(biquad: &Biquad<f32>, input: f32, range: (f32, f32)) -> Self {
+        let &[b0, b1, b2, a1, a2] = biquad.ba();
+        // Poles are the roots of z^2 - a1 z - a2
+        let d = a1 * a1 + 4.0 * a2;
+        let pole_radius = if d < 0.0 {
+            (-a2).sqrt()
+        } else {
+            (a1.abs() + d.sqrt()) / 2.0
+        };
+        let dc_gain = (b0 + b1 + b2) / (1.0 - a1 - a2);
+
+        let stable = pole_radius < 1.0;
+        let peak_gain = if stable {
+            let (mut y1, mut y2) = (0.0, 0.0);
+            let mut x = [1.0, 0.0, 0.0];
+            let mut sum = 0.0;
+            for _ in 0..IMPULSE_LENGTH {
+                let y0 = b0 * x[0] + b1 * x[1] + b2 * x[2] + a1 * y1 + a2 * y2;
+                sum += y0.abs();
+                (y2, y1) = (y1, y0);
+                x = [0.0, x[0], x[1]];
+            }
+            sum
+        } else {
+            f32::INFINITY
+        };
+
+        let (min, max) = (biquad.min(), biquad.max());
+        let limit = min.abs().max(max.abs());
+        let (bound, saturates) = if stable {
+            // Offset response
+            let u = biquad.u() / (1.0 - a1 - a2);
+            let bound = peak_gain * input;
+            (
+                limit.min(u.abs() + bound),
+                u + bound > max || u - bound < min,
+            )
+        } else {
+            (limit, false)
+        };
+
+        // Comparisons reject NaN.
+        let issue = if !(min <= max && min >= range.0 && max <= range.1) {
+            Some(Issue::Limits)
+        } else if !(pole_radius <= 1.0 + POLE_TOLERANCE) {
+            Some(Issue::Unstable)
+        } else if saturates {
+            Some(Issue::Saturation)
+        } else {
+            None
+        };
+
+        Self {
+            pole_radius,
+            dc_gain,
+            peak_gain,
+            bound,
+            issue,
+        }
+    }
+
+    /// Whether the coefficients are clearly invalid and should not be used.
+    pub fn invalid(&self) -> bool {
+        matches!(self.issue, Some(Issue::Unstable | Issue::Limits))
+    }
+
+    /// Analyze a cascade of biquads.
+    ///
+    /// The input bound of each stage is the output bound of the previous stage.
+    ///
+    /// # Args
+    /// * `cascade` - The biquads in processing order.
+    /// * `input` - Input magnitude bound of the first stage.
+    /// * `range` - Allowed output range of the last stage.
+    pub fn cascade<const N: usize>(
+        cascade: &[Biquad<f32>; N],
+        input: f32,
+        range: (f32, f32),
+    ) -> [Self; N] {
+        let mut bound = input;
+        core::array::from_fn(|i| {
+            let range = if i == N - 1 {
+                range
+            } else {
+                (f32::NEG_INFINITY, f32::INFINITY)
+            };
+            let analysis = Self::new(&cascade[i], bound, range);
+            bound = analysis.bound;
+            analysis
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn biquad(ba: [f32; 5]) -> Biquad<f32> {
+        let mut biquad = Biquad::from(ba);
+        biquad.set_min(-1.0);
+        biquad.set_max(1.0);
+        biquad
+    }
+
+    #[test]
+    fn stable_lowpass() {
+        // Single pole at z = 0.9, unity DC gain
+        let a =
+            Analysis::new(&biquad([0.1, 0.0, 0.0, 0.9, 0.0]), 0.5, (-1.0, 1.0));
+        assert!((a.pole_radius - 0.9).abs() < 1e-6);
+        assert!((a.dc_gain - 1.0).abs() < 1e-5);
+        assert!((a.peak_gain - 1.0).abs() < 1e-4);
+        assert!((a.bound - 0.5).abs() < 1e-4);
+        assert_eq!(a.issue, None);
+        assert!(!a.invalid());
+    }
+
+    #[test]
+    fn unit_circle() {
+        // Integrator and undamped resonator are marginally stable.
+        let omega: f32 = 0.1;
+        for ba in [
+            [1.0, 0.0, 0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0, 2.0 * omega.cos(), -1.0],
+        ] {
+            let a = Analysis::new(&biquad(ba), 0.5, (-1.0, 1.0));
+            assert!((a.pole_radius - 1.0).abs() < 1e-6);
+            assert!(a.peak_gain.is_infinite());
+            assert_eq!(a.bound, 1.0);
+            assert_eq!(a.issue, None);
+        }
+        let a =
+            Analysis::new(&biquad([1.0, 0.0, 0.0, 1.0, 0.0]), 0.5, (-1.0, 1.0));
+        assert!(a.dc_gain.is_infinite());
+    }
+
+    #[test]
+    fn unstable() {
+        // Real and complex pole pairs with radius 1.1
+        for ba in [[1.0, 0.0, 0.0, 1.1, 0.0], [1.0, 0.0, 0.0, 0.0, -1.21]] {
+            let a = Analysis::new(&biquad(ba), 0.5, (-1.0, 1.0));
+            assert!((a.pole_radius - 1.1).abs() < 1e-6);
+            assert_eq!(a.issue, Some(Issue::Unstable));
+            assert!(a.invalid());
+        }
+    }
+
+    #[test]
+    fn high_gain() {
+        let a = Analysis::new(
+            &biquad([10.0, 0.0, 0.0, 0.0, 0.0]),
+            0.5,
+            (-1.0, 1.0),
+        );
+        assert!((a.peak_gain - 10.0).abs() < 1e-6);
+        assert_eq!(a.bound, 1.0);
+        assert_eq!(a.issue, Some(Issue::Saturation));
+        assert!(!a.invalid());
+        // A smaller input does not saturate.
+        let a = Analysis::new(
+            &biquad([10.0, 0.0, 0.0, 0.0, 0.0]),
+            0.05,
+            (-1.0, 1.0),
+        );
+        assert_eq!(a.issue, None);
+    }
+
+    #[test]
+    fn limits() {
+        let mut b = biquad([1.0, 0.0, 0.0, 0.0, 0.0]);
+        b.set_min(0.5);
+        b.set_max(-0.5);
+        let a = Analysis::new(&b, 0.1, (-1.0, 1.0));
+        assert_eq!(a.issue, Some(Issue::Limits));
+        assert!(a.invalid());
+        // Limits beyond the output range
+        let a = Analysis::new(&biquad([1.0; 5]), 0.1, (-0.5, 0.5));
+        assert_eq!(a.issue, Some(Issue::Limits));
+    }
+
+    #[test]
+    fn cascade() {
+        let cascade = [
+            biquad([10.0, 0.0, 0.0, 0.0, 0.0]),
+            biquad([0.1, 0.0, 0.0, 0.9, 0.0]),
+        ];
+        let [first, second] = Analysis::cascade(&cascade, 0.05, (-1.0, 1.0));
+        assert!((first.bound - 0.5).abs() < 1e-6);
+        assert!((second.bound - 0.5).abs() < 1e-4);
+        // Intermediate stages are not limited by the output range.
+        assert_eq!(first.issue, None);
+        assert_eq!(second.issue, None);
+    }
+}
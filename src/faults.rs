@@ -0,0 +1,84 @@
+//! Latched fault register
+//!
+//! # Design
+//! Faults are recorded as they are detected. Each fault latches its flag and increments its
+//! counter. Flags remain set until they are cleared explicitly. Counters accumulate since boot.
+//! The register is reported in telemetry as a single place to check device health.
+use serde::Serialize;
+
+/// A device fault
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// DMA transfer or direct mode error
+    Dma,
+    /// Stream data dropped or not sent
+    Stream,
+    /// I2C peripheral access failure
+    I2c,
+    /// Output at the range limits
+    Saturation,
+    /// Interlock trip
+    Interlock,
+}
+
+/// State of a fault
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct FaultState {
+    /// The fault occurred since the register was cleared
+    pub latched: bool,
+    /// Number of occurrences since boot
+    pub count: u32,
+}
+
+/// Fault register
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct Faults {
+    /// DMA transfer or direct mode errors
+    pub dma: FaultState,
+    /// Stream frames or batches dropped
+    pub stream: FaultState,
+    /// I2C peripheral access failures
+    pub i2c: FaultState,
+    /// Sample batches with outputs at the range limits
+    pub saturation: FaultState,
+    /// Interlock trips
+    pub interlock: FaultState,
+}
+
+impl Faults {
+    /// Record occurrences of a fault.
+    ///
+    /// # Args
+    /// * `fault` - The fault.
+    /// * `count` - The number of occurrences. Zero has no effect.
+    pub fn record(&mut self, fault: Fault, count: u32) {
+        if count == 0 {
+            return;
+        }
+        let state = match fault {
+            Fault::Dma => &mut self.dma,
+            Fault::Stream => &mut self.stream,
+            Fault::I2c => &mut self.i2c,
+            Fault::Saturation => &mut self.saturation,
+            Fault::Interlock => &mut self.interlock,
+        };
+        if !state.latched {
+            log::warn!("Fault: {fault:?}");
+        }
+        state.latched = true;
+        state.count = state.count.wrapping_add(count);
+    }
+
+    /// Clear the latched flags. The counters are retained.
+    pub fn clear(&mut self) {
+        for state in [
+            &mut self.dma,
+            &mut self.stream,
+            &mut self.i2c,
+            &mut self.saturation,
+            &mut self.interlock,
+        ] {
+            state.latched = false;
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! ADS1115 external I2C ADC driver
+//!
+//! The ADS1115 is a four channel 16 bit delta-sigma ADC. Conversions are single-shot and
+//! single-ended (channel against GND) at 128 samples per second, i.e. a conversion takes about
+//! 8 ms.
+use crate::aux_adc::{AuxAdc, Range};
+use embedded_hal_02::blocking::i2c::{Write, WriteRead};
+
+/// The I2C address with the ADDR pin connected to GND.
+pub const I2C_ADDR: u8 = 0x48;
+
+const CONVERSION: u8 = 0x00;
+const CONFIG: u8 = 0x01;
+
+/// Config register: start a single conversion (write), conversion idle (read)
+const OS: u16 = 1 << 15;
+/// Config register: single-shot mode
+const MODE: u16 = 1 << 8;
+/// Config register: 128 samples per second
+const DR_128: u16 = 0b100 << 5;
+/// Config register: comparator disabled
+const COMP_QUE_DISABLE: u16 = 0b11;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    I2c,
+    InvalidChannel,
+}
+
+pub struct Ads1115<I2C> {
+    i2c: I2C,
+    address: u8,
+    range: Range,
+}
+
+impl<I2C, E> Ads1115<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Detect an ADS1115 and construct the driver.
+    ///
+    /// # Args
+    /// * `i2c` - The I2C bus.
+    /// * `address` - The I2C address of the device.
+    ///
+    /// # Returns
+    /// The driver if the device responds with its power-on configuration, else the bus.
+    pub fn detect(mut i2c: I2C, address: u8) -> Result<Self, I2C> {
+        let mut config = [0u8; 2];
+        match i2c.write_read(address, &[CONFIG], &mut config) {
+            // Power-on default configuration
+            Ok(()) if u16::from_be_bytes(config) == 0x8583 => Ok(Self {
+                i2c,
+                address,
+                range: Range::V2_048,
+            }),
+            _ => Err(i2c),
+        }
+    }
+
+    fn mux(channel: usize) -> Result<u16, Error> {
+        if channel >= 4 {
+            return Err(Error::InvalidChannel);
+        }
+        // Single-ended AINx against GND
+        Ok((0b100 | channel as u16) << 12)
+    }
+
+    fn pga(range: Range) -> u16 {
+        (match range {
+            Range::V6_144 => 0b000,
+            Range::V4_096 => 0b001,
+            Range::V2_048 => 0b010,
+            Range::V1_024 => 0b011,
+            Range::V0_512 => 0b100,
+            Range::V0_256 => 0b101,
+        }) << 9
+    }
+}
+
+impl<I2C, E> AuxAdc for Ads1115<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = Error;
+
+    const CHANNELS: usize = 4;
+
+    fn start(&mut self, channel: usize, range: Range) -> Result<(), Error> {
+        let config = OS
+            | Self::mux(channel)?
+            | Self::pga(range)
+            | MODE
+            | DR_128
+            | COMP_QUE_DISABLE;
+        let [hi, lo] = config.to_be_bytes();
+        self.i2c
+            .write(self.address, &[CONFIG, hi, lo])
+            .map_err(|_| Error::I2c)?;
+        self.range = range;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<Option<f32>, Error> {
+        let mut buf = [0u8; 2];
+        self.i2c
+            .write_read(self.address, &[CONFIG], &mut buf)
+            .map_err(|_| Error::I2c)?;
+        if u16::from_be_bytes(buf) & OS == 0 {
+            return Ok(None);
+        }
+        self.i2c
+            .write_read(self.address, &[CONVERSION], &mut buf)
+            .map_err(|_| Error::I2c)?;
+        let code = i16::from_be_bytes(buf);
+        Ok(Some(
+            code as f32 * (self.range.full_scale() / (1 << 15) as f32),
+        ))
+    }
+}
@@ -0,0 +1,150 @@
+//! Ethernet and USB network interface bridge
+//!
+//! # Design
+//! A single network stack serves both the Ethernet port and the USB network interface
+//! ([super::ecm]). [Bridge] joins both into one smoltcp device with the Ethernet MAC address,
+//! like a switch with the stack attached: Frames are received from either port in turn. Broadcast
+//! and multicast frames are transmitted on both ports, frames addressed to the USB host (learned
+//! from the frames it sends) only to USB and all other frames only to Ethernet. Replies to frames
+//! received over USB go to USB.
+//!
+//! While the USB host interface is down, frames are passed to the Ethernet device without a copy.
+//! Otherwise outgoing frames are serialized into a buffer and copied to the ports.
+use super::ecm::{EcmDevice, Frame, MTU, Sender};
+use smoltcp_nal::smoltcp::{
+    phy::{
+        ChecksumCapabilities, Device, DeviceCapabilities, PacketMeta, RxToken,
+        TxToken,
+    },
+    time::Instant,
+};
+
+/// Network device bridging Ethernet and the USB network interface
+pub struct Bridge<D> {
+    ethernet: D,
+    usb: EcmDevice,
+    usb_turn: bool,
+}
+
+impl<D> Bridge<D> {
+    /// Join the ports.
+    ///
+    /// # Args
+    /// * `ethernet` - The Ethernet device.
+    /// * `usb` - The network stack end of the USB network interface.
+    pub fn new(ethernet: D, usb: EcmDevice) -> Self {
+        Self {
+            ethernet,
+            usb,
+            usb_turn: false,
+        }
+    }
+}
+
+impl<D: Device> Device for Bridge<D> {
+    type RxToken<'a>
+        = Received<D::RxToken<'a>>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = Transmit<'a, D::TxToken<'a>>
+    where
+        Self: 'a;
+
+    fn receive(
+        &mut self,
+        timestamp: Instant,
+    ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        self.usb_turn = !self.usb_turn;
+        if !(self.usb_turn && self.usb.pending()) {
+            if let Some((rx, tx)) = self.ethernet.receive(timestamp) {
+                return Some((
+                    Received::Ethernet(rx),
+                    Transmit {
+                        ethernet: Some(tx),
+                        usb: self.usb.sender(),
+                    },
+                ));
+            }
+        }
+        let (frame, sender) = self.usb.receive()?;
+        Some((
+            Received::Usb(frame),
+            Transmit {
+                ethernet: None,
+                usb: Some(sender),
+            },
+        ))
+    }
+
+    fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        let token = Transmit {
+            ethernet: self.ethernet.transmit(timestamp),
+            usb: self.usb.sender(),
+        };
+        (token.ethernet.is_some() || token.usb.is_some()).then_some(token)
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = self.ethernet.capabilities();
+        // Frames to the USB host are not processed by the Ethernet MAC.
+        capabilities.checksum = ChecksumCapabilities::default();
+        capabilities.max_transmission_unit =
+            capabilities.max_transmission_unit.min(MTU);
+        capabilities
+    }
+}
+
+/// Frame received from one of the ports
+pub enum Received<R> {
+    Ethernet(R),
+    Usb(Frame),
+}
+
+impl<R: RxToken> RxToken for Received<R> {
+    fn consume<T, F>(self, f: F) -> T
+    where
+        F: FnOnce(&mut [u8]) -> T,
+    {
+        match self {
+            Self::Ethernet(token) => token.consume(f),
+            Self::Usb(mut frame) => f(&mut frame),
+        }
+    }
+}
+
+/// Frame to be transmitted to one or both ports
+pub struct Transmit<'a, T> {
+    ethernet: Option<T>,
+    usb: Option<Sender<'a>>,
+}
+
+impl<T: TxToken> TxToken for Transmit<'_, T> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let Some(usb) = self.usb else {
+            // Note(unwrap): A token has at least one port.
+            return self.ethernet.unwrap().consume(len, f);
+        };
+        let mut frame = Frame::new();
+        // smoltcp does not exceed the MTU from the capabilities.
+        frame.resize_default(len).ok();
+        let result = f(&mut frame);
+        let ethernet = self.ethernet.filter(|_| !usb.is_host(&frame));
+        if let Some(ethernet) = ethernet {
+            ethernet.consume(len, |buffer| buffer.copy_from_slice(&frame));
+        }
+        if usb.accepts(&frame) {
+            usb.send(frame);
+        }
+        result
+    }
+
+    fn set_meta(&mut self, meta: PacketMeta) {
+        if let Some(ethernet) = self.ethernet.as_mut() {
+            ethernet.set_meta(meta)
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! DSCP marking of outgoing IPv4 traffic
+//!
+//! # Design
+//! smoltcp does not expose the IPv4 type of service field to sockets. [DscpMarker] wraps the
+//! Ethernet device and rewrites the DSCP bits of outgoing IPv4 packets after smoltcp has
+//! serialized them. UDP traffic other than DNS and DHCP is the data stream. TCP traffic is control
+//! traffic (MQTT and HTTP). The IPv4 header checksum is updated incrementally (RFC 1624).
+use platform::Dscp;
+use smoltcp_nal::smoltcp::{
+    phy::{Device, DeviceCapabilities, PacketMeta, TxToken},
+    time::Instant,
+};
+
+const ETHERTYPE_OFFSET: usize = 12;
+const IP_OFFSET: usize = 14;
+const ETHERTYPE_IPV4: [u8; 2] = [0x08, 0x00];
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+const PORT_DNS: u16 = 53;
+const PORT_DHCP_SERVER: u16 = 67;
+
+/// Ethernet device marking outgoing traffic with DSCP values.
+pub struct DscpMarker<D> {
+    device: D,
+    dscp: Dscp,
+}
+
+impl<D> DscpMarker<D> {
+    /// Wrap a device.
+    ///
+    /// # Args
+    /// * `device` - The Ethernet device.
+    /// * `dscp` - The DSCP values to mark traffic with.
+    pub fn new(device: D, dscp: Dscp) -> Self {
+        Self { device, dscp }
+    }
+}
+
+impl<D: Device> Device for DscpMarker<D> {
+    type RxToken<'a>
+        = D::RxToken<'a>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = MarkingToken<D::TxToken<'a>>
+    where
+        Self: 'a;
+
+    fn receive(
+        &mut self,
+        timestamp: Instant,
+    ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let dscp = self.dscp;
+        self.device
+            .receive(timestamp)
+            .map(|(rx, tx)| (rx, MarkingToken { token: tx, dscp }))
+    }
+
+    fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        let dscp = self.dscp;
+        self.device
+            .transmit(timestamp)
+            .map(|token| MarkingToken { token, dscp })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.device.capabilities()
+    }
+}
+
+/// Transmit token marking the frame before handing it to the device.
+pub struct MarkingToken<T> {
+    token: T,
+    dscp: Dscp,
+}
+
+impl<T: TxToken> TxToken for MarkingToken<T> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let dscp = self.dscp;
+        self.token.consume(len, |frame| {
+            let result = f(frame);
+            mark(frame, &dscp);
+            result
+        })
+    }
+
+    fn set_meta(&mut self, meta: PacketMeta) {
+        self.token.set_meta(meta)
+    }
+}
+
+/// Rewrite the DSCP bits of an Ethernet frame carrying an IPv4 packet.
+fn mark(frame: &mut [u8], dscp: &Dscp) {
+    if frame.get(ETHERTYPE_OFFSET..IP_OFFSET) != Some(&ETHERTYPE_IPV4[..]) {
+        return;
+    }
+    let Some(ip) = frame.get_mut(IP_OFFSET..) else {
+        return;
+    };
+    if ip.len() < 20 {
+        return;
+    }
+    let header = ((ip[0] & 0xf) as usize) * 4;
+    let port = |offset| {
+        ip.get(header + offset..header + offset + 2)
+            .map(|p| u16::from_be_bytes([p[0], p[1]]))
+    };
+    let value = match ip[9] {
+        PROTOCOL_TCP => dscp.control,
+        PROTOCOL_UDP => match (port(0), port(2)) {
+            (Some(src), Some(dst))
+                if ![src, dst]
+                    .iter()
+                    .any(|p| [PORT_DNS, PORT_DHCP_SERVER].contains(p)) =>
+            {
+                dscp.stream
+            }
+            _ => return,
+        },
+        _ => return,
+    };
+    let tos = ((value & 0x3f) << 2) | (ip[1] & 0x3);
+    if tos == ip[1] {
+        return;
+    }
+    // RFC 1624: HC' = ~(~HC + ~m + m')
+    let old = u16::from_be_bytes([ip[0], ip[1]]);
+    ip[1] = tos;
+    let new = u16::from_be_bytes([ip[0], ip[1]]);
+    let checksum = u16::from_be_bytes([ip[10], ip[11]]);
+    let mut sum = !checksum as u32 + !old as u32 + new as u32;
+    sum = (sum & 0xffff) + (sum >> 16);
+    sum = (sum & 0xffff) + (sum >> 16);
+    ip[10..12].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+}
@@ -0,0 +1,446 @@
+//! USB network interface (CDC-ECM)
+//!
+//! # Design
+//! [EcmClass] implements the USB Communications Device Class Ethernet Control Model next to the
+//! serial terminal. Ethernet frames are exchanged with the host as bulk transfers terminated by a
+//! short (possibly empty) packet. The class is polled by the USB task. The network stack accesses
+//! the interface through [EcmDevice] (see [super::bridge]). Both exchange frames through single
+//! producer, single consumer queues in [Storage] so that the tasks share no other state. Frames
+//! are dropped when a queue is full.
+//!
+//! The host names its end of the link with the address from the MAC address string descriptor.
+//! The interface is active while the host has selected the data interface alternate setting with
+//! the endpoints, i.e. while the host interface is up.
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use heapless::{
+    String, Vec,
+    spsc::{Consumer, Producer, Queue},
+};
+use usb_device::{LangID, UsbError, class_prelude::*, control};
+
+const USB_CLASS_CDC: u8 = 0x02;
+const USB_CLASS_CDC_DATA: u8 = 0x0a;
+const CDC_SUBCLASS_ECM: u8 = 0x06;
+const CDC_PROTOCOL_NONE: u8 = 0x00;
+const CS_INTERFACE: u8 = 0x24;
+const CDC_TYPE_HEADER: u8 = 0x00;
+const CDC_TYPE_UNION: u8 = 0x06;
+const CDC_TYPE_ETHERNET: u8 = 0x0f;
+const SET_ETHERNET_PACKET_FILTER: u8 = 0x43;
+const NETWORK_CONNECTION: u8 = 0x00;
+const CONNECTION_SPEED_CHANGE: u8 = 0x2a;
+const REQUEST_CLASS_INTERFACE_IN: u8 = 0xa1;
+
+/// Full speed bulk packet size
+const PACKET: u16 = 64;
+
+/// Full speed bit rate reported to the host
+const SPEED: u32 = 12_000_000;
+
+/// Maximum Ethernet frame length (without frame check sequence)
+pub const MTU: usize = 1514;
+
+/// Size of the frame queues. A queue holds one frame less.
+const QUEUE: usize = 4;
+
+/// An Ethernet frame
+pub type Frame = Vec<u8, MTU>;
+
+/// Frame queues shared between the USB class and the network stack
+pub struct Storage {
+    rx: Queue<Frame, QUEUE>,
+    tx: Queue<Frame, QUEUE>,
+    active: AtomicBool,
+}
+
+impl Storage {
+    /// Empty queues with the interface down.
+    pub const fn new() -> Self {
+        Self {
+            rx: Queue::new(),
+            tx: Queue::new(),
+            active: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Notification {
+    Connection,
+    Speed,
+}
+
+/// The CDC-ECM USB class
+pub struct EcmClass<'a, B: UsbBus> {
+    comm_if: InterfaceNumber,
+    comm_ep: EndpointIn<'a, B>,
+    data_if: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    mac_string: StringIndex,
+    mac: String<12>,
+    alt: u8,
+    notification: Option<Notification>,
+    active: &'static AtomicBool,
+    rx: Producer<'static, Frame, QUEUE>,
+    receiving: Frame,
+    overflow: bool,
+    tx: Consumer<'static, Frame, QUEUE>,
+    sending: Option<(Frame, usize)>,
+    busy: bool,
+}
+
+impl<'a, B: UsbBus> EcmClass<'a, B> {
+    /// Allocate the class and its network stack end.
+    ///
+    /// # Args
+    /// * `alloc` - The USB bus allocator.
+    /// * `host` - The MAC address of the host end of the link.
+    /// * `storage` - The frame queues.
+    pub fn new(
+        alloc: &'a UsbBusAllocator<B>,
+        host: [u8; 6],
+        storage: &'static mut Storage,
+    ) -> (Self, EcmDevice) {
+        let mut mac = String::new();
+        for byte in host {
+            // Note(unwrap): The string holds six bytes in hexadecimal.
+            write!(mac, "{byte:02X}").unwrap();
+        }
+        let (rx, stack_rx) = storage.rx.split();
+        let (stack_tx, tx) = storage.tx.split();
+        let active = &storage.active;
+        let class = Self {
+            comm_if: alloc.interface(),
+            comm_ep: alloc.interrupt(16, 32),
+            data_if: alloc.interface(),
+            read_ep: alloc.bulk(PACKET),
+            write_ep: alloc.bulk(PACKET),
+            mac_string: alloc.string(),
+            mac,
+            alt: 0,
+            notification: None,
+            active,
+            rx,
+            receiving: Frame::new(),
+            overflow: false,
+            tx,
+            sending: None,
+            busy: false,
+        };
+        let device = EcmDevice {
+            rx: stack_rx,
+            tx: stack_tx,
+            active,
+            host: None,
+        };
+        (class, device)
+    }
+
+    /// Whether the host interface is up.
+    pub fn is_active(&self) -> bool {
+        self.alt == 1
+    }
+
+    fn set_alt(&mut self, alt: u8) {
+        self.alt = alt;
+        self.active.store(self.is_active(), Ordering::Relaxed);
+        self.notification =
+            self.is_active().then_some(Notification::Connection);
+        self.receiving.clear();
+        self.overflow = false;
+        self.sending = None;
+        self.busy = false;
+    }
+
+    fn notify(&mut self) {
+        let index = u8::from(self.comm_if);
+        let written = match self.notification {
+            None => return,
+            Some(Notification::Connection) => self.comm_ep.write(&[
+                REQUEST_CLASS_INTERFACE_IN,
+                NETWORK_CONNECTION,
+                1,
+                0,
+                index,
+                0,
+                0,
+                0,
+            ]),
+            Some(Notification::Speed) => {
+                let mut notification = [0; 16];
+                notification[..8].copy_from_slice(&[
+                    REQUEST_CLASS_INTERFACE_IN,
+                    CONNECTION_SPEED_CHANGE,
+                    0,
+                    0,
+                    index,
+                    0,
+                    8,
+                    0,
+                ]);
+                notification[8..12].copy_from_slice(&SPEED.to_le_bytes());
+                notification[12..].copy_from_slice(&SPEED.to_le_bytes());
+                self.comm_ep.write(&notification)
+            }
+        };
+        if written.is_ok() {
+            self.notification = match self.notification {
+                Some(Notification::Connection) => Some(Notification::Speed),
+                _ => None,
+            };
+        }
+    }
+
+    fn read(&mut self) {
+        let mut packet = [0; PACKET as usize];
+        let Ok(len) = self.read_ep.read(&mut packet) else {
+            return;
+        };
+        if self.receiving.extend_from_slice(&packet[..len]).is_err() {
+            self.overflow = true;
+        }
+        if len < packet.len() {
+            let frame = core::mem::take(&mut self.receiving);
+            if !core::mem::take(&mut self.overflow) {
+                self.rx.enqueue(frame).ok();
+            }
+        }
+    }
+
+    fn write(&mut self) {
+        if self.busy || !self.is_active() {
+            return;
+        }
+        if self.sending.is_none() {
+            self.sending = self.tx.dequeue().map(|frame| (frame, 0));
+        }
+        let Some((frame, offset)) = self.sending.as_mut() else {
+            return;
+        };
+        let end = frame.len().min(*offset + PACKET as usize);
+        match self.write_ep.write(&frame[*offset..end]) {
+            Ok(_) => {
+                self.busy = true;
+                if end - *offset < PACKET as usize {
+                    self.sending = None;
+                } else {
+                    *offset = end;
+                }
+            }
+            Err(UsbError::WouldBlock) => {}
+            Err(_) => self.sending = None,
+        }
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for EcmClass<'_, B> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.iad(
+            self.comm_if,
+            2,
+            USB_CLASS_CDC,
+            CDC_SUBCLASS_ECM,
+            CDC_PROTOCOL_NONE,
+            None,
+        )?;
+        writer.interface(
+            self.comm_if,
+            USB_CLASS_CDC,
+            CDC_SUBCLASS_ECM,
+            CDC_PROTOCOL_NONE,
+        )?;
+        writer.write(CS_INTERFACE, &[CDC_TYPE_HEADER, 0x10, 0x01])?;
+        writer.write(
+            CS_INTERFACE,
+            &[CDC_TYPE_UNION, self.comm_if.into(), self.data_if.into()],
+        )?;
+        let [mtu0, mtu1] = (MTU as u16).to_le_bytes();
+        // No statistics, no multicast or power filters
+        writer.write(
+            CS_INTERFACE,
+            &[
+                CDC_TYPE_ETHERNET,
+                self.mac_string.into(),
+                0,
+                0,
+                0,
+                0,
+                mtu0,
+                mtu1,
+                0,
+                0,
+                0,
+            ],
+        )?;
+        writer.endpoint(&self.comm_ep)?;
+        writer.interface_alt(
+            self.data_if,
+            0,
+            USB_CLASS_CDC_DATA,
+            0,
+            0,
+            None,
+        )?;
+        writer.interface_alt(
+            self.data_if,
+            1,
+            USB_CLASS_CDC_DATA,
+            0,
+            0,
+            None,
+        )?;
+        writer.endpoint(&self.write_ep)?;
+        writer.endpoint(&self.read_ep)?;
+        Ok(())
+    }
+
+    fn get_string(&self, index: StringIndex, _lang_id: LangID) -> Option<&str> {
+        (index == self.mac_string).then_some(self.mac.as_str())
+    }
+
+    fn reset(&mut self) {
+        self.set_alt(0);
+    }
+
+    fn get_alt_setting(&mut self, interface: InterfaceNumber) -> Option<u8> {
+        (interface == self.data_if).then_some(self.alt)
+    }
+
+    fn set_alt_setting(
+        &mut self,
+        interface: InterfaceNumber,
+        alternative: u8,
+    ) -> bool {
+        if interface != self.data_if || alternative > 1 {
+            return false;
+        }
+        self.set_alt(alternative);
+        true
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let request = xfer.request();
+        if request.request_type != control::RequestType::Class
+            || request.recipient != control::Recipient::Interface
+            || request.index != u8::from(self.comm_if) as u16
+        {
+            return;
+        }
+        match request.request {
+            // Frames are not filtered.
+            SET_ETHERNET_PACKET_FILTER => xfer.accept().ok(),
+            _ => xfer.reject().ok(),
+        };
+    }
+
+    fn poll(&mut self) {
+        self.notify();
+        self.write();
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        if addr == self.read_ep.address() {
+            self.read();
+        }
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.write_ep.address() {
+            self.busy = false;
+            self.write();
+        } else if addr == self.comm_ep.address() {
+            self.notify();
+        }
+    }
+}
+
+/// The network stack end of the USB network interface
+pub struct EcmDevice {
+    rx: Consumer<'static, Frame, QUEUE>,
+    tx: Producer<'static, Frame, QUEUE>,
+    active: &'static AtomicBool,
+    host: Option<[u8; 6]>,
+}
+
+impl EcmDevice {
+    /// Whether the host interface is up.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Whether a received frame is pending.
+    pub fn pending(&self) -> bool {
+        self.rx.ready()
+    }
+
+    /// Take a frame received from the host.
+    ///
+    /// The host address is learned from the frame source address.
+    ///
+    /// # Returns
+    /// The frame and a [Sender] for the reply. `None` if no frame is pending or the transmit queue
+    /// is full.
+    pub fn receive(&mut self) -> Option<(Frame, Sender<'_>)> {
+        if !self.tx.ready() {
+            return None;
+        }
+        let frame = self.rx.dequeue()?;
+        if let Some(source) = frame.get(6..12) {
+            self.host = source.try_into().ok();
+        }
+        Some((
+            frame,
+            Sender {
+                tx: &mut self.tx,
+                host: self.host,
+            },
+        ))
+    }
+
+    /// Prepare transmission of a frame to the host.
+    ///
+    /// # Returns
+    /// `None` if the host interface is down or the transmit queue is full.
+    pub fn sender(&mut self) -> Option<Sender<'_>> {
+        (self.is_active() && self.tx.ready()).then(|| Sender {
+            tx: &mut self.tx,
+            host: self.host,
+        })
+    }
+}
+
+/// Transmission of a frame to the host
+pub struct Sender<'a> {
+    tx: &'a mut Producer<'static, Frame, QUEUE>,
+    host: Option<[u8; 6]>,
+}
+
+impl Sender<'_> {
+    /// Whether a frame is addressed to the host only.
+    pub fn is_host(&self, frame: &[u8]) -> bool {
+        self.host
+            .is_some_and(|host| frame.get(..6) == Some(&host[..]))
+    }
+
+    /// Whether the host receives a frame: broadcast, multicast or addressed to the host.
+    pub fn accepts(&self, frame: &[u8]) -> bool {
+        frame.first().is_some_and(|dst| dst & 1 != 0) || self.is_host(frame)
+    }
+
+    /// Queue a frame for transmission.
+    pub fn send(self, frame: Frame) {
+        // The queue was ready when the sender was created.
+        self.tx.enqueue(frame).ok();
+    }
+}
@@ -66,6 +66,21 @@ impl InputStamper {
         self.capture_channel.enable();
     }
 
+    /// Configure the input conditioning of the timestamped edges.
+    ///
+    /// # Args
+    /// * `filter` - The input capture filter.
+    /// * `prescaler` - The number of edges per timestamp.
+    #[allow(dead_code)]
+    pub fn configure(
+        &mut self,
+        filter: timers::InputFilter,
+        prescaler: timers::Prescaler,
+    ) {
+        self.capture_channel.configure_filter(filter);
+        self.capture_channel.configure_prescaler(prescaler);
+    }
+
     /// Get the latest timestamp that has occurred.
     ///
     /// # Note
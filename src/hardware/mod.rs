@@ -8,35 +8,75 @@ use hal::{
 };
 pub use stm32h7xx_hal as hal;
 
-use platform::{ApplicationMetadata, AsyncFlash, UnlockFlash};
+use platform::{
+    ApplicationMetadata, AsyncFlash, BufferSizes, Counters, UnlockFlash,
+};
+
+use crate::design_parameters::net_buffers;
 
 pub mod adc;
+pub mod ads1115;
 pub mod afe;
+pub mod bridge;
 pub mod cpu_temp_sensor;
 pub mod dac;
+pub mod dscp;
+pub mod ecm;
 mod eeprom;
 pub mod input_stamper;
 pub mod net;
 pub mod pounder;
+pub mod pwm;
 pub mod setup;
 pub mod shared_adc;
 pub mod timers;
 
+/// The logger printing over RTT
+pub static LOGGER: platform::Logger =
+    platform::Logger::new(|line| rtt_target::rprintln!("{}", line));
+
+/// Take the number of DMA streams with transfer or direct mode errors since the last call.
+///
+/// The error flags of all streams of DMA1 and DMA2 are cleared.
+pub fn take_dma_errors() -> u32 {
+    // TEIF and DMEIF of streams 0/4, 1/5, 2/6 and 3/7
+    const MASK: u32 = 0x0C | (0x0C << 6) | (0x0C << 16) | (0x0C << 22);
+    let mut errors = 0;
+    // Note(unsafe): Only the error flags are read and cleared. Their clear bits are not used
+    // elsewhere.
+    for dma in [unsafe { &*hal::stm32::DMA1::ptr() }, unsafe {
+        &*hal::stm32::DMA2::ptr()
+    }] {
+        let low = dma.lisr.read().bits() & MASK;
+        let high = dma.hisr.read().bits() & MASK;
+        dma.lifcr.write(|w| unsafe { w.bits(low) });
+        dma.hifcr.write(|w| unsafe { w.bits(high) });
+        errors += low.count_ones() + high.count_ones();
+    }
+    errors
+}
+
 // Type alias for the analog front-end
 pub type Pgia = afe::ProgrammableGainAmplifier<
     Forward<ErasedPin<Output>, ForwardOutputPin>,
 >;
 
+// Type alias for the external auxiliary ADC on the I2C2 bus
+pub type AuxAdc = ads1115::Ads1115<hal::i2c::I2c<hal::stm32::I2C2>>;
+
 pub type UsbBus = hal::usb_hs::UsbBus<hal::usb_hs::USB2>;
 
 // Type alias for the USB device.
 pub type UsbDevice = usb_device::device::UsbDevice<'static, UsbBus>;
 
+// Type alias for the USB network interface class.
+pub type UsbEthernet = ecm::EcmClass<'static, UsbBus>;
+
 pub struct Gpio {
     pub lvds4: gpio::gpiod::PD1<Input>,
     pub lvds5: gpio::gpiod::PD2<Input>,
-    pub lvds6: gpio::gpiod::PD3<Output>,
-    pub lvds7: gpio::gpiod::PD4<Output>,
+    /// The LVDS6 and LVDS7 outputs
+    pub pwm: pwm::Pwm,
 }
 
 pub type Urukul = urukul::Urukul<
@@ -123,7 +163,11 @@ mod build_info {
 ///
 /// # Returns
 /// A reference to the global metadata.
-pub fn metadata(version: &'static str) -> &'static ApplicationMetadata {
+pub fn metadata(
+    version: &'static str,
+    settings_version: u32,
+    counters: Counters,
+) -> &'static ApplicationMetadata {
     cortex_m::singleton!(: ApplicationMetadata = ApplicationMetadata {
         firmware_version: build_info::GIT_VERSION.unwrap_or("Unspecified"),
         rust_version: build_info::RUSTC_VERSION,
@@ -131,6 +175,18 @@ pub fn metadata(version: &'static str) -> &'static ApplicationMetadata {
         git_dirty: build_info::GIT_DIRTY.unwrap_or(false),
         features: build_info::FEATURES_STR,
         hardware_version: version,
+        settings_version,
+        counters,
+        buffers: BufferSizes {
+            tcp: [net_buffers::TCP_RX_BUFFER, net_buffers::TCP_TX_BUFFER],
+            udp: [net_buffers::UDP_RX_BUFFER, net_buffers::UDP_TX_BUFFER],
+            udp_packets: net_buffers::UDP_PACKETS,
+            mqtt: [
+                net_buffers::MQTT_TELEMETRY_BUFFER,
+                net_buffers::MQTT_SETTINGS_BUFFER,
+            ],
+            stream_frames: stream::FRAME_COUNT,
+        },
         panic_info: panic_persist::get_panic_message_utf8().unwrap_or("None"),
     })
     .unwrap()
@@ -8,14 +8,25 @@
 use heapless;
 use miniconf;
 
-use crate::hardware::{SystemTimer, hal::ethernet};
-use platform::{ApplicationMetadata, NetSettings, TelemetryClient};
-use stream::{DataStream, FrameGenerator, Target};
+use crate::design_parameters::net_buffers;
+use crate::hardware::{
+    SystemTimer, Systick, bridge::Bridge, dscp::DscpMarker, hal::ethernet,
+};
+use platform::{
+    ApplicationMetadata, BrokerState, Command, CommandAuth,
+    DFU_CONFIRM_TIMEOUT, HttpServer, NetSettings, PROBATION_PATHS, Provisioner,
+    TelemetryClient, Validate,
+};
+use stream::{DataStream, FrameGenerator};
 
 use core::fmt::Write;
-use heapless::String;
-use miniconf::{TreeDeserializeOwned, TreeSerialize};
+use heapless::{String, Vec};
+use miniconf::{
+    Path, TreeDeserializeOwned, TreeSchema, TreeSerialize, json_core,
+};
 use miniconf_mqtt::minimq;
+use rtic_monotonics::Monotonic;
+use serde::Serialize;
 
 pub type EthernetPhy = ethernet::phy::LAN8742A<ethernet::EthernetMAC>;
 
@@ -28,28 +39,28 @@ pub const TX_DESRING_CNT: usize = 4;
 // Number of RX descriptors in the ethernet descriptor ring.
 pub const RX_DESRING_CNT: usize = 4;
 
-pub type NetworkStack = smoltcp_nal::NetworkStack<
-    'static,
-    ethernet::EthernetDMA<TX_DESRING_CNT, RX_DESRING_CNT>,
-    SystemTimer,
->;
+/// The network device: Ethernet bridged with the USB network interface
+pub type NetworkDevice =
+    DscpMarker<Bridge<ethernet::EthernetDMA<TX_DESRING_CNT, RX_DESRING_CNT>>>;
 
-pub type NetworkManager = smoltcp_nal::shared::NetworkManager<
-    'static,
-    ethernet::EthernetDMA<TX_DESRING_CNT, RX_DESRING_CNT>,
-    SystemTimer,
->;
+pub type NetworkStack =
+    smoltcp_nal::NetworkStack<'static, NetworkDevice, SystemTimer>;
+
+pub type NetworkManager =
+    smoltcp_nal::shared::NetworkManager<'static, NetworkDevice, SystemTimer>;
 
 struct MqttStorage {
-    telemetry: [u8; 2048],
-    settings: [u8; 1024],
+    telemetry: [u8; net_buffers::MQTT_TELEMETRY_BUFFER],
+    settings: [u8; net_buffers::MQTT_SETTINGS_BUFFER],
+    secondary: [u8; net_buffers::MQTT_TELEMETRY_BUFFER],
 }
 
 impl Default for MqttStorage {
     fn default() -> Self {
         Self {
-            telemetry: [0u8; 2048],
-            settings: [0u8; 1024],
+            telemetry: [0u8; net_buffers::MQTT_TELEMETRY_BUFFER],
+            settings: [0u8; net_buffers::MQTT_SETTINGS_BUFFER],
+            secondary: [0u8; net_buffers::MQTT_TELEMETRY_BUFFER],
         }
     }
 }
@@ -59,6 +70,7 @@ pub enum UpdateState {
     Updated,
 }
 
+#[derive(Copy, Clone)]
 pub enum NetworkState {
     SettingsChanged,
     Updated,
@@ -67,6 +79,71 @@ pub enum NetworkState {
 
 const MAX_DEPTH: usize = 16;
 
+/// DFU reboot handshake state change event
+#[derive(Serialize)]
+struct DfuEvent {
+    /// A DFU reboot is armed and awaits confirmation
+    dfu_armed: bool,
+}
+
+/// Settings probation state change event
+#[derive(Copy, Clone, Debug, Serialize)]
+enum Probation {
+    /// A risky change is on probation
+    Started,
+    /// The probation window elapsed and the change is kept
+    Committed,
+    /// The broker connection was lost and the previous settings are restored
+    Reverted,
+}
+
+#[derive(Serialize)]
+struct ProbationEvent {
+    probation: Probation,
+}
+
+/// Safe boot event
+#[derive(Serialize)]
+struct SafeBootEvent {
+    /// The persisted application settings were skipped at boot
+    safe_boot: bool,
+}
+
+/// Stream pause state change event
+#[derive(Serialize)]
+struct StreamEvent {
+    /// Stream transmission is paused because the target is unreachable
+    stream_paused: bool,
+}
+
+/// Whether any leaf below the path prefixes differs between two settings.
+fn changed<S: TreeSerialize + TreeSchema>(
+    a: &S,
+    b: &S,
+    prefixes: &[String<64>],
+) -> bool {
+    let mut buf = [[0u8; 256]; 2];
+    S::SCHEMA
+        .nodes::<Path<String<128>, '/'>, MAX_DEPTH>()
+        .any(|path| {
+            // Note(unwrap): The paths fit by design.
+            let path = path.unwrap();
+            if !prefixes.iter().any(|p| path.0.starts_with(p.as_str())) {
+                return false;
+            }
+            let [x, y] = &mut buf;
+            let path = Path::<_, '/'>(path.0.as_str());
+            match (
+                json_core::get_by_key(a, path, x),
+                json_core::get_by_key(b, path, y),
+            ) {
+                (Ok(i), Ok(j)) => x[..i] != y[..j],
+                (Err(_), Err(_)) => false,
+                _ => true,
+            }
+        })
+}
+
 /// A structure of Stabilizer's default network users.
 pub struct NetworkUsers<S> {
     miniconf: miniconf_mqtt::MqttClient<
@@ -81,31 +158,54 @@ pub struct NetworkUsers<S> {
     stream: DataStream<NetworkReference>,
     generator: Option<FrameGenerator>,
     pub telemetry: TelemetryClient<SystemTimer, NetworkReference>,
+    http: Option<HttpServer<NetworkReference>>,
+    provisioner: Option<Provisioner<NetworkReference>>,
+    /// Time a DFU reboot was armed (ms)
+    dfu_armed: Option<u32>,
+    /// Probation window (ms)
+    probation: Option<u32>,
+    probation_paths: Vec<String<64>, PROBATION_PATHS>,
+    /// The last settings not on probation
+    committed: Option<S>,
+    /// Time a risky change was applied (ms)
+    probation_started: Option<u32>,
+    /// Probation event not yet published
+    probation_event: Option<Probation>,
+    /// Safe boot event not yet published
+    safe_boot_event: bool,
+    /// Stream pause state as last published
+    stream_paused: bool,
 }
 
 impl<S> NetworkUsers<S>
 where
-    S: TreeDeserializeOwned + TreeSerialize,
+    S: TreeDeserializeOwned + TreeSerialize + TreeSchema + Clone + Validate,
 {
     /// Construct Stabilizer's default network users.
     ///
     /// # Args
     /// * `stack` - The network stack that will be used to share with all network users.
     /// * `phy` - The ethernet PHY connecting the network.
+    /// * `nonce` - The random initial remote command nonce.
     /// * `clock` - A `SystemTimer` implementing `Clock`.
     /// * `app` - The name of the application.
     /// * `net_settings` - The network-specific settings to use for the application.
     /// * `metadata` - The application metadata
+    /// * `provision` - Fetch the initial settings from the provisioning server.
+    /// * `safe_boot` - The persisted application settings were skipped.
     ///
     /// # Returns
     /// A new struct of network users.
     pub fn new(
         stack: NetworkStack,
         phy: EthernetPhy,
+        nonce: u64,
         clock: SystemTimer,
         app: &str,
         net_settings: &NetSettings,
         metadata: &'static ApplicationMetadata,
+        provision: bool,
+        safe_boot: bool,
     ) -> Self {
         let stack_manager =
             cortex_m::singleton!(: NetworkManager = NetworkManager::new(stack))
@@ -114,7 +214,7 @@ where
         let processor =
             NetworkProcessor::new(stack_manager.acquire_stack(), phy);
 
-        let prefix = cortex_m::singleton!(: String<128> = get_device_prefix(app, &net_settings.id)).unwrap();
+        let prefix: &'static str = cortex_m::singleton!(: String<128> = get_device_prefix(app, &net_settings.id)).unwrap();
 
         let store =
             cortex_m::singleton!(: MqttStorage = MqttStorage::default())
@@ -127,9 +227,10 @@ where
         .unwrap();
         let miniconf = miniconf_mqtt::MqttClient::<_, _, _, _, MAX_DEPTH>::new(
             stack_manager.acquire_stack(),
-            prefix.as_str(),
+            prefix,
             clock,
             minimq::ConfigBuilder::new(named_broker, &mut store.settings)
+                .keepalive_interval(net_settings.mqtt_keepalive)
                 .client_id(&get_client_id(&net_settings.id, "settings"))
                 .unwrap(),
         )
@@ -144,23 +245,109 @@ where
             stack_manager.acquire_stack(),
             clock,
             minimq::ConfigBuilder::new(named_broker, &mut store.telemetry)
-                // The telemetry client doesn't receive any messages except MQTT control packets.
-                // As such, we don't need much of the buffer for RX.
-                .rx_buffer(minimq::config::BufferConfig::Maximum(100))
+                // The telemetry client doesn't receive any messages except MQTT control packets
+                // and remote commands. As such, we don't need much of the buffer for RX.
+                .rx_buffer(minimq::config::BufferConfig::Maximum(256))
+                .keepalive_interval(net_settings.mqtt_keepalive)
                 .client_id(&get_client_id(&net_settings.id, "tlm"))
                 .unwrap(),
         );
 
-        let telemetry = TelemetryClient::new(mqtt, prefix, metadata);
+        let secondary =
+            (!net_settings.secondary_broker.is_empty()).then(|| {
+                let named_broker = minimq::broker::NamedBroker::new(
+                    &net_settings.secondary_broker,
+                    stack_manager.acquire_stack(),
+                )
+                .unwrap();
+                minimq::Minimq::new(
+                    stack_manager.acquire_stack(),
+                    clock,
+                    minimq::ConfigBuilder::new(
+                        named_broker,
+                        &mut store.secondary,
+                    )
+                    // The secondary broker client doesn't receive any messages except MQTT
+                    // control packets.
+                    .rx_buffer(minimq::config::BufferConfig::Maximum(256))
+                    .keepalive_interval(net_settings.mqtt_keepalive)
+                    .client_id(&get_client_id(&net_settings.id, "mon"))
+                    .unwrap(),
+                )
+            });
+
+        let telemetry_prefix = if net_settings.telemetry_prefix.is_empty() {
+            prefix
+        } else {
+            let expanded = expand_prefix(
+                &net_settings.telemetry_prefix,
+                app,
+                &net_settings.id,
+            );
+            if expanded.is_none() {
+                log::error!("Telemetry prefix too long, using the default");
+            }
+            match expanded {
+                Some(expanded) => {
+                    let expanded: &'static str =
+                        cortex_m::singleton!(: String<128> = expanded).unwrap();
+                    expanded
+                }
+                None => prefix,
+            }
+        };
+
+        let telemetry = TelemetryClient::new(
+            mqtt,
+            telemetry_prefix,
+            metadata,
+            CommandAuth::new(&net_settings.secret, nonce),
+            secondary,
+        );
 
         let (generator, stream) = stream::setup(stack_manager.acquire_stack());
 
+        let http = net_settings.http.map(|port| {
+            HttpServer::new(
+                stack_manager.acquire_stack(),
+                port,
+                &net_settings.http_token,
+                metadata,
+            )
+        });
+
+        let provisioner = provision.then(|| {
+            log::info!(
+                "Fetching the initial settings from `{}`",
+                net_settings.provisioning
+            );
+            Provisioner::new(
+                stack_manager.acquire_stack(),
+                &net_settings.provisioning,
+                app,
+                &net_settings.id,
+            )
+        });
+
         NetworkUsers {
             miniconf,
             processor,
             telemetry,
             stream,
             generator: Some(generator),
+            http,
+            provisioner,
+            dfu_armed: None,
+            probation: net_settings
+                .probation
+                .filter(|window| *window > 0.0)
+                .map(|window| (window * 1e3) as u32),
+            probation_paths: net_settings.probation_paths.clone(),
+            committed: None,
+            probation_started: None,
+            probation_event: None,
+            safe_boot_event: safe_boot,
+            stream_paused: false,
         }
     }
 
@@ -177,16 +364,78 @@ where
         generator
     }
 
-    /// Direct the stream to the provided remote target.
+    /// Direct the stream to the provided remote target and apply the frame limits.
     ///
     /// # Args
-    /// * `remote` - The destination for the streamed data.
-    pub fn direct_stream(&mut self, remote: Target) {
+    /// * `config` - The destination and framing for the streamed data.
+    pub fn direct_stream(&mut self, config: &stream::Config) {
         if self.generator.is_none() {
-            self.stream.set_remote(remote);
+            self.stream.configure(config);
         }
     }
 
+    /// Get the data stream transmission statistics.
+    pub fn stream_statistics(&self) -> stream::Statistics {
+        self.stream.statistics()
+    }
+
+    /// Update the telemetry and metrics served over HTTP.
+    ///
+    /// # Args
+    /// * `telemetry` - The latest application telemetry.
+    pub fn serve_telemetry<T: Serialize>(&mut self, telemetry: &T) {
+        let stats = self.stream.statistics();
+        let Some(http) = self.http.as_mut() else {
+            return;
+        };
+        http.set_telemetry(telemetry)
+            .map_err(|e| log::error!("Telemetry serialization error: {e:?}"))
+            .ok();
+        http.clear_metrics();
+        http.add_metrics("stabilizer", telemetry)
+            .and_then(|_| http.add_metrics("stabilizer_stream", &stats))
+            .map_err(|e| log::error!("Metrics rendering error: {e:?}"))
+            .ok();
+    }
+
+    /// Queue live data for the WebSocket client of the HTTP server.
+    ///
+    /// # Args
+    /// * `data` - The data to send.
+    ///
+    /// # Returns
+    /// Whether the data was queued.
+    pub fn push_live(&mut self, data: &[u8]) -> bool {
+        self.http.as_mut().is_some_and(|http| http.push_live(data))
+    }
+
+    /// Take the initial settings received from the provisioning server.
+    ///
+    /// # Args
+    /// * `f` - Called with the provisioned settings, one `<path> <JSON value>` per line, once
+    ///   they are received. Provisioning ends.
+    ///
+    /// # Returns
+    /// The return value of `f` if it was called.
+    pub fn take_provisioned<R>(
+        &mut self,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Option<R> {
+        let ret = f(self.provisioner.as_ref()?.received()?);
+        self.provisioner = None;
+        Some(ret)
+    }
+
+    /// Whether the primary broker connection is established.
+    pub fn is_connected(&mut self) -> bool {
+        self.telemetry.is_connected()
+    }
+
+    /// The connection state of the primary and the secondary broker.
+    pub fn brokers(&self) -> BrokerState {
+        self.telemetry.brokers()
+    }
+
     /// Update and process all of the network users state.
     ///
     /// # Returns
@@ -194,12 +443,62 @@ where
     /// The SettingsChanged option contains the path of the settings that changed.
     pub fn update(&mut self, settings: &mut S) -> NetworkState {
         // Update the MQTT clients.
-        self.telemetry.update();
+        let now = Systick::now().ticks();
+        let was_armed = self.dfu_armed.is_some();
+        if self
+            .dfu_armed
+            .is_some_and(|t| now.wrapping_sub(t) >= DFU_CONFIRM_TIMEOUT)
+        {
+            log::warn!("DFU reboot confirmation timed out");
+            self.dfu_armed = None;
+        }
+        match self.telemetry.update(&*settings) {
+            Some(Command::Reboot) => cortex_m::peripheral::SCB::sys_reset(),
+            Some(Command::Dfu) => {
+                log::warn!("DFU reboot armed");
+                self.dfu_armed = Some(now);
+            }
+            Some(Command::Confirm) => {
+                if self.dfu_armed.take().is_some() {
+                    platform::dfu_reboot();
+                } else {
+                    log::warn!("DFU reboot not armed");
+                }
+            }
+            None => {}
+        }
+        if was_armed != self.dfu_armed.is_some() {
+            self.telemetry.publish_telemetry(
+                "/event",
+                &DfuEvent {
+                    dfu_armed: self.dfu_armed.is_some(),
+                },
+            );
+        }
+        // The safe boot event is published once connected.
+        if self.safe_boot_event && self.telemetry.is_connected() {
+            self.telemetry.publish_telemetry(
+                "/event",
+                &SafeBootEvent { safe_boot: true },
+            );
+            self.safe_boot_event = false;
+        }
 
         // Update the data stream.
         if self.generator.is_none() {
             self.stream.process();
         }
+        // Pause state changes are published once connected.
+        let paused = self.stream.paused();
+        if paused != self.stream_paused && self.telemetry.is_connected() {
+            self.telemetry.publish_telemetry(
+                "/event",
+                &StreamEvent {
+                    stream_paused: paused,
+                },
+            );
+            self.stream_paused = paused;
+        }
 
         // Poll for incoming data.
         let poll_result = match self.processor.update() {
@@ -207,11 +506,71 @@ where
             UpdateState::Updated => NetworkState::Updated,
         };
 
+        let http = self
+            .http
+            .as_mut()
+            .is_some_and(|http| http.update(settings, now));
+
+        if let Some(provisioner) = self.provisioner.as_mut() {
+            provisioner.update(now);
+        }
+
         let res = self.miniconf.update(settings);
-        match res {
+        let state = match res {
             Ok(true) => NetworkState::SettingsChanged,
+            _ if http => NetworkState::SettingsChanged,
             _ => poll_result,
+        };
+        let state = self.probation(settings, now, state);
+        if matches!(state, NetworkState::SettingsChanged) {
+            self.telemetry.invalidate_settings();
         }
+        self.telemetry.publish_settings(settings);
+        state
+    }
+
+    /// Track risky settings changes and revert them if the broker connection is lost.
+    fn probation(
+        &mut self,
+        settings: &mut S,
+        now: u32,
+        mut state: NetworkState,
+    ) -> NetworkState {
+        let Some(window) = self.probation else {
+            return state;
+        };
+        let committed = self.committed.get_or_insert_with(|| settings.clone());
+        if let Some(started) = self.probation_started {
+            if !self.telemetry.is_connected() {
+                log::warn!("Connection lost on probation. Reverting settings");
+                settings.clone_from(committed);
+                self.probation_started = None;
+                self.probation_event = Some(Probation::Reverted);
+                state = NetworkState::SettingsChanged;
+            } else if now.wrapping_sub(started) >= window {
+                log::info!("Settings probation passed");
+                committed.clone_from(settings);
+                self.probation_started = None;
+                self.probation_event = Some(Probation::Committed);
+            }
+        } else if matches!(state, NetworkState::SettingsChanged) {
+            if changed(committed, settings, &self.probation_paths) {
+                log::warn!("Risky settings change on probation");
+                self.probation_started = Some(now);
+                self.probation_event = Some(Probation::Started);
+            } else {
+                committed.clone_from(settings);
+            }
+        }
+
+        // Events are published once the connection is restored.
+        if self.telemetry.is_connected() {
+            if let Some(probation) = self.probation_event.take() {
+                self.telemetry
+                    .publish_telemetry("/event", &ProbationEvent { probation });
+            }
+        }
+        state
     }
 }
 
@@ -246,6 +605,35 @@ fn get_device_prefix(app: &str, id: &str) -> String<128> {
     prefix
 }
 
+/// Expand a topic prefix template.
+///
+/// # Args
+/// * `template` - The template with `{app}` and `{id}` placeholders.
+/// * `app` - The name of the application that is executing.
+/// * `id` - The MQTT ID of the device.
+///
+/// # Returns
+/// The expanded prefix or `None` if it does not fit.
+fn expand_prefix(template: &str, app: &str, id: &str) -> Option<String<128>> {
+    let mut prefix: String<128> = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        prefix.push_str(&rest[..start]).ok()?;
+        let tail = &rest[start..];
+        let (value, len) = if tail.starts_with("{app}") {
+            (app, "{app}".len())
+        } else if tail.starts_with("{id}") {
+            (id, "{id}".len())
+        } else {
+            ("{", 1)
+        };
+        prefix.push_str(value).ok()?;
+        rest = &tail[len..];
+    }
+    prefix.push_str(rest).ok()?;
+    Some(prefix)
+}
+
 // Task to process network hardware.
 //
 // # Design
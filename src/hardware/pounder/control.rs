@@ -0,0 +1,317 @@
+//! Pounder RF control
+//!
+//! # Design
+//! [PounderControl] owns the Pounder devices and applies the [PounderConfig] of the application:
+//! [PounderControl::update] applies configuration changes and executes the one-shot requests,
+//! [PounderControl::service] runs the periodic amplitude locks, amplitude modulations and RF power
+//! servos and watches the board temperature. The DDS profile stream and the DDS updates of the
+//! processing task are only locked for the DDS writes.
+//!
+//! Follow-up work that spans longer than an update (clock verification, attenuation ramps, phase
+//! synchronization and profile verification) is returned to the application as a [Followup] to be
+//! scheduled.
+use super::{
+    Channel, DdsAmplitude, DdsUpdates, Error, PounderDevices,
+    dds_output::DdsOutput,
+};
+use crate::pounder_config::{
+    LockInput, PounderConfig, PounderRequests, RegisterAccess, RegisterResponse,
+};
+use rtic::Mutex;
+
+/// Period of the temperature and input power watch (s)
+const WATCH_PERIOD: f32 = 0.1;
+
+/// Follow-up work of a configuration update
+#[derive(Clone, Debug, Default)]
+pub struct Followup {
+    /// The external reference clock was selected and should be verified
+    pub clock_check: bool,
+    /// Attenuations are ramping, see [PounderDevices::ramp_attenuators]
+    pub attenuation_ramp: bool,
+    /// The DDS channel phases should be synchronized, see [PounderDevices::sync]
+    pub sync: bool,
+    /// The DDS profiles should be verified, see [PounderDevices::verify_dds]
+    pub verify: bool,
+    /// The response to a register access request, published on `/dds/register`
+    pub register: Option<RegisterResponse>,
+}
+
+/// Pounder RF control
+pub struct PounderControl {
+    devices: PounderDevices,
+    /// Servoed amplitudes of the amplitude locks
+    amplitude: [Option<f32>; 2],
+    /// Filtered inputs of the amplitude modulations
+    filtered: [Option<f32>; 2],
+    /// Input detector powers (dBm) of the last watch, NaN if unavailable
+    power: [f32; 2],
+    /// Time since the last watch (s)
+    elapsed: f32,
+}
+
+impl PounderControl {
+    /// Construct the control.
+    ///
+    /// Args:
+    /// * `devices` - The Pounder devices.
+    pub fn new(devices: PounderDevices) -> Self {
+        Self {
+            devices,
+            amplitude: [None; 2],
+            filtered: [None; 2],
+            power: [f32::NAN; 2],
+            elapsed: 0.0,
+        }
+    }
+
+    /// The Pounder devices, e.g. for the follow-up work.
+    pub fn devices(&mut self) -> &mut PounderDevices {
+        &mut self.devices
+    }
+
+    /// The input detector powers (dBm) of the last watch, NaN if unavailable.
+    pub fn power(&self) -> [f32; 2] {
+        self.power
+    }
+
+    /// Apply a configuration and execute the one-shot requests.
+    ///
+    /// Errors are logged. The frequency response measurement request is handled by the
+    /// application.
+    ///
+    /// Args:
+    /// * `config` - The configuration to apply.
+    /// * `requests` - The one-shot requests taken from the configuration.
+    /// * `now` - The current wrapping timestamp in milliseconds.
+    /// * `period` - The period of the [DdsUpdates::write] updates in seconds.
+    /// * `dds` - The DDS profile stream.
+    /// * `updates` - The DDS updates of the processing task.
+    ///
+    /// Returns:
+    /// The follow-up work to schedule.
+    pub fn update(
+        &mut self,
+        config: &PounderConfig,
+        requests: PounderRequests,
+        now: u32,
+        period: f32,
+        dds: &mut impl Mutex<T = Option<DdsOutput>>,
+        updates: &mut impl Mutex<T = DdsUpdates>,
+    ) -> Followup {
+        let mut followup = Followup::default();
+        if requests.clear_alarm {
+            self.devices.clear_alarm();
+        }
+        match self.configure(config, now, period, dds, updates, &mut followup) {
+            Ok(()) => {
+                followup.sync = requests.sync;
+                followup.verify = config.verify;
+            }
+            Err(err) => log::error!("Failed to update Pounder: {:?}", err),
+        }
+        if let Err(err) = self.devices.update_leds(config) {
+            log::error!("Failed to update Pounder LEDs: {err:?}");
+        }
+        followup.register = requests
+            .register
+            .map(|request| Self::access(config, request, dds));
+        followup
+    }
+
+    /// Apply the clock, detector, attenuator and DDS configuration.
+    fn configure(
+        &mut self,
+        config: &PounderConfig,
+        now: u32,
+        period: f32,
+        dds: &mut impl Mutex<T = Option<DdsOutput>>,
+        updates: &mut impl Mutex<T = DdsUpdates>,
+        followup: &mut Followup,
+    ) -> Result<(), Error> {
+        followup.clock_check = self.devices.update_clock(config)?;
+        self.devices.update_detectors(config)?;
+        followup.attenuation_ramp =
+            self.devices.update_attenuators(config, now)?;
+        // Only the DDS writes block the processing task.
+        (dds, updates).lock(|dds, updates| {
+            // Note(unwrap): The DDS output is present with the Pounder devices.
+            let dds = dds.as_mut().unwrap();
+            dds.pause(config.register.maintenance);
+            updates.slew.set_rate(config.slew, period);
+            let scan = self.devices.update_dds(
+                config,
+                dds,
+                &mut updates.slew,
+                period,
+            )?;
+            updates.set(scan, DdsAmplitude::new(config)?);
+            Ok(())
+        })
+    }
+
+    /// Execute a raw register access request.
+    fn access(
+        config: &PounderConfig,
+        request: RegisterAccess,
+        dds: &mut impl Mutex<T = Option<DdsOutput>>,
+    ) -> RegisterResponse {
+        let data = if config.register.maintenance {
+            // Note(unwrap): The DDS output is present with the Pounder devices.
+            dds.lock(|dds| dds.as_mut().unwrap().access(&request))
+                .map_err(|err| {
+                    log::error!("DDS register access failed: {err:?}")
+                })
+                .ok()
+        } else {
+            log::error!("DDS register access requires maintenance mode");
+            None
+        };
+        RegisterResponse {
+            address: request.address,
+            channels: request.channels,
+            data,
+        }
+    }
+
+    /// Servo or modulate the output amplitudes, servo the output powers and watch the board
+    /// temperature.
+    ///
+    /// The amplitudes and attenuations are held while the interlock is tripped. Errors are
+    /// logged.
+    ///
+    /// Args:
+    /// * `config` - The applied configuration.
+    /// * `adc` - The ADC input voltages for the amplitude locks.
+    /// * `tripped` - Whether the interlock is tripped.
+    /// * `period` - The service period in seconds.
+    /// * `dds` - The DDS profile stream.
+    /// * `updates` - The DDS updates of the processing task.
+    pub fn service(
+        &mut self,
+        config: &PounderConfig,
+        adc: [f32; 2],
+        tripped: bool,
+        period: f32,
+        dds: &mut impl Mutex<T = Option<DdsOutput>>,
+        updates: &mut impl Mutex<T = DdsUpdates>,
+    ) {
+        self.elapsed += period;
+        if self.elapsed >= WATCH_PERIOD {
+            self.elapsed = 0.0;
+            self.watch(config, dds);
+        }
+        if !tripped {
+            let attenuation = config.out_channel.map(|ch| ch.attenuation);
+            if let Err(err) = self.devices.servo_power(
+                &config.power_servo,
+                attenuation,
+                period,
+            ) {
+                log::warn!("RF power servo failed: {err:?}");
+            }
+        }
+        for (i, (lock, modulation)) in config
+            .amplitude_lock
+            .iter()
+            .zip(config.aux_modulation.iter())
+            .enumerate()
+        {
+            if lock.is_none() {
+                self.amplitude[i] = None;
+            }
+            if lock.is_some() || modulation.is_none() {
+                self.filtered[i] = None;
+            }
+        }
+        if tripped {
+            return;
+        }
+        let inputs = [Channel::In0, Channel::In1];
+        let mut corrected = [None; 2];
+        for (i, (channel, flatness)) in config
+            .out_channel
+            .iter()
+            .zip(config.flatness.iter())
+            .enumerate()
+        {
+            let dds_config = &channel.dds;
+            let a = match (&config.amplitude_lock[i], &config.aux_modulation[i])
+            {
+                (Some(lock), _) => {
+                    let measurement = match lock.input {
+                        LockInput::Power(ch) => inputs.get(ch).and_then(|ch| {
+                            self.devices.measure_power(*ch).ok()
+                        }),
+                        LockInput::Adc(ch) => adc.get(ch).copied(),
+                    }
+                    .unwrap_or(f32::NAN);
+                    let a = lock.update(
+                        self.amplitude[i].unwrap_or(dds_config.amplitude),
+                        measurement,
+                        period,
+                    );
+                    self.amplitude[i] = Some(a);
+                    a
+                }
+                (None, Some(modulation)) => {
+                    let input = inputs
+                        .get(modulation.input)
+                        .and_then(|ch| self.devices.sample_aux_adc(*ch).ok())
+                        .unwrap_or(f32::NAN);
+                    modulation.update(
+                        &mut self.filtered[i],
+                        dds_config.amplitude,
+                        input,
+                        period,
+                    )
+                }
+                (None, None) => continue,
+            };
+            corrected[i] =
+                Some(flatness.correct(dds_config.frequency as f32, a));
+        }
+        if corrected.iter().all(Option::is_none) {
+            return;
+        }
+        // The amplitudes are written by the processing task. The output channels follow the
+        // input channels in the DDS channel order.
+        updates.lock(|updates| {
+            for (i, a) in corrected.iter().enumerate() {
+                let Some(a) = a else {
+                    continue;
+                };
+                if updates.queue_amplitude(2 + i, *a).is_err() {
+                    log::warn!("Invalid amplitude control output");
+                }
+            }
+        });
+    }
+
+    /// Check the board temperature and measure the input powers.
+    fn watch(
+        &mut self,
+        config: &PounderConfig,
+        dds: &mut impl Mutex<T = Option<DdsOutput>>,
+    ) {
+        let latched = self
+            .devices
+            .check_temperature(config.over_temperature)
+            .inspect_err(|err| {
+                log::warn!("Pounder over-temperature check failed: {err:?}")
+            })
+            .unwrap_or(false);
+        if latched {
+            // Only the short power-down write blocks the processing task.
+            dds.lock(|dds| {
+                if let Some(dds) = dds {
+                    self.devices.power_down(dds);
+                }
+            });
+            log::error!("Pounder over-temperature, RF disabled");
+        }
+        let devices = &mut self.devices;
+        self.power = [Channel::In0, Channel::In1]
+            .map(|ch| devices.measure_power(ch).unwrap_or(f32::NAN));
+    }
+}
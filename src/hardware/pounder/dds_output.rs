@@ -16,6 +16,10 @@
 //! collection of outputs simultaneously. This is done by serializing the register writes to the
 //! DDS into a single buffer of data and then writing the data over QSPI to the DDS.
 //!
+//! The linear sweep engine of the DDS is configured through the same stream: the sweep
+//! parameters (ramp rate, delta words, end point) are serialized as a separate profile ahead of the
+//! channel profile that enables the sweep in the channel function register.
+//!
 //! In order to minimize software overhead, data is written directly into the QSPI output FIFO. In
 //! order to accomplish this most efficiently, serialized data is written as 32-bit words to
 //! minimize the number of bus cycles necessary to write to the peripheral FIFO. A consequence of
@@ -29,12 +33,17 @@
 //! software can schedule the DDS updates and then continue data processing. DDS updates then take
 //! place in the future when the IO-update is toggled by hardware.
 //!
+//! Profiles of several channels can be staged and then committed together. The staged profiles
+//! are written back to back and latched by a single IO-update so that multi-channel updates take
+//! effect simultaneously.
+//!
 //!
 //! # Limitations
 //!
 //! The QSPI output FIFO is used as an intermediate buffer for holding pending QSPI writes. Because
 //! of this, the implementation only supports up to 16 serialized bytes (the QSPI FIFO is 8 32-bit
-//! words, or 32 bytes, wide) in a single update.
+//! words, or 32 bytes, wide) in a single update. Committing larger staged updates stalls until the
+//! FIFO has drained sufficiently.
 //!
 //! There is currently no synchronization between completion of the QSPI data write and the
 //! IO-update signal. It is currently assumed that the QSPI transfer will always complete within a
@@ -54,14 +63,25 @@
 //! buffer to avoid the software overhead of much of the serialization process.
 use log::warn;
 
-use super::{QspiInterface, hal, hrtimer::HighResTimerE};
-use ad9959::{Mode, ProfileSerializer};
+use super::{Error, QspiInterface, hal, hrtimer::HighResTimerE};
+use crate::pounder_config::RegisterAccess;
+use ad9959::{Address, Channel, Interface, Mode, ProfileSerializer};
+use arbitrary_int::{u4, u7};
+use heapless::Vec;
+
+/// Capacity for staged profiles in 32-bit words.
+///
+/// This covers the sweep and channel profiles of all four channels.
+const STAGE_WORDS: usize = 64;
 
 /// The DDS profile update stream.
 pub struct DdsOutput {
-    _qspi: QspiInterface,
+    qspi: QspiInterface,
     io_update_trigger: HighResTimerE,
     mode: Mode,
+    paused: bool,
+    /// Serialized profiles pending for the next commit
+    staged: Vec<u32, STAGE_WORDS>,
 }
 
 impl DdsOutput {
@@ -84,11 +104,26 @@ impl DdsOutput {
         qspi.start_stream().unwrap();
         Self {
             mode,
-            _qspi: qspi,
+            qspi,
             io_update_trigger,
+            paused: false,
+            staged: Vec::new(),
         }
     }
 
+    /// Pause or resume the profile stream.
+    ///
+    /// While paused, profile writes are discarded and only raw register
+    /// accesses reach the DDS.
+    pub fn pause(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Whether the profile stream is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
     /// Get a builder for serializing a Pounder DDS profile.
     pub fn builder(&mut self) -> ProfileSerializer {
         ProfileSerializer::new(self.mode)
@@ -104,7 +139,155 @@ impl DdsOutput {
     /// # Args
     /// * `profile` - The serialized DDS profile to write.
     #[inline]
-    pub fn write(&mut self, mut profile: ProfileSerializer) {
+    pub fn write(&mut self, profile: ProfileSerializer) {
+        if !self.paused {
+            self.write_profile(profile);
+        }
+    }
+
+    /// Write a profile to the stream regardless of the pause state.
+    ///
+    /// This is meant for writes that must reach the DDS in maintenance mode, like powering down
+    /// the channels. The note on [Self::write] applies.
+    ///
+    /// # Args
+    /// * `profile` - The serialized DDS profile to write.
+    #[inline]
+    pub fn write_unpaused(&mut self, profile: ProfileSerializer) {
+        self.write_profile(profile);
+    }
+
+    /// Stage a profile to be written by the next [Self::commit].
+    ///
+    /// Profiles staged while the stream is paused are discarded.
+    ///
+    /// # Args
+    /// * `profile` - The serialized DDS profile to stage.
+    ///
+    /// # Returns
+    /// [Error::Bounds] if the staged profiles exceed the capacity. The profile is then discarded.
+    #[inline]
+    pub fn stage(
+        &mut self,
+        mut profile: ProfileSerializer,
+    ) -> Result<(), Error> {
+        if self.paused {
+            return Ok(());
+        }
+        let words = profile.finalize();
+        if self.staged.len() + words.len() > self.staged.capacity() {
+            return Err(Error::Bounds);
+        }
+        // Note(unwrap): The capacity was checked above.
+        self.staged.extend_from_slice(words).unwrap();
+        Ok(())
+    }
+
+    /// Write the staged profiles and generate a single IO_Update for all of them.
+    ///
+    /// Nothing is written if no profiles are staged or if the stream is paused.
+    #[inline]
+    pub fn commit(&mut self) {
+        let staged = core::mem::take(&mut self.staged);
+        if !self.paused && !staged.is_empty() {
+            self.write_words(&staged);
+        }
+    }
+
+    /// Write a raw register of the DDS.
+    ///
+    /// The write takes effect with the IO_Update pulse.
+    ///
+    /// # Args
+    /// * `channels` - The channels to select for channel registers.
+    /// * `register` - The register to write. The channel select register (CSR)
+    ///   can not be written as it also configures the interface mode.
+    /// * `data` - The register contents.
+    pub fn write_register(
+        &mut self,
+        channels: Channel,
+        register: Address,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        if register == Address::CSR {
+            return Err(Error::InvalidAddress);
+        }
+        if data.len() != register.width() {
+            return Err(Error::Bounds);
+        }
+        let mut profile = self.builder();
+        profile.push_register(channels, register, data);
+        self.write_profile(profile);
+        Ok(())
+    }
+
+    /// Read a raw register of the DDS.
+    ///
+    /// The stream is stopped for the read and restarted afterwards.
+    ///
+    /// # Args
+    /// * `channels` - The channels to select for channel registers.
+    /// * `register` - The register to read.
+    /// * `data` - The buffer for the register contents. The length must match
+    ///   the register width.
+    pub fn read_register(
+        &mut self,
+        channels: Channel,
+        register: Address,
+        data: &mut [u8],
+    ) -> Result<(), Error> {
+        if data.len() != register.width() {
+            return Err(Error::Bounds);
+        }
+        self.qspi.stop_stream();
+        let csr = ad9959::Csr::select(self.mode, channels);
+        let result = self
+            .qspi
+            .write(Address::CSR, &csr.raw_value().to_be_bytes())
+            .and_then(|_| self.qspi.read(register, data));
+        self.qspi.start_stream()?;
+        result
+    }
+
+    /// Execute a raw register access request.
+    ///
+    /// # Args
+    /// * `request` - The register access.
+    ///
+    /// # Returns
+    /// The register contents read or written.
+    pub fn access(
+        &mut self,
+        request: &RegisterAccess,
+    ) -> Result<Vec<u8, 4>, Error> {
+        if request.address >= 0x80 {
+            return Err(Error::InvalidAddress);
+        }
+        let register = Address::new_with_raw_value(u7::new(request.address))
+            .or(Err(Error::InvalidAddress))?;
+        let channels = u4::new(request.channels & 0xf);
+        match &request.data {
+            Some(data) => {
+                self.write_register(channels, register, data)?;
+                Ok(data.clone())
+            }
+            None => {
+                let mut data = [0; 4];
+                let data = &mut data[..register.width()];
+                self.read_register(channels, register, data)?;
+                // Note(unwrap): Registers are at most 4 bytes wide.
+                Ok(Vec::from_slice(data).unwrap())
+            }
+        }
+    }
+
+    #[inline]
+    fn write_profile(&mut self, mut profile: ProfileSerializer) {
+        self.write_words(profile.finalize());
+    }
+
+    #[inline]
+    fn write_words(&mut self, words: &[u32]) {
         // Note(unsafe): We own the QSPI interface, so it is safe to access the registers in a raw
         // fashion.
         let regs = unsafe { &*hal::stm32::QUADSPI::ptr() };
@@ -114,7 +297,7 @@ impl DdsOutput {
             warn!("QSPI stalling")
         }
 
-        for word in profile.finalize().iter() {
+        for word in words.iter() {
             // Note(unsafe): any bit pattern is valid for a TX FIFO write.
             regs.dr.write(|w| unsafe { w.bits(*word) });
         }
@@ -0,0 +1,132 @@
+//! DDS frequency tuning word updates
+//!
+//! Frequency tuning words (FTW) computed from the configuration are fractional. [FtwDither]
+//! resolves the fraction by dithering successive updates and [FtwSlew] limits the rate of
+//! frequency changes.
+use crate::design_parameters::DDS_SYSTEM_CLK;
+use num_traits::Float;
+
+/// Frequency tuning word dither
+///
+/// A first order sigma-delta modulator of the fractional FTW.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FtwDither {
+    ftw: u32,
+    fraction: u32,
+    accu: u32,
+}
+
+impl FtwDither {
+    /// Construct a dither for a fractional frequency tuning word.
+    ///
+    /// # Args
+    /// * `ftw` - The frequency tuning word including its fractional part.
+    pub fn new(ftw: f64) -> Self {
+        Self {
+            ftw: ftw as u32,
+            fraction: (ftw.fract() * (1u64 << 32) as f64) as u32,
+            accu: 0,
+        }
+    }
+
+    /// The frequency tuning word for the next update.
+    pub fn update(&mut self) -> u32 {
+        let carry;
+        (self.accu, carry) = self.accu.overflowing_add(self.fraction);
+        self.ftw.wrapping_add(carry as u32)
+    }
+}
+
+/// DDS frequency slew limiter
+///
+/// Frequency changes of unscanned channels are approached at the maximum slew rate with one
+/// frequency tuning word (FTW) update per sample batch. Scanned channel frequencies are limited to
+/// the same rate. The first frequency of a channel and the frequencies of dithered channels are
+/// applied without limiting.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FtwSlew {
+    /// Maximum fractional FTW change per update, `None` to disable
+    step: Option<f64>,
+    /// Current fractional FTWs, `None` before the first update
+    ftw: [Option<f64>; 4],
+    /// Pending fractional FTWs of unscanned channels
+    target: [Option<f64>; 4],
+}
+
+impl FtwSlew {
+    /// Set the maximum slew rate.
+    ///
+    /// # Args
+    /// * `rate` - The maximum frequency slew rate in Hz/ms. `None` to disable.
+    /// * `period` - The update period in seconds.
+    pub fn set_rate(&mut self, rate: Option<f32>, period: f32) {
+        let ftw_per_hz = (1u64 << 32) as f64 / DDS_SYSTEM_CLK.to_Hz() as f64;
+        self.step =
+            rate.map(|rate| (rate.abs() * 1e3 * period) as f64 * ftw_per_hz);
+    }
+
+    /// Limit the change of a channel FTW.
+    ///
+    /// # Args
+    /// * `index` - The channel index in [PounderConfig::channels] order.
+    /// * `ftw` - The desired fractional FTW.
+    ///
+    /// # Returns
+    /// The fractional FTW to apply.
+    pub fn limit(&mut self, index: usize, ftw: f64) -> f64 {
+        let ftw = match (self.step, self.ftw[index]) {
+            (Some(step), Some(current)) => {
+                current + (ftw - current).clamp(-step, step)
+            }
+            _ => ftw,
+        };
+        self.ftw[index] = Some(ftw);
+        ftw
+    }
+
+    /// Set the FTW of an unscanned channel.
+    ///
+    /// # Args
+    /// * `index` - The channel index in [PounderConfig::channels] order.
+    /// * `ftw` - The fractional FTW.
+    /// * `limit` - Whether the change is to be slew limited.
+    ///
+    /// # Returns
+    /// The fractional FTW to apply immediately, `None` if the change is pending.
+    pub fn set(&mut self, index: usize, ftw: f64, limit: bool) -> Option<f64> {
+        if limit && self.step.is_some() && self.ftw[index].is_some() {
+            self.target[index] = Some(ftw);
+            None
+        } else {
+            self.target[index] = None;
+            self.ftw[index] = Some(ftw);
+            Some(ftw)
+        }
+    }
+
+    /// Cancel a pending change, e.g. of a channel that is then scanned.
+    pub fn cancel(&mut self, index: usize) {
+        self.target[index] = None;
+    }
+
+    /// Whether a change of a channel is pending.
+    pub fn pending(&self, index: usize) -> bool {
+        self.target[index].is_some()
+    }
+
+    /// Advance the pending change of a channel.
+    ///
+    /// # Args
+    /// * `index` - The channel index in [PounderConfig::channels] order.
+    ///
+    /// # Returns
+    /// The fractional FTW to apply, `None` if no change is pending.
+    pub fn advance(&mut self, index: usize) -> Option<f64> {
+        let target = self.target[index]?;
+        let ftw = self.limit(index, target);
+        if ftw == target {
+            self.target[index] = None;
+        }
+        Some(ftw)
+    }
+}
@@ -1,4 +1,5 @@
 //! The HRTimer (High Resolution Timer) is used to generate IO_Update pulses to the Pounder DDS.
+//! It can also generate a continuous clock or trigger pulse train.
 use super::hal::{
     self,
     rcc::{CoreClocks, ResetEnable, rec},
@@ -59,6 +60,7 @@ impl HighResTimerE {
     ) {
         // Disable the timer before configuration.
         self.master.mcr.modify(|_, w| w.tecen().clear_bit());
+        self.timer.timecr.modify(|_, w| w.cont().clear_bit());
 
         // Configure the desired timer for single shot mode with set and reset of the specified
         // channel at the desired durations. The HRTIM is on APB2 (D2 domain), and the kernel clock
@@ -66,21 +68,7 @@ impl HighResTimerE {
         let clk = self.clocks.timy_ker_ck().to_Hz() as f32;
         let end = ((delay + duration) * clk) as u32 + 1;
 
-        // Determine the clock divider, which may be 1, 2, or 4. We will choose a clock divider that
-        // allows us the highest resolution per tick, so lower dividers are favored.
-        let div: u8 = if end < 0xFFDF {
-            1
-        } else if (end / 2) < 0xFFDF {
-            2
-        } else if (end / 4) < 0xFFDF {
-            3
-        } else {
-            panic!("Unattainable timing parameters!");
-        };
-
-        // The period register must be greater than or equal to 3 cycles.
-        let period = (end / (1 << (div - 1)) as u32) as u16;
-        assert!(period > 2);
+        let (div, period) = Self::prescale(end);
 
         // We now have the prescaler and the period registers. Configure the timer.
         // Note(unsafe): The prescaler is guaranteed to be greater than or equal to 4 (minimum
@@ -122,6 +110,92 @@ impl HighResTimerE {
         self.master.mcr.modify(|_, w| w.tecen().set_bit());
     }
 
+    /// Determine the clock divider and period register for a timer period.
+    ///
+    /// # Args
+    /// * `end` - The timer period in kernel clock ticks.
+    ///
+    /// # Returns
+    /// (div, period) where `div` is the prescaler setting (1, 2, or 3 for division by 1, 2, or 4)
+    /// and `period` is the value of the period register.
+    fn prescale(end: u32) -> (u8, u16) {
+        // Determine the clock divider, which may be 1, 2, or 4. We will choose a clock divider that
+        // allows us the highest resolution per tick, so lower dividers are favored.
+        let div: u8 = if end < 0xFFDF {
+            1
+        } else if (end / 2) < 0xFFDF {
+            2
+        } else if (end / 4) < 0xFFDF {
+            3
+        } else {
+            panic!("Unattainable timing parameters!");
+        };
+
+        // The period register must be greater than or equal to 3 cycles.
+        let period = (end / (1 << (div - 1)) as u32) as u16;
+        assert!(period > 2);
+        (div, period)
+    }
+
+    /// Configure the timer to continuously output a pulse train.
+    ///
+    /// # Note
+    /// This can be used as a square wave clock or trigger output (e.g. for cameras or shutters)
+    /// derived from the same clock tree as the sampling timers. The output is asserted at the start
+    /// of each period and deasserted after `duty` of the period. The pulse train starts once the
+    /// timer is configured and runs until reconfigured.
+    ///
+    /// # Args
+    /// * `channel` - The timer output channel to configure.
+    /// * `frequency` - The pulse repetition frequency in Hz.
+    /// * `duty` - The fraction of the period that the output is asserted, between 0 and 1.
+    pub fn configure_pulse_train(
+        &mut self,
+        channel: Channel,
+        frequency: f32,
+        duty: f32,
+    ) {
+        // Disable the timer before configuration.
+        self.master.mcr.modify(|_, w| w.tecen().clear_bit());
+
+        let clk = self.clocks.timy_ker_ck().to_Hz() as f32;
+        let (div, period) = Self::prescale((clk / frequency) as u32);
+
+        // Note(unsafe): The setting is always 1, 2, or 3, which represents all valid values after
+        // the offset of 4.
+        self.timer.timecr.modify(|_, w| unsafe {
+            w.ck_pscx().bits(div + 4).cont().set_bit()
+        });
+
+        // Note(unsafe): The period register is guaranteed to be a 16-bit value, which will fit in
+        // this register.
+        self.timer.perer.write(|w| unsafe { w.perx().bits(period) });
+
+        // The compare register must be at least 3 and below the period.
+        let compare = ((period as f32 * duty) as u16).clamp(3, period - 1);
+        // Note(unsafe): The compare value is clamped to the valid range.
+        self.timer
+            .cmp1er
+            .write(|w| unsafe { w.cmp1x().bits(compare) });
+
+        // Set upon reaching PER, reset on compare with CMP1.
+        match channel {
+            Channel::One => {
+                self.timer.sete1r.write(|w| w.per().set_bit());
+                self.timer.rste1r.write(|w| w.cmp1().set_bit());
+                self.common.oenr.write(|w| w.te1oen().set_bit());
+            }
+            Channel::Two => {
+                self.timer.sete2r.write(|w| w.per().set_bit());
+                self.timer.rste2r.write(|w| w.cmp1().set_bit());
+                self.common.oenr.write(|w| w.te2oen().set_bit());
+            }
+        }
+
+        // Enable the timer now that it is configured.
+        self.master.mcr.modify(|_, w| w.tecen().set_bit());
+    }
+
     /// Generate a single trigger of the timer to start the output pulse generation.
     pub fn trigger(&mut self) {
         // Generate a reset event to force the timer to start counting.
@@ -1,12 +1,23 @@
 use super::hal;
+use crate::convert::DacCode;
+use crate::design_parameters::DDS_SYSTEM_CLK;
 use crate::hardware::{I2c1Proxy, shared_adc::AdcChannel};
+use crate::pounder_config::{
+    Flatness, LedStatus, Modulation, PounderConfig, PowerServo,
+};
 use ad9959::Address;
+use arbitrary_int::{u2, u10, u14};
+use dds_output::DdsOutput;
 use embedded_hal_02::blocking::spi::Transfer;
+use ftw::{FtwDither, FtwSlew};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 
+pub mod control;
 pub mod dds_output;
+pub mod ftw;
 pub mod hrtimer;
+pub mod sync_phase;
 
 #[cfg(not(feature = "pounder_v1_0"))]
 pub mod timestamp;
@@ -210,6 +221,17 @@ impl QspiInterface {
 
         Ok(())
     }
+
+    /// Leave the streaming mode to allow reads.
+    ///
+    /// Pending stream data is written before the transaction is aborted.
+    pub fn stop_stream(&mut self) {
+        let qspi_regs = unsafe { &*hal::stm32::QUADSPI::ptr() };
+        while qspi_regs.sr.read().flevel().bits() != 0 {}
+        qspi_regs.cr.modify(|_, w| w.abort().set_bit());
+        while qspi_regs.cr.read().abort().bit_is_set() {}
+        self.streaming = false;
+    }
 }
 
 impl ad9959::Interface for QspiInterface {
@@ -325,16 +347,17 @@ enum IoExpander {
 }
 
 impl IoExpander {
-    fn new(i2c: I2c1Proxy) -> Self {
+    fn new(i2c: I2c1Proxy) -> Result<Self, Error> {
         // Population option on Pounder v1.2 and later.
-        let mut mcp23017 =
-            mcp230xx::Mcp230xx::new_default(i2c.clone()).unwrap();
-        if mcp23017.read(0).is_ok() {
+        let mut mcp23017 = mcp230xx::Mcp230xx::new_default(i2c.clone())
+            .map_err(|_| Error::I2c)?;
+        Ok(if mcp23017.read(0).is_ok() {
             Self::Mcp(mcp23017)
         } else {
-            let pca9359 = tca9539::Pca9539::new_default(i2c).unwrap();
+            let pca9359 =
+                tca9539::Pca9539::new_default(i2c).map_err(|_| Error::I2c)?;
             Self::Pca(pca9359)
-        }
+        })
     }
 
     /// Set the state (its electrical level) of the given GPIO pin on Pounder.
@@ -378,9 +401,467 @@ impl IoExpander {
     }
 }
 
+/// Repeated linear frequency chirp
+#[derive(Copy, Clone, Debug)]
+struct Chirp {
+    /// Fractional frequency tuning word change per update
+    step: f64,
+    /// Updates per chirp
+    updates: u32,
+    /// Remaining chirps, `None` to repeat continuously
+    remaining: Option<u32>,
+    /// Update index within the current chirp
+    index: u32,
+}
+
+impl Chirp {
+    /// The frequency tuning word offset of the next update.
+    ///
+    /// Returns:
+    /// The offset from the start frequency, `None` once all chirps are complete.
+    fn next(&mut self) -> Option<f64> {
+        if self.index == self.updates {
+            self.index = 0;
+            if let Some(remaining) = self.remaining.as_mut() {
+                *remaining = remaining.saturating_sub(1);
+                if *remaining == 0 {
+                    return None;
+                }
+            }
+        }
+        let offset = self.step * self.index as f64;
+        self.index += 1;
+        Some(offset)
+    }
+}
+
+/// The signal driving a DDS frequency scan
+#[derive(Copy, Clone, Debug)]
+enum Drive {
+    /// Signal generator of a Stabilizer channel
+    Generator {
+        source: usize,
+        /// Frequency tuning word change per signal generator output LSB
+        ftw_per_lsb: f64,
+    },
+    /// Pre-computed chirp
+    Chirp(Chirp),
+}
+
+/// DDS frequency scan driven by a signal generator or a chirp
+#[derive(Copy, Clone, Debug)]
+pub struct DdsScan {
+    /// Channel index in [PounderConfig::channels] order
+    index: usize,
+    /// Fractional frequency tuning word at zero signal generator output or at the chirp start
+    ftw: f64,
+    drive: Drive,
+}
+
+impl DdsScan {
+    /// Advance the scan.
+    ///
+    /// Chirps are not slew limited.
+    ///
+    /// Args:
+    /// * `slew` - The frequency slew limiter.
+    /// * `source` - The current signal generator output of the Stabilizer channels in DAC LSB.
+    ///
+    /// Returns:
+    /// The fractional FTW to apply and whether the chirps are complete. Completed chirps return
+    /// to their start frequency.
+    fn update(&mut self, slew: &mut FtwSlew, source: [i16; 2]) -> (f64, bool) {
+        match &mut self.drive {
+            Drive::Generator {
+                source: i,
+                ftw_per_lsb,
+            } => (
+                slew.limit(
+                    self.index,
+                    self.ftw + *ftw_per_lsb * source[*i] as f64,
+                ),
+                false,
+            ),
+            Drive::Chirp(chirp) => {
+                let offset = chirp.next();
+                // Note(unwrap): Immediate changes are always applied.
+                let ftw = slew
+                    .set(self.index, self.ftw + offset.unwrap_or(0.0), false)
+                    .unwrap();
+                (ftw, offset.is_none())
+            }
+        }
+    }
+}
+
+/// DDS amplitude driven by the processing output of a Stabilizer channel
+#[derive(Copy, Clone, Debug)]
+pub struct DdsAmplitude {
+    /// Stabilizer channel of the processing output
+    source: usize,
+    /// Corrected amplitude at zero output
+    offset: f32,
+    /// Corrected amplitude change per processing output LSB
+    gain: f32,
+    /// The DAC is driven by the processing output
+    dac: bool,
+}
+
+impl DdsAmplitude {
+    /// Construct the amplitude drives of an RF configuration.
+    ///
+    /// Args:
+    /// * `config` - The RF configuration.
+    ///
+    /// Returns:
+    /// The amplitude drives of the output channels.
+    pub fn new(config: &PounderConfig) -> Result<[Option<Self>; 2], Error> {
+        let mut drives = [None; 2];
+        for (i, drive) in drives.iter_mut().enumerate() {
+            let Some(d) = config.dsp_amplitude[i] else {
+                continue;
+            };
+            if d.source >= 2 {
+                return Err(Error::InvalidChannel);
+            }
+            let dds = &config.out_channel[i].dds;
+            if config.amplitude_lock[i].is_some()
+                || config.aux_modulation[i].is_some()
+                || matches!(dds.modulation, Some(Modulation::Amplitude(_)))
+            {
+                return Err(Error::InvalidState);
+            }
+            // The flatness correction is a constant factor at the channel frequency.
+            let scale = 10f32.powf(
+                -config.flatness[i].deviation(dds.frequency as f32) / 20.0,
+            );
+            *drive = Some(Self {
+                source: d.source,
+                offset: dds.amplitude * scale,
+                gain: d.gain * DacCode::VOLT_PER_LSB * scale,
+                dac: d.dac,
+            });
+        }
+        Ok(drives)
+    }
+
+    /// The Stabilizer channel whose DAC output is replaced by the amplitude drive, if any.
+    pub fn replaces_dac(&self) -> Option<usize> {
+        (!self.dac).then_some(self.source)
+    }
+
+    /// The amplitude control register for a processing output.
+    ///
+    /// Args:
+    /// * `output` - The current processing output of the Stabilizer channels in DAC LSB.
+    ///
+    /// Returns:
+    /// The ACR, `None` for an invalid output.
+    fn acr(&self, output: [f32; 2]) -> Option<ad9959::Acr> {
+        let amplitude = self.offset + self.gain * output[self.source];
+        ad9959::amplitude_to_acr(amplitude.clamp(0.0, 1.0))
+    }
+}
+
+/// DDS updates written by the processing task
+///
+/// The processing task writes the frequencies of the scanned, chirped and slewing channels and the
+/// amplitudes driven by the processing outputs. Only changed words are written. A batch writes at
+/// most one profile sized to fit the QSPI FIFO so that the write never stalls. Channels that do
+/// not fit are written with the next batch, which starts with the first deferred channel.
+///
+/// Lower priority tasks queue their frequency and amplitude updates here instead of writing the
+/// DDS.
+#[derive(Clone, Debug, Default)]
+pub struct DdsUpdates {
+    /// Scanned and chirped channels in [PounderConfig] order
+    scans: [Option<DdsScan>; 4],
+    /// Frequency slew limiter
+    pub slew: FtwSlew,
+    /// Amplitude drives of the output channels
+    amplitudes: [Option<DdsAmplitude>; 2],
+    /// Frequency tuning words as last written in [PounderConfig] order
+    ftw: [Option<u32>; 4],
+    /// Amplitude control registers as last written in [PounderConfig] order
+    acr: [Option<ad9959::Acr>; 4],
+    /// Frequency tuning words queued by lower priority tasks
+    queued_ftw: [Option<u32>; 4],
+    /// Amplitude control registers queued by lower priority tasks
+    queued_acr: [Option<ad9959::Acr>; 4],
+    /// Channel index to start the next batch with
+    start: usize,
+}
+
+impl DdsUpdates {
+    /// Set the scans and amplitude drives of a newly applied configuration.
+    ///
+    /// Args:
+    /// * `scans` - The frequency scans and chirps in [PounderConfig] order.
+    /// * `amplitudes` - The amplitude drives of the output channels.
+    pub fn set(
+        &mut self,
+        scans: [Option<DdsScan>; 4],
+        amplitudes: [Option<DdsAmplitude>; 2],
+    ) {
+        self.scans = scans;
+        self.amplitudes = amplitudes;
+        // The configuration has overwritten the channel profiles.
+        self.ftw = [None; 4];
+        self.acr = [None; 4];
+        self.queued_ftw = [None; 4];
+        self.queued_acr = [None; 4];
+    }
+
+    /// Queue a frequency tuning word to be written by the processing task.
+    ///
+    /// A word queued earlier and not yet written is replaced. Scans and slewing changes of the
+    /// channel take precedence.
+    ///
+    /// Args:
+    /// * `index` - The channel index in [PounderConfig::channels] order.
+    /// * `ftw` - The frequency tuning word.
+    pub fn queue_ftw(&mut self, index: usize, ftw: u32) {
+        self.queued_ftw[index] = Some(ftw);
+    }
+
+    /// Queue an amplitude to be written by the processing task.
+    ///
+    /// An amplitude queued earlier and not yet written is replaced. Amplitude drives of the
+    /// channel take precedence.
+    ///
+    /// Args:
+    /// * `index` - The channel index in [PounderConfig::channels] order.
+    /// * `amplitude` - The amplitude relative to full scale.
+    pub fn queue_amplitude(
+        &mut self,
+        index: usize,
+        amplitude: f32,
+    ) -> Result<(), Error> {
+        let acr = ad9959::amplitude_to_acr(amplitude).ok_or(Error::Bounds)?;
+        self.queued_acr[index] = Some(acr);
+        Ok(())
+    }
+
+    /// The Stabilizer channels whose DAC outputs are replaced by amplitude drives.
+    pub fn replaced_dacs(&self) -> [bool; 2] {
+        let mut replaced = [false; 2];
+        for ch in self
+            .amplitudes
+            .iter()
+            .flatten()
+            .filter_map(DdsAmplitude::replaces_dac)
+        {
+            replaced[ch] = true;
+        }
+        replaced
+    }
+
+    /// Write the changed frequencies and amplitudes.
+    ///
+    /// All written channels are updated with a single IO_Update.
+    ///
+    /// Args:
+    /// * `dds` - The DDS profile stream.
+    /// * `source` - The current signal generator output of the Stabilizer channels in DAC LSB.
+    /// * `output` - The current processing output of the Stabilizer channels in DAC LSB.
+    pub fn write(
+        &mut self,
+        dds: &mut DdsOutput,
+        source: [i16; 2],
+        output: [f32; 2],
+    ) {
+        let mut profile = dds.builder();
+        let mut pending = false;
+        let channels = DDS_CHANNELS.len();
+        for k in 0..channels {
+            let index = (self.start + k) % channels;
+            let drive = index.checked_sub(2).and_then(|i| self.amplitudes[i]);
+            let frequency = self.scans[index].is_some()
+                || self.slew.pending(index)
+                || self.queued_ftw[index].is_some();
+            let amplitude = drive.is_some() || self.queued_acr[index].is_some();
+            if !frequency && !amplitude {
+                continue;
+            }
+            // Channel select, FTW and ACR writes including their addresses
+            let size = 2 + 5 * frequency as usize + 4 * amplitude as usize;
+            if !profile.fits(size) {
+                self.start = index;
+                break;
+            }
+            let update = self.scans[index]
+                .as_mut()
+                .map(|scan| scan.update(&mut self.slew, source));
+            let ftw = match update {
+                Some((ftw, done)) => {
+                    if done {
+                        self.scans[index] = None;
+                    }
+                    Some(ftw)
+                }
+                None => self.slew.advance(index),
+            }
+            // The float to integer conversion saturates. Limit to Nyquist.
+            .map(|ftw| (ftw as u32).min(1 << 31))
+            .or(self.queued_ftw[index].take())
+            .filter(|ftw| self.ftw[index] != Some(*ftw));
+            let queued = self.queued_acr[index].take();
+            let acr = match drive {
+                Some(drive) => drive.acr(output),
+                None => queued,
+            }
+            .filter(|acr| self.acr[index] != Some(*acr));
+            if ftw.is_none() && acr.is_none() {
+                continue;
+            }
+            profile.push(DDS_CHANNELS[index].into(), ftw, None, acr);
+            self.ftw[index] = ftw.or(self.ftw[index]);
+            self.acr[index] = acr.or(self.acr[index]);
+            pending = true;
+        }
+        if pending {
+            dds.write(profile);
+        }
+    }
+}
+
+/// A Pounder peripheral that can fail
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Peripheral {
+    /// The GPIO expander and attenuators during initialization
+    Devices,
+    /// The LM75 temperature sensor
+    Temperature,
+    /// The input power detector of an input channel
+    Power(usize),
+}
+
+/// A Pounder peripheral fault or recovery
+///
+/// Published on the `/event` topic when the fault state of a peripheral changes.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct FaultEvent {
+    /// The peripheral
+    pub peripheral: Peripheral,
+    /// The peripheral failed
+    pub fault: bool,
+}
+
+/// Pounder sensor telemetry
+///
+/// Readings of failed sensors are `None`.
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct PounderTelemetry {
+    /// Board temperature (°C)
+    pub temperature: Option<f32>,
+    /// Input power (dBm)
+    pub input_power: [Option<f32>; 2],
+    /// Auxiliary ADC input voltage (V)
+    pub aux_adc: [Option<f32>; 2],
+    /// Output channel RF power servo state, `None` if disabled
+    pub power_servo: [Option<PowerServoStatus>; 2],
+    /// Number of DDS profile readback mismatches
+    pub dds_mismatches: u32,
+    /// Latched over-temperature alarm
+    pub over_temperature: bool,
+    /// Requested attenuations (dB) in [PounderConfig] order, `None` if unknown
+    pub attenuation: [Option<f32>; 4],
+    /// Programmed attenuations corrected by the attenuator calibration (dB)
+    /// in [PounderConfig] order, `None` if unknown
+    pub attenuation_corrected: [Option<f32>; 4],
+    /// Powered down input detectors
+    #[serde(skip)]
+    detector_power_down: [bool; 2],
+}
+
+/// RF power servo state
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct PowerServoStatus {
+    /// Controlled attenuation before quantization (dB)
+    pub attenuation: f32,
+    /// Last power measurement (dBm), `None` if invalid
+    pub power: Option<f32>,
+    /// The attenuation is at a limit
+    pub saturated: bool,
+}
+
+impl PounderTelemetry {
+    /// The fault states of the sensors.
+    pub fn faults(&self) -> [FaultEvent; 3] {
+        [
+            FaultEvent {
+                peripheral: Peripheral::Temperature,
+                fault: self.temperature.is_none(),
+            },
+            FaultEvent {
+                peripheral: Peripheral::Power(0),
+                fault: self.input_power[0].is_none()
+                    && !self.detector_power_down[0],
+            },
+            FaultEvent {
+                peripheral: Peripheral::Power(1),
+                fault: self.input_power[1].is_none()
+                    && !self.detector_power_down[1],
+            },
+        ]
+    }
+}
+
+/// Attenuation ramp of a channel
+#[derive(Copy, Clone, Debug)]
+struct AttenuationRamp {
+    /// Attenuation at the start (dB)
+    start: f32,
+    /// Target attenuation (dB)
+    target: f32,
+    /// Start time (ms)
+    since: u32,
+    /// Duration (ms)
+    duration: u32,
+}
+
+/// Quantize an attenuation to the 0.5 dB attenuator resolution.
+fn quantize(attenuation: f32) -> f32 {
+    (attenuation * 2.0).round() / 2.0
+}
+
+/// DDS channels in [PounderConfig] order
+const DDS_CHANNELS: [Channel; 4] =
+    [Channel::In0, Channel::In1, Channel::Out0, Channel::Out1];
+
 /// A structure containing implementation for Pounder hardware.
 pub struct PounderDevices {
     io: IoExpander,
+    dither: [Option<FtwDither>; 4],
+    /// Channel function registers as last configured
+    cfr: [ad9959::Cfr; 4],
+    /// Output channel RF power servo states
+    power_servo: [Option<PowerServoStatus>; 2],
+    /// Requested attenuations by attenuator index, `None` if unknown
+    attenuation: [Option<f32>; 4],
+    /// Programmed attenuations by attenuator index, `None` if unknown
+    attenuation_corrected: [Option<f32>; 4],
+    /// Calibrated attenuation errors by attenuator index (dB)
+    attenuation_error: [f32; 4],
+    /// Attenuation ramps by attenuator index
+    ramp: [Option<AttenuationRamp>; 4],
+    /// Powered down input detectors
+    detector_power_down: [bool; 2],
+    /// The external reference clock is selected
+    ext_clk: bool,
+    /// The external reference clock is requested
+    ext_clk_request: bool,
+    /// The last DDS configuration was applied
+    dds_configured: bool,
+    /// LED states as last written, `None` if unknown
+    leds: [Option<bool>; 6],
+    /// Number of DDS readback mismatches
+    dds_mismatches: u32,
+    /// Profiles pending readback verification in [PounderConfig] order: FTW, POW and ACR
+    /// `None` if not verified
+    dds_expected: [Option<(Option<u32>, u14, Option<ad9959::Acr>)>; 4],
+    /// Latched over-temperature alarm
+    over_temperature: bool,
     lm75: lm75::Lm75<I2c1Proxy, lm75::ic::Lm75>,
     attenuator_spi: hal::spi::Spi<hal::stm32::SPI1, hal::spi::Enabled, u8>,
     pwr: (
@@ -447,7 +928,23 @@ impl PounderDevices {
     ) -> Result<Self, Error> {
         let mut devices = Self {
             lm75: lm75::Lm75::new(i2c.clone(), lm75::Address::default()),
-            io: IoExpander::new(i2c.clone()),
+            io: IoExpander::new(i2c.clone())?,
+            dither: Default::default(),
+            cfr: Default::default(),
+            power_servo: [None; 2],
+            attenuation: [None; 4],
+            attenuation_corrected: [None; 4],
+            attenuation_error: [0.0; 4],
+            ramp: [None; 4],
+            detector_power_down: [false; 2],
+            ext_clk: false,
+            ext_clk_request: false,
+            dds_configured: false,
+            // All LEDs are turned off below.
+            leds: [Some(false); 6],
+            dds_mismatches: 0,
+            dds_expected: [None; 4],
+            over_temperature: false,
             attenuator_spi,
             pwr,
             aux_adc,
@@ -461,9 +958,9 @@ impl PounderDevices {
             devices.io.set_gpio_dir(pin, mcp230xx::Direction::Output)?;
         }
 
-        devices.reset_attenuators().unwrap();
+        devices.reset_attenuators()?;
 
-        devices.reset_dds().unwrap();
+        devices.reset_dds()?;
 
         Ok(devices)
     }
@@ -471,10 +968,11 @@ impl PounderDevices {
     /// Sample one of the two auxiliary ADC channels associated with the respective RF input channel.
     pub fn sample_aux_adc(&mut self, channel: Channel) -> Result<f32, Error> {
         let adc_scale = match channel {
-            Channel::In0 => self.aux_adc.0.read_normalized().unwrap(),
-            Channel::In1 => self.aux_adc.1.read_normalized().unwrap(),
+            Channel::In0 => self.aux_adc.0.read_normalized(),
+            Channel::In1 => self.aux_adc.1.read_normalized(),
             _ => return Err(Error::InvalidChannel),
-        };
+        }
+        .map_err(|_| Error::Adc)?;
 
         // Convert analog percentage to voltage. Note that the ADC uses an external 2.048V analog
         // reference.
@@ -490,7 +988,74 @@ impl PounderDevices {
         };
         // Active low
         self.io.set_gpio_level(GpioPin::OscEnN, level)?;
-        self.io.set_gpio_level(GpioPin::ExtClkSel, level)
+        self.io.set_gpio_level(GpioPin::ExtClkSel, level)?;
+        self.ext_clk = enabled;
+        Ok(())
+    }
+
+    /// Whether the external reference clock is selected.
+    pub fn ext_clk(&self) -> bool {
+        self.ext_clk
+    }
+
+    /// Select the reference clock requested by an RF configuration.
+    ///
+    /// The clock is only switched when the request changes so that a fallback from an absent
+    /// external reference (see [Self::set_ext_clk]) persists.
+    ///
+    /// Args:
+    /// * `config` - The configuration to apply.
+    ///
+    /// Returns:
+    /// Whether the external clock was newly selected and should be verified.
+    pub fn update_clock(
+        &mut self,
+        config: &PounderConfig,
+    ) -> Result<bool, Error> {
+        if config.external_clock == self.ext_clk_request {
+            return Ok(false);
+        }
+        self.ext_clk_request = config.external_clock;
+        self.set_ext_clk(config.external_clock)?;
+        Ok(config.external_clock)
+    }
+
+    /// Update the front LEDs (LED4 to LED9) of an RF configuration.
+    ///
+    /// The extender is only written for changed LED states.
+    ///
+    /// Args:
+    /// * `config` - The configuration to apply.
+    pub fn update_leds(&mut self, config: &PounderConfig) -> Result<(), Error> {
+        let pins = [
+            GpioPin::Led4Green,
+            GpioPin::Led5Red,
+            GpioPin::Led6Green,
+            GpioPin::Led7Red,
+            GpioPin::Led8Green,
+            GpioPin::Led9Red,
+        ];
+        let settled = self.attenuation.iter().all(Option::is_some)
+            && self.ramp.iter().all(Option::is_none);
+        for (i, pin) in pins.into_iter().enumerate() {
+            let on = match config.led_status[i] {
+                None => config.leds[i],
+                Some(LedStatus::Dds) => self.dds_configured,
+                Some(LedStatus::ExternalClock) => self.ext_clk,
+                Some(LedStatus::Attenuation) => settled,
+            };
+            if self.leds[i] != Some(on) {
+                self.leds[i] = None;
+                let level = if on {
+                    mcp230xx::Level::High
+                } else {
+                    mcp230xx::Level::Low
+                };
+                self.io.set_gpio_level(pin, level)?;
+                self.leds[i] = Some(on);
+            }
+        }
+        Ok(())
     }
 
     /// Reset the DDS via the GPIO extender (Pounder v1.2 and later)
@@ -507,6 +1072,553 @@ impl PounderDevices {
     pub fn temperature(&mut self) -> Result<f32, Error> {
         self.lm75.read_temperature().map_err(|_| Error::I2c)
     }
+
+    /// Check the board temperature against the over-temperature threshold.
+    ///
+    /// Above the threshold the alarm latches: all attenuators are set to maximum attenuation
+    /// until the alarm is cleared with [Self::clear_alarm]. The DDS channels must then be
+    /// powered down with [Self::power_down]. This also applies in maintenance mode.
+    ///
+    /// Args:
+    /// * `threshold` - The temperature threshold (°C), `None` to disable.
+    ///
+    /// Returns:
+    /// Whether the alarm was latched by this check.
+    pub fn check_temperature(
+        &mut self,
+        threshold: Option<f32>,
+    ) -> Result<bool, Error> {
+        let Some(threshold) = threshold else {
+            return Ok(false);
+        };
+        if self.over_temperature || self.temperature()? <= threshold {
+            return Ok(false);
+        }
+        self.over_temperature = true;
+        self.ramp = [None; 4];
+        for channel in DDS_CHANNELS {
+            self.set_attenuation(channel, 31.5)?;
+        }
+        Ok(true)
+    }
+
+    /// Power down the DDS channels during an over-temperature alarm.
+    ///
+    /// The function registers of all channels are written with a single profile that fits the
+    /// QSPI FIFO. The write is not paused in maintenance mode.
+    ///
+    /// Args:
+    /// * `dds` - The DDS profile stream.
+    pub fn power_down(&self, dds: &mut DdsOutput) {
+        let mut profile = dds.builder();
+        for (channel, cfr) in DDS_CHANNELS.into_iter().zip(self.cfr) {
+            profile.push_function(channel.into(), self.function(cfr), None);
+        }
+        dds.write_unpaused(profile);
+    }
+
+    /// Clear a latched over-temperature alarm.
+    ///
+    /// The configuration must be applied again to restore the RF outputs.
+    pub fn clear_alarm(&mut self) {
+        self.over_temperature = false;
+    }
+
+    /// The channel function register to write, powered down during an over-temperature alarm.
+    fn function(&self, cfr: ad9959::Cfr) -> ad9959::Cfr {
+        if self.over_temperature {
+            cfr.with_digital_pd(true).with_dac_pd(true)
+        } else {
+            cfr
+        }
+    }
+
+    /// Read the sensor telemetry.
+    ///
+    /// Failed reads are marked as `None` in the telemetry.
+    pub fn telemetry(&mut self) -> PounderTelemetry {
+        PounderTelemetry {
+            temperature: self.temperature().ok(),
+            input_power: [Channel::In0, Channel::In1]
+                .map(|ch| self.measure_power(ch).ok()),
+            aux_adc: [Channel::In0, Channel::In1]
+                .map(|ch| self.sample_aux_adc(ch).ok()),
+            power_servo: self.power_servo,
+            detector_power_down: self.detector_power_down,
+            dds_mismatches: self.dds_mismatches,
+            over_temperature: self.over_temperature,
+            attenuation: DDS_CHANNELS.map(|ch| self.attenuation[ch as usize]),
+            attenuation_corrected: DDS_CHANNELS
+                .map(|ch| self.attenuation_corrected[ch as usize]),
+        }
+    }
+
+    /// Power down or enable the input detectors of an RF configuration.
+    ///
+    /// Args:
+    /// * `config` - The configuration to apply.
+    pub fn update_detectors(
+        &mut self,
+        config: &PounderConfig,
+    ) -> Result<(), Error> {
+        for (i, (pin, power_down)) in
+            [GpioPin::DetPwrdown0, GpioPin::DetPwrdown1]
+                .into_iter()
+                .zip(config.detector_power_down)
+                .enumerate()
+        {
+            let level = if power_down {
+                mcp230xx::Level::High
+            } else {
+                mcp230xx::Level::Low
+            };
+            self.io.set_gpio_level(pin, level)?;
+            self.detector_power_down[i] = power_down;
+        }
+        Ok(())
+    }
+
+    /// Update the RF power servos of the output channels.
+    ///
+    /// The attenuators are only written when the quantized attenuation changes.
+    ///
+    /// Args:
+    /// * `servos` - The servo configurations of the output channels.
+    /// * `attenuation` - The configured attenuations of the output channels.
+    /// * `period` - The update period in seconds.
+    pub fn servo_power(
+        &mut self,
+        servos: &[Option<PowerServo>; 2],
+        attenuation: [f32; 2],
+        period: f32,
+    ) -> Result<(), Error> {
+        // The attenuators are held during an over-temperature alarm.
+        if self.over_temperature {
+            return Ok(());
+        }
+        for (i, servo) in servos.iter().enumerate() {
+            let Some(servo) = servo else {
+                self.power_servo[i] = None;
+                continue;
+            };
+            let input = [Channel::In0, Channel::In1]
+                .get(servo.input)
+                .ok_or(Error::InvalidChannel)?;
+            let power = self.measure_power(*input).ok();
+            let state = self.power_servo[i];
+            let last = state.map_or(attenuation[i], |s| s.attenuation);
+            let att = servo.update(last, power.unwrap_or(f32::NAN), period);
+            self.power_servo[i] = Some(PowerServoStatus {
+                attenuation: att,
+                power,
+                saturated: att <= 0.0 || att >= 31.5,
+            });
+            if state.is_none() || quantize(att) != quantize(last) {
+                self.set_attenuation(
+                    [Channel::Out0, Channel::Out1][i],
+                    quantize(att),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the attenuations of an RF configuration.
+    ///
+    /// During an over-temperature alarm all attenuators are set to maximum attenuation.
+    /// Outputs with an active RF power servo keep the controlled attenuation. With a ramp
+    /// duration configured, changed attenuations are ramped by [Self::ramp_attenuators] instead
+    /// of applied. The attenuator calibration is evaluated at the DDS frequencies. Invalid
+    /// calibration tables are rejected, see [Flatness::is_valid].
+    ///
+    /// Args:
+    /// * `config` - The configuration to apply.
+    /// * `now` - The current wrapping timestamp in milliseconds.
+    ///
+    /// Returns:
+    /// Whether any attenuation is ramping.
+    pub fn update_attenuators(
+        &mut self,
+        config: &PounderConfig,
+        now: u32,
+    ) -> Result<bool, Error> {
+        if !config.attenuator_calibration.iter().all(Flatness::is_valid) {
+            return Err(Error::Bounds);
+        }
+        for ((channel, ch), calibration) in DDS_CHANNELS
+            .into_iter()
+            .zip(config.channels())
+            .zip(&config.attenuator_calibration)
+        {
+            self.attenuation_error[channel as usize] =
+                calibration.deviation(ch.dds.frequency as f32);
+        }
+        if self.over_temperature {
+            for channel in DDS_CHANNELS {
+                self.set_attenuation(channel, 31.5)?;
+            }
+            return Ok(false);
+        }
+        for (index, (channel, ch)) in
+            DDS_CHANNELS.into_iter().zip(config.channels()).enumerate()
+        {
+            // Output channels follow the DDS input channels.
+            let servo = index
+                .checked_sub(2)
+                .filter(|i| config.power_servo[*i].is_some())
+                .and_then(|i| self.power_servo[i]);
+            let ramp = &mut self.ramp[channel as usize];
+            match (servo, self.attenuation[channel as usize]) {
+                (Some(servo), _) => {
+                    *ramp = None;
+                    self.set_attenuation(channel, quantize(servo.attenuation))?;
+                }
+                (None, Some(current))
+                    if config.attenuation_ramp > 0
+                        && current != ch.attenuation
+                        && crate::convert::att_is_valid(ch.attenuation) =>
+                {
+                    // An ongoing ramp to the same target continues.
+                    if ramp.is_none_or(|r| r.target != ch.attenuation) {
+                        *ramp = Some(AttenuationRamp {
+                            start: current,
+                            target: ch.attenuation,
+                            since: now,
+                            duration: config.attenuation_ramp,
+                        });
+                    }
+                }
+                (None, _) => {
+                    *ramp = None;
+                    self.set_attenuation(channel, ch.attenuation)?;
+                }
+            }
+        }
+        Ok(self.ramp.iter().any(Option::is_some))
+    }
+
+    /// Advance the attenuation ramps.
+    ///
+    /// Args:
+    /// * `now` - The current wrapping timestamp in milliseconds.
+    ///
+    /// Returns:
+    /// Whether any attenuation is still ramping.
+    pub fn ramp_attenuators(&mut self, now: u32) -> Result<bool, Error> {
+        for channel in DDS_CHANNELS {
+            let Some(ramp) = self.ramp[channel as usize] else {
+                continue;
+            };
+            let progress = (now.wrapping_sub(ramp.since) as f32
+                / ramp.duration as f32)
+                .min(1.0);
+            let attenuation =
+                quantize(ramp.start + (ramp.target - ramp.start) * progress);
+            if self.attenuation[channel as usize] != Some(attenuation) {
+                self.set_attenuation(channel, attenuation)?;
+            }
+            if progress >= 1.0 {
+                self.ramp[channel as usize] = None;
+            }
+        }
+        Ok(self.ramp.iter().any(Option::is_some))
+    }
+
+    /// Apply the DDS configuration of an RF configuration.
+    ///
+    /// The output channel amplitudes are corrected using the respective flatness calibration.
+    /// Invalid flatness tables are rejected, see [Flatness::is_valid].
+    /// Frequency changes of unscanned, unswept and undithered channels are slew limited: they
+    /// are advanced by [DdsUpdates::write]. Chirps are advanced likewise. Swept channels have the
+    /// sweep parameters written ahead of their profile. The modulation level is written with the
+    /// profile. The profiles of all channels are staged and take effect with a single IO_Update.
+    /// On error, the profiles staged so far are written with the next commit. With verification
+    /// enabled, the profiles are marked for readback with [Self::verify_dds].
+    ///
+    /// Args:
+    /// * `config` - The configuration to apply.
+    /// * `dds` - The DDS profile stream.
+    /// * `slew` - The frequency slew limiter.
+    /// * `period` - The period of the [DdsUpdates::write] updates in seconds.
+    ///
+    /// Returns:
+    /// The frequency scans and chirps of the channels in [PounderConfig] order.
+    pub fn update_dds(
+        &mut self,
+        config: &PounderConfig,
+        dds: &mut DdsOutput,
+        slew: &mut FtwSlew,
+        period: f32,
+    ) -> Result<[Option<DdsScan>; 4], Error> {
+        if !config.flatness.iter().all(Flatness::is_valid) {
+            return Err(Error::Bounds);
+        }
+        let flat = Flatness::default();
+        let flatness = [&flat, &flat, &config.flatness[0], &config.flatness[1]];
+        let sysclk = DDS_SYSTEM_CLK.to_Hz() as f64;
+        let ftw_per_hz = (1u64 << 32) as f64 / sysclk;
+        let mut scans = [None; 4];
+        let mut expected = [None; 4];
+        self.dds_configured = false;
+        self.dds_expected = [None; 4];
+        for (index, (((channel, ch), flatness), (dither, scan))) in DDS_CHANNELS
+            .into_iter()
+            .zip(config.channels())
+            .zip(flatness)
+            .zip(self.dither.iter_mut().zip(scans.iter_mut()))
+            .enumerate()
+        {
+            // Validate the frequency.
+            let start = ad9959::frequency_to_ftw(ch.dds.frequency, sysclk)
+                .ok_or(Error::Bounds)?;
+            let sweep = ch
+                .dds
+                .sweep
+                .map(|s| {
+                    let stop = ad9959::frequency_to_ftw(s.stop, sysclk)
+                        .filter(|stop| *stop > start)
+                        .ok_or(Error::Bounds)?;
+                    let step = (s.step * ftw_per_hz) as u32;
+                    // The ramp rate is in SYNC_CLK cycles of four system clock cycles.
+                    let rate = (s.dwell as f64 * sysclk / 4.0).round();
+                    if step == 0 || !(1.0..=255.0).contains(&rate) {
+                        return Err(Error::Bounds);
+                    }
+                    if ch.scan.is_some() || ch.dds.modulation.is_some() {
+                        return Err(Error::InvalidState);
+                    }
+                    Ok((stop, step, rate as u8))
+                })
+                .transpose()?;
+            let chirp = ch
+                .chirp
+                .map(|c| {
+                    ad9959::frequency_to_ftw(ch.dds.frequency + c.span, sysclk)
+                        .ok_or(Error::Bounds)?;
+                    if !(c.duration > 0.0) {
+                        return Err(Error::Bounds);
+                    }
+                    if ch.scan.is_some() || sweep.is_some() {
+                        return Err(Error::InvalidState);
+                    }
+                    // The float to integer conversion saturates.
+                    let updates = ((c.duration / period).round() as u32).max(1);
+                    Ok(Chirp {
+                        step: c.span * ftw_per_hz / updates as f64,
+                        updates,
+                        remaining: (c.repeat > 0).then_some(c.repeat),
+                        index: 0,
+                    })
+                })
+                .transpose()?;
+            let drive = match (ch.scan, chirp) {
+                (Some(s), _) if s.source >= 2 => {
+                    return Err(Error::InvalidChannel);
+                }
+                (Some(s), _) => Some(Drive::Generator {
+                    source: s.source,
+                    ftw_per_lsb: s.slope as f64
+                        * DacCode::VOLT_PER_LSB as f64
+                        * ftw_per_hz,
+                }),
+                (None, chirp) => chirp.map(Drive::Chirp),
+            };
+            *scan = drive.map(|drive| DdsScan {
+                index,
+                ftw: ch.dds.frequency * ftw_per_hz,
+                drive,
+            });
+            // Scanned, chirped and swept channels are not dithered.
+            *dither = (ch.dds.dither && scan.is_none() && sweep.is_none())
+                .then(|| FtwDither::new(ch.dds.frequency * ftw_per_hz));
+            // Scans are limited with each write.
+            let ftw = if scan.is_some() {
+                slew.cancel(index);
+                None
+            } else if sweep.is_some() {
+                slew.cancel(index);
+                Some(start)
+            } else {
+                slew.set(index, ch.dds.frequency * ftw_per_hz, dither.is_none())
+                    // The validated frequency is below Nyquist.
+                    .map(|ftw| ftw as u32)
+            };
+            let pow = ad9959::phase_to_pow(ch.dds.phase_offset);
+            let mut acr = ad9959::amplitude_to_acr(
+                flatness.correct(ch.dds.frequency as f32, ch.dds.amplitude),
+            )
+            .ok_or(Error::Bounds)?;
+            let mut cfr = ad9959::Cfr::default()
+                .with_digital_pd(!ch.dds.enable)
+                .with_dac_pd(!ch.dds.enable);
+            // Channel words are MSB aligned: 32 bit FTW, 14 bit POW, 10 bit ASF.
+            let word = match ch.dds.modulation {
+                None => None,
+                Some(Modulation::Frequency(frequency)) => {
+                    cfr = cfr.with_afp_select(u2::new(0b10));
+                    Some(
+                        ad9959::frequency_to_ftw(frequency, sysclk)
+                            .ok_or(Error::Bounds)?,
+                    )
+                }
+                Some(Modulation::Phase(phase)) => {
+                    cfr = cfr.with_afp_select(u2::new(0b11));
+                    Some((ad9959::phase_to_pow(phase).value() as u32) << 18)
+                }
+                Some(Modulation::Amplitude(amplitude)) => {
+                    cfr = cfr.with_afp_select(u2::new(0b01));
+                    let low = ad9959::acr_to_amplitude(acr);
+                    let high = ad9959::amplitude_to_acr(
+                        flatness.correct(ch.dds.frequency as f32, amplitude),
+                    )
+                    .map(ad9959::acr_to_amplitude)
+                    .ok_or(Error::Bounds)?;
+                    // The multiplier is required for both levels.
+                    let [low, high] = [low, high]
+                        .map(|a| ((a * (1 << 10) as f32) as u32).min(0x3ff));
+                    acr = acr
+                        .with_multiplier(true)
+                        .with_asf(u10::new(low as u16));
+                    Some(high << 22)
+                }
+            };
+            if let Some((stop, step, rate)) = sweep {
+                let mut profile = dds.builder();
+                profile.push_sweep(channel.into(), stop, step, rate);
+                dds.stage(profile)?;
+                // Frequency sweep
+                cfr = cfr.with_afp_select(u2::new(0b10)).with_sweep(true);
+            }
+            let mut profile = dds.builder();
+            profile.push(channel.into(), ftw, Some(pow), Some(acr));
+            profile.push_function(channel.into(), self.function(cfr), word);
+            dds.stage(profile)?;
+            self.cfr[index] = cfr;
+            // Words changed by the processing task are not verified.
+            let driven = index.checked_sub(2).is_some_and(|i| {
+                config.dsp_amplitude[i].is_some()
+                    || config.amplitude_lock[i].is_some()
+                    || config.aux_modulation[i].is_some()
+            });
+            expected[index] = Some((
+                ftw.filter(|_| dither.is_none()),
+                pow,
+                (!driven).then_some(acr),
+            ));
+        }
+        dds.commit();
+        if config.verify && !dds.paused() {
+            self.dds_expected = expected;
+        }
+        self.dds_configured = true;
+        Ok(scans)
+    }
+
+    /// Verify the profile of the next channel pending readback verification.
+    ///
+    /// Each readback stops the profile stream. Channels are verified one at a time so that the
+    /// DDS stream is only held briefly. Mismatches are logged and counted.
+    ///
+    /// Args:
+    /// * `dds` - The DDS profile stream.
+    ///
+    /// Returns:
+    /// Whether further channels are pending verification.
+    pub fn verify_dds(&mut self, dds: &mut DdsOutput) -> Result<bool, Error> {
+        let Some((channel, expected)) = DDS_CHANNELS
+            .into_iter()
+            .zip(self.dds_expected.iter_mut())
+            .find(|(_, expected)| expected.is_some())
+        else {
+            return Ok(false);
+        };
+        // Note(unwrap): The entry was checked above.
+        let (ftw, pow, acr) = expected.take().unwrap();
+        if !Self::verify_profile(dds, channel, ftw, pow, acr)? {
+            log::warn!("DDS readback mismatch on {channel:?}");
+            self.dds_mismatches = self.dds_mismatches.wrapping_add(1);
+        }
+        Ok(self.dds_expected.iter().any(Option::is_some))
+    }
+
+    /// Check the profile registers of a DDS channel against the written contents.
+    ///
+    /// Args:
+    /// * `dds` - The DDS profile stream.
+    /// * `channel` - The DDS channel.
+    /// * `ftw` - The frequency tuning word if written.
+    /// * `pow` - The phase offset word.
+    /// * `acr` - The amplitude control register if written.
+    ///
+    /// Returns:
+    /// Whether the registers match.
+    fn verify_profile(
+        dds: &mut DdsOutput,
+        channel: Channel,
+        ftw: Option<u32>,
+        pow: u14,
+        acr: Option<ad9959::Acr>,
+    ) -> Result<bool, Error> {
+        let channel = channel.into();
+        if let Some(ftw) = ftw {
+            let mut data = [0; 4];
+            dds.read_register(channel, Address::CFTW0, &mut data)?;
+            if u32::from_be_bytes(data) != ftw {
+                return Ok(false);
+            }
+        }
+        let mut data = [0; 2];
+        dds.read_register(channel, Address::CPOW0, &mut data)?;
+        if u16::from_be_bytes(data) & 0x3fff != pow.value() {
+            return Ok(false);
+        }
+        let Some(acr) = acr else {
+            return Ok(true);
+        };
+        let mut data = [0; 3];
+        dds.read_register(channel, Address::ACR, &mut data)?;
+        Ok(data == acr.raw_value().to_be_bytes())
+    }
+
+    /// Hold or release the phase accumulators of the DDS channels.
+    ///
+    /// The phase accumulators of all channels are held cleared with one IO_Update and released
+    /// with a later one so that they restart simultaneously. The release must only be written
+    /// once the IO_Update of the hold has been generated. The relative phases of the channels are
+    /// then given by their phase offsets until the next frequency change.
+    ///
+    /// The function registers of all channels are written with a single profile that fits the
+    /// QSPI FIFO.
+    ///
+    /// Args:
+    /// * `clear` - Hold the phase accumulators cleared.
+    /// * `dds` - The DDS profile stream.
+    pub fn sync(&self, clear: bool, dds: &mut DdsOutput) {
+        let mut profile = dds.builder();
+        for (channel, cfr) in DDS_CHANNELS.into_iter().zip(self.cfr) {
+            profile.push_function(
+                channel.into(),
+                self.function(cfr).with_clear_phase(clear),
+                None,
+            );
+        }
+        dds.write(profile);
+    }
+
+    /// Update the frequency tuning words of the channels with dithering enabled.
+    ///
+    /// The words are queued for the processing task, which writes all channels with a single
+    /// IO_Update.
+    ///
+    /// Args:
+    /// * `updates` - The DDS updates of the processing task.
+    pub fn dither(&mut self, updates: &mut DdsUpdates) {
+        for (index, dither) in self.dither.iter_mut().enumerate() {
+            if let Some(dither) = dither {
+                updates.queue_ftw(index, dither.update());
+            }
+        }
+    }
 }
 
 impl PounderDevices {
@@ -550,10 +1662,16 @@ impl PounderDevices {
 
     /// Set the attenuation of a single channel.
     ///
+    /// The calibrated attenuation error of the channel is compensated within the attenuator range,
+    /// except during an over-temperature alarm.
+    ///
     /// Args:
     /// * `channel` - The pounder channel to configure the attenuation of.
     /// * `attenuation` - The desired attenuation of the channel in dB. This has a resolution of
     ///   0.5dB.
+    ///
+    /// Returns:
+    /// The programmed attenuation in dB.
     pub fn set_attenuation(
         &mut self,
         channel: Channel,
@@ -563,9 +1681,16 @@ impl PounderDevices {
             return Err(Error::Bounds);
         }
 
+        let error = if self.over_temperature {
+            0.0
+        } else {
+            self.attenuation_error[channel as usize]
+        };
+        let corrected = (attenuation - error).clamp(0.0, 31.5);
+
         // Calculate the attenuation code to program into the attenuator. The attenuator uses a
         // code where the LSB is 0.5 dB.
-        let attenuation_code = (attenuation * 2.0) as u8;
+        let attenuation_code = (corrected * 2.0).round() as u8;
 
         // Read all the channels, modify the channel of interest, and write all the channels back.
         // This ensures the staging register and the output register are always in sync.
@@ -580,6 +1705,9 @@ impl PounderDevices {
 
         // Finally, latch the output of the updated channel to force it into an active state.
         self.latch_attenuator(channel)?;
+        self.attenuation[channel as usize] = Some(quantize(attenuation));
+        self.attenuation_corrected[channel as usize] =
+            Some(attenuation_code as f32 / 2.0);
 
         Ok(attenuation_code as f32 / 2.0)
     }
@@ -623,10 +1751,11 @@ impl PounderDevices {
     /// The sampled voltage of the specified channel.
     fn sample_converter(&mut self, channel: Channel) -> Result<f32, Error> {
         let adc_scale = match channel {
-            Channel::In0 => self.pwr.0.read_normalized().unwrap(),
-            Channel::In1 => self.pwr.1.read_normalized().unwrap(),
+            Channel::In0 => self.pwr.0.read_normalized(),
+            Channel::In1 => self.pwr.1.read_normalized(),
             _ => return Err(Error::InvalidChannel),
-        };
+        }
+        .map_err(|_| Error::Adc)?;
 
         // Convert analog percentage to voltage. Note that the ADC uses an external 2.048V analog
         // reference.
@@ -641,6 +1770,14 @@ impl PounderDevices {
     /// Returns:
     /// Power in dBm after the digitally controlled attenuator before the amplifier.
     pub fn measure_power(&mut self, channel: Channel) -> Result<f32, Error> {
+        let detector = match channel {
+            Channel::In0 => 0,
+            Channel::In1 => 1,
+            _ => return Err(Error::InvalidChannel),
+        };
+        if self.detector_power_down[detector] {
+            return Err(Error::InvalidState);
+        }
         let analog_measurement = self.sample_converter(channel)?;
 
         // The AD8363 with VSET connected to VOUT provides an output voltage of 51.7 mV/dB at
@@ -0,0 +1,96 @@
+//! DDS clock phase tracking
+use serde::Serialize;
+
+/// DDS clock phase tracking from the Pounder timestamps
+///
+/// The Pounder timestamp timer counts the DDS SYNC_CLK divided by 4 and is captured at the end of
+/// each sample batch. The deviation of the timestamp increments from the nominal number of ticks
+/// per batch accumulates to the phase of the DDS clock relative to the Stabilizer sampling clock,
+/// e.g. the beat note phase of two references. Missed captures are bridged as long as the phase
+/// changes by less than half a batch between timestamps.
+#[derive(Copy, Clone, Debug)]
+pub struct SyncPhase {
+    /// Nominal timer ticks per batch
+    nominal: u32,
+    last: Option<u16>,
+    /// Accumulated phase (ticks)
+    phase: i64,
+    /// Phase at the start of the current interval (ticks)
+    start: i64,
+    /// Nominal ticks elapsed during the current interval
+    elapsed: u64,
+    /// Nominal ticks elapsed in total
+    total: u64,
+}
+
+/// DDS clock phase report
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct SyncPhaseTelemetry {
+    /// Fractional frequency offset of the DDS clock relative to the sampling clock during the last
+    /// interval. Positive if the DDS clock is fast.
+    pub offset: f32,
+    /// Accumulated phase in timestamp ticks (32 ns)
+    pub phase: i64,
+}
+
+impl SyncPhase {
+    /// Construct a phase tracker.
+    ///
+    /// # Args
+    /// * `nominal` - The nominal number of timer ticks per batch.
+    pub fn new(nominal: u32) -> Self {
+        Self {
+            nominal: nominal.max(1),
+            last: None,
+            phase: 0,
+            start: 0,
+            elapsed: 0,
+            total: 0,
+        }
+    }
+
+    /// The total number of nominal ticks elapsed.
+    ///
+    /// This only advances while the DDS clock is running.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Add a timestamp.
+    ///
+    /// # Args
+    /// * `timestamp` - The captured timer count.
+    ///
+    /// # Returns
+    /// The accumulated phase in ticks, wrapping.
+    pub fn update(&mut self, timestamp: u16) -> i32 {
+        if let Some(last) = self.last.replace(timestamp) {
+            let nominal = self.nominal as i64;
+            let delta = timestamp.wrapping_sub(last) as i64;
+            // Batches elapsed since the last timestamp
+            let batches = (delta + nominal / 2) / nominal;
+            self.phase += delta - batches * nominal;
+            self.elapsed += (batches * nominal) as u64;
+            self.total += (batches * nominal) as u64;
+        }
+        self.phase as i32
+    }
+
+    /// Complete the current measurement interval.
+    ///
+    /// # Returns
+    /// The phase report, `None` if no timestamps were captured during the interval.
+    pub fn finish(&mut self) -> Option<SyncPhaseTelemetry> {
+        let elapsed = core::mem::take(&mut self.elapsed);
+        let start = core::mem::replace(&mut self.start, self.phase);
+        if elapsed == 0 {
+            // Restart without the stale timestamp.
+            self.last = None;
+            return None;
+        }
+        Some(SyncPhaseTelemetry {
+            offset: ((self.phase - start) as f64 / elapsed as f64) as f32,
+            phase: self.phase,
+        })
+    }
+}
@@ -0,0 +1,126 @@
+//! PWM outputs on the EEM GPIO outputs
+//!
+//! # Design
+//! With the `Gpio` EEM population variant, the LVDS6 and LVDS7 EEM signals are outputs. They are
+//! not connected to timer channels. The otherwise unused TIM4 generates the PWM period with its
+//! update event and the two duty cycles with its compare channels 1 and 2. The TIM4 interrupt
+//! drives the outputs accordingly.
+//!
+//! The edges are subject to interrupt latency and to blocking by higher or equal priority tasks.
+//! The resulting jitter is acceptable for heaters, fans or LED drivers at PWM frequencies up to a
+//! few kHz. The compare registers are preloaded so that duty cycle updates take effect at the
+//! start of the next period.
+use super::hal;
+use hal::gpio::{ErasedPin, Output};
+
+/// Two PWM outputs sharing a period.
+pub struct Pwm {
+    timer: hal::timer::Timer<hal::stm32::TIM4>,
+    outputs: [ErasedPin<Output>; 2],
+    frequency: f32,
+    compare: [u32; 2],
+}
+
+impl Pwm {
+    /// Construct the PWM outputs.
+    ///
+    /// The outputs are low until a duty cycle is set.
+    ///
+    /// # Args
+    /// * `timer` - The timer generating period and duty cycles.
+    /// * `outputs` - The output pins.
+    pub fn new(
+        mut timer: hal::timer::Timer<hal::stm32::TIM4>,
+        mut outputs: [ErasedPin<Output>; 2],
+    ) -> Self {
+        for output in outputs.iter_mut() {
+            output.set_low();
+        }
+
+        // Note(unsafe): The timer is owned. Only its compare and interrupt configuration is
+        // modified here.
+        let regs = unsafe { &*hal::stm32::TIM4::ptr() };
+        regs.ccr1.write(|w| unsafe { w.bits(0) });
+        regs.ccr2.write(|w| unsafe { w.bits(0) });
+        regs.ccmr1_output()
+            .modify(|_, w| w.oc1pe().set_bit().oc2pe().set_bit());
+        timer.listen(hal::timer::Event::TimeOut);
+        regs.dier
+            .modify(|_, w| w.cc1ie().set_bit().cc2ie().set_bit());
+
+        let mut pwm = Self {
+            timer,
+            outputs,
+            frequency: 0.0,
+            compare: [0; 2],
+        };
+        pwm.set_frequency(1e3);
+        pwm
+    }
+
+    /// Set the PWM frequency.
+    ///
+    /// The duty cycles must be set again after a frequency change.
+    ///
+    /// # Args
+    /// * `frequency` - The PWM frequency in Hz.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        if frequency == self.frequency || !(frequency >= 1.0) {
+            return;
+        }
+        self.frequency = frequency;
+        self.timer
+            .set_freq(hal::time::Hertz::from_raw(frequency as u32));
+    }
+
+    /// Set the duty cycle of an output.
+    ///
+    /// # Args
+    /// * `index` - The output index.
+    /// * `duty` - The duty cycle. It is limited to the range 0 to 1.
+    pub fn set_duty(&mut self, index: usize, duty: f32) {
+        // Note(unsafe): Only the compare register of the channel is accessed.
+        let regs = unsafe { &*hal::stm32::TIM4::ptr() };
+        let period = regs.arr.read().bits() + 1;
+        // The float to integer conversion saturates and maps NaN to 0.
+        let compare = (duty.clamp(0.0, 1.0) * period as f32) as u32;
+        if compare == self.compare[index] {
+            return;
+        }
+        self.compare[index] = compare;
+        // A compare value beyond the period never matches: the output remains high.
+        match index {
+            0 => regs.ccr1.write(|w| unsafe { w.bits(compare) }),
+            _ => regs.ccr2.write(|w| unsafe { w.bits(compare) }),
+        }
+        if compare == 0 {
+            self.outputs[index].set_low();
+        }
+    }
+
+    /// Drive the outputs.
+    ///
+    /// # Note
+    /// This must be called from the timer interrupt.
+    pub fn update(&mut self) {
+        // Note(unsafe): Only the status register is accessed.
+        let regs = unsafe { &*hal::stm32::TIM4::ptr() };
+        let sr = regs.sr.read();
+        // Clear the pending flags. Writing 1 has no effect.
+        regs.sr.write(|w| unsafe { w.bits(!sr.bits()) });
+
+        if sr.uif().bit_is_set() {
+            for (output, compare) in self.outputs.iter_mut().zip(self.compare) {
+                if compare > 0 {
+                    output.set_high();
+                }
+            }
+        }
+        if sr.cc1if().bit_is_set() {
+            self.outputs[0].set_low();
+        }
+        if sr.cc2if().bit_is_set() {
+            self.outputs[1].set_low();
+        }
+    }
+}
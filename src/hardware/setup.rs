@@ -17,22 +17,25 @@ use smoltcp_nal::smoltcp;
 
 use platform::{AppSettings, ApplicationMetadata, NetSettings};
 
-use crate::design_parameters;
+use crate::calibration::InputCalibration;
+use crate::design_parameters::{self, net_buffers};
 
 use super::{
-    DigitalInput0, DigitalInput1, Eem, Gpio, HardwareVersion, Pgia,
-    SerialTerminal, SystemTimer, Systick, UsbDevice, adc, afe,
+    AuxAdc, DigitalInput0, DigitalInput1, Eem, Gpio, HardwareVersion, Pgia,
+    SerialTerminal, SystemTimer, Systick, UsbDevice, UsbEthernet, adc, ads1115,
+    afe, bridge,
     cpu_temp_sensor::CpuTempSensor,
-    dac, eeprom,
+    dac, dscp, ecm, eeprom,
     input_stamper::InputStamper,
     net::{EthernetPhy, NetworkStack},
     pounder,
     pounder::dds_output::DdsOutput,
+    pwm,
     shared_adc::SharedAdc,
     timers,
 };
 
-const NUM_TCP_SOCKETS: usize = 4;
+const NUM_TCP_SOCKETS: usize = 7;
 const NUM_UDP_SOCKETS: usize = 1;
 const NUM_SOCKETS: usize = NUM_UDP_SOCKETS + NUM_TCP_SOCKETS;
 
@@ -47,40 +50,45 @@ pub struct NetStorage {
     pub dns_storage: [Option<smoltcp::socket::dns::DnsQuery>; 1],
 }
 
+// The UDP transmit buffer must hold a complete stream frame.
+const _: () = assert!(net_buffers::UDP_TX_BUFFER >= ::stream::FRAME_SIZE);
+
 #[derive(Clone)]
 pub struct UdpSocketStorage {
-    rx_storage: [u8; 1024],
-    tx_storage: [u8; 2048],
+    rx_storage: [u8; net_buffers::UDP_RX_BUFFER],
+    tx_storage: [u8; net_buffers::UDP_TX_BUFFER],
     tx_metadata: [smoltcp::storage::PacketMetadata<
         smoltcp::socket::udp::UdpMetadata,
-    >; 10],
+    >; net_buffers::UDP_PACKETS],
     rx_metadata: [smoltcp::storage::PacketMetadata<
         smoltcp::socket::udp::UdpMetadata,
-    >; 10],
+    >; net_buffers::UDP_PACKETS],
 }
 
 impl Default for UdpSocketStorage {
     fn default() -> Self {
         Self {
-            rx_storage: [0; 1024],
-            tx_storage: [0; 2048],
-            tx_metadata: [smoltcp::storage::PacketMetadata::EMPTY; 10],
-            rx_metadata: [smoltcp::storage::PacketMetadata::EMPTY; 10],
+            rx_storage: [0; net_buffers::UDP_RX_BUFFER],
+            tx_storage: [0; net_buffers::UDP_TX_BUFFER],
+            tx_metadata: [smoltcp::storage::PacketMetadata::EMPTY;
+                net_buffers::UDP_PACKETS],
+            rx_metadata: [smoltcp::storage::PacketMetadata::EMPTY;
+                net_buffers::UDP_PACKETS],
         }
     }
 }
 
 #[derive(Clone)]
 pub struct TcpSocketStorage {
-    rx_storage: [u8; 1024],
-    tx_storage: [u8; 1024],
+    rx_storage: [u8; net_buffers::TCP_RX_BUFFER],
+    tx_storage: [u8; net_buffers::TCP_TX_BUFFER],
 }
 
 impl Default for TcpSocketStorage {
     fn default() -> Self {
         Self {
-            rx_storage: [0; 1024],
-            tx_storage: [0; 1024],
+            rx_storage: [0; net_buffers::TCP_RX_BUFFER],
+            tx_storage: [0; net_buffers::TCP_TX_BUFFER],
         }
     }
 }
@@ -105,6 +113,12 @@ pub struct NetworkDevices {
     pub stack: NetworkStack,
     pub phy: EthernetPhy,
     pub mac_address: smoltcp::wire::EthernetAddress,
+    /// Random initial nonce for remote command authentication
+    pub nonce: u64,
+    /// Fetch the initial settings from the provisioning server
+    pub provision: bool,
+    /// The persisted application settings were skipped (safe boot)
+    pub safe_boot: bool,
 }
 
 /// The available hardware interfaces on Stabilizer.
@@ -118,11 +132,17 @@ pub struct Stabilizer<C: serial_settings::Settings + 'static> {
     pub timestamp_timer: timers::TimestampTimer,
     pub network_devices: NetworkDevices,
     pub digital_inputs: (DigitalInput0, DigitalInput1),
+    /// The external auxiliary ADC or `None` if it was not detected.
+    pub aux_adc: Option<AuxAdc>,
     pub usb_serial: SerialTerminal<C>,
+    /// The USB network interface class
+    pub usb_ethernet: UsbEthernet,
     pub usb: UsbDevice,
     pub fp_led: [gpio::ErasedPin<gpio::Output>; 4],
     pub metadata: &'static ApplicationMetadata,
     pub settings: C,
+    /// The persisted input offset calibration
+    pub calibration: InputCalibration,
 }
 
 pub enum Mezzanine {
@@ -132,7 +152,8 @@ pub enum Mezzanine {
 
 /// The available Pounder-specific hardware interfaces.
 pub struct Pounder {
-    pub pounder: pounder::PounderDevices,
+    /// The Pounder peripherals or `None` if their initialization failed.
+    pub pounder: Option<pounder::PounderDevices>,
     pub dds_output: DdsOutput,
 
     #[cfg(not(feature = "pounder_v1_0"))]
@@ -148,6 +169,10 @@ static DES_RING: GroundedCell<
     >,
 > = GroundedCell::uninit();
 
+#[unsafe(link_section = ".axisram.usb")]
+/// Static storage for the USB network interface frame queues.
+static USB_FRAMES: GroundedCell<ecm::Storage> = GroundedCell::uninit();
+
 /// Setup ITCM and load its code from flash.
 ///
 /// For portability and maintainability this is implemented in Rust.
@@ -258,10 +283,8 @@ where
             );
         }
 
-        static LOGGER: rtt_logger::RTTLogger =
-            rtt_logger::RTTLogger::new(log::LevelFilter::Info);
-        log::set_logger(&LOGGER)
-            .map(|()| log::set_max_level(log::LevelFilter::Trace))
+        log::set_logger(&super::LOGGER)
+            .map(|()| log::set_max_level(log::LevelFilter::Info))
             .unwrap();
         log::info!("Starting");
     }
@@ -277,7 +300,11 @@ where
     // Enable SRAM3 for the ethernet descriptor ring.
     device.RCC.ahb2enr.modify(|_, w| w.sram3en().set_bit());
 
-    // Clear reset flags.
+    // Record the reset cause and clear reset flags.
+    let reset = device.RCC.rsr.read();
+    let power_on = reset.porrstf().bit_is_set();
+    let watchdog =
+        reset.iwdg1rstf().bit_is_set() || reset.wwdg1rstf().bit_is_set();
     device.RCC.rsr.write(|w| w.rmvf().set_bit());
 
     // Select the PLLs for SPI.
@@ -316,6 +343,10 @@ where
     // After ITCM loading.
     core.SCB.enable_icache();
 
+    // Enable the cycle counter for latency measurements.
+    core.DCB.enable_trace();
+    core.DWT.enable_cycle_counter();
+
     // Note: Frequencies are scaled by 2 to account for the M7 dual instruction pipeline.
     let mut delay = platform::AsmDelay::new(ccdr.clocks.c_ck().to_Hz() * 2);
 
@@ -616,6 +647,17 @@ where
         )
     };
 
+    let mut flash = {
+        let (_, flash_bank2) = device.FLASH.split();
+        platform::AsyncFlash(crate::hardware::Flash(flash_bank2.unwrap()))
+    };
+
+    let mut counters = platform::fetch_counters(&mut flash);
+    counters.boot(power_on, watchdog);
+    platform::store_counters(&mut flash, &counters);
+
+    let calibration = platform::fetch_calibration(&mut flash);
+
     let metadata = {
         // Read the hardware version pins.
         let hardware_version = HardwareVersion::from(
@@ -626,7 +668,7 @@ where
                 gpiog.pg3.into_pull_down_input().is_high(),
             ][..],
         );
-        crate::hardware::metadata(hardware_version.into())
+        crate::hardware::metadata(hardware_version.into(), C::VERSION, counters)
     };
 
     let mac_addr = smoltcp::wire::EthernetAddress(eeprom::read_eui48(
@@ -635,17 +677,104 @@ where
     ));
     log::info!("EUI48: {}", mac_addr);
 
-    let mut flash = {
-        let (_, flash_bank2) = device.FLASH.split();
-        platform::AsyncFlash(crate::hardware::Flash(flash_bank2.unwrap()))
-    };
+    // The EEPROM bus also serves an optional external ADC.
+    let aux_adc = ads1115::Ads1115::detect(eeprom_i2c, ads1115::I2C_ADDR).ok();
+    if aux_adc.is_some() {
+        log::info!("Found ADS1115 auxiliary ADC");
+    }
+
+    // Safe boot: Asserting DI0 during boot skips the persisted application settings as a
+    // recovery path from a bad configuration. The network settings are loaded to remain
+    // reachable.
+    let safe_boot = digital_inputs.0.is_high();
 
     let mut settings = C::new(NetSettings::new(mac_addr));
-    platform::SerialSettingsPlatform::<_, _, ()>::load(
+    let persisted = platform::SerialSettingsPlatform::<_, _, ()>::load(
         &mut settings,
         &mut flash,
+        safe_boot,
     );
 
+    super::LOGGER.configure(&settings.net().log);
+
+    // The override only applies to the Ethernet interface. The USB serial number keeps the
+    // EUI-48.
+    let eth_addr = settings.net().mac_address();
+    if eth_addr != mac_addr {
+        log::info!("MAC address override: {}", eth_addr);
+    }
+
+    let (usb_device, usb_serial, usb_ethernet, ecm_device) = {
+        let _usb_id = gpioa.pa10.into_alternate::<10>();
+        let usb_n = gpioa.pa11.into_alternate();
+        let usb_p = gpioa.pa12.into_alternate();
+        let usb = hal::usb_hs::USB2::new(
+            device.OTG2_HS_GLOBAL,
+            device.OTG2_HS_DEVICE,
+            device.OTG2_HS_PWRCLK,
+            usb_n,
+            usb_p,
+            ccdr.peripheral.USB2OTG,
+            &ccdr.clocks,
+        );
+
+        let endpoint_memory =
+            cortex_m::singleton!(: Option<&'static mut [u32]> = None).unwrap();
+        endpoint_memory.replace(
+            &mut cortex_m::singleton!(: [u32; 1024] = [0; 1024]).unwrap()[..],
+        );
+        let usb_bus = cortex_m::singleton!(
+            : usb_device::bus::UsbBusAllocator<super::UsbBus> = hal::usb_hs::UsbBus::new(
+            usb,
+            endpoint_memory.take().unwrap(),
+        ))
+        .unwrap();
+
+        let read_store = cortex_m::singleton!(: [u8; 128] = [0; 128]).unwrap();
+        let write_store =
+            cortex_m::singleton!(: [u8; 1024] = [0; 1024]).unwrap();
+        let serial = usbd_serial::SerialPort::new_with_store(
+            usb_bus,
+            &mut read_store[..],
+            &mut write_store[..],
+        );
+
+        // The host end of the USB network link uses a locally administered address derived
+        // from the Ethernet address.
+        let mut host_addr = eth_addr.0;
+        host_addr[0] |= 0x02;
+        host_addr[5] ^= 0x01;
+        let storage = unsafe {
+            let storage = USB_FRAMES.get();
+            storage.write(ecm::Storage::new());
+            &mut *storage
+        };
+        let (usb_ethernet, ecm_device) =
+            ecm::EcmClass::new(usb_bus, host_addr, storage);
+
+        // Generate a device serial number from the MAC address.
+        let serial_number = cortex_m::singleton!(: String<17> = {
+            let mut s = String::new();
+            write!(s, "{mac_addr}").unwrap();
+            s
+        })
+        .unwrap();
+
+        let usb_device = usb_device::device::UsbDeviceBuilder::new(
+            usb_bus,
+            usb_device::device::UsbVidPid(0x1209, 0x392F),
+        )
+        .strings(&[usb_device::device::StringDescriptors::default()
+            .manufacturer("ARTIQ/Sinara")
+            .product("Stabilizer")
+            .serial_number(serial_number)])
+        .unwrap()
+        .composite_with_iads()
+        .build();
+
+        (usb_device, serial, usb_ethernet, ecm_device)
+    };
+
     let network_devices = {
         let ethernet_pins = {
             // Reset the PHY before configuring pins.
@@ -682,11 +811,37 @@ where
             // Note(unsafe): We only call this function once to take ownership of the
             // descriptor ring.
             ring,
-            mac_addr,
+            eth_addr,
             ccdr.peripheral.ETH1MAC,
             &ccdr.clocks,
         );
 
+        let vlan = settings.net().vlan.filter(|vlan| {
+            let valid = vlan.is_valid();
+            if !valid {
+                log::error!("Invalid VLAN {vlan:?}, tagging disabled");
+            }
+            valid
+        });
+        if let Some(vlan) = vlan {
+            log::info!("VLAN: {} priority {}", vlan.id, vlan.priority);
+            // Note(unsafe): The MAC is owned by the ethernet driver. Only the VLAN tagging and
+            // filtering configuration is modified here, before the MAC is in use.
+            let regs = unsafe { &*hal::stm32::ETHERNET_MAC::ptr() };
+            // Insert the tag from MACVIR.VLT into all transmitted frames (VLC = 0b10). VLC only
+            // takes effect with VLP set.
+            regs.macvir.write(|w| unsafe {
+                w.bits((1 << 18) | (0b10 << 16) | vlan.tci() as u32)
+            });
+            // Compare the 12 bit VLAN identifier (ETV) and always strip the tag (EVLS = 0b11).
+            regs.macvtr.write(|w| unsafe {
+                w.bits((0b11 << 21) | (1 << 16) | (vlan.id & 0xfff) as u32)
+            });
+            // Drop tagged frames failing the VLAN tag filter (VTFE).
+            regs.macpfr
+                .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 16)) });
+        }
+
         // Reset and initialize the ethernet phy.
         let mut lan8742a =
             ethernet::phy::LAN8742A::new(eth_mac.set_phy_addr(0));
@@ -710,7 +865,7 @@ where
         let random_seed = {
             let mut rng =
                 device.RNG.constrain(ccdr.peripheral.RNG, &ccdr.clocks);
-            let mut data = [0u8; 8];
+            let mut data = [0u8; 16];
             rng.fill(&mut data).unwrap();
             data
         };
@@ -723,9 +878,10 @@ where
         store.ip_addrs[0] = smoltcp::wire::IpCidr::new(ip_addrs, 24);
 
         let mut ethernet_config = smoltcp::iface::Config::new(
-            smoltcp::wire::HardwareAddress::Ethernet(mac_addr),
+            smoltcp::wire::HardwareAddress::Ethernet(eth_addr),
         );
-        ethernet_config.random_seed = u64::from_be_bytes(random_seed);
+        ethernet_config.random_seed =
+            u64::from_be_bytes(random_seed[..8].try_into().unwrap());
 
         let mut interface = smoltcp::iface::Interface::new(
             ethernet_config,
@@ -746,6 +902,15 @@ where
             }
         });
 
+        // The keep-alive configuration persists across connections of a socket.
+        let keepalive = settings
+            .net()
+            .tcp_keepalive
+            .filter(|interval| *interval > 0.0)
+            .map(|interval| {
+                smoltcp::time::Duration::from_millis((interval * 1e3) as u64)
+            });
+
         let mut sockets =
             smoltcp::iface::SocketSet::new(&mut store.sockets[..]);
         for storage in store.tcp_socket_storage[..].iter_mut() {
@@ -757,7 +922,11 @@ where
                     &mut storage.tx_storage[..],
                 );
 
-                smoltcp::socket::tcp::Socket::new(rx_buffer, tx_buffer)
+                let mut socket =
+                    smoltcp::socket::tcp::Socket::new(rx_buffer, tx_buffer);
+                socket.set_keep_alive(keepalive);
+                socket.set_timeout(keepalive.map(|interval| interval * 3));
+                socket
             };
 
             sockets.add(tcp_socket);
@@ -789,15 +958,24 @@ where
             sockets.add(udp_socket);
         }
 
+        let device = dscp::DscpMarker::new(
+            bridge::Bridge::new(eth_dma, ecm_device),
+            settings.net().dscp,
+        );
         let mut stack =
-            smoltcp_nal::NetworkStack::new(interface, eth_dma, sockets, clock);
+            smoltcp_nal::NetworkStack::new(interface, device, sockets, clock);
 
-        stack.seed_random_port(&random_seed);
+        stack.seed_random_port(&random_seed[..8]);
 
         NetworkDevices {
             stack,
             phy: lan8742a,
-            mac_address: mac_addr,
+            mac_address: eth_addr,
+            nonce: u64::from_be_bytes(random_seed[8..].try_into().unwrap()),
+            provision: !safe_boot
+                && !persisted
+                && !settings.net().provisioning.is_empty(),
+            safe_boot,
         }
     };
 
@@ -905,13 +1083,16 @@ where
         let aux_adc0 = adc3.create_channel(gpiof.pf3.into_analog());
         let aux_adc1 = adc3.create_channel(gpiof.pf4.into_analog());
 
+        // A failure of the Pounder I2C peripherals does not prevent operation of Stabilizer and
+        // the DDS.
         let pounder_devices = pounder::PounderDevices::new(
             i2c1.acquire_i2c(),
             spi,
             (pwr0, pwr1),
             (aux_adc0, aux_adc1),
         )
-        .unwrap();
+        .inspect_err(|e| log::error!("Failed to initialize Pounder: {e:?}"))
+        .ok();
 
         let ad9959 = {
             let qspi = {
@@ -1120,11 +1301,20 @@ where
     let eem = if !is_floating.finish() {
         log::info!("EEM population variant 'Gpio' detected");
         force_eem_source.set_low();
+        let pwm_timer =
+            device
+                .TIM4
+                .timer(1.kHz(), ccdr.peripheral.TIM4, &ccdr.clocks);
         Eem::Gpio(Gpio {
             lvds4: gpiod.pd1.into_floating_input(),
             lvds5: gpiod.pd2.into_floating_input(),
-            lvds6: gpiod.pd3.into_push_pull_output(),
-            lvds7: gpiod.pd4.into_push_pull_output(),
+            pwm: pwm::Pwm::new(
+                pwm_timer,
+                [
+                    gpiod.pd3.into_push_pull_output().erase(),
+                    gpiod.pd4.into_push_pull_output().erase(),
+                ],
+            ),
         })
     } else {
         log::info!("EEM population variant 'Urukul' detected");
@@ -1177,64 +1367,6 @@ where
         }
     };
 
-    let (usb_device, usb_serial) = {
-        let _usb_id = gpioa.pa10.into_alternate::<10>();
-        let usb_n = gpioa.pa11.into_alternate();
-        let usb_p = gpioa.pa12.into_alternate();
-        let usb = hal::usb_hs::USB2::new(
-            device.OTG2_HS_GLOBAL,
-            device.OTG2_HS_DEVICE,
-            device.OTG2_HS_PWRCLK,
-            usb_n,
-            usb_p,
-            ccdr.peripheral.USB2OTG,
-            &ccdr.clocks,
-        );
-
-        let endpoint_memory =
-            cortex_m::singleton!(: Option<&'static mut [u32]> = None).unwrap();
-        endpoint_memory.replace(
-            &mut cortex_m::singleton!(: [u32; 1024] = [0; 1024]).unwrap()[..],
-        );
-        let usb_bus = cortex_m::singleton!(
-            : usb_device::bus::UsbBusAllocator<super::UsbBus> = hal::usb_hs::UsbBus::new(
-            usb,
-            endpoint_memory.take().unwrap(),
-        ))
-        .unwrap();
-
-        let read_store = cortex_m::singleton!(: [u8; 128] = [0; 128]).unwrap();
-        let write_store =
-            cortex_m::singleton!(: [u8; 1024] = [0; 1024]).unwrap();
-        let serial = usbd_serial::SerialPort::new_with_store(
-            usb_bus,
-            &mut read_store[..],
-            &mut write_store[..],
-        );
-
-        // Generate a device serial number from the MAC address.
-        let serial_number = cortex_m::singleton!(: String<17> = {
-            let mut s = String::new();
-            write!(s, "{mac_addr}").unwrap();
-            s
-        })
-        .unwrap();
-
-        let usb_device = usb_device::device::UsbDeviceBuilder::new(
-            usb_bus,
-            usb_device::device::UsbVidPid(0x1209, 0x392F),
-        )
-        .strings(&[usb_device::device::StringDescriptors::default()
-            .manufacturer("ARTIQ/Sinara")
-            .product("Stabilizer")
-            .serial_number(serial_number)])
-        .unwrap()
-        .device_class(usbd_serial::USB_CLASS_CDC)
-        .build();
-
-        (usb_device, serial)
-    };
-
     let usb_serial = {
         let input_buffer =
             cortex_m::singleton!(: [u8; 128] = [0u8; 128]).unwrap();
@@ -1248,6 +1380,9 @@ where
                 ),
                 storage: flash,
                 metadata,
+                counters,
+                uptime: Default::default(),
+                logger: &super::LOGGER,
                 _settings_marker: core::marker::PhantomData,
             },
             input_buffer,
@@ -1263,15 +1398,18 @@ where
         dacs,
         temperature_sensor,
         usb_serial,
+        usb_ethernet,
         input_stamper,
         network_devices,
         sampling_timer,
         timestamp_timer,
         digital_inputs,
+        aux_adc,
         usb: usb_device,
         fp_led,
         metadata,
         settings,
+        calibration,
     };
 
     log::info!("setup() complete");
@@ -142,6 +142,12 @@ macro_rules! timer_channels {
                     self.timer.resume();
                 }
 
+                /// Stop the timer.
+                #[allow(dead_code)]
+                pub fn pause(&mut self) {
+                    self.timer.pause();
+                }
+
                 /// Configure the timer peripheral to generate a trigger based on the provided
                 /// source.
                 #[allow(dead_code)]
@@ -393,6 +399,41 @@ macro_rules! timer_channels {
 }
 
 timer_channels!(SamplingTimer, TIM2, u32);
+
+impl SamplingTimer {
+    /// The maximum channel skew in timer ticks for the current period.
+    ///
+    /// The delayed ADC1 transfer must complete before the next period begins.
+    pub fn max_skew(&self) -> u32 {
+        (self.get_period() + 1)
+            .saturating_sub(3 + crate::design_parameters::ADC_TRANSFER_TICKS)
+    }
+
+    /// Delay the ADC1 and DAC1 conversion instants relative to ADC0 and DAC0.
+    ///
+    /// # Note
+    /// The compare values are preloaded and take effect at the next update event such that no
+    /// conversion trigger is lost or duplicated.
+    ///
+    /// # Args
+    /// * `skew` - The delay in timer ticks. It is limited to [SamplingTimer::max_skew].
+    ///
+    /// # Returns
+    /// The applied delay.
+    pub fn set_skew(&mut self, skew: u32) -> u32 {
+        let skew = skew.min(self.max_skew());
+        // Note(unsafe): Only the compare values and preload bits of the ADC1 (channel 2) and DAC1
+        // (channel 4) trigger channels are modified. Their DMA request configuration is owned by
+        // the ADC and DAC and remains untouched. The nominal compare values are those of the
+        // `adc` and `dac` modules.
+        let regs = unsafe { &*hal::stm32::TIM2::ptr() };
+        regs.ccmr1_output().modify(|_, w| w.oc2pe().set_bit());
+        regs.ccmr2_output().modify(|_, w| w.oc4pe().set_bit());
+        regs.ccr[1].write(|w| w.ccr().bits(3 + skew));
+        regs.ccr[3].write(|w| w.ccr().bits(5 + skew));
+        skew
+    }
+}
 timer_channels!(ShadowSamplingTimer, TIM3, u16);
 
 timer_channels!(TimestampTimer, TIM5, u32);
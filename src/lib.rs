@@ -9,3 +9,17 @@ pub mod hardware;
 pub mod telemetry;
 
 pub mod convert;
+
+pub mod dsp;
+
+pub mod timebase;
+
+pub mod pounder_config;
+
+pub mod aux_adc;
+
+pub mod faults;
+
+pub mod calibration;
+
+pub mod schedule;
@@ -0,0 +1,659 @@
+//! Pounder RF configuration
+//!
+//! # Design
+//! The configuration covers the four AD9959 DDS channels of Pounder: the two output channels and
+//! the two mixer (local oscillator) channels of the inputs, together with the respective digital
+//! attenuators. It is applied by [crate::hardware::pounder::control::PounderControl].
+//!
+//! The output level of Pounder varies with frequency due to the DDS sinc roll-off and the
+//! filter and amplifier responses. Each output channel has a flatness calibration table of the
+//! measured output level deviation versus frequency. The DDS amplitude is corrected for the
+//! interpolated deviation at the configured frequency whenever the configuration is applied so that
+//! the delivered RF power stays constant across the band.
+//!
+//! The 32 bit frequency tuning word (FTW) of the AD9959 has a resolution of about 0.12 Hz. With
+//! dithering enabled, the fractional part of the FTW is distributed over successive frequency
+//! updates by a first order sigma-delta modulator so that the average frequency resolves steps
+//! below one FTW LSB.
+//!
+//! The frequency of a DDS channel can be scanned along with a signal generator (DAC output) of
+//! Stabilizer. The frequency follows the signal generator output with a fixed slope, e.g. to keep a
+//! double-pass AOM aligned during a scan. The frequency tuning word is updated once per sample
+//! batch.
+//!
+//! A DDS channel can also chirp its frequency repeatedly (see [ChirpConfig]). The frequency
+//! tuning words of the chirp are pre-computed and streamed with the scans, one per sample batch.
+//! The IO_Update pulse of each batch is generated by the high resolution timer at a fixed delay
+//! after the batch so that the chirps are repeatable with microsecond timing.
+//!
+//! The AD9959 linear sweep engine ramps the frequency of a DDS channel in hardware (see
+//! [DdsSweep]), e.g. for AOM chirps, without the latency of a profile update per step. The sweep
+//! direction follows the DDS profile pin of the channel.
+//!
+//! Two-level frequency, phase or amplitude shift keying (see [Modulation]) switches a DDS channel
+//! between its configured state and a second level with the DDS profile pin of the channel. A
+//! sweep and a modulation are exclusive per channel.
+//!
+//! The amplitude of an output channel can be servoed to hold a measured signal constant (see
+//! [AmplitudeLock]), e.g. to stabilize the RF power of an injection lock or the diffraction
+//! efficiency of an AOM. Alternatively a Pounder auxiliary ADC input can modulate the amplitude
+//! (see [AuxModulation]) as a simple analog intensity control input.
+//!
+//! For fast intensity stabilization through the AOM RF amplitude, the processing output of a
+//! Stabilizer channel can drive the DDS amplitude of an output channel (see [DspAmplitude]). The
+//! amplitude control word is streamed once per sample batch from the processing task along with
+//! the frequency scans.
+//!
+//! The RF power of an output channel can also be leveled with its attenuator (see [PowerServo])
+//! using the input detectors, leaving the DDS amplitude untouched.
+//!
+//! For maintenance, the DDS management (see [RegisterConfig]) can be paused to access raw AD9959
+//! registers not modeled by the configuration.
+//!
+//! The rate of DDS frequency changes can be limited (see
+//! [crate::hardware::pounder::ftw::FtwSlew]) so that resonant RF components downstream are not
+//! shocked by frequency jumps.
+//!
+//! The phases of all DDS channels can be synchronized on request by restarting their phase
+//! accumulators simultaneously, e.g. for interferometric setups.
+//!
+//! The Pounder timestamps of the sample batches in DDS clock ticks track the phase of the DDS
+//! clock relative to the sampling clock (see
+//! [crate::hardware::pounder::sync_phase::SyncPhase]).
+//!
+//! A scalar network analysis of the RF chain between an output and an input of Pounder steps the
+//! output frequency and measures the input power with the AD8363 detector at each point (see
+//! [BodeConfig]).
+use heapless::Vec;
+use miniconf::Tree;
+use num_traits::Float;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of flatness calibration points per channel
+pub const FLATNESS_POINTS: usize = 16;
+
+/// Maximum number of frequency response points
+pub const BODE_POINTS: usize = 64;
+
+/// DDS channel configuration
+#[derive(Copy, Clone, Debug, Tree)]
+#[tree(meta(doc, typename))]
+pub struct DdsChannelConfig {
+    /// Enable the channel
+    ///
+    /// A disabled channel is powered down (digital core and DAC) instead of
+    /// outputting zero amplitude, reducing spurs and power dissipation.
+    pub enable: bool,
+    /// Frequency (Hz)
+    pub frequency: f64,
+    /// Phase offset (turns)
+    pub phase_offset: f32,
+    /// Amplitude relative to full scale
+    pub amplitude: f32,
+    /// Dither the frequency tuning word LSB to resolve the fractional FTW on
+    /// average
+    pub dither: bool,
+    /// Linear frequency sweep from `frequency`, `null` to disable
+    ///
+    /// Dithering, scans and the slew limit do not apply to a swept channel.
+    #[tree(with=miniconf::leaf)]
+    pub sweep: Option<DdsSweep>,
+    /// Two-level modulation by the DDS profile pin, `null` to disable
+    #[tree(with=miniconf::leaf)]
+    pub modulation: Option<Modulation>,
+}
+
+impl Default for DdsChannelConfig {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            frequency: 0.0,
+            phase_offset: 0.0,
+            amplitude: 0.0,
+            dither: false,
+            sweep: None,
+            modulation: None,
+        }
+    }
+}
+
+/// DDS two-level modulation
+///
+/// The channel outputs the configured state while the DDS profile pin of the
+/// channel is low and the given level while it is high.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Modulation {
+    /// Frequency shift keying to the given frequency (Hz)
+    Frequency(f64),
+    /// Phase shift keying to the given phase offset (turns)
+    Phase(f32),
+    /// Amplitude shift keying to the given amplitude relative to full scale
+    ///
+    /// The flatness correction applies to both levels.
+    Amplitude(f32),
+}
+
+/// DDS linear frequency sweep
+///
+/// The sweep runs from the channel frequency up to `stop` while the DDS
+/// profile pin of the channel is high and back down while it is low. The
+/// frequency then dwells at the respective end.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct DdsSweep {
+    /// Stop frequency (Hz), above the channel frequency
+    pub stop: f64,
+    /// Frequency step (Hz)
+    pub step: f64,
+    /// Time per step (s), 8 ns to 2.04 µs in steps of 8 ns (4 DDS system
+    /// clock cycles)
+    pub dwell: f32,
+}
+
+/// DDS frequency scan configuration
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ScanConfig {
+    /// Stabilizer channel whose signal generator drives the scan
+    pub source: usize,
+    /// Frequency change per signal generator output voltage (Hz/V)
+    pub slope: f32,
+}
+
+/// DDS frequency chirp configuration
+///
+/// The frequency ramps linearly from the channel frequency by `span` over
+/// `duration` and then returns to the channel frequency for the next chirp.
+/// The frequency is updated once per sample batch.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ChirpConfig {
+    /// Frequency span (Hz), negative for a downward chirp
+    pub span: f64,
+    /// Duration of a chirp (s)
+    pub duration: f32,
+    /// Number of chirps, `0` to repeat continuously
+    pub repeat: u32,
+}
+
+/// RF channel configuration
+#[derive(Copy, Clone, Debug, Tree)]
+#[tree(meta(doc, typename))]
+pub struct ChannelConfig {
+    /// DDS configuration
+    pub dds: DdsChannelConfig,
+    /// Attenuation (dB), 0 to 31.5 in steps of 0.5
+    pub attenuation: f32,
+    /// Scan the DDS frequency with a signal generator.
+    ///
+    /// The frequency is `dds/frequency + slope * source_output`. Dithering is
+    /// not applied to a scanned channel. `null` to disable.
+    #[tree(with=miniconf::leaf)]
+    pub scan: Option<ScanConfig>,
+    /// Chirp the DDS frequency.
+    ///
+    /// The chirps start whenever the configuration is applied. After the last
+    /// chirp the frequency returns to `dds/frequency`. Dithering and the slew
+    /// limit are not applied. Exclusive with a scan and a sweep. `null` to
+    /// disable.
+    #[tree(with=miniconf::leaf)]
+    pub chirp: Option<ChirpConfig>,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            dds: Default::default(),
+            attenuation: 31.5,
+            scan: None,
+            chirp: None,
+        }
+    }
+}
+
+/// RF power servo
+///
+/// An integral controller running in a slow task (every 10 ms) trims the
+/// attenuation of an output channel such that the power measured by the
+/// detector of an input channel (e.g. with a coupler on the output) equals the
+/// setpoint. It starts from the configured attenuation. The attenuator
+/// resolution is 0.5 dB. Invalid measurements hold the attenuation.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct PowerServo {
+    /// Input channel whose detector measures the power (0 or 1)
+    pub input: usize,
+    /// Power setpoint (dBm)
+    pub setpoint: f32,
+    /// Integral gain: attenuation change per second per power error (dB/s/dB)
+    pub gain: f32,
+}
+
+impl PowerServo {
+    /// Update the controlled attenuation.
+    ///
+    /// # Args
+    /// * `attenuation` - The current attenuation (dB).
+    /// * `power` - The measured power (dBm).
+    /// * `period` - The update period in seconds.
+    ///
+    /// # Returns
+    /// The new attenuation.
+    pub fn update(&self, attenuation: f32, power: f32, period: f32) -> f32 {
+        if !power.is_finite() {
+            return attenuation;
+        }
+        (attenuation + self.gain * (power - self.setpoint) * period)
+            .clamp(0.0, 31.5)
+    }
+}
+
+/// Amplitude lock measurement
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockInput {
+    /// Pounder input detector power of the input channel with the given index
+    /// (dBm)
+    Power(usize),
+    /// Stabilizer ADC input with the given index, e.g. a demodulated signal
+    /// (V at the AFE input)
+    Adc(usize),
+}
+
+/// Amplitude-locked loop
+///
+/// An integral controller running in a slow task (every 10 ms) adjusts the DDS
+/// amplitude of an output channel such that the measurement equals the
+/// setpoint. It starts from the configured amplitude. The flatness correction
+/// applies to the controlled amplitude. Invalid measurements hold the
+/// amplitude.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct AmplitudeLock {
+    /// Measured signal
+    pub input: LockInput,
+    /// Measurement setpoint (dBm or V)
+    pub setpoint: f32,
+    /// Integral gain: amplitude change per second per unit measurement
+    /// error. The sign must match the response of the measurement to the
+    /// amplitude.
+    pub gain: f32,
+    /// Minimum amplitude relative to full scale
+    pub min: f32,
+    /// Maximum amplitude relative to full scale
+    pub max: f32,
+}
+
+impl AmplitudeLock {
+    /// Update the controlled amplitude.
+    ///
+    /// # Args
+    /// * `amplitude` - The current amplitude.
+    /// * `measurement` - The measured signal.
+    /// * `period` - The update period in seconds.
+    ///
+    /// # Returns
+    /// The new amplitude.
+    pub fn update(&self, amplitude: f32, measurement: f32, period: f32) -> f32 {
+        if !measurement.is_finite() {
+            return amplitude;
+        }
+        (amplitude + self.gain * (self.setpoint - measurement) * period)
+            .clamp(self.min.max(0.0), self.max.min(1.0))
+    }
+}
+
+/// Amplitude modulation by a Pounder auxiliary ADC input
+///
+/// The amplitude of an output channel is `amplitude + gain * input` with the
+/// low-pass filtered input voltage, limited to full scale. It is updated in a
+/// slow task (every 10 ms). An amplitude lock of the channel takes precedence.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct AuxModulation {
+    /// Pounder input channel whose auxiliary ADC input modulates (0 or 1)
+    pub input: usize,
+    /// Amplitude change per input voltage (1/V)
+    pub gain: f32,
+    /// Input low-pass bandwidth (Hz), limited by the 10 ms update period
+    pub bandwidth: f32,
+}
+
+/// DDS amplitude control by the processing output of a Stabilizer channel
+///
+/// The amplitude of an output channel is `amplitude + gain * output` with the
+/// processing output voltage at the end of each sample batch, limited to full
+/// scale. It is written to the DDS once per batch. The flatness correction at
+/// the channel frequency applies. While the DAC outputs are held in the
+/// failsafe state, the amplitude is `amplitude`. Exclusive with an amplitude
+/// lock, an auxiliary ADC modulation and amplitude shift keying.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct DspAmplitude {
+    /// Stabilizer channel whose processing output drives the amplitude
+    pub source: usize,
+    /// Amplitude change per output voltage (1/V)
+    pub gain: f32,
+    /// Also drive the DAC with the processing output, else hold it at 0 V
+    pub dac: bool,
+}
+
+impl AuxModulation {
+    /// Update the filtered input and compute the amplitude.
+    ///
+    /// # Args
+    /// * `filtered` - The filtered input (V), `None` before the first valid input.
+    /// * `amplitude` - The unmodulated amplitude.
+    /// * `input` - The input voltage. Invalid inputs hold the filtered input.
+    /// * `period` - The update period in seconds.
+    ///
+    /// # Returns
+    /// The modulated amplitude.
+    pub fn update(
+        &self,
+        filtered: &mut Option<f32>,
+        amplitude: f32,
+        input: f32,
+        period: f32,
+    ) -> f32 {
+        if input.is_finite() {
+            let k = 1.0
+                - (-core::f32::consts::TAU * self.bandwidth.max(0.0) * period)
+                    .exp();
+            *filtered = Some(match *filtered {
+                Some(y) => y + k * (input - y),
+                None => input,
+            });
+        }
+        (amplitude + self.gain * filtered.unwrap_or(0.0)).clamp(0.0, 1.0)
+    }
+}
+
+/// Output flatness calibration table
+///
+/// Points are `[frequency (Hz), level deviation (dB)]` with strictly increasing frequencies. The
+/// deviation is linearly interpolated between points and held constant beyond the first and the
+/// last point. An empty table applies no correction.
+///
+/// The attenuator calibration tables use the same format with the attenuation error (dB) as the
+/// deviation.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Flatness(pub Vec<[f32; 2], FLATNESS_POINTS>);
+
+impl Flatness {
+    /// Whether the points are finite and their frequencies strictly increasing.
+    pub fn is_valid(&self) -> bool {
+        self.0.iter().flatten().all(|x| x.is_finite())
+            && self.0.windows(2).all(|w| w[0][0] < w[1][0])
+    }
+
+    /// Get the output level deviation at a frequency.
+    ///
+    /// # Args
+    /// * `frequency` - The output frequency in Hz.
+    ///
+    /// # Returns
+    /// The interpolated level deviation in dB.
+    pub fn deviation(&self, frequency: f32) -> f32 {
+        let points = &self.0;
+        let i = points.partition_point(|[f, _]| *f < frequency);
+        match (points.get(i.wrapping_sub(1)), points.get(i)) {
+            (None, None) => 0.0,
+            (Some([_, l]), None) | (None, Some([_, l])) => *l,
+            (Some([f0, l0]), Some([f1, l1])) => {
+                l0 + (l1 - l0) * (frequency - f0) / (f1 - f0)
+            }
+        }
+    }
+
+    /// Correct a DDS amplitude for the output level deviation.
+    ///
+    /// # Args
+    /// * `frequency` - The output frequency in Hz.
+    /// * `amplitude` - The desired amplitude relative to full scale.
+    ///
+    /// # Returns
+    /// The corrected amplitude. It is limited to full scale.
+    pub fn correct(&self, frequency: f32, amplitude: f32) -> f32 {
+        let amplitude =
+            amplitude * 10f32.powf(-self.deviation(frequency) / 20.0);
+        if amplitude > 1.0 {
+            log::warn!("Flatness correction limited to full scale");
+        }
+        amplitude.min(1.0)
+    }
+}
+
+/// Frequency response measurement
+///
+/// The output channel is set to each frequency with the configured amplitude and attenuation. After
+/// the settling time the power at the input channel is measured. The response is published as
+/// `[frequency (Hz), power (dBm)]` pairs on `/bode` and the configuration is restored. Dithering of
+/// the output channel frequency should be disabled during the measurement.
+#[derive(Clone, Debug, Tree)]
+#[tree(meta(doc, typename))]
+pub struct BodeConfig {
+    /// Stimulus output channel (0 or 1)
+    pub output: usize,
+    /// Response input channel (0 or 1)
+    pub input: usize,
+    /// Start frequency (Hz)
+    pub start: f64,
+    /// Stop frequency (Hz)
+    pub stop: f64,
+    /// Number of points (at most 64)
+    pub points: usize,
+    /// Logarithmic frequency spacing
+    pub log: bool,
+    /// Settling time before each measurement (ms)
+    pub settle: u32,
+    /// Start a measurement
+    pub run: bool,
+}
+
+impl Default for BodeConfig {
+    fn default() -> Self {
+        Self {
+            output: 0,
+            input: 0,
+            start: 10e6,
+            stop: 200e6,
+            points: 20,
+            log: false,
+            settle: 10,
+            run: false,
+        }
+    }
+}
+
+impl BodeConfig {
+    /// The measurement frequencies in Hz.
+    pub fn frequencies(&self) -> impl Iterator<Item = f64> + '_ {
+        let n = self.points.min(BODE_POINTS);
+        let step = 1.0 / (n.max(2) - 1) as f64;
+        (0..n).map(move |i| {
+            let x = i as f64 * step;
+            if self.log {
+                self.start * (self.stop / self.start).powf(x)
+            } else {
+                self.start + (self.stop - self.start) * x
+            }
+        })
+    }
+}
+
+/// Raw DDS register access
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RegisterAccess {
+    /// AD9959 register address. The channel select register (0) can not be
+    /// written.
+    pub address: u8,
+    /// DDS channel mask for channel registers (bit 0: OUT0, 1: IN0,
+    /// 2: OUT1, 3: IN1)
+    pub channels: u8,
+    /// Register contents to write, most significant byte first. The length
+    /// must match the register width. `null` to read.
+    pub data: Option<Vec<u8, 4>>,
+}
+
+/// Raw DDS register access response
+#[derive(Clone, Debug, Serialize)]
+pub struct RegisterResponse {
+    /// AD9959 register address
+    pub address: u8,
+    /// DDS channel mask
+    pub channels: u8,
+    /// Register contents read or written, `null` on failure
+    pub data: Option<Vec<u8, 4>>,
+}
+
+/// Raw DDS register access configuration
+///
+/// In maintenance mode the DDS profile updates are paused: the channel configuration, dithering,
+/// scans and the amplitude locks do not reach the DDS. A request is executed once when settings
+/// are applied and reset to `null`. The response is published on `/dds/register`. The channel
+/// configuration is restored when maintenance mode ends.
+#[derive(Clone, Debug, Tree, Default)]
+#[tree(meta(doc, typename))]
+pub struct RegisterConfig {
+    /// Maintenance mode
+    pub maintenance: bool,
+    /// Register access request, only executed in maintenance mode
+    #[tree(with=miniconf::leaf)]
+    pub request: Option<RegisterAccess>,
+}
+
+/// Pounder LED status indication
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LedStatus {
+    /// The DDS configuration was applied
+    Dds,
+    /// The external reference clock is selected
+    ExternalClock,
+    /// All attenuations are programmed and no ramp is in progress
+    Attenuation,
+}
+
+/// Pounder RF configuration
+#[derive(Clone, Debug, Tree, Default)]
+#[tree(meta(doc, typename))]
+pub struct PounderConfig {
+    /// Input channel (mixer) configuration
+    pub in_channel: [ChannelConfig; 2],
+    /// Output channel configuration
+    pub out_channel: [ChannelConfig; 2],
+    /// Output channel flatness calibration
+    #[tree(with=miniconf::leaf)]
+    pub flatness: [Flatness; 2],
+    /// Attenuator calibration of the channels in the order IN0, IN1, OUT0, OUT1
+    ///
+    /// Points are `[frequency (Hz), attenuation error (dB)]`, the error being
+    /// the actual minus the nominal attenuation at the DDS frequency of the
+    /// channel. The attenuators are programmed to compensate for the error
+    /// within their range.
+    #[tree(with=miniconf::leaf)]
+    pub attenuator_calibration: [Flatness; 4],
+    /// Frequency response measurement
+    pub bode: BodeConfig,
+    /// Output channel amplitude locks, `null` to disable
+    #[tree(with=miniconf::leaf)]
+    pub amplitude_lock: [Option<AmplitudeLock>; 2],
+    /// Output channel RF power servos trimming the attenuators, `null` to
+    /// disable
+    #[tree(with=miniconf::leaf)]
+    pub power_servo: [Option<PowerServo>; 2],
+    /// Output channel amplitude modulation by the auxiliary ADC inputs, `null`
+    /// to disable
+    #[tree(with=miniconf::leaf)]
+    pub aux_modulation: [Option<AuxModulation>; 2],
+    /// Output channel amplitude control by the processing outputs, `null` to
+    /// disable
+    #[tree(with=miniconf::leaf)]
+    pub dsp_amplitude: [Option<DspAmplitude>; 2],
+    /// Raw DDS register access
+    pub register: RegisterConfig,
+    /// Maximum DDS frequency slew rate (Hz/ms), `null` for unlimited
+    ///
+    /// Applies to configuration changes and scans, not to dithering and the
+    /// frequency response measurement.
+    #[tree(with=miniconf::leaf)]
+    pub slew: Option<f32>,
+    /// Front LED (LED4 to LED9) states
+    #[tree(with=miniconf::leaf)]
+    pub leds: [bool; 6],
+    /// Front LED status indications overriding `leds`, `null` for the
+    /// configured state
+    #[tree(with=miniconf::leaf)]
+    pub led_status: [Option<LedStatus>; 6],
+    /// Power down the input power detectors
+    ///
+    /// The input power readings of powered down detectors are `null`.
+    pub detector_power_down: [bool; 2],
+    /// Attenuation ramp duration (ms), `0` to step
+    ///
+    /// Attenuation changes are ramped to the target in 0.5 dB steps over the
+    /// duration to avoid transients downstream. Servoed outputs are not
+    /// ramped.
+    pub attenuation_ramp: u32,
+    /// Over-temperature alarm threshold of the board temperature (°C), `null`
+    /// to disable
+    ///
+    /// Above the threshold, all DDS channels are powered down and all
+    /// attenuators set to maximum attenuation. The alarm latches until
+    /// cleared and is reported as `over_temperature` in the `/pounder`
+    /// telemetry.
+    #[tree(with=miniconf::leaf)]
+    pub over_temperature: Option<f32>,
+    /// Clear a latched over-temperature alarm and restore the configuration
+    ///
+    /// Executed once and reset to `false`. The alarm latches again if the
+    /// temperature is still above the threshold.
+    pub clear_alarm: bool,
+    /// Verify the DDS profiles by readback
+    ///
+    /// The frequency, phase and amplitude registers are read back after the
+    /// configuration is applied. Mismatches are counted in `dds_mismatches`
+    /// of the `/pounder` telemetry.
+    pub verify: bool,
+    /// Use the external reference clock input
+    ///
+    /// The reference must have the frequency of the on-board oscillator
+    /// (100 MHz). Its presence is verified through the Pounder timestamps
+    /// while sampling. If it is absent, the on-board oscillator is selected
+    /// again until this is changed.
+    pub external_clock: bool,
+    /// Synchronize the DDS channel phases
+    ///
+    /// The phase accumulators of all channels are restarted simultaneously
+    /// after the configuration is applied. The relative phases are then the
+    /// differences of the phase offsets. Executed once and reset to `false`.
+    pub sync: bool,
+}
+
+impl PounderConfig {
+    /// The channel configurations in DDS channel order: IN0, IN1, OUT0, OUT1.
+    pub fn channels(&self) -> [&ChannelConfig; 4] {
+        [
+            &self.in_channel[0],
+            &self.in_channel[1],
+            &self.out_channel[0],
+            &self.out_channel[1],
+        ]
+    }
+
+    /// Take the one-shot requests, resetting them in the configuration.
+    pub fn take_requests(&mut self) -> PounderRequests {
+        PounderRequests {
+            clear_alarm: core::mem::take(&mut self.clear_alarm),
+            sync: core::mem::take(&mut self.sync),
+            bode: core::mem::take(&mut self.bode.run),
+            register: self.register.request.take(),
+        }
+    }
+}
+
+/// One-shot Pounder requests taken from the configuration
+#[derive(Clone, Debug, Default)]
+pub struct PounderRequests {
+    /// Clear a latched over-temperature alarm
+    pub clear_alarm: bool,
+    /// Synchronize the DDS channel phases
+    pub sync: bool,
+    /// Start a frequency response measurement
+    pub bode: bool,
+    /// Raw DDS register access
+    pub register: Option<RegisterAccess>,
+}
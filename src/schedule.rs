@@ -0,0 +1,135 @@
+//! Scheduled maintenance actions
+//!
+//! # Design
+//! Long-running unattended systems periodically run maintenance actions, e.g. an input offset
+//! calibration. A [Scheduler] runs the actions of a list of [Entry]s at fixed intervals or daily at
+//! a time of day. It is updated from a slow task. Actions are run by the application.
+//!
+//! The device has no wall clock. The current time of day is provided by the host and then kept
+//! with the local timebase. Daily entries are inactive until the time of day is known. Interval
+//! entries first run one interval after they were configured.
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of schedule entries
+pub const SCHEDULE_ENTRIES: usize = 8;
+
+/// Milliseconds per day
+const DAY: u64 = 86_400_000;
+
+/// When an action is run
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trigger {
+    /// At a fixed interval (s)
+    Every(u32),
+    /// Daily at a time of day (s since midnight)
+    Daily(u32),
+}
+
+/// A scheduled action
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry<A> {
+    /// The action to run
+    pub action: A,
+    /// When to run the action
+    pub trigger: Trigger,
+}
+
+/// Maintenance action scheduler.
+#[derive(Clone, Debug)]
+pub struct Scheduler<A> {
+    entries: Vec<Entry<A>, SCHEDULE_ENTRIES>,
+    /// Time when the entries are due next, `None` if inactive (ms since the first update)
+    due: [Option<u64>; SCHEDULE_ENTRIES],
+    /// Time since the first update (ms)
+    uptime: u64,
+    /// Time stamp of the last update (ms)
+    last: Option<u32>,
+    /// Time of day at the start of the uptime (ms)
+    midnight: Option<u64>,
+}
+
+impl<A> Default for Scheduler<A> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            due: [None; SCHEDULE_ENTRIES],
+            uptime: 0,
+            last: None,
+            midnight: None,
+        }
+    }
+}
+
+impl<A: Copy + PartialEq> Scheduler<A> {
+    /// Set the current time of day.
+    ///
+    /// # Args
+    /// * `time` - The time of day in seconds since midnight.
+    pub fn set_time(&mut self, time: u32) {
+        let time = (time as u64 * 1000) % DAY;
+        self.midnight = Some((time + DAY - self.uptime % DAY) % DAY);
+        for (entry, due) in self.entries.iter().zip(self.due.iter_mut()) {
+            if matches!(entry.trigger, Trigger::Daily(_)) {
+                *due = None;
+            }
+        }
+    }
+
+    /// The current time of day in seconds since midnight, `None` if unknown.
+    pub fn time(&self) -> Option<u32> {
+        self.midnight
+            .map(|midnight| ((midnight + self.uptime) % DAY / 1000) as u32)
+    }
+
+    fn next(&self, trigger: Trigger) -> Option<u64> {
+        match trigger {
+            Trigger::Every(interval) => {
+                Some(self.uptime + interval.max(1) as u64 * 1000)
+            }
+            Trigger::Daily(at) => self.midnight.map(|midnight| {
+                let now = (midnight + self.uptime) % DAY;
+                let until = (at as u64 * 1000 % DAY + DAY - now) % DAY;
+                self.uptime + if until == 0 { DAY } else { until }
+            }),
+        }
+    }
+
+    /// Update the schedule and run the due actions.
+    ///
+    /// # Note
+    /// This should be called regularly, at least once per 49 days. A changed schedule restarts.
+    ///
+    /// # Args
+    /// * `entries` - The schedule. Entries beyond [SCHEDULE_ENTRIES] are ignored.
+    /// * `now` - The current wrapping timestamp in milliseconds.
+    /// * `run` - Called with each due action.
+    pub fn update(
+        &mut self,
+        entries: &[Entry<A>],
+        now: u32,
+        mut run: impl FnMut(A),
+    ) {
+        if let Some(last) = self.last.replace(now) {
+            self.uptime += now.wrapping_sub(last) as u64;
+        }
+        let entries = &entries[..entries.len().min(SCHEDULE_ENTRIES)];
+        if self.entries != entries {
+            self.entries.clear();
+            // Note(unwrap): The entries are truncated to the capacity.
+            self.entries.extend_from_slice(entries).unwrap();
+            self.due = [None; SCHEDULE_ENTRIES];
+        }
+        for i in 0..self.entries.len() {
+            let entry = self.entries[i];
+            match self.due[i] {
+                Some(due) if due <= self.uptime => {
+                    run(entry.action);
+                    self.due[i] = self.next(entry.trigger);
+                }
+                Some(_) => {}
+                None => self.due[i] = self.next(entry.trigger),
+            }
+        }
+    }
+}
@@ -1,5 +1,157 @@
 use crate::convert::{AdcCode, DacCode, Gain};
-use serde::Serialize;
+use crate::design_parameters::SYSCLK;
+use crate::dsp::Rms;
+use crate::faults::Faults;
+use serde::{Deserialize, Serialize};
+
+/// Minimum and maximum of a measured duration in CPU cycles.
+#[derive(Copy, Clone)]
+pub struct Latency {
+    min: u32,
+    max: u32,
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self {
+            min: u32::MAX,
+            max: 0,
+        }
+    }
+}
+
+impl Latency {
+    /// Record a duration.
+    ///
+    /// # Args
+    /// * `cycles` - The duration in CPU cycles.
+    pub fn update(&mut self, cycles: u32) {
+        self.min = self.min.min(cycles);
+        self.max = self.max.max(cycles);
+    }
+
+    /// Get the minimum and maximum in seconds or zero if no duration was recorded.
+    fn seconds(&self) -> [f32; 2] {
+        if self.min > self.max {
+            return [0.0; 2];
+        }
+        let period = 1.0 / SYSCLK.to_Hz() as f32;
+        [self.min as f32 * period, self.max as f32 * period]
+    }
+}
+
+/// Fraction of samples meeting a condition.
+#[derive(Copy, Clone, Default)]
+pub struct Occupancy {
+    hits: u32,
+    total: u32,
+}
+
+impl Occupancy {
+    /// Record a sample.
+    ///
+    /// # Args
+    /// * `hit` - The sample meets the condition.
+    pub fn update(&mut self, hit: bool) {
+        self.hits += hit as u32;
+        self.total += 1;
+    }
+
+    /// Get the fraction of samples meeting the condition or `None` if no sample was recorded.
+    fn fraction(&self) -> Option<f32> {
+        (self.total > 0).then(|| self.hits as f32 / self.total as f32)
+    }
+}
+
+/// Physical unit of a scaled telemetry value
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Unit {
+    #[default]
+    V,
+    #[serde(rename = "mV")]
+    MilliVolt,
+    A,
+    #[serde(rename = "mA")]
+    MilliAmpere,
+    #[serde(rename = "µA")]
+    MicroAmpere,
+    W,
+    #[serde(rename = "mW")]
+    MilliWatt,
+    #[serde(rename = "µW")]
+    MicroWatt,
+    #[serde(rename = "nW")]
+    NanoWatt,
+    #[serde(rename = "Hz")]
+    Hertz,
+    #[serde(rename = "kHz")]
+    KiloHertz,
+    #[serde(rename = "MHz")]
+    MegaHertz,
+    #[serde(rename = "Pa")]
+    Pascal,
+    #[serde(rename = "kPa")]
+    KiloPascal,
+    #[serde(rename = "°C")]
+    Celsius,
+    K,
+    #[serde(rename = "m")]
+    Meter,
+    #[serde(rename = "µm")]
+    MicroMeter,
+    #[serde(rename = "nm")]
+    NanoMeter,
+    #[serde(rename = "dB")]
+    Decibel,
+    #[serde(rename = "%")]
+    Percent,
+}
+
+/// Conversion of a signal in volts to user units
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scaling {
+    /// User units per volt
+    pub scale: f32,
+    /// User units at zero volts
+    pub offset: f32,
+    /// The user unit
+    pub unit: Unit,
+}
+
+impl Scaling {
+    /// Convert a signal.
+    ///
+    /// # Args
+    /// * `volts` - The signal in volts.
+    ///
+    /// # Returns
+    /// The signal in user units.
+    pub fn apply(&self, volts: f32) -> Quantity {
+        Quantity {
+            value: volts * self.scale + self.offset,
+            unit: self.unit,
+        }
+    }
+}
+
+/// A value in user units
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Quantity {
+    /// The value
+    pub value: f32,
+    /// The unit of the value
+    pub unit: Unit,
+}
+
+/// Sampled quantities captured in the same sample batch.
+#[derive(Copy, Clone, Default)]
+struct Capture {
+    adcs: [AdcCode; 2],
+    dacs: [DacCode; 2],
+    digital_inputs: [bool; 2],
+}
 
 /// The telemetry buffer is used for storing sample values during execution.
 ///
@@ -7,6 +159,13 @@ use serde::Serialize;
 /// These values can be converted to SI units immediately before reporting to save processing time.
 /// This allows for the DSP process to continually update the values without incurring significant
 /// run-time overhead during conversion to SI units.
+///
+/// # Snapshots
+/// The sampled quantities (ADC and DAC codes, digital inputs) of a telemetry report are captured
+/// together at the same sample instant: the reporting task requests a capture with
+/// [TelemetryBuffer::request], the DSP process latches the quantities of its next batch in
+/// [TelemetryBuffer::update], and [TelemetryBuffer::snapshot] reports the latched values. Slow
+/// peripheral readings should be acquired right after the capture.
 #[derive(Clone, Default)]
 pub struct TelemetryBuffer {
     /// The latest input sample on ADC0/ADC1.
@@ -15,13 +174,27 @@ pub struct TelemetryBuffer {
     pub dacs: [DacCode; 2],
     /// The latest digital input states during processing.
     pub digital_inputs: [bool; 2],
+    /// The latency from ADC batch completion to DAC buffer update.
+    pub latency: Latency,
+    /// The RMS accumulators of ADC0/ADC1.
+    pub rms: [Rms; 2],
+    /// The error deadband occupancy of channel 0/1.
+    pub deadband: [Occupancy; 2],
+    /// The number of batches with a DAC output at the range limits.
+    pub saturation: u32,
+    /// The latest CPU temperature in degrees Celsius.
+    pub cpu_temp: f32,
+    /// A capture is requested.
+    requested: bool,
+    /// The captured quantities.
+    capture: Option<Capture>,
 }
 
 /// The telemetry structure is data that is ultimately reported as telemetry over MQTT.
 ///
 /// # Note
 /// This structure should be generated on-demand by the buffer when required to minimize conversion
-/// overhead.
+/// overhead. The ADC, DAC and digital input values are captured at the same sample instant.
 #[derive(Serialize)]
 pub struct Telemetry {
     /// Most recent input voltage measurement.
@@ -35,27 +208,162 @@ pub struct Telemetry {
 
     /// The CPU temperature in degrees Celsius.
     pub cpu_temp: f32,
+
+    /// Minimum and maximum latency in seconds from ADC batch completion to DAC buffer update
+    /// since the last telemetry report.
+    ///
+    /// # Note
+    /// The total ADC to DAC latency additionally includes the batch acquisition and DAC output
+    /// periods.
+    pub latency: [f32; 2],
+
+    /// RMS input voltage with the mean removed since the last telemetry report.
+    pub rms: [f32; 2],
+
+    /// Most recent input level in dB relative to the logarithmic conversion reference.
+    ///
+    /// `None` if logarithmic conversion is disabled.
+    pub db: [Option<f32>; 2],
+
+    /// The interlock is tripped.
+    pub interlock: bool,
+
+    /// Fraction of time the error was within the deadband since the last telemetry report.
+    ///
+    /// `None` if the deadband is disabled.
+    pub deadband: [Option<f32>; 2],
+
+    /// Latched faults and their counters.
+    pub faults: Faults,
+
+    /// The low-power standby is active: sampling and processing are stopped.
+    pub standby: bool,
+
+    /// Most recent input measurement in user units.
+    ///
+    /// `None` if no scaling is configured.
+    pub scaled_adcs: [Option<Quantity>; 2],
+
+    /// Most recent output in user units.
+    ///
+    /// `None` if no scaling is configured.
+    pub scaled_dacs: [Option<Quantity>; 2],
+}
+
+impl Telemetry {
+    /// Report the inputs and outputs additionally in user units.
+    ///
+    /// # Args
+    /// * `inputs` - The scaling of the input voltages on ADC0/ADC1.
+    /// * `outputs` - The scaling of the output voltages on DAC0/DAC1.
+    pub fn scale(
+        &mut self,
+        inputs: [Option<Scaling>; 2],
+        outputs: [Option<Scaling>; 2],
+    ) {
+        for (scaled, (volts, scaling)) in self
+            .scaled_adcs
+            .iter_mut()
+            .zip(self.adcs.iter().zip(inputs))
+        {
+            *scaled = scaling.map(|s| s.apply(*volts));
+        }
+        for (scaled, (volts, scaling)) in self
+            .scaled_dacs
+            .iter_mut()
+            .zip(self.dacs.iter().zip(outputs))
+        {
+            *scaled = scaling.map(|s| s.apply(*volts));
+        }
+    }
 }
 
 impl TelemetryBuffer {
+    /// Update the sampled quantities.
+    ///
+    /// The quantities are latched if a capture is requested.
+    ///
+    /// # Args
+    /// * `adcs` - The input samples on ADC0/ADC1.
+    /// * `dacs` - The output codes on DAC0/DAC1 for the same sample.
+    /// * `digital_inputs` - The digital input levels during the batch.
+    pub fn update(
+        &mut self,
+        adcs: [AdcCode; 2],
+        dacs: [DacCode; 2],
+        digital_inputs: [bool; 2],
+    ) {
+        self.adcs = adcs;
+        self.dacs = dacs;
+        self.digital_inputs = digital_inputs;
+        if core::mem::replace(&mut self.requested, false) {
+            self.capture = Some(Capture {
+                adcs,
+                dacs,
+                digital_inputs,
+            });
+        }
+    }
+
+    /// Request a capture of the sampled quantities with the next update.
+    pub fn request(&mut self) {
+        self.requested = true;
+        self.capture = None;
+    }
+
+    /// Whether the requested capture is complete.
+    pub fn captured(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Get the current buffer and reset the statistics accumulated over the telemetry interval.
+    ///
+    /// The sampled quantities are those of the last capture. They are the latest ones if the
+    /// capture is not complete.
+    pub fn snapshot(&mut self) -> Self {
+        let mut current = self.clone();
+        if let Some(capture) = self.capture.take() {
+            current.adcs = capture.adcs;
+            current.dacs = capture.dacs;
+            current.digital_inputs = capture.digital_inputs;
+        }
+        self.requested = false;
+        self.latency = Default::default();
+        self.rms = Default::default();
+        self.deadband = Default::default();
+        self.saturation = 0;
+        current
+    }
+
     /// Convert the telemetry buffer to finalized, SI-unit telemetry for reporting.
     ///
     /// # Args
     /// * `afe0` - The current AFE configuration for channel 0.
     /// * `afe1` - The current AFE configuration for channel 1.
-    /// * `cpu_temp` - The current CPU temperature.
     ///
     /// # Returns
     /// The finalized telemetry structure that can be serialized and reported.
-    pub fn finalize(self, afe0: Gain, afe1: Gain, cpu_temp: f32) -> Telemetry {
+    pub fn finalize(self, afe0: Gain, afe1: Gain) -> Telemetry {
         let in0_volts = f32::from(self.adcs[0]) / afe0.gain();
         let in1_volts = f32::from(self.adcs[1]) / afe1.gain();
 
         Telemetry {
-            cpu_temp,
+            cpu_temp: self.cpu_temp,
             adcs: [in0_volts, in1_volts],
             dacs: [self.dacs[0].into(), self.dacs[1].into()],
             digital_inputs: self.digital_inputs,
+            latency: self.latency.seconds(),
+            rms: [
+                self.rms[0].rms() * AdcCode::VOLT_PER_LSB / afe0.gain(),
+                self.rms[1].rms() * AdcCode::VOLT_PER_LSB / afe1.gain(),
+            ],
+            db: [None; 2],
+            interlock: false,
+            deadband: self.deadband.map(|d| d.fraction()),
+            faults: Faults::default(),
+            standby: false,
+            scaled_adcs: [None; 2],
+            scaled_dacs: [None; 2],
         }
     }
 }
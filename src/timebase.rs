@@ -0,0 +1,87 @@
+//! External frequency reference timebase measurement
+//!
+//! # Design
+//! The edges of an external frequency reference are timestamped with the local timer. The
+//! timestamps are accumulated over a measurement interval and compared to the nominal number of
+//! timer ticks per reference period. This yields the fractional frequency offset of the local
+//! timebase relative to the reference.
+//!
+//! The local timebase is not steered. Instead the offset is used to correct frequencies derived
+//! from the local timebase (e.g. signal generators) and is reported so that sample timestamps can
+//! be related to the reference.
+
+/// Frequency offset measurement of the local timebase against an external reference.
+#[derive(Copy, Clone, Debug)]
+pub struct Timebase {
+    /// Nominal number of timer ticks between timestamps
+    period: f32,
+    last: Option<u32>,
+    /// Timer ticks elapsed during the current measurement interval
+    ticks: u64,
+    /// Reference periods elapsed during the current measurement interval
+    periods: u64,
+    offset: Option<f32>,
+}
+
+impl Timebase {
+    /// Construct a timebase measurement.
+    ///
+    /// # Args
+    /// * `period` - The nominal number of timer ticks between timestamps.
+    pub fn new(period: f32) -> Self {
+        Self {
+            period,
+            last: None,
+            ticks: 0,
+            periods: 0,
+            offset: None,
+        }
+    }
+
+    /// Add a timestamp of a reference edge.
+    ///
+    /// # Note
+    /// Timestamps must be provided often enough such that the accumulated frequency offset
+    /// between two timestamps is well below half a reference period. Repeated timestamps are
+    /// ignored.
+    ///
+    /// # Args
+    /// * `timestamp` - The timer counter value at the reference edge.
+    pub fn update(&mut self, timestamp: u32) {
+        if let Some(last) = self.last.replace(timestamp) {
+            let ticks = timestamp.wrapping_sub(last);
+            self.ticks += ticks as u64;
+            self.periods += (ticks as f32 / self.period + 0.5) as u64;
+        }
+    }
+
+    /// Complete the current measurement interval.
+    ///
+    /// # Returns
+    /// The fractional frequency offset of the local timebase relative to the reference.
+    /// Positive if the local timebase is fast. `None` if no reference edges were seen during the
+    /// interval.
+    pub fn finish(&mut self) -> Option<f32> {
+        self.offset = if self.periods > 0 {
+            let nominal = self.periods as f64 * self.period as f64;
+            Some(((self.ticks as f64 - nominal) / nominal) as f32)
+        } else {
+            // Restart without the stale timestamp.
+            self.last = None;
+            None
+        };
+        self.ticks = 0;
+        self.periods = 0;
+        self.offset
+    }
+
+    /// The nominal number of timer ticks between timestamps.
+    pub fn period(&self) -> f32 {
+        self.period
+    }
+
+    /// The fractional frequency offset measured during the last interval.
+    pub fn offset(&self) -> Option<f32> {
+        self.offset
+    }
+}
@@ -0,0 +1,24 @@
+use std::{env, fs, path::Path};
+
+fn main() {
+    // The number of stream frame buffers can be overridden at build time.
+    println!("cargo:rerun-if-env-changed=STABILIZER_STREAM_FRAMES");
+    let frames = env::var("STABILIZER_STREAM_FRAMES").map_or(4, |value| {
+        value.parse().expect("Invalid STABILIZER_STREAM_FRAMES")
+    });
+    // The frame size can be increased for jumbo frames.
+    println!("cargo:rerun-if-env-changed=STABILIZER_STREAM_FRAME_SIZE");
+    let size: usize = env::var("STABILIZER_STREAM_FRAME_SIZE")
+        .map_or(1500 - 40 - 8 - 32 - 20, |value| {
+            value.parse().expect("Invalid STABILIZER_STREAM_FRAME_SIZE")
+        });
+    assert!(
+        (8..=u16::MAX as usize).contains(&size),
+        "STABILIZER_STREAM_FRAME_SIZE out of range"
+    );
+    let out = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out).join("frame_count.rs"), format!("{frames}"))
+        .unwrap();
+    fs::write(Path::new(&out).join("frame_size.rs"), format!("{size}"))
+        .unwrap();
+}
@@ -27,8 +27,9 @@
 
 use core::{fmt::Write, net::SocketAddr};
 use heapless::String;
+use miniconf::Tree;
 use num_enum::IntoPrimitive;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_with::DeserializeFromStr;
 
 /// Represents the destination for the UDP stream to send data to.
@@ -71,9 +72,103 @@ impl core::str::FromStr for Target {
     }
 }
 
+/// The maximum size of each frame in bytes.
+///
+/// The default ensures the resulting ethernet frame is within the MTU:
+/// 1500 MTU - 40 IP6 header - 8 UDP header - 32 VPN - 20 IP4
+///
+/// Set `STABILIZER_STREAM_FRAME_SIZE` at build time to override the default, e.g. for jumbo
+/// frames. The UDP transmit buffer (`STABILIZER_UDP_TX_BUFFER`) must hold at least one frame.
+pub const FRAME_SIZE: usize =
+    include!(concat!(env!("OUT_DIR"), "/frame_size.rs"));
+
+/// The size of the frame header in bytes.
+///
+/// The header has a 16-bit magic word, an 8-bit format, 8-bit batch-size, and 32-bit sequence
+/// number.
+pub const HEADER_SIZE: usize = 8;
+
+/// The number of frames that can be buffered.
+///
+/// Set `STABILIZER_STREAM_FRAMES` at build time to override the default of 4.
+pub const FRAME_COUNT: usize =
+    include!(concat!(env!("OUT_DIR"), "/frame_count.rs"));
+
+/// Stream configuration
+#[derive(Copy, Clone, Debug, Tree, PartialEq, Eq)]
+#[tree(meta(doc, typename))]
+pub struct Config {
+    /// Target IP and port for UDP streaming.
+    ///
+    /// Can be multicast.
+    #[tree(with=miniconf::leaf)]
+    pub target: Target,
+
+    /// Serialization format of the streamed data.
+    ///
+    /// Applications fall back to their default format if the format is not supported.
+    #[tree(with=miniconf::leaf)]
+    pub format: Format,
+
+    /// Maximum number of batches per frame.
+    ///
+    /// `0` packs as many batches as fit into the payload limit.
+    /// Fewer batches per frame reduce the amount of data lost with each dropped packet at the
+    /// cost of more header overhead.
+    pub batches: u8,
+
+    /// Maximum UDP payload size in bytes, including the frame header.
+    ///
+    /// Reduce this for networks with a small MTU (e.g. tunnels). Values outside
+    /// [HEADER_SIZE]..=[FRAME_SIZE] are rejected and the previous limits are kept.
+    pub payload: u16,
+}
+
+impl Config {
+    /// Whether the payload limit is supported by the frame buffers.
+    pub fn is_valid(&self) -> bool {
+        (HEADER_SIZE..=FRAME_SIZE).contains(&(self.payload as usize))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            target: Target::default(),
+            format: Format::AdcDacData,
+            batches: 0,
+            payload: FRAME_SIZE as _,
+        }
+    }
+}
+
+/// Stream transmission statistics.
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct Statistics {
+    /// Current stream target.
+    pub target: Target,
+    /// Whether a socket to the target is currently open.
+    pub connected: bool,
+    /// Whether transmission is paused because the target is unreachable.
+    ///
+    /// While paused, frames are dropped except for occasional probes. Transmission resumes
+    /// once a probe is sent.
+    pub paused: bool,
+    /// Number of frames sent.
+    pub frames: u32,
+    /// Number of payload bytes sent.
+    pub bytes: u64,
+    /// Number of batches dropped because no frame buffer was available.
+    pub dropped: u32,
+    /// Number of frames that could not be sent.
+    pub errors: u32,
+}
+
 /// Specifies the format of streamed data
 #[repr(u8)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, IntoPrimitive)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, IntoPrimitive, Serialize, Deserialize,
+)]
 pub enum Format {
     /// Reserved, unused format specifier.
     Unknown = 0,
@@ -92,6 +187,65 @@ pub enum Format {
 
     /// Thermostat-EEM data. See `thermostat-eem` repo and application.
     ThermostatEem = 3,
+
+    /// ADC0, ADC1, DAC0, and DAC1 sequentially as little-endian `f32` in volts.
+    ///
+    /// The ADC samples are referred to the AFE input, i.e. the AFE gain is applied.
+    ///
+    /// # Example
+    /// With a batch size of 2, the serialization would take the following form:
+    /// ```
+    /// <ADC0[0]> <ADC0[1]> <ADC1[0]> <ADC1[1]> <DAC0[0]> <DAC0[1]> <DAC1[0]> <DAC1[1]>
+    /// ```
+    AdcDacVolts = 4,
+
+    /// Demodulated lockin output at the decimated rate, one batch per lockin output sample.
+    ///
+    /// Each batch consists of the demodulation (LO) frequency, the in-phase, and the quadrature
+    /// component, all `i32` in little-endian format. The frequency is in units of the sample rate
+    /// scaled by `1 << 32`. The in-phase and quadrature components are normalized such that
+    /// `1 << 31` corresponds to ADC full scale.
+    ///
+    /// # Example
+    /// ```
+    /// <frequency> <in-phase> <quadrature>
+    /// ```
+    LockinIq = 5,
+
+    /// Two application-defined internal signals ("probes") sequentially as little-endian `f32`
+    /// in volts. Refer to the application for the available signals.
+    ///
+    /// # Example
+    /// With a batch size of 2, the serialization would take the following form:
+    /// ```
+    /// <PROBE0[0]> <PROBE0[1]> <PROBE1[0]> <PROBE1[1]>
+    /// ```
+    Probe = 6,
+
+    /// Averaged frequency domain estimates, one batch per frequency bin.
+    ///
+    /// Each batch consists of the bin frequency in Hz, the power spectral density of the analyzed
+    /// signal in V²/Hz, and the real and imaginary parts of the transfer function estimate (H1)
+    /// from the excitation to the analyzed signal, all `f32` in little-endian format. The transfer
+    /// function is NaN without excitation.
+    ///
+    /// # Example
+    /// ```
+    /// <frequency> <psd> <transfer.re> <transfer.im>
+    /// ```
+    Spectrum = 7,
+
+    /// Pounder DDS clock phase, one batch per sample batch.
+    ///
+    /// Each batch consists of the phase of the DDS clock relative to the sampling clock at the end
+    /// of the sample batch in Pounder timestamp ticks (32 ns) as a wrapping little-endian `i32`.
+    /// The sample batch follows from the frame sequence number.
+    ///
+    /// # Example
+    /// ```
+    /// <phase>
+    /// ```
+    PounderPhase = 8,
 }
 
 #[cfg(target_arch = "arm")]
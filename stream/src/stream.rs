@@ -1,7 +1,12 @@
 #![allow(non_camel_case_types)] // https://github.com/rust-embedded/heapless/issues/411
 
-use super::{Format, Target};
-use core::mem::MaybeUninit;
+use super::{
+    Config, FRAME_COUNT, FRAME_SIZE, Format, HEADER_SIZE, Statistics, Target,
+};
+use core::{
+    mem::MaybeUninit,
+    sync::atomic::{AtomicU8, AtomicU32, AtomicUsize, Ordering},
+};
 use heapless::{
     box_pool,
     pool::boxed::{Box, BoxBlock},
@@ -12,27 +17,47 @@ use smoltcp_nal::embedded_nal::{UdpClientStack, nb};
 // Magic first bytes indicating a UDP frame of straming data
 const MAGIC: u16 = 0x057B;
 
-// The size of the header, calculated in words.
-// The header has a 16-bit magic word, an 8-bit format, 8-bit batch-size, and 32-bit sequence
-// number, which corresponds to 8 bytes.
-const HEADER_SIZE: usize = 8;
-
-// The number of frames that can be buffered.
-const FRAME_COUNT: usize = 4;
-
-// The size of each frame in bytes.
-// Ensure the resulting ethernet frame is within the MTU:
-// 1500 MTU - 40 IP6 header - 8 UDP header - 32 VPN - 20 IP4
-const FRAME_SIZE: usize = 1500 - 40 - 8 - 32 - 20;
-
 // The size of the frame queue must be at least as large as the number of frame buffers. Every
 // allocated frame buffer should fit in the queue.
 const FRAME_QUEUE_SIZE: usize = FRAME_COUNT * 2;
 
+// Consecutive frames that could not be queued for transmission before the target is considered
+// unreachable.
+const PAUSE_THRESHOLD: u32 = 64;
+
+// While paused, only every n-th frame is sent to probe whether the target is reachable again.
+const PROBE_INTERVAL: u32 = 256;
+
 type Frame = [MaybeUninit<u8>; FRAME_SIZE];
 
 box_pool!(FRAME_POOL: Frame);
 
+/// State shared between the generator and the consumer.
+///
+/// # Note
+/// The frame limits are updated by the consumer and picked up by the generator whenever it starts
+/// a new frame. The drop counter is updated by the generator.
+struct Shared {
+    size: AtomicUsize,
+    batches: AtomicU8,
+    dropped: AtomicU32,
+}
+
+impl Shared {
+    const fn new() -> Self {
+        Self {
+            size: AtomicUsize::new(FRAME_SIZE),
+            batches: AtomicU8::new(0),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    fn set(&self, config: &Config) {
+        self.size.store(config.payload as _, Ordering::Relaxed);
+        self.batches.store(config.batches, Ordering::Relaxed);
+    }
+}
+
 /// Configure streaming on a device.
 ///
 /// # Args
@@ -52,6 +77,9 @@ pub fn setup<N: UdpClientStack<Error = smoltcp_nal::NetworkError>>(
             .unwrap();
     let (producer, consumer) = queue.split();
 
+    let shared: &'static Shared =
+        cortex_m::singleton!(: Shared = Shared::new()).unwrap();
+
     #[allow(clippy::declare_interior_mutable_const)]
     const FRAME: BoxBlock<Frame> = BoxBlock::new();
     let memory =
@@ -63,9 +91,9 @@ pub fn setup<N: UdpClientStack<Error = smoltcp_nal::NetworkError>>(
         FRAME_POOL.manage(block);
     }
 
-    let generator = FrameGenerator::new(producer);
+    let generator = FrameGenerator::new(producer, shared);
 
-    let stream = DataStream::new(stack, consumer);
+    let stream = DataStream::new(stack, consumer, shared);
 
     (generator, stream)
 }
@@ -75,6 +103,8 @@ struct StreamFrame {
     buffer: Box<FRAME_POOL>,
     offset: usize,
     batches: u8,
+    size: usize,
+    max_batches: u8,
 }
 
 impl StreamFrame {
@@ -82,6 +112,7 @@ impl StreamFrame {
         mut buffer: Box<FRAME_POOL>,
         format_id: u8,
         sequence_number: u32,
+        limits: &Shared,
     ) -> Self {
         for (byte, buf) in MAGIC
             .to_le_bytes()
@@ -97,6 +128,8 @@ impl StreamFrame {
             buffer,
             offset: HEADER_SIZE,
             batches: 0,
+            size: limits.size.load(Ordering::Relaxed),
+            max_batches: limits.batches.load(Ordering::Relaxed),
         }
     }
 
@@ -111,7 +144,11 @@ impl StreamFrame {
     }
 
     pub fn is_full(&self, len: usize) -> bool {
-        self.offset + len > self.buffer.len()
+        // Note: A single batch larger than the size limit is still sent in a frame of its own as
+        // long as it fits into the buffer.
+        self.offset + len > self.size
+            || self.batches == u8::MAX
+            || (self.max_batches != 0 && self.batches >= self.max_batches)
     }
 
     pub fn finish(&mut self) -> &[MaybeUninit<u8>] {
@@ -126,12 +163,17 @@ pub struct FrameGenerator {
     current_frame: Option<StreamFrame>,
     sequence_number: u32,
     format: u8,
+    shared: &'static Shared,
 }
 
 impl FrameGenerator {
-    fn new(queue: Producer<'static, StreamFrame, FRAME_QUEUE_SIZE>) -> Self {
+    fn new(
+        queue: Producer<'static, StreamFrame, FRAME_QUEUE_SIZE>,
+        shared: &'static Shared,
+    ) -> Self {
         Self {
             queue,
+            shared,
             format: Format::Unknown.into(),
             current_frame: None,
             sequence_number: 0,
@@ -141,12 +183,21 @@ impl FrameGenerator {
     /// Configure the format of the stream.
     ///
     /// # Note:
-    /// This function shall only be called once upon initializing streaming
+    /// If the format changes, the current frame is enqueued for transmission and subsequent
+    /// batches are added to a new frame.
     ///
     /// # Args
     /// * `format` - The desired format of the stream.
     pub fn configure(&mut self, format: impl Into<u8>) {
-        self.format = format.into();
+        let format = format.into();
+        if format != self.format {
+            if let Some(frame) = self.current_frame.take() {
+                // Note(unwrap): The queue is designed to be at least as large as the frame buffer
+                // count, so this enqueue should always succeed.
+                self.queue.enqueue(frame).unwrap();
+            }
+            self.format = format;
+        }
     }
 
     /// Add a batch to the current stream frame.
@@ -171,8 +222,10 @@ impl FrameGenerator {
                         buffer,
                         self.format,
                         sequence_number,
+                        self.shared,
                     ))
                 } else {
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
                     return;
                 }
             }
@@ -200,6 +253,13 @@ pub struct DataStream<N: UdpClientStack> {
     socket: Option<<N as UdpClientStack>::UdpSocket>,
     queue: Consumer<'static, StreamFrame, FRAME_QUEUE_SIZE>,
     remote: Target,
+    shared: &'static Shared,
+    frames: u32,
+    bytes: u64,
+    errors: u32,
+    failures: u32,
+    paused: bool,
+    skipped: u32,
 }
 
 impl<N: UdpClientStack<Error = smoltcp_nal::NetworkError>> DataStream<N> {
@@ -208,20 +268,29 @@ impl<N: UdpClientStack<Error = smoltcp_nal::NetworkError>> DataStream<N> {
     /// # Args
     /// * `stack` - A reference to the shared network stack.
     /// * `consumer` - The read side of the queue containing data to transmit.
-    /// * `frame_pool` - The Pool to return stream frame objects into.
+    /// * `shared` - The state shared with the generator.
     fn new(
         stack: N,
         consumer: Consumer<'static, StreamFrame, FRAME_QUEUE_SIZE>,
+        shared: &'static Shared,
     ) -> Self {
         Self {
             stack,
             socket: None,
             remote: Target::default(),
             queue: consumer,
+            shared,
+            frames: 0,
+            bytes: 0,
+            errors: 0,
+            failures: 0,
+            paused: false,
+            skipped: 0,
         }
     }
 
     fn close(&mut self) {
+        self.resume();
         if let Some(socket) = self.socket.take() {
             log::info!("Closing stream");
             // Note(unwrap): We guarantee that the socket is available above.
@@ -252,6 +321,28 @@ impl<N: UdpClientStack<Error = smoltcp_nal::NetworkError>> DataStream<N> {
         Ok(())
     }
 
+    /// Account for a frame that could not be queued for transmission.
+    ///
+    /// # Note
+    /// smoltcp holds frames for a target with an unresolved link address in the socket buffer.
+    /// Once the buffer is full, every send fails. Port-unreachable ICMP messages are not
+    /// reported through the UDP socket and can not be detected.
+    fn fail(&mut self) {
+        self.errors = self.errors.wrapping_add(1);
+        self.failures = self.failures.saturating_add(1);
+        if !self.paused && self.failures >= PAUSE_THRESHOLD {
+            log::warn!("Stream target {} unreachable. Pausing", self.remote.0);
+            self.paused = true;
+        }
+    }
+
+    fn resume(&mut self) {
+        self.failures = 0;
+        if core::mem::take(&mut self.paused) {
+            log::info!("Stream target {} reachable. Resuming", self.remote.0);
+        }
+    }
+
     /// Configure the remote endpoint of the stream.
     ///
     /// # Args
@@ -264,6 +355,44 @@ impl<N: UdpClientStack<Error = smoltcp_nal::NetworkError>> DataStream<N> {
         self.remote = remote;
     }
 
+    /// Configure the stream.
+    ///
+    /// # Note
+    /// Frame limits take effect with the next frame started by the generator.
+    ///
+    /// # Args
+    /// * `config` - The stream target and frame limits. Invalid limits are rejected and the
+    ///   previous limits are kept.
+    pub fn configure(&mut self, config: &Config) {
+        if config.is_valid() {
+            self.shared.set(config);
+        } else {
+            log::error!(
+                "Stream payload {} outside {HEADER_SIZE}..={FRAME_SIZE}, keeping the previous limits",
+                config.payload
+            );
+        }
+        self.set_remote(config.target);
+    }
+
+    /// Whether transmission is paused because the target is unreachable.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Get the stream transmission statistics.
+    pub fn statistics(&self) -> Statistics {
+        Statistics {
+            target: self.remote,
+            connected: self.socket.is_some(),
+            paused: self.paused,
+            frames: self.frames,
+            bytes: self.bytes,
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+            errors: self.errors,
+        }
+    }
+
     /// Process any data for transmission.
     pub fn process(&mut self) {
         match self.socket.as_mut() {
@@ -278,6 +407,16 @@ impl<N: UdpClientStack<Error = smoltcp_nal::NetworkError>> DataStream<N> {
             }
             Some(handle) => {
                 if let Some(mut frame) = self.queue.dequeue() {
+                    // While the target is unreachable, drop frames except for occasional probes.
+                    if self.paused {
+                        self.skipped = self.skipped.wrapping_add(1);
+                        if self.skipped % PROBE_INTERVAL != 0 {
+                            self.errors = self.errors.wrapping_add(1);
+                            drop(frame.buffer);
+                            return;
+                        }
+                    }
+
                     // Transmit the frame and return it to the pool.
                     let buf = frame.finish();
                     let data = unsafe {
@@ -290,20 +429,28 @@ impl<N: UdpClientStack<Error = smoltcp_nal::NetworkError>> DataStream<N> {
                     // If we fail to send, it can only be because the socket got closed on us (i.e.
                     // address update due to DHCP). If this happens, reopen the socket.
                     match self.stack.send(handle, data) {
-                        Ok(_) => {},
+                        Ok(_) => {
+                            self.frames = self.frames.wrapping_add(1);
+                            self.bytes = self.bytes.wrapping_add(data.len() as _);
+                            self.resume();
+                        },
 
                         // Our IP address may have changedm so handle reopening the UDP stream.
                         Err(nb::Error::Other(smoltcp_nal::NetworkError::UdpWriteFailure(smoltcp_nal::smoltcp::socket::udp::SendError::Unaddressable))) => {
+                            self.errors = self.errors.wrapping_add(1);
                             log::warn!( "IP address updated during stream. Reopening socket");
                             let socket = self.socket.take().unwrap();
                             self.stack.close(socket).unwrap();
                         }
 
-                        // The buffer should clear up once ICMP resolves the IP address, so ignore
-                        // this error.
-                        Err(nb::Error::Other(smoltcp_nal::NetworkError::UdpWriteFailure(smoltcp_nal::smoltcp::socket::udp::SendError::BufferFull))) => {}
+                        // The buffer should clear up once ARP resolves the IP address. If it
+                        // persists, the target is unreachable and the stream is paused.
+                        Err(nb::Error::Other(smoltcp_nal::NetworkError::UdpWriteFailure(smoltcp_nal::smoltcp::socket::udp::SendError::BufferFull))) => {
+                            self.fail();
+                        }
 
                         Err(other) => {
+                            self.errors = self.errors.wrapping_add(1);
                             log::warn!("Unexpected UDP error during data stream: {other:?}");
                         }
                     }
@@ -100,6 +100,10 @@ pub fn att_to_mu(att: f32) -> u8 {
     255 - (att * 8.0).round() as u8
 }
 
+pub const fn mu_to_att(mu: u8) -> f32 {
+    (255 - mu) as f32 / 8.0
+}
+
 pub struct Urukul<'a, B, P> {
     att_spi: RefCellDevice<'a, B, EncodedPin<'a, P, 3>, NoDelay>,
     cfg_spi: RefCellDevice<'a, B, EncodedPin<'a, P, 3>, NoDelay>,